@@ -2,10 +2,11 @@ import 'dart:async';
 
 import 'package:device_info_plus/device_info_plus.dart';
 import 'package:easy_localization/easy_localization.dart';
-import 'package:flutter/cupertino.dart';
+import 'package:flutter/material.dart';
 import 'package:flutter/foundation.dart';
 import 'package:intl/date_symbol_data_local.dart';
 import 'package:memolanes/common/app_lifecycle_service.dart';
+import 'package:memolanes/common/db_key_store.dart';
 import 'package:memolanes/common/share_handler_util.dart';
 import 'package:memolanes/common/update_notifier.dart';
 import 'package:memolanes/common/gps_manager.dart';
@@ -53,7 +54,7 @@ void delayedInit(UpdateNotifier updateNotifier) {
             buildNumber: packageInfo.buildNumber));
 
     // Db optimization check
-    const currentOptimizationCheckVersion = 1;
+    const currentOptimizationCheckVersion = 2;
     final dbOptimizeCheck = MMKVUtil.getInt(MMKVKey.dbOptimizationCheck);
     if (dbOptimizeCheck < currentOptimizationCheckVersion) {
       if (await api.mainDbRequireOptimization()) {
@@ -125,11 +126,70 @@ class AppBootstrap {
       cacheDirFuture,
     ]);
 
-    await api.init(
-        tempDir: (await tempDirFuture).path,
-        docDir: (await docDirFuture).path,
-        supportDir: (await supportDirFuture).path,
-        systemCacheDir: (await cacheDirFuture).path);
+    final tempDir = (await tempDirFuture).path;
+    final docDir = (await docDirFuture).path;
+    final supportDir = (await supportDirFuture).path;
+    final systemCacheDir = (await cacheDirFuture).path;
+    // Builds with `--dart-define=REQUIRE_DB_ENCRYPTION=true` encrypt the db from
+    // the first run, otherwise it is opt-in from the advanced settings.
+    var dbKey = await DbKeyStore.read();
+    if (dbKey == null &&
+        const bool.fromEnvironment("REQUIRE_DB_ENCRYPTION")) {
+      dbKey = await DbKeyStore.create();
+    }
+    initApi() => api.init(
+        tempDir: tempDir,
+        docDir: docDir,
+        supportDir: supportDir,
+        systemCacheDir: systemCacheDir,
+        dbKey: dbKey);
+
+    try {
+      await initApi();
+    } catch (error) {
+      // Only a damaged main db is replaced by a backup, and only if the user
+      // agrees since backups don't have everything. The old db is kept next to
+      // the restored one.
+      if (!api.isMainDbCorrupted()) rethrow;
+      log.error("[bootstrap] Main db is corrupted $error");
+      if (!await _confirmRestoreBackup()) rethrow;
+      final String name;
+      try {
+        name = await api.restoreLatestBackupBeforeInit(
+            supportDir: supportDir, dbKey: dbKey);
+      } catch (restoreError) {
+        log.error("[bootstrap] Failed to restore latest backup $restoreError");
+        rethrow;
+      }
+      log.info("[bootstrap] Restored backup $name");
+      await initApi();
+    }
+  }
+
+  // Runs before the real app, so a minimal one is used just for the dialog.
+  // `main` replaces it with the real one.
+  static Future<bool> _confirmRestoreBackup() {
+    final result = Completer<bool>();
+    runApp(
+      EasyLocalization(
+        supportedLocales: const [
+          Locale('en', 'US'),
+          Locale('zh', 'CN'),
+        ],
+        path: 'assets/translations',
+        fallbackLocale: const Locale('en', 'US'),
+        saveLocale: false,
+        child: Builder(
+          builder: (context) => MaterialApp(
+            supportedLocales: context.supportedLocales,
+            localizationsDelegates: context.localizationDelegates,
+            locale: context.locale,
+            home: _RestoreBackupPrompt(onResult: result.complete),
+          ),
+        ),
+      ),
+    );
+    return result.future;
   }
 
   static void startAppServices({
@@ -165,3 +225,33 @@ class AppBootstrap {
     return _mainMapReady;
   }
 }
+
+class _RestoreBackupPrompt extends StatefulWidget {
+  const _RestoreBackupPrompt({required this.onResult});
+
+  final void Function(bool) onResult;
+
+  @override
+  State<_RestoreBackupPrompt> createState() => _RestoreBackupPromptState();
+}
+
+class _RestoreBackupPromptState extends State<_RestoreBackupPrompt> {
+  @override
+  void initState() {
+    super.initState();
+    WidgetsBinding.instance.addPostFrameCallback((_) async {
+      final confirmed = await showCommonDialog(
+        context,
+        context.tr("backups.restore_corrupted_message"),
+        hasCancel: true,
+        confirmButtonText: context.tr("backups.restore"),
+      );
+      widget.onResult(confirmed);
+    });
+  }
+
+  @override
+  Widget build(BuildContext context) {
+    return const Scaffold();
+  }
+}
@@ -13,6 +13,8 @@ import 'package:memolanes/common/utils.dart';
 import 'package:memolanes/src/rust/api/import.dart' as import_api;
 import 'package:memolanes/src/rust/api/utils.dart';
 import 'package:memolanes/src/rust/journey_header.dart';
+import 'package:memolanes/src/rust/preprocessor_detector.dart';
+import 'package:memolanes/src/rust/transport_mode.dart';
 
 class JourneyInfoEditPage extends StatefulWidget {
   const JourneyInfoEditPage({
@@ -24,8 +26,10 @@ class JourneyInfoEditPage extends StatefulWidget {
     required this.saveData,
     this.previewData,
     this.journeyKind,
+    this.transportMode,
     this.importType,
     this.preprocessor,
+    this.recommendation,
   });
 
   final DateTime? startTime;
@@ -33,10 +37,12 @@ class JourneyInfoEditPage extends StatefulWidget {
   final NaiveDate journeyDate;
   final String? note;
   final JourneyKind? journeyKind;
+  final TransportMode? transportMode;
   final Function saveData;
   final Function? previewData;
   final ImportType? importType;
   final import_api.ImportPreprocessor? preprocessor;
+  final PreprocessorRecommendation? recommendation;
 
   @override
   State<JourneyInfoEditPage> createState() => _JourneyInfoEditPageState();
@@ -53,6 +59,9 @@ class _JourneyInfoEditPageState extends State<JourneyInfoEditPage> {
   JourneyKind _journeyKind = JourneyKind.defaultKind;
   final TextEditingController _noteController = TextEditingController();
   late import_api.ImportPreprocessor _preprocessor;
+  import_api.ImportSplitRule? _splitRule;
+
+  static const int _splitGapHours = 2;
 
   Future<DateTime?> selectDateAndTime(
       BuildContext context, DateTime? datetime) async {
@@ -122,9 +131,10 @@ class _JourneyInfoEditPageState extends State<JourneyInfoEditPage> {
         startTime: _startTime,
         endTime: _endTime,
         note: _note,
-        journeyKind: _journeyKind);
+        journeyKind: _journeyKind,
+        transportMode: widget.transportMode);
     if (widget.importType != null) {
-      await widget.saveData(journeyInfo, _preprocessor);
+      await widget.saveData(journeyInfo, _preprocessor, _splitRule);
     } else {
       await widget.saveData(journeyInfo);
     }
@@ -205,25 +215,26 @@ class _JourneyInfoEditPageState extends State<JourneyInfoEditPage> {
                     label: context.tr("journey.preprocessor"),
                     infoLabelOnTap: () => showCommonDialog(
                       context,
-                      context.tr("preprocessor.description_md"),
+                      _preprocessorDescription(context),
                       markdown: true,
                     ),
                     position: LabelTilePosition.single,
                     trailing: LabelTileContent(
-                      content: switch (_preprocessor) {
-                        import_api.ImportPreprocessor.none =>
-                          context.tr("preprocessor.none"),
-                        import_api.ImportPreprocessor.generic =>
-                          context.tr("preprocessor.generic"),
-                        import_api.ImportPreprocessor.flightTrack =>
-                          context.tr("preprocessor.flightTrack"),
-                        import_api.ImportPreprocessor.spare =>
-                          context.tr("preprocessor.spare"),
-                      },
+                      content: _preprocessorLabel(context, _preprocessor),
                       showArrow: true,
                     ),
                     onTap: () => _showJourneyPreprocessorCard(context),
                   ),
+          if (widget.importType == ImportType.gpxOrKml)
+            LabelTile(
+              label: context.tr("journey.split_rule"),
+              position: LabelTilePosition.single,
+              trailing: LabelTileContent(
+                content: _splitRuleLabel(context, _splitRule),
+                showArrow: true,
+              ),
+              onTap: () => _showSplitRuleCard(context),
+            ),
           LabelTile(
             label: context.tr("journey.journey_kind"),
             position: LabelTilePosition.single,
@@ -349,4 +360,103 @@ class _JourneyInfoEditPageState extends State<JourneyInfoEditPage> {
       ),
     );
   }
+
+  String _preprocessorLabel(
+      BuildContext context, import_api.ImportPreprocessor preprocessor) {
+    return switch (preprocessor) {
+      import_api.ImportPreprocessor.none => context.tr("preprocessor.none"),
+      import_api.ImportPreprocessor.generic =>
+        context.tr("preprocessor.generic"),
+      import_api.ImportPreprocessor.flightTrack =>
+        context.tr("preprocessor.flightTrack"),
+      import_api.ImportPreprocessor.spare => context.tr("preprocessor.spare"),
+    };
+  }
+
+  String _preprocessorReasonLabel(
+      BuildContext context, PreprocessorReason reason) {
+    String percent(double x) => (x * 100).toStringAsFixed(0);
+    return switch (reason) {
+      PreprocessorReason_HighAltitude(:final medianAltitudeM) => context.tr(
+          "preprocessor.reason.high_altitude",
+          args: [medianAltitudeM.toStringAsFixed(0)]),
+      PreprocessorReason_LowTimestampCoverage(:final coverage) => context.tr(
+          "preprocessor.reason.low_timestamp_coverage",
+          args: [percent(coverage)]),
+      PreprocessorReason_NotEnoughTimedPoints() =>
+        context.tr("preprocessor.reason.not_enough_timed_points"),
+      PreprocessorReason_BadTimestamps(:final ratio) => context
+          .tr("preprocessor.reason.bad_timestamps", args: [percent(ratio)]),
+      PreprocessorReason_SamplingStats(
+        :final medianIntervalSec,
+        :final medianSpeedKmPerH
+      ) =>
+        context.tr("preprocessor.reason.sampling_stats", args: [
+          medianIntervalSec.toStringAsFixed(0),
+          medianSpeedKmPerH.toStringAsFixed(0)
+        ]),
+      PreprocessorReason_HighSpeed() =>
+        context.tr("preprocessor.reason.high_speed"),
+      PreprocessorReason_Sparse(:final defaultBreakRatio) => context
+          .tr("preprocessor.reason.sparse", args: [percent(defaultBreakRatio)]),
+      PreprocessorReason_NormalTrack() =>
+        context.tr("preprocessor.reason.normal_track"),
+    };
+  }
+
+  String _preprocessorDescription(BuildContext context) {
+    final description = context.tr("preprocessor.description_md");
+    final recommendation = widget.recommendation;
+    if (recommendation == null) return description;
+    final reasons = recommendation.reasons
+        .map((x) => "- ${_preprocessorReasonLabel(context, x)}")
+        .join("\n");
+    return "$description\n\n${context.tr("preprocessor.recommended", args: [
+          _preprocessorLabel(context, recommendation.preprocessor)
+        ])}\n$reasons";
+  }
+
+  String _splitRuleLabel(
+      BuildContext context, import_api.ImportSplitRule? splitRule) {
+    return switch (splitRule) {
+      null => context.tr("import_split.none"),
+      import_api.ImportSplitRule_LocalDay() =>
+        context.tr("import_split.local_day"),
+      import_api.ImportSplitRule_TimeGap(:final gapMinutes) => context
+          .tr("import_split.time_gap", args: [(gapMinutes ~/ 60).toString()]),
+      import_api.ImportSplitRule_TransportMode() =>
+        context.tr("import_split.transport_mode"),
+    };
+  }
+
+  void _showSplitRuleCard(BuildContext context) {
+    final splitRules = <import_api.ImportSplitRule?>[
+      null,
+      import_api.ImportSplitRule.localDay(),
+      import_api.ImportSplitRule.timeGap(gapMinutes: _splitGapHours * 60),
+      import_api.ImportSplitRule.transportMode(),
+    ];
+    showBasicCard(
+      context,
+      child: OptionCard(
+        children: [
+          for (final (i, splitRule) in splitRules.indexed)
+            CardLabelTile(
+              position: i == 0
+                  ? CardLabelTilePosition.top
+                  : i == splitRules.length - 1
+                      ? CardLabelTilePosition.bottom
+                      : CardLabelTilePosition.middle,
+              label: _splitRuleLabel(context, splitRule),
+              onTap: () {
+                setState(() {
+                  _splitRule = splitRule;
+                });
+              },
+              top: false,
+            ),
+        ],
+      ),
+    );
+  }
 }
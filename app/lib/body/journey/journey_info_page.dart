@@ -103,6 +103,7 @@ class _JourneyInfoPage extends State<JourneyInfoPage> {
             journeyDate: _journeyHeader.journeyDate,
             note: _journeyHeader.note,
             journeyKind: _journeyHeader.journeyKind,
+            transportMode: _journeyHeader.transportMode,
             saveData: (JourneyInfo journeyInfo) async {
               await api.updateJourneyMetadata(
                   id: _journeyHeader.id, journeyInfo: journeyInfo);
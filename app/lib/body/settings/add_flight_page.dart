@@ -0,0 +1,142 @@
+import 'package:easy_localization/easy_localization.dart';
+import 'package:flutter/material.dart';
+import 'package:fluttertoast/fluttertoast.dart';
+import 'package:memolanes/common/component/capsule_style_app_bar.dart';
+import 'package:memolanes/common/component/scroll_views/single_child_scroll_view.dart';
+import 'package:memolanes/common/component/tiles/label_tile.dart';
+import 'package:memolanes/common/component/tiles/label_tile_content.dart';
+import 'package:memolanes/common/log.dart';
+import 'package:memolanes/common/utils.dart';
+import 'package:memolanes/src/rust/api/import.dart' as import_api;
+
+/// Logging a flight with just the airport codes, for flights without any
+/// recorded data.
+class AddFlightPage extends StatefulWidget {
+  const AddFlightPage({super.key});
+
+  @override
+  State<AddFlightPage> createState() => _AddFlightPageState();
+}
+
+class _AddFlightPageState extends State<AddFlightPage> {
+  final DateFormat dateFormat = DateFormat("yyyy-MM-dd");
+  final TextEditingController _originController = TextEditingController();
+  final TextEditingController _destinationController =
+      TextEditingController();
+  DateTime _journeyDate = DateTime.now();
+
+  @override
+  void dispose() {
+    _originController.dispose();
+    _destinationController.dispose();
+    super.dispose();
+  }
+
+  Widget _airportField(BuildContext context, TextEditingController controller) {
+    final width = MediaQueryData.fromView(View.of(context)).size.width;
+    return SizedBox(
+      width: width * 0.5,
+      child: TextField(
+        controller: controller,
+        textCapitalization: TextCapitalization.characters,
+        maxLength: 4,
+        onChanged: (_) => setState(() {}),
+        decoration: InputDecoration(
+          border: InputBorder.none,
+          counterText: '',
+          hintText: "IATA / ICAO",
+          hintStyle: TextStyle(fontSize: 14.0),
+        ),
+        textAlign: TextAlign.right,
+      ),
+    );
+  }
+
+  String _airportName(TextEditingController controller) {
+    return import_api.lookupAirport(code: controller.text)?.name ?? "";
+  }
+
+  Future<void> _save(BuildContext context) async {
+    final origin = _originController.text;
+    final destination = _destinationController.text;
+    if (import_api.lookupAirport(code: origin) == null ||
+        import_api.lookupAirport(code: destination) == null) {
+      Fluttertoast.showToast(msg: context.tr("add_flight.unknown_airport"));
+      return;
+    }
+    try {
+      await import_api.createFlightJourney(
+          origin: origin,
+          destination: destination,
+          journeyDate: dateTimeToNaiveDate(_journeyDate));
+    } catch (error) {
+      log.error("[add_flight] Failed to create the flight: $error");
+      if (!context.mounted) return;
+      await showCommonDialog(context, context.tr("add_flight.failed"));
+      return;
+    }
+    if (!context.mounted) return;
+    Fluttertoast.showToast(msg: context.tr("add_flight.successful"));
+    Navigator.pop(context);
+  }
+
+  @override
+  Widget build(BuildContext context) {
+    return Scaffold(
+      appBar: CapsuleStyleAppBar(
+        title: context.tr("add_flight.title"),
+      ),
+      body: MlSingleChildScrollView(
+        padding: EdgeInsets.all(8.0),
+        children: [
+          LabelTile(
+            label: context.tr("add_flight.origin"),
+            desc: _airportName(_originController),
+            position: LabelTilePosition.top,
+            trailing: _airportField(context, _originController),
+          ),
+          LabelTile(
+            label: context.tr("add_flight.destination"),
+            desc: _airportName(_destinationController),
+            position: LabelTilePosition.middle,
+            trailing: _airportField(context, _destinationController),
+          ),
+          LabelTile(
+            label: context.tr("journey.journey_date"),
+            position: LabelTilePosition.bottom,
+            trailing:
+                LabelTileContent(content: dateFormat.format(_journeyDate)),
+            onTap: () async {
+              DateTime? time = await showDatePicker(
+                context: context,
+                initialDate: _journeyDate,
+                firstDate: DateTime(1990),
+                lastDate: DateTime.now(),
+              );
+              if (time != null) {
+                setState(() {
+                  _journeyDate = time;
+                });
+              }
+            },
+          ),
+          const SizedBox(height: 16.0),
+          Center(
+            child: ElevatedButton(
+              onPressed: () => _save(context),
+              style: ElevatedButton.styleFrom(
+                backgroundColor: const Color(0xFFB6E13D),
+                foregroundColor: Colors.black,
+                fixedSize: Size(280, 42),
+                shape: RoundedRectangleBorder(
+                  borderRadius: BorderRadius.circular(25.0),
+                ),
+              ),
+              child: Text(context.tr("common.save")),
+            ),
+          ),
+        ],
+      ),
+    );
+  }
+}
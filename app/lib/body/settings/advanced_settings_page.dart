@@ -2,12 +2,17 @@ import 'package:easy_localization/easy_localization.dart';
 import 'package:flutter/material.dart';
 import 'package:memolanes/common/component/capsule_style_app_bar.dart';
 import 'package:memolanes/common/gps_manager.dart';
+import 'package:memolanes/body/settings/backups_page.dart';
+import 'package:memolanes/body/settings/privacy_zones_page.dart';
 import 'package:memolanes/body/settings/raw_data_page.dart';
 import 'package:memolanes/common/component/scroll_views/single_child_scroll_view.dart';
 import 'package:memolanes/common/component/tiles/label_tile.dart';
 import 'package:memolanes/common/component/tiles/label_tile_content.dart';
+import 'package:memolanes/common/db_key_store.dart';
+import 'package:memolanes/common/log.dart';
 import 'package:memolanes/common/utils.dart';
 import 'package:memolanes/src/rust/api/api.dart' as api;
+import 'package:memolanes/src/rust/journey_splitter.dart';
 import 'package:memolanes/utils/nav_helper.dart';
 import 'package:path_provider/path_provider.dart';
 import 'package:provider/provider.dart';
@@ -21,6 +26,39 @@ class AdvancedSettingsPage extends StatefulWidget {
 }
 
 class _AdvancedSettingsPageState extends State<AdvancedSettingsPage> {
+  JourneySplitOptions _journeySplitOptions = api.getJourneySplitOptions();
+  bool _dbEncrypted = api.isDbEncrypted();
+
+  Future<void> _setDbEncrypted(bool value) async {
+    if (!value) {
+      final confirmed = await showCommonDialog(
+        context,
+        context.tr("general.advanced_settings.db_encryption_disable_confirm"),
+        hasCancel: true,
+      );
+      if (!confirmed) return;
+    }
+    try {
+      // The key has to be persisted before the db is encrypted with it.
+      final key = value ? await DbKeyStore.create() : null;
+      await showLoadingDialog(asyncTask: api.rotateDbKey(newKey: key));
+    } catch (error) {
+      log.error("[advanced_settings] Failed to change db encryption $error");
+      if (mounted) {
+        await showCommonDialog(context, error.toString());
+      }
+    }
+    // Rotating can fail after the db itself is done (e.g. on the backup), so
+    // the stored key follows the actual state of the db.
+    if (!api.isDbEncrypted()) {
+      await DbKeyStore.delete();
+    }
+    if (!mounted) return;
+    setState(() {
+      _dbEncrypted = api.isDbEncrypted();
+    });
+  }
+
   @override
   Widget build(BuildContext context) {
     var gpsManager = context.watch<GpsManager>();
@@ -115,6 +153,42 @@ class _AdvancedSettingsPageState extends State<AdvancedSettingsPage> {
             position: LabelTilePosition.middle,
             onTap: () => navigatorPush(context, page: RawDataPage()),
           ),
+          LabelTile(
+            label: context.tr("privacy_zones.title"),
+            position: LabelTilePosition.middle,
+            onTap: () => navigatorPush(context, page: PrivacyZonesPage()),
+          ),
+          LabelTile(
+            label: context.tr("backups.title"),
+            position: LabelTilePosition.middle,
+            onTap: () => navigatorPush(context, page: BackupsPage()),
+          ),
+          LabelTile(
+            label: context
+                .tr("general.advanced_settings.split_journey_at_midnight"),
+            position: LabelTilePosition.middle,
+            trailing: Switch(
+              value: _journeySplitOptions.splitAtLocalDate,
+              onChanged: (value) async {
+                final options = JourneySplitOptions(
+                  splitAtLocalDate: value,
+                  maxGapMins: _journeySplitOptions.maxGapMins,
+                );
+                await api.setJourneySplitOptions(options: options);
+                setState(() {
+                  _journeySplitOptions = options;
+                });
+              },
+            ),
+          ),
+          LabelTile(
+            label: context.tr("general.advanced_settings.db_encryption"),
+            position: LabelTilePosition.middle,
+            trailing: Switch(
+              value: _dbEncrypted,
+              onChanged: _setDbEncrypted,
+            ),
+          ),
           LabelTile(
             label: context.tr("general.advanced_settings.rebuild_cache"),
             position: LabelTilePosition.middle,
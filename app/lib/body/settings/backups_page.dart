@@ -0,0 +1,130 @@
+import 'package:easy_localization/easy_localization.dart';
+import 'package:flutter/material.dart';
+import 'package:memolanes/common/component/capsule_style_app_bar.dart';
+import 'package:memolanes/common/component/tiles/label_tile.dart';
+import 'package:memolanes/common/log.dart';
+import 'package:memolanes/common/utils.dart';
+import 'package:memolanes/src/rust/api/api.dart' as api;
+import 'package:memolanes/src/rust/backup_store.dart';
+import 'package:memolanes/src/rust/storage.dart';
+
+/// Automatic local backups, they are created by the heartbeat.
+class BackupsPage extends StatefulWidget {
+  const BackupsPage({super.key});
+
+  @override
+  State<BackupsPage> createState() => _BackupsPageState();
+}
+
+class _BackupsPageState extends State<BackupsPage> {
+  static final DateFormat _dateFormat = DateFormat('yyyy-MM-dd HH:mm');
+
+  BackupPolicy _policy = api.getBackupPolicy();
+  List<BackupFile> items = [];
+
+  @override
+  void initState() {
+    super.initState();
+    _loadList();
+  }
+
+  void _loadList() async {
+    var list = await api.listBackups();
+    setState(() {
+      items = list;
+    });
+  }
+
+  Future<void> _setEnabled(bool enabled) async {
+    final policy = BackupPolicy(
+      enabled: enabled,
+      keepDaily: _policy.keepDaily,
+      keepWeekly: _policy.keepWeekly,
+      keepMonthly: _policy.keepMonthly,
+    );
+    await api.setBackupPolicy(policy: policy);
+    setState(() {
+      _policy = policy;
+    });
+  }
+
+  Future<void> _createBackup(BuildContext context) async {
+    try {
+      await showLoadingDialog(asyncTask: api.createBackup());
+    } catch (error) {
+      log.error("[backups] Failed to create backup $error");
+      if (context.mounted) {
+        await showCommonDialog(context, context.tr("backups.create_failed"));
+      }
+    }
+    _loadList();
+  }
+
+  Future<void> _restore(BuildContext context, BackupFile item) async {
+    if (!await showCommonDialog(context, context.tr("backups.restore_message"),
+        hasCancel: true, confirmButtonText: context.tr("backups.restore"))) {
+      return;
+    }
+    try {
+      await showLoadingDialog(asyncTask: api.restoreBackup(name: item.name));
+      if (context.mounted) {
+        await showCommonDialog(context, context.tr("import.successful"));
+      }
+    } catch (error) {
+      log.error("[backups] Failed to restore backup $error");
+      if (context.mounted) {
+        await showCommonDialog(context, context.tr("backups.restore_failed"));
+      }
+    }
+  }
+
+  @override
+  Widget build(BuildContext context) {
+    return Scaffold(
+      appBar: CapsuleStyleAppBar(
+        title: context.tr("backups.title"),
+      ),
+      body: Column(
+        crossAxisAlignment: CrossAxisAlignment.center,
+        children: [
+          const SizedBox(height: 8),
+          Padding(
+            padding: EdgeInsets.symmetric(horizontal: 8.0),
+            child: LabelTile(
+              label: context.tr("backups.enabled"),
+              position: LabelTilePosition.single,
+              trailing: Switch(
+                value: _policy.enabled,
+                onChanged: _setEnabled,
+              ),
+            ),
+          ),
+          const SizedBox(height: 8),
+          ElevatedButton(
+            onPressed: () => _createBackup(context),
+            child: Text(context.tr("backups.create")),
+          ),
+          const SizedBox(height: 16),
+          Expanded(
+            child: ListView(
+              shrinkWrap: true,
+              children: items.map((item) {
+                return ListTile(
+                  leading: const Icon(Icons.backup_outlined),
+                  title: Text(_dateFormat.format(item.createdAt)),
+                  subtitle: Text(context.tr("backups.size", args: [
+                    (item.sizeBytes.toInt() / 1024 / 1024).toStringAsFixed(1)
+                  ])),
+                  trailing: ElevatedButton(
+                    onPressed: () => _restore(context, item),
+                    child: const Icon(Icons.restore),
+                  ),
+                );
+              }).toList(),
+            ),
+          ),
+        ],
+      ),
+    );
+  }
+}
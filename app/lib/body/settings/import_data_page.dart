@@ -10,6 +10,7 @@ import 'package:memolanes/common/utils.dart';
 import 'package:memolanes/src/rust/api/api.dart' as api;
 import 'package:memolanes/src/rust/api/import.dart' as import_api;
 import 'package:memolanes/src/rust/journey_data.dart';
+import 'package:memolanes/src/rust/preprocessor_detector.dart';
 import 'package:pointer_interceptor/pointer_interceptor.dart';
 import 'package:sliding_up_panel/sliding_up_panel.dart';
 
@@ -33,6 +34,7 @@ class _ImportDataPage extends State<ImportDataPage> {
   api.MapRendererProxy? _mapRendererProxy;
   MapView? _initialMapView;
   late import_api.ImportPreprocessor _preprocessor;
+  PreprocessorRecommendation? _recommendation;
 
   @override
   void initState() {
@@ -43,6 +45,11 @@ class _ImportDataPage extends State<ImportDataPage> {
   }
 
   Future<void> _initFlow() async {
+    if (widget.importType == ImportType.gpxOrKml &&
+        import_api.shouldImportByLocalDay(filePath: widget.path)) {
+      await _importByLocalDay();
+      return;
+    }
     try {
       if (!await showLoadingDialog(
         asyncTask: () async {
@@ -86,17 +93,63 @@ class _ImportDataPage extends State<ImportDataPage> {
         break;
 
       case ImportType.gpxOrKml:
-        var (journeyInfo, rawVectorData, detectedProcessor) =
+        var (journeyInfo, rawVectorData, detectedProcessor, warnings) =
             await import_api.loadGpxOrKml(filePath: path);
+        final recommendation = await import_api.recommendImportPreprocessor(
+            vectorData: rawVectorData);
         setState(() {
+          _recommendation = recommendation;
           this.journeyInfo = journeyInfo;
           _preprocessor = detectedProcessor;
           journeyDataMaybeRaw = f.Either.right(rawVectorData);
         });
+        if (warnings != null && context.mounted) {
+          showCommonDialog(
+              context, context.tr("import.warnings", args: [warnings]));
+        }
         break;
     }
   }
 
+  /// Large GPX files are imported directly, one journey per day, since the
+  /// preview needs the whole file in memory.
+  Future<void> _importByLocalDay() async {
+    if (!await showCommonDialog(
+        context, context.tr("import.large_file_message"),
+        hasCancel: true)) {
+      if (context.mounted && Navigator.canPop(context)) {
+        Navigator.pop(context);
+      }
+      return;
+    }
+    try {
+      final result = await showLoadingDialog(
+          asyncTask: import_api.importGpxByLocalDay(filePath: widget.path));
+      if (!context.mounted) return;
+      if (result.importedJourneyCount.toInt() == 0) {
+        await showCommonDialog(context, context.tr("import.empty_data"));
+      } else {
+        await showCommonDialog(
+            context,
+            context.tr("import.large_file_successful",
+                args: ["${result.importedJourneyCount}"]));
+      }
+      final warnings = result.warnings;
+      if (warnings != null && context.mounted) {
+        await showCommonDialog(
+            context, context.tr("import.warnings", args: [warnings]));
+      }
+    } catch (error) {
+      log.error("[import_data] Large file import failed $error");
+      if (context.mounted) {
+        await showCommonDialog(context, context.tr("import.parsing_failed"));
+      }
+    }
+    if (context.mounted && Navigator.canPop(context)) {
+      Navigator.pop(context);
+    }
+  }
+
   Future<void> _previewData(import_api.ImportPreprocessor preprocessor) async {
     if (preprocessor == _preprocessor) return;
 
@@ -146,22 +199,83 @@ class _ImportDataPage extends State<ImportDataPage> {
     );
   }
 
-  Future<void> _saveData(import_api.JourneyInfo journeyInfo,
+  /// Returns `false` if there is nothing to import.
+  Future<bool> _importVectorData(import_api.JourneyInfo journeyInfo,
+      import_api.RawVectorData vectorData,
       import_api.ImportPreprocessor processor) async {
+    final journeyData = await import_api.processVectorData(
+        vectorData: vectorData, importProcessor: processor);
+    if (await import_api.isJourneyDataEmpty(journeyData: journeyData)) {
+      return false;
+    }
+    if (processor == import_api.ImportPreprocessor.flightTrack) {
+      await import_api.importJourneyDataWithPlaces(
+          journeyInfo: journeyInfo,
+          journeyData: journeyData,
+          vectorData: vectorData);
+    } else {
+      // flights in the data are imported as separate journeys
+      await import_api.importVectorDataWithFlights(
+          journeyInfo: journeyInfo,
+          vectorData: vectorData,
+          importProcessor: processor);
+    }
+    return true;
+  }
+
+  Future<void> _saveData(
+      import_api.JourneyInfo journeyInfo,
+      import_api.ImportPreprocessor processor,
+      import_api.ImportSplitRule? splitRule) async {
+    // flights are imported as separate journeys with their own time range, so
+    // the date and time from the page can't be used
+    if (journeyDataMaybeRaw case f.Right(value: final r)) {
+      if (processor != import_api.ImportPreprocessor.flightTrack) {
+        final flightCount = await import_api.countFlights(vectorData: r);
+        if (flightCount > 0 &&
+            !await showCommonDialog(
+                context,
+                context.tr("import.split_flights_message",
+                    args: [flightCount.toString()]),
+                hasCancel: true)) {
+          throw Exception("[import_data] Import canceled");
+        }
+      }
+    }
     final success = await showLoadingDialog<bool>(
       asyncTask: (() async {
-        final journeyDataMaybeRaw = this.journeyDataMaybeRaw;
-        final journeyData = switch (journeyDataMaybeRaw) {
-          f.Left(value: final l) => l,
-          f.Right(value: final r) => await import_api.processVectorData(
-              vectorData: r, importProcessor: processor),
-        };
-        if (await import_api.isJourneyDataEmpty(journeyData: journeyData)) {
-          return false;
+        switch (journeyDataMaybeRaw) {
+          case f.Left(value: final journeyData):
+            if (await import_api.isJourneyDataEmpty(
+                journeyData: journeyData)) {
+              return false;
+            }
+            await import_api.importJourneyData(
+                journeyInfo: journeyInfo, journeyData: journeyData);
+            return true;
+          case f.Right(value: final r):
+            if (splitRule == null) {
+              return await _importVectorData(journeyInfo, r, processor);
+            }
+            // Each part has its own time range, only the kind and the note
+            // from the page apply to all of them.
+            var imported = false;
+            for (final (partInfo, partData) in await import_api
+                .splitRawVectorData(vectorData: r, splitRule: splitRule)) {
+              final info = import_api.JourneyInfo(
+                journeyDate: partInfo.journeyDate,
+                startTime: partInfo.startTime,
+                endTime: partInfo.endTime,
+                journeyKind: journeyInfo.journeyKind,
+                note: journeyInfo.note,
+                transportMode: partInfo.transportMode,
+              );
+              if (await _importVectorData(info, partData, processor)) {
+                imported = true;
+              }
+            }
+            return imported;
         }
-        await import_api.importJourneyData(
-            journeyInfo: journeyInfo, journeyData: journeyData);
-        return true;
       })(),
     );
     if (success) {
@@ -195,7 +309,7 @@ class _ImportDataPage extends State<ImportDataPage> {
                     topRight: Radius.circular(16.0),
                   ),
                   maxHeight:
-                      widget.importType == ImportType.gpxOrKml ? 530 : 510,
+                      widget.importType == ImportType.gpxOrKml ? 590 : 510,
                   defaultPanelState: PanelState.OPEN,
                   panel: PointerInterceptor(
                     child: Center(
@@ -215,10 +329,12 @@ class _ImportDataPage extends State<ImportDataPage> {
                             endTime: journeyInfo.endTime,
                             journeyDate: journeyInfo.journeyDate,
                             note: journeyInfo.note,
+                            transportMode: journeyInfo.transportMode,
                             saveData: _saveData,
                             previewData: _previewData,
                             importType: widget.importType,
                             preprocessor: _preprocessor,
+                            recommendation: _recommendation,
                           ),
                         ],
                       ),
@@ -0,0 +1,146 @@
+import 'package:easy_localization/easy_localization.dart';
+import 'package:flutter/material.dart';
+import 'package:fluttertoast/fluttertoast.dart';
+import 'package:memolanes/common/component/capsule_style_app_bar.dart';
+import 'package:memolanes/common/component/tiles/label_tile.dart';
+import 'package:memolanes/common/gps_manager.dart';
+import 'package:memolanes/common/utils.dart';
+import 'package:memolanes/src/rust/api/api.dart' as api;
+import 'package:memolanes/src/rust/gps_processor.dart';
+import 'package:memolanes/src/rust/privacy_zone.dart';
+import 'package:provider/provider.dart';
+
+/// Areas (e.g. home) that are removed from exports and shared renders.
+class PrivacyZonesPage extends StatefulWidget {
+  const PrivacyZonesPage({super.key});
+
+  @override
+  State<PrivacyZonesPage> createState() => _PrivacyZonesPageState();
+}
+
+class _PrivacyZonesPageState extends State<PrivacyZonesPage> {
+  static const double _defaultRadiusM = 300;
+
+  final TextEditingController _nameController = TextEditingController();
+  List<PrivacyZone> items = [];
+
+  @override
+  void initState() {
+    super.initState();
+    _loadList();
+  }
+
+  @override
+  void dispose() {
+    _nameController.dispose();
+    super.dispose();
+  }
+
+  void _loadList() async {
+    var list = await api.listPrivacyZones();
+    setState(() {
+      items = list;
+    });
+  }
+
+  Future<void> _addAtCurrentLocation(
+      BuildContext context, GpsManager gpsManager) async {
+    final position = gpsManager.latestPosition;
+    if (position == null) {
+      Fluttertoast.showToast(msg: context.tr("privacy_zones.no_location"));
+      return;
+    }
+    final name = _nameController.text.trim();
+    if (name.isEmpty) {
+      Fluttertoast.showToast(msg: context.tr("privacy_zones.empty_name"));
+      return;
+    }
+    await api.addPrivacyZone(
+      name: name,
+      shape: PrivacyZoneShape.circle(
+        center: Point(
+            latitude: position.latitude, longitude: position.longitude),
+        radiusM: _defaultRadiusM,
+      ),
+    );
+    _nameController.clear();
+    _loadList();
+  }
+
+  String _describe(PrivacyZoneShape shape) {
+    return switch (shape) {
+      PrivacyZoneShape_Circle(:final radiusM) =>
+        context.tr("privacy_zones.circle", args: [radiusM.round().toString()]),
+      PrivacyZoneShape_Polygon(:final points) =>
+        context.tr("privacy_zones.polygon", args: [points.length.toString()]),
+    };
+  }
+
+  @override
+  Widget build(BuildContext context) {
+    var gpsManager = context.watch<GpsManager>();
+
+    return Scaffold(
+      appBar: CapsuleStyleAppBar(
+        title: context.tr("privacy_zones.title"),
+      ),
+      body: Column(
+        crossAxisAlignment: CrossAxisAlignment.center,
+        children: [
+          const SizedBox(height: 8),
+          Padding(
+            padding: EdgeInsets.symmetric(horizontal: 8.0),
+            child: LabelTile(
+              label: context.tr("privacy_zones.name"),
+              position: LabelTilePosition.single,
+              trailing: SizedBox(
+                width: MediaQuery.of(context).size.width * 0.5,
+                child: TextField(
+                  controller: _nameController,
+                  decoration: InputDecoration(
+                    border: InputBorder.none,
+                    hintText: context.tr("privacy_zones.name_hint"),
+                    hintStyle: TextStyle(fontSize: 14.0),
+                  ),
+                  textAlign: TextAlign.right,
+                ),
+              ),
+            ),
+          ),
+          const SizedBox(height: 8),
+          ElevatedButton(
+            onPressed: () => _addAtCurrentLocation(context, gpsManager),
+            child: Text(context.tr("privacy_zones.add_current_location")),
+          ),
+          const SizedBox(height: 16),
+          Expanded(
+            child: ListView(
+              shrinkWrap: true,
+              children: items.map((item) {
+                return ListTile(
+                  leading: const Icon(Icons.shield_outlined),
+                  title: Text(item.name),
+                  subtitle: Text(_describe(item.shape)),
+                  trailing: ElevatedButton(
+                    onPressed: () async {
+                      if (await showCommonDialog(
+                          context, context.tr("privacy_zones.delete_message"),
+                          hasCancel: true,
+                          confirmButtonText: context.tr("common.delete"),
+                          confirmGroundColor: Colors.red,
+                          confirmTextColor: Colors.white)) {
+                        await api.deletePrivacyZone(id: item.id);
+                        _loadList();
+                      }
+                    },
+                    child: const Icon(Icons.delete),
+                  ),
+                );
+              }).toList(),
+            ),
+          ),
+        ],
+      ),
+    );
+  }
+}
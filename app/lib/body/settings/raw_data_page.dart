@@ -78,8 +78,10 @@ class _RawDataPage extends State<RawDataPage> {
           CardLabelTile(
             position: CardLabelTilePosition.top,
             label: context.tr("general.advanced_settings.raw_data_export_csv"),
-            onTap: () {
-              showCommonExport(context, filePath, deleteFile: false);
+            onTap: () async {
+              final csvPath =
+                  await api.exportRawDataCsvFile(csvFilepath: filePath);
+              showCommonExport(context, csvPath, deleteFile: true);
             },
             top: false,
           ),
@@ -3,6 +3,7 @@ import 'package:easy_localization/easy_localization.dart';
 import 'package:file_picker/file_picker.dart';
 import 'package:flutter/material.dart';
 import 'package:geolocator/geolocator.dart';
+import 'package:memolanes/body/settings/add_flight_page.dart';
 import 'package:memolanes/body/settings/advanced_settings_page.dart';
 import 'package:memolanes/body/settings/import_data_page.dart';
 import 'package:memolanes/body/settings/map_settings_page.dart';
@@ -13,10 +14,12 @@ import 'package:memolanes/common/component/tiles/label_tile.dart';
 import 'package:memolanes/common/component/tiles/label_tile_content.dart';
 import 'package:memolanes/common/component/tiles/label_tile_title.dart';
 import 'package:memolanes/common/gps_manager.dart';
+import 'package:memolanes/common/log.dart';
 import 'package:memolanes/common/mmkv_util.dart';
 import 'package:memolanes/common/update_notifier.dart';
 import 'package:memolanes/common/utils.dart';
 import 'package:memolanes/src/rust/api/api.dart' as api;
+import 'package:memolanes/src/rust/api/import.dart' as import_api;
 import 'package:memolanes/utils/nav_helper.dart';
 import 'package:package_info_plus/package_info_plus.dart';
 import 'package:path_provider/path_provider.dart';
@@ -84,6 +87,52 @@ class _SettingsBodyState extends State<SettingsBody> {
     }
   }
 
+  Future<void> _importFlightLog(BuildContext context) async {
+    final result = await FilePicker.platform.pickFiles(type: FileType.any);
+    final path = result?.files.single.path;
+    if (path == null || !context.mounted) return;
+    try {
+      final entries = await import_api.loadFlightLogCsv(filePath: path);
+      if (!context.mounted) return;
+      if (entries.isEmpty) {
+        await showCommonDialog(context, context.tr("import.empty_data"));
+        return;
+      }
+      if (!await showCommonDialog(
+        context,
+        context.tr("flight_log.confirm_message", args: ["${entries.length}"]),
+        hasCancel: true,
+      )) {
+        return;
+      }
+      final importResult = await showLoadingDialog(
+        asyncTask: import_api.importFlightLog(entries: entries),
+      );
+      if (!context.mounted) return;
+      final dateFormat = DateFormat('yyyy-MM-dd');
+      final skippedRoutes = importResult.skippedEntries
+          .map((entry) => "${dateFormat.format(entry.date)} "
+              "${entry.origin} → ${entry.destination}")
+          .join("\n");
+      await showCommonDialog(
+        context,
+        [
+          context.tr("flight_log.result_message",
+              args: ["${importResult.importedCount}"]),
+          if (importResult.skippedEntries.isNotEmpty)
+            context.tr("flight_log.skipped_message", args: [
+              "${importResult.skippedEntries.length}",
+              skippedRoutes
+            ]),
+        ].join("\n\n"),
+      );
+    } catch (error) {
+      log.error("[flight_log] Import failed $error");
+      if (!context.mounted) return;
+      await showCommonDialog(context, context.tr("import.parsing_failed"));
+    }
+  }
+
   Future<void> _loadNotificationStatus() async {
     setState(() {
       _isUnexpectedExitNotificationEnabled = MMKVUtil.getBool(
@@ -214,8 +263,27 @@ class _SettingsBodyState extends State<SettingsBody> {
             final timestamp = DateFormat('yyyy-MM-dd-HH-mm-ss').format(now);
             final filepath = "${tmpDir.path}/all-journeys-$timestamp.mldx";
             if (!context.mounted) return;
+            final passphrase = await showPassphraseDialog(
+                context, context.tr("passphrase.export_message"),
+                confirm: true);
+            if (passphrase == null) return;
+            // a full archive keeps everything unless it is meant for sharing
+            var applyPrivacyZones = false;
+            if ((await api.listPrivacyZones()).isNotEmpty) {
+              if (!context.mounted) return;
+              applyPrivacyZones = await showCommonDialog(
+                context,
+                context.tr("privacy_zones.export_all_message"),
+                hasCancel: true,
+                confirmButtonText: context.tr("privacy_zones.export_trimmed"),
+                cancelButtonText: context.tr("privacy_zones.export_everything"),
+              );
+            }
             await showLoadingDialog(
-              asyncTask: api.generateFullArchive(targetFilepath: filepath),
+              asyncTask: api.generateFullArchive(
+                  targetFilepath: filepath,
+                  passphrase: passphrase.isEmpty ? null : passphrase,
+                  applyPrivacyZones: applyPrivacyZones),
             );
             if (!context.mounted) return;
             await showCommonExport(context, filepath, deleteFile: true);
@@ -316,6 +384,20 @@ class _SettingsBodyState extends State<SettingsBody> {
               _selectImportFile(context, ImportType.gpxOrKml);
             },
           ),
+          CardLabelTile(
+            position: CardLabelTilePosition.middle,
+            label: context.tr("flight_log.import"),
+            onTap: () async {
+              await _importFlightLog(context);
+            },
+          ),
+          CardLabelTile(
+            position: CardLabelTilePosition.middle,
+            label: context.tr("add_flight.title"),
+            onTap: () async {
+              navigatorPush(context, page: AddFlightPage());
+            },
+          ),
           CardLabelTile(
             position: CardLabelTilePosition.bottom,
             label: context.tr("journey.import_fog_of_world_data"),
@@ -0,0 +1,36 @@
+import 'dart:convert';
+import 'dart:math';
+
+import 'package:flutter/foundation.dart';
+import 'package:flutter_secure_storage/flutter_secure_storage.dart';
+
+/// Keeps the main db key in the platform keystore (Keychain on iOS, Keystore
+/// backed storage on Android). No key means the db is not encrypted.
+class DbKeyStore {
+  static const String _key = "mainDbKey";
+  static const int _keyLength = 32;
+
+  static const FlutterSecureStorage _storage = FlutterSecureStorage(
+    aOptions: AndroidOptions(encryptedSharedPreferences: true),
+    iOptions: IOSOptions(
+        accessibility: KeychainAccessibility.first_unlock_this_device),
+  );
+
+  static Future<Uint8List?> read() async {
+    final value = await _storage.read(key: _key);
+    if (value == null) return null;
+    return base64Decode(value);
+  }
+
+  static Future<Uint8List> create() async {
+    final random = Random.secure();
+    final key = Uint8List.fromList(
+        List.generate(_keyLength, (_) => random.nextInt(256)));
+    await _storage.write(key: _key, value: base64Encode(key));
+    return key;
+  }
+
+  static Future<void> delete() async {
+    await _storage.delete(key: _key);
+  }
+}
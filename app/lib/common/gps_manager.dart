@@ -40,7 +40,7 @@ class GpsManager extends ChangeNotifier {
 
   Timer? _lastPositionTooOldTimer;
 
-  StreamSubscription<LocationData>? _locationUpdateSub;
+  StreamSubscription<List<LocationData>>? _locationUpdateSub;
 
   // Notify the user that the recording was unexpectedly stopped.
   // The app is a little hacky so I minted: https://github.com/flutter/flutter/issues/156139
@@ -73,6 +73,8 @@ class GpsManager extends ChangeNotifier {
       if (MMKVUtil.getBool(MMKVKey.isRecording) &&
           await PermissionService().checkLocationPermission()) {
         recordingStatus = GpsRecordingStatus.recording;
+        // the app may have been killed while pausing
+        await api.resumeRecording();
       } else if (await api.hasOngoingJourney()) {
         recordingStatus = GpsRecordingStatus.paused;
       }
@@ -129,11 +131,13 @@ class GpsManager extends ChangeNotifier {
         bool enableBackground = newState == _InternalState.recording;
         await _locationService.startLocationUpdates(enableBackground);
 
-        _locationUpdateSub = _locationService.onLocationUpdate((data) async {
-          if (_positionTooOld(data)) {
+        _locationUpdateSub = _locationService.onLocationUpdate((batch) async {
+          final dataList = batch.where((data) => !_positionTooOld(data));
+          if (dataList.isEmpty) {
             return;
           }
-          latestPosition = data;
+          latestPosition = dataList
+              .reduce((a, b) => a.timestampMs >= b.timestampMs ? a : b);
           notifyListeners();
 
           if (_internalState == _InternalState.recording) {
@@ -147,17 +151,24 @@ class GpsManager extends ChangeNotifier {
               _tryFinalizeJourneyCountDown = now;
             }
 
-            var meaningful = await api.onLocationUpdate(
-              rawData: RawData(
-                point: Point(
-                  latitude: data.latitude,
-                  longitude: data.longitude,
-                ),
-                timestampMs: data.timestampMs,
-                accuracy: data.accuracy,
-                altitude: data.altitude,
-                speed: data.speed,
-              ),
+            var meaningful = await api.onLocationUpdates(
+              rawDataList: dataList
+                  .map((data) => RawData(
+                        point: Point(
+                          latitude: data.latitude,
+                          longitude: data.longitude,
+                        ),
+                        timestampMs: data.timestampMs,
+                        accuracy: data.accuracy,
+                        altitude: data.altitude,
+                        speed: data.speed,
+                        bearing: data.bearing,
+                        verticalAccuracy: data.verticalAccuracy,
+                        speedAccuracy: data.speedAccuracy,
+                        provider: data.provider,
+                        satelliteCount: data.satelliteCount,
+                      ))
+                  .toList(),
               receivedTimestampMs: now.millisecondsSinceEpoch,
             );
 
@@ -211,6 +222,16 @@ class GpsManager extends ChangeNotifier {
     await _m.protect(() async {
       var needToFinalize =
           recordingStatus != to && to == GpsRecordingStatus.none;
+      if (recordingStatus != to) {
+        if (to == GpsRecordingStatus.paused) {
+          await api.pauseRecording();
+        } else if (to == GpsRecordingStatus.recording) {
+          // the data after resuming starts a new segment. Also needed when
+          // starting from none, a pause is left over if the app was closed
+          // while paused before anything was recorded.
+          await api.resumeRecording();
+        }
+      }
       recordingStatus = to;
       notifyListeners();
 
@@ -44,12 +44,15 @@ class _PokeGeolocatorTask {
 }
 
 class GeoLocatorService implements ILocationService {
+  static const bool _forceLocationManager = false;
+
   StreamSubscription<Position>? _positionStreamSub;
   _PokeGeolocatorTask? _pokeTask;
   Timer? _tooOldTimer;
   RestartableTimer? _bufferFlushTimer;
 
-  final _locationUpdateController = StreamController<LocationData>.broadcast();
+  final _locationUpdateController =
+      StreamController<List<LocationData>>.broadcast();
 
   LocationData? _latestLocation;
   final List<LocationData> _buffer = [];
@@ -107,6 +110,12 @@ class GeoLocatorService implements ILocationService {
       timestampMs: pos.timestamp.millisecondsSinceEpoch,
       altitude: pos.altitude,
       speed: pos.speed,
+      bearing: pos.heading,
+      verticalAccuracy: pos.altitudeAccuracy,
+      speedAccuracy: pos.speedAccuracy,
+      provider: _providerOf(pos),
+      satelliteCount:
+          pos is AndroidPosition ? pos.satellitesUsedInFix.round() : null,
     );
 
     _latestLocation = data;
@@ -123,23 +132,32 @@ class GeoLocatorService implements ILocationService {
     }
   }
 
+  // `Position` doesn't tell which provider it comes from, so this is the one
+  // we asked for. Without Google Play services, Android falls back to the
+  // location manager even if it is not forced.
+  String? _providerOf(Position pos) {
+    if (pos.isMocked) return "mock";
+    return switch (defaultTargetPlatform) {
+      TargetPlatform.android =>
+        _forceLocationManager ? "location_manager" : "fused",
+      TargetPlatform.iOS || TargetPlatform.macOS => "core_location",
+      _ => null,
+    };
+  }
+
   void _flushBuffer() {
     if (_buffer.isEmpty) return;
 
-    final sorted = List<LocationData>.from(_buffer)
-      ..sort((a, b) => a.timestampMs.compareTo(b.timestampMs));
-
-    for (final loc in sorted) {
-      _locationUpdateController.add(loc);
-    }
+    // delivered as a batch, it could be out of order
+    _locationUpdateController.add(List<LocationData>.from(_buffer));
 
     _buffer.clear();
     _firstBufferReceiveTime = null;
   }
 
   @override
-  StreamSubscription<LocationData> onLocationUpdate(
-      void Function(LocationData) callback) {
+  StreamSubscription<List<LocationData>> onLocationUpdate(
+      void Function(List<LocationData>) callback) {
     return _locationUpdateController.stream.listen(callback);
   }
 
@@ -160,7 +178,7 @@ class GeoLocatorService implements ILocationService {
         return AndroidSettings(
           accuracy: accuracy,
           distanceFilter: 0, // On Android, `0` means no distance filter.
-          forceLocationManager: false,
+          forceLocationManager: _forceLocationManager,
           // 1 sec feels like a reasonable interval
           intervalDuration: const Duration(seconds: 1),
           foregroundNotificationConfig:
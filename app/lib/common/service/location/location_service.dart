@@ -10,6 +10,11 @@ class LocationData {
   final double accuracy;
   final double? altitude;
   final double? speed;
+  final double? bearing;
+  final double? verticalAccuracy;
+  final double? speedAccuracy;
+  final String? provider;
+  final int? satelliteCount;
 
   LocationData({
     required this.latitude,
@@ -18,13 +23,18 @@ class LocationData {
     required this.timestampMs,
     this.altitude,
     this.speed,
+    this.bearing,
+    this.verticalAccuracy,
+    this.speedAccuracy,
+    this.provider,
+    this.satelliteCount,
   });
 
   DateTime get timestamp => DateTime.fromMillisecondsSinceEpoch(timestampMs);
 
   @override
   String toString() {
-    return 'LocationData(latitude: $latitude,longitude: $longitude, timestampMs: $timestampMs, accuracy: $accuracy, altitude: $altitude, speed: $speed)';
+    return 'LocationData(latitude: $latitude,longitude: $longitude, timestampMs: $timestampMs, accuracy: $accuracy, altitude: $altitude, speed: $speed, bearing: $bearing, verticalAccuracy: $verticalAccuracy, speedAccuracy: $speedAccuracy, provider: $provider, satelliteCount: $satelliteCount)';
   }
 }
 
@@ -44,8 +54,9 @@ abstract class ILocationService {
 
   Future<void> stopLocationUpdates();
 
-  StreamSubscription<LocationData> onLocationUpdate(
-      void Function(LocationData) callback);
+  // Updates are delivered in batches, which could be out of order.
+  StreamSubscription<List<LocationData>> onLocationUpdate(
+      void Function(List<LocationData>) callback);
 
   LocationBackend get locationBackend;
 }
@@ -110,7 +110,7 @@ class ShareHandlerUtil {
   }
 
   static ImportType? _resolveImportType(String lowerPath) {
-    const trackExtensions = ['.kml', '.gpx'];
+    const trackExtensions = ['.kml', '.kmz', '.gpx'];
     const fowExtensions = ['.fwss', '.zip'];
 
     if (trackExtensions.any(lowerPath.endsWith)) {
@@ -68,6 +68,86 @@ Future<bool> showCommonDialog(BuildContext context, String message,
   return result ?? false;
 }
 
+/// Returns `null` if cancelled. An empty passphrase is returned as is, it is
+/// up to the caller to decide what it means.
+/// With `confirm`, the passphrase has to be entered twice, e.g. for encrypting.
+Future<String?> showPassphraseDialog(BuildContext context, String message,
+    {title, confirm = false}) async {
+  title = title ?? context.tr("common.info");
+  final controller = TextEditingController();
+  final confirmController = TextEditingController();
+  var mismatch = false;
+  final result = await showDialog<String>(
+    context: context,
+    barrierDismissible: false,
+    builder: (BuildContext context) {
+      return StatefulBuilder(
+        builder: (context, setState) => AlertDialog(
+          shape: RoundedRectangleBorder(
+            borderRadius: BorderRadius.circular(24),
+          ),
+          title: Text(title),
+          content: Column(
+            mainAxisSize: MainAxisSize.min,
+            crossAxisAlignment: CrossAxisAlignment.start,
+            children: [
+              Text(message),
+              TextField(
+                controller: controller,
+                obscureText: true,
+                autofocus: true,
+                decoration: InputDecoration(
+                  hintText: context.tr("passphrase.hint"),
+                ),
+              ),
+              if (confirm)
+                TextField(
+                  controller: confirmController,
+                  obscureText: true,
+                  decoration: InputDecoration(
+                    hintText: context.tr("passphrase.confirm_hint"),
+                    errorText:
+                        mismatch ? context.tr("passphrase.mismatch") : null,
+                  ),
+                ),
+            ],
+          ),
+          actionsPadding: const EdgeInsets.fromLTRB(24, 0, 24, 16),
+          actions: [
+            FilledButton(
+              onPressed: () {
+                if (confirm && controller.text != confirmController.text) {
+                  setState(() {
+                    mismatch = true;
+                  });
+                  return;
+                }
+                Navigator.of(context).pop(controller.text);
+              },
+              style: FilledButton.styleFrom(
+                backgroundColor: StyleConstants.defaultColor,
+                foregroundColor: Colors.black,
+              ),
+              child: Text(context.tr("common.ok")),
+            ),
+            FilledButton(
+              onPressed: () => Navigator.of(context).pop(),
+              style: FilledButton.styleFrom(
+                backgroundColor: Colors.grey,
+                foregroundColor: Colors.black,
+              ),
+              child: Text(context.tr("common.cancel")),
+            ),
+          ],
+        ),
+      );
+    },
+  );
+  controller.dispose();
+  confirmController.dispose();
+  return result;
+}
+
 Future<T> showLoadingDialog<T>({
   required Future<T> asyncTask,
 }) async {
@@ -145,9 +225,16 @@ void showBasicCard(
 }
 
 Future<void> importMldx(BuildContext context, String path) async {
+  String? passphrase;
   try {
+    if (await api.archiveRequiresPassphrase(mldxFilePath: path)) {
+      if (!context.mounted) return;
+      passphrase = await showPassphraseDialog(
+          context, context.tr("passphrase.import_message"));
+      if (passphrase == null) return;
+    }
     await showLoadingDialog(
-      asyncTask: api.importArchive(mldxFilePath: path),
+      asyncTask: api.importArchive(mldxFilePath: path, passphrase: passphrase),
     );
     if (context.mounted) {
       await showCommonDialog(
@@ -157,7 +244,11 @@ Future<void> importMldx(BuildContext context, String path) async {
     }
   } catch (error) {
     if (context.mounted) {
-      await showCommonDialog(context, context.tr("import.parsing_failed"));
+      await showCommonDialog(
+          context,
+          context.tr(passphrase == null
+              ? "import.parsing_failed"
+              : "passphrase.import_failed"));
       log.error("[import_data] Data parsing failed $error");
     }
   }
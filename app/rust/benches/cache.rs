@@ -108,7 +108,8 @@ fn setup_storage_with(src: &DataSource) -> (Storage, TempDir, TempDir, TempDir,
         doc_dir.path().to_str().unwrap().to_string(),
         support_dir.path().to_str().unwrap().to_string(),
         cache_dir.path().to_str().unwrap().to_string(),
-    );
+    )
+    .unwrap();
 
     populate_storage(&storage, src);
 
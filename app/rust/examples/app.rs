@@ -14,13 +14,14 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         ".".to_string(),
         ".".to_string(),
         ".".to_string(),
-    );
+        None,
+    )?;
 
     // Check if an MLDX file path is provided as an argument
     if args.len() > 1 {
         let mldx_file_path = &args[1];
         println!("Importing MLDX file: {mldx_file_path}");
-        match import_archive(mldx_file_path.to_string()) {
+        match import_archive(mldx_file_path.to_string(), None) {
             Ok(_) => println!("Successfully imported MLDX file"),
             Err(e) => eprintln!("Failed to import MLDX file: {e:?}"),
         }
@@ -0,0 +1,67 @@
+// Regenerates `src/airports.csv` from the OpenFlights data
+// (https://openflights.org/data), see `just update-airports`.
+//
+// Usage: cargo run --example update_airports -- airports.dat countries.dat
+use std::collections::HashMap;
+use std::env;
+
+const OUTPUT_PATH: &str = "src/airports.csv";
+// OpenFlights uses `\N` for missing values.
+const NULL: &str = "\\N";
+
+fn reader(path: &str) -> csv::Result<csv::Reader<std::fs::File>> {
+    csv::ReaderBuilder::new().has_headers(false).from_path(path)
+}
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        return Err("usage: update_airports <airports.dat> <countries.dat>".into());
+    }
+
+    // name, iso_code, dafif_code
+    let mut country_codes = HashMap::new();
+    for record in reader(&args[2])?.records() {
+        let record = record?;
+        if record[1] != *NULL {
+            country_codes.insert(record[0].to_string(), record[1].to_string());
+        }
+    }
+
+    // id, name, city, country, iata, icao, latitude, longitude, ...
+    let mut writer = csv::Writer::from_path(OUTPUT_PATH)?;
+    writer.write_record(["iata", "icao", "name", "country", "latitude", "longitude"])?;
+    let mut count = 0;
+    for record in reader(&args[1])?.records() {
+        let record = record?;
+        let code = |i: usize| {
+            if record[i] == *NULL {
+                ""
+            } else {
+                &record[i]
+            }
+        };
+        let (iata, icao) = (code(4), code(5));
+        if iata.len() != 3 && icao.len() != 4 {
+            continue;
+        }
+        let Some(country) = country_codes.get(&record[3]) else {
+            eprintln!("unknown country {:?} of {}", &record[3], &record[1]);
+            continue;
+        };
+        let latitude: f64 = record[6].parse()?;
+        let longitude: f64 = record[7].parse()?;
+        writer.write_record([
+            iata,
+            icao,
+            &record[1],
+            country,
+            &format!("{latitude:.4}"),
+            &format!("{longitude:.4}"),
+        ])?;
+        count += 1;
+    }
+    writer.flush()?;
+    println!("{count} airports written to {OUTPUT_PATH}");
+    Ok(())
+}
@@ -0,0 +1,42 @@
+/* An offline airport table, so a flight could be logged with just the
+airport codes (see `api::import::create_flight_journey`).
+
+The bundled table (`airports.csv`) is currently a hand-picked list of about 200
+major airports, so codes of smaller airports are not found and such flights are
+reported as skipped by the flight log import. `just update-airports` replaces it
+with the full OpenFlights airport database (https://openflights.org/data,
+airports with an IATA or ICAO code) in the same format. Coordinates are the
+airport reference points rounded to 4 decimal places which is more than enough
+for drawing a flight.
+*/
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Airport {
+    pub iata: String,
+    pub icao: String,
+    pub name: String,
+    /// ISO 3166-1 alpha-2 code.
+    pub country: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+lazy_static! {
+    static ref AIRPORTS: Vec<Airport> =
+        csv::Reader::from_reader(include_str!("airports.csv").as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .expect("invalid airports.csv");
+}
+
+/// `code` is either an IATA (3 letters) or ICAO (4 letters) code, case
+/// insensitive.
+pub fn find_airport(code: &str) -> Option<&'static Airport> {
+    let code = code.trim().to_ascii_uppercase();
+    match code.len() {
+        3 => AIRPORTS.iter().find(|x| x.iata == code),
+        4 => AIRPORTS.iter().find(|x| x.icao == code),
+        _ => None,
+    }
+}
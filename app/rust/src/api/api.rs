@@ -1,28 +1,38 @@
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::BufWriter;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, OnceLock};
 
 use anyhow::{Context, Result};
 use auto_context::auto_context;
-use chrono::NaiveDate;
-use csv::Reader;
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
 use flutter_rust_bridge::frb;
 
 use super::import::JourneyInfo;
+use crate::auto_finalize::{AutoFinalizeExplanation, AutoFinalizePolicy};
+use crate::backup_store::BackupPolicy;
 use crate::cache_db::LayerKind;
+use crate::duplicate_detector::{self, DuplicateJourneyPair};
 use crate::frb_generated::StreamSink;
-use crate::gps_processor::{GpsPreprocessor, ProcessResult};
+use crate::gps_processor::{
+    GpsFilter, GpsPreprocessor, PreprocessingProfile, PreprocessingThresholds, ProcessResult,
+};
 use crate::journey_bitmap::JourneyBitmap;
 use crate::journey_data::JourneyData;
 use crate::journey_header::{JourneyHeader, JourneyKind, JourneyType};
+use crate::journey_splitter::JourneySplitOptions;
 use crate::logs;
+use crate::main_db::RecorderState;
+use crate::places::{Place, PlaceVisit};
+use crate::privacy_zone::{self, PrivacyZone, PrivacyZoneShape};
+use crate::raw_data_store::{self, RawDataRetention};
 use crate::renderer::get_default_camera_option_from_journey_bitmap;
 use crate::renderer::internal_server::{Request, RequestResponse, TileRangeResponse};
 use crate::renderer::MapRenderer;
-use crate::storage::{RawDataFile, Storage};
-use crate::{archive, build_info, export_data, gps_processor, main_db};
+use crate::storage::{BackupFile, RawDataFile, Storage};
+use crate::{archive, build_info, export_data, gps_processor, main_db, utils};
 
 use crate::renderer::CameraOptionInternal;
 
@@ -41,6 +51,12 @@ pub(super) struct MainState {
 }
 
 static MAIN_STATE: OnceLock<MainState> = OnceLock::new();
+// so the main state is only initialized once
+static INIT_LOCK: Mutex<()> = Mutex::new(());
+// the cache dir and logging are set up once, even if `init` is retried
+static REAL_CACHE_DIR: OnceLock<String> = OnceLock::new();
+// set by `init`, a backup is only restored before init if this is true
+static MAIN_DB_CORRUPTED: AtomicBool = AtomicBool::new(false);
 
 #[frb(ignore)]
 pub fn get() -> &'static MainState {
@@ -74,62 +90,122 @@ fn reload_main_map_bitmap(storage: &Storage, main_map_state: &mut MainMapState)
     Ok(())
 }
 
-pub fn init(temp_dir: String, doc_dir: String, support_dir: String, system_cache_dir: String) {
-    let mut already_initialized = true;
-    MAIN_STATE.get_or_init(|| {
-        already_initialized = false;
+/// If `db_key` is set, location data in the dbs is encrypted with it (see
+/// `db_encryption`), existing data is migrated on the first run. Fails if the
+/// dbs can't be opened, e.g. the key is wrong, `init` can be called again then.
+pub fn init(
+    temp_dir: String,
+    doc_dir: String,
+    support_dir: String,
+    system_cache_dir: String,
+    db_key: Option<Vec<u8>>,
+) -> Result<()> {
+    let _init_lock = INIT_LOCK.lock().unwrap();
+    if MAIN_STATE.get().is_some() {
+        warn!("`init` is called multiple times");
+        return Ok(());
+    }
 
-        let (real_cache_dir, logs) = prepare_real_cache_dir(&support_dir, &system_cache_dir)
-            .expect("Failed to initialize cache dir");
+    let real_cache_dir = REAL_CACHE_DIR
+        .get_or_init(|| {
+            let (real_cache_dir, logs) = prepare_real_cache_dir(&support_dir, &system_cache_dir)
+                .expect("Failed to initialize cache dir");
 
-        // init logging
-        logs::init(&real_cache_dir).expect("Failed to initialize logging");
+            // init logging
+            logs::init(&real_cache_dir).expect("Failed to initialize logging");
 
-        if let Some(logs) = logs {
-            for (level, message) in logs {
-                write_log(message, level);
+            if let Some(logs) = logs {
+                for (level, message) in logs {
+                    write_log(message, level);
+                }
             }
-        }
-
-        let mut storage = Storage::init(temp_dir, doc_dir, support_dir, real_cache_dir);
-        info!("initialized");
+            real_cache_dir
+        })
+        .clone();
+
+    let mut storage = Storage::init_with_db_key(
+        temp_dir,
+        doc_dir,
+        support_dir,
+        real_cache_dir,
+        db_key.as_deref(),
+    )
+    .inspect_err(|e| {
+        error!("Failed to initialize storage: {e:?}");
+        MAIN_DB_CORRUPTED.store(main_db::is_corruption_error(e), Ordering::Relaxed);
+    })?;
+    info!("initialized");
 
-        let default_layer_filter = LayerFilter {
-            current_journey: true,
-            default_kind: true,
-            flight_kind: false,
-        };
+    let default_layer_filter = LayerFilter {
+        current_journey: true,
+        default_kind: true,
+        flight_kind: false,
+    };
 
-        // TODO: use an empty journey bitmap first, because loading could be slow (especially when we don't have cache).
-        // Ideally, we should support main map renderer being none, combine together with `dropped_for_power_saving`
-        // to be more type safe.
-        let main_map_state = Arc::new(Mutex::new(MainMapState {
-            map_renderer: MapRenderer::new(JourneyBitmap::new()),
-            dropped_for_power_saving: false,
-            layer_filter: default_layer_filter,
-        }));
-        let main_map_state_copy = main_map_state.clone();
-        // TODO: redesign the callback to better handle locks and avoid deadlocks
-        storage.set_finalized_journey_changed_callback(Box::new(move |storage| {
-            let mut main_map_state = main_map_state_copy.lock().unwrap();
-            match reload_main_map_bitmap(storage, &mut main_map_state) {
-                Ok(()) => (),
-                Err(e) => {
-                    error!("Failed to get latest bitmap for main map renderer: {e:?}");
-                }
+    // TODO: use an empty journey bitmap first, because loading could be slow (especially when we don't have cache).
+    // Ideally, we should support main map renderer being none, combine together with `dropped_for_power_saving`
+    // to be more type safe.
+    let main_map_state = Arc::new(Mutex::new(MainMapState {
+        map_renderer: MapRenderer::new(JourneyBitmap::new()),
+        dropped_for_power_saving: false,
+        layer_filter: default_layer_filter,
+    }));
+    let main_map_state_copy = main_map_state.clone();
+    // TODO: redesign the callback to better handle locks and avoid deadlocks
+    storage.set_finalized_journey_changed_callback(Box::new(move |storage| {
+        let mut main_map_state = main_map_state_copy.lock().unwrap();
+        match reload_main_map_bitmap(storage, &mut main_map_state) {
+            Ok(()) => (),
+            Err(e) => {
+                error!("Failed to get latest bitmap for main map renderer: {e:?}");
             }
-        }));
-        info!("main map renderer initialized");
-
-        MainState {
-            storage,
-            gps_preprocessor: Mutex::new(GpsPreprocessor::new()),
-            main_map_state,
         }
+    }));
+    info!("main map renderer initialized");
+
+    let gps_preprocessor = GpsPreprocessor::new_with_thresholds_and_filter(
+        storage.get_preprocessing_thresholds(storage.get_preprocessing_profile()),
+        storage.get_gps_filter(),
+    );
+
+    // can't fail, `init` is serialized by `INIT_LOCK`
+    let _ = MAIN_STATE.set(MainState {
+        storage,
+        gps_preprocessor: Mutex::new(gps_preprocessor),
+        main_map_state,
     });
-    if already_initialized {
-        warn!("`init` is called multiple times");
+    Ok(())
+}
+
+/// Whether the last `init` failed because the main db is corrupted, see
+/// `restore_latest_backup_before_init`.
+#[frb(sync)]
+pub fn is_main_db_corrupted() -> bool {
+    MAIN_DB_CORRUPTED.load(Ordering::Relaxed)
+}
+
+/// For when `init` failed because the main db is corrupted: the newest backup
+/// is restored into a new main db and the old one is kept next to it. Only
+/// journeys are in backups, see `Storage::restore_latest_backup_before_init`.
+/// Returns the name of the restored backup, `init` should be called again.
+pub fn restore_latest_backup_before_init(
+    support_dir: String,
+    db_key: Option<Vec<u8>>,
+) -> Result<String> {
+    let _init_lock = INIT_LOCK.lock().unwrap();
+    if MAIN_STATE.get().is_some() {
+        bail!("Already initialized, use `restore_backup` instead");
     }
+    if !MAIN_DB_CORRUPTED.load(Ordering::Relaxed) {
+        bail!("The main db is not corrupted");
+    }
+    let cache_dir = REAL_CACHE_DIR
+        .get()
+        .context("`init` should be called first")?;
+    let name =
+        Storage::restore_latest_backup_before_init(&support_dir, cache_dir, db_key.as_deref())?;
+    MAIN_DB_CORRUPTED.store(false, Ordering::Relaxed);
+    Ok(name)
 }
 
 // On iOS, we use `NSCachesDirectory` for storing cache file,
@@ -326,6 +402,36 @@ pub fn get_map_renderer_proxy_for_journey_date_range(
     to_date_inclusive: NaiveDate,
     journey_kinds: HashSet<JourneyKind>,
 ) -> Result<MapRendererProxy> {
+    let journey_bitmap =
+        journey_bitmap_for_date_range(from_date_inclusive, to_date_inclusive, &journey_kinds)?;
+    let map_renderer = MapRenderer::new(journey_bitmap);
+    Ok(MapRendererProxy::DynamicRenderer(Arc::new(Mutex::new(
+        map_renderer,
+    ))))
+}
+
+/// Same as `get_map_renderer_proxy_for_journey_date_range` but privacy zones
+/// are hidden, for renders that are going to be shared.
+pub fn get_shareable_map_renderer_proxy_for_journey_date_range(
+    from_date_inclusive: NaiveDate,
+    to_date_inclusive: NaiveDate,
+    journey_kinds: HashSet<JourneyKind>,
+) -> Result<MapRendererProxy> {
+    let mut journey_bitmap =
+        journey_bitmap_for_date_range(from_date_inclusive, to_date_inclusive, &journey_kinds)?;
+    let privacy_zones = get().storage.with_db_txn(|txn| txn.list_privacy_zones())?;
+    privacy_zone::apply_to_journey_bitmap(&privacy_zones, &mut journey_bitmap);
+    let map_renderer = MapRenderer::new(journey_bitmap);
+    Ok(MapRendererProxy::DynamicRenderer(Arc::new(Mutex::new(
+        map_renderer,
+    ))))
+}
+
+fn journey_bitmap_for_date_range(
+    from_date_inclusive: NaiveDate,
+    to_date_inclusive: NaiveDate,
+    journey_kinds: &HashSet<JourneyKind>,
+) -> Result<JourneyBitmap> {
     let state = get();
     let get = |journey_kind| {
         state
@@ -341,11 +447,7 @@ pub fn get_map_renderer_proxy_for_journey_date_range(
         (false, true) => get(Some(&JourneyKind::Flight))?,
         (true, true) => get(None)?,
     };
-
-    let map_renderer = MapRenderer::new(journey_bitmap);
-    Ok(MapRendererProxy::DynamicRenderer(Arc::new(Mutex::new(
-        map_renderer,
-    ))))
+    Ok(journey_bitmap)
 }
 
 pub(super) fn get_map_renderer_proxy_for_journey_data_internal(
@@ -383,51 +485,118 @@ pub fn get_map_renderer_proxy_for_journey_data(
 // Return `true` if this update contains meaningful data.
 // Meaningful data means it is not ignored by the gps preprocessor.
 pub fn on_location_update(raw_data: gps_processor::RawData, received_timestamp_ms: i64) -> bool {
+    on_location_updates(vec![raw_data], received_timestamp_ms)
+}
+
+/// Same as `on_location_update` but for a batch of location updates, which
+/// could be out of order (e.g. on Android in the background). Returns `true` if
+/// any of them is meaningful.
+pub fn on_location_updates(
+    raw_data_list: Vec<gps_processor::RawData>,
+    received_timestamp_ms: i64,
+) -> bool {
     let state = get();
-    // NOTE: On Android, we might received a batch of location updates that are out of order.
-    // Not very sure why yet.
 
     // we need handle a batch in one go so we hold the lock for the whole time
     let mut gps_preprocessor = state.gps_preprocessor.lock().unwrap();
+    // Skips the processing while paused. Pausing takes the lock above, the
+    // write checks it again in its own transaction anyway.
+    if state.storage.is_recording_paused() {
+        return false;
+    }
     let mut main_map_state = state.main_map_state.lock().unwrap();
 
-    let last_point = gps_preprocessor.last_kept_point();
-    let process_result = gps_preprocessor.preprocess(&raw_data);
-    if !main_map_state.dropped_for_power_saving && main_map_state.layer_filter.current_journey {
-        let line_to_add = match process_result {
-            ProcessResult::Ignore => None,
-            ProcessResult::NewSegment => Some((&raw_data.point, &raw_data.point)),
-            ProcessResult::Append => {
-                let start = last_point.as_ref().unwrap_or(&raw_data.point);
-                Some((start, &raw_data.point))
-            }
-        };
-        match line_to_add {
-            None => (),
-            Some((start, end)) => {
-                main_map_state.map_renderer.update(
-                    |journey_bitmap: &mut crate::journey_bitmap::JourneyBitmap, tile_changed| {
-                        journey_bitmap.add_line_with_change_callback(
-                            start.longitude,
-                            start.latitude,
-                            end.longitude,
-                            end.latitude,
-                            tile_changed,
-                        );
-                    },
-                );
-            }
+    let mut processed = Vec::with_capacity(raw_data_list.len());
+    for raw_data in gps_processor::reorder_batch(raw_data_list) {
+        let last_point = gps_preprocessor.last_kept_point();
+        let (process_result, filtered_data) = gps_preprocessor.preprocess_and_filter(&raw_data);
+        if !main_map_state.dropped_for_power_saving && main_map_state.layer_filter.current_journey {
+            let line_to_add = match process_result {
+                ProcessResult::Ignore => None,
+                ProcessResult::NewSegment => Some((&filtered_data.point, &filtered_data.point)),
+                ProcessResult::Append => {
+                    let start = last_point.as_ref().unwrap_or(&filtered_data.point);
+                    Some((start, &filtered_data.point))
+                }
+            };
+            match line_to_add {
+                None => (),
+                Some((start, end)) => {
+                    main_map_state.map_renderer.update(
+                        |journey_bitmap: &mut crate::journey_bitmap::JourneyBitmap,
+                         tile_changed| {
+                            journey_bitmap.add_line_with_change_callback(
+                                start.longitude,
+                                start.latitude,
+                                end.longitude,
+                                end.latitude,
+                                tile_changed,
+                            );
+                        },
+                    );
+                }
+            };
         };
-    };
+        processed.push((raw_data, filtered_data, process_result));
+    }
 
     state
         .storage
-        .record_gps_data(&raw_data, process_result, received_timestamp_ms);
+        .record_gps_data_batch(&processed, received_timestamp_ms);
+
+    processed
+        .iter()
+        .any(|(_, _, process_result)| match process_result {
+            ProcessResult::Ignore => false,
+            ProcessResult::Append | ProcessResult::NewSegment => true,
+        })
+}
+
+#[frb(sync)]
+pub fn get_gps_filter() -> GpsFilter {
+    get().gps_preprocessor.lock().unwrap().filter()
+}
 
-    match process_result {
-        ProcessResult::Ignore => false,
-        ProcessResult::Append | ProcessResult::NewSegment => true,
+/// Used for live location updates, the setting is persisted.
+pub fn set_gps_filter(gps_filter: GpsFilter) -> Result<()> {
+    let state = get();
+    let mut gps_preprocessor = state.gps_preprocessor.lock().unwrap();
+    state.storage.set_gps_filter(gps_filter)?;
+    gps_preprocessor.set_filter(gps_filter);
+    Ok(())
+}
+
+#[frb(sync)]
+pub fn get_preprocessing_profile() -> PreprocessingProfile {
+    get().storage.get_preprocessing_profile()
+}
+
+/// Used for live location updates, the setting is persisted.
+pub fn set_preprocessing_profile(profile: PreprocessingProfile) -> Result<()> {
+    let state = get();
+    let mut gps_preprocessor = state.gps_preprocessor.lock().unwrap();
+    state.storage.set_preprocessing_profile(profile)?;
+    gps_preprocessor.set_thresholds(state.storage.get_preprocessing_thresholds(profile));
+    Ok(())
+}
+
+#[frb(sync)]
+pub fn get_preprocessing_thresholds(profile: PreprocessingProfile) -> PreprocessingThresholds {
+    get().storage.get_preprocessing_thresholds(profile)
+}
+
+/// Thresholds of the `Custom` profile. It takes effect immediately if it is
+/// the current profile.
+pub fn set_custom_preprocessing_thresholds(thresholds: PreprocessingThresholds) -> Result<()> {
+    let state = get();
+    let mut gps_preprocessor = state.gps_preprocessor.lock().unwrap();
+    state
+        .storage
+        .set_custom_preprocessing_thresholds(&thresholds)?;
+    if state.storage.get_preprocessing_profile() == PreprocessingProfile::Custom {
+        gps_preprocessor.set_thresholds(thresholds);
     }
+    Ok(())
 }
 
 pub fn list_all_raw_data() -> Result<Vec<RawDataFile>> {
@@ -498,17 +667,98 @@ where
     let finalized = state.storage.with_db_txn(finalize_op)?;
     // when journey is finalized, we should reset the gps_preprocessor to prevent old state affecting new journey
     if finalized {
-        *gps_preprocessor = GpsPreprocessor::new();
+        gps_preprocessor.reset();
     }
     Ok(finalized)
 }
 
+/// Also the way to finish a manual journey.
 pub fn finalize_ongoing_journey() -> Result<bool> {
-    reset_gps_preprocessor_if_finalized(|txn| txn.finalize_ongoing_journey())
+    let split_options = get().storage.get_journey_split_options();
+    reset_gps_preprocessor_if_finalized(|txn| {
+        txn.finalize_ongoing_journey_with_split(&split_options)
+    })
 }
 
 pub fn try_auto_finalize_journey() -> Result<bool> {
-    reset_gps_preprocessor_if_finalized(|txn| txn.try_auto_finalize_journey())
+    let policy = get().storage.get_auto_finalize_policy();
+    let split_options = get().storage.get_journey_split_options();
+    reset_gps_preprocessor_if_finalized(|txn| {
+        txn.try_auto_finalize_journey(&policy, &split_options)
+    })
+}
+
+pub fn get_recorder_state() -> Result<RecorderState> {
+    get().storage.with_db_txn(|txn| txn.get_recorder_state())
+}
+
+/// Location updates are ignored until `resume_recording`, the ongoing journey
+/// is kept.
+pub fn pause_recording() -> Result<()> {
+    let state = get();
+    // `on_location_updates` holds this lock for a whole batch, so no data is
+    // recorded after this returns
+    let _gps_preprocessor = state.gps_preprocessor.lock().unwrap();
+    state.storage.with_db_txn(|txn| txn.pause_recording())
+}
+
+pub fn resume_recording() -> Result<()> {
+    let state = get();
+    let mut gps_preprocessor = state.gps_preprocessor.lock().unwrap();
+    state.storage.with_db_txn(|txn| txn.resume_recording())?;
+    // so the next data starts a new segment instead of connecting to the data
+    // before pausing
+    gps_preprocessor.reset();
+    Ok(())
+}
+
+/// Data recorded so far is finalized first, the new journey is named `title`
+/// and is never auto finalized. Returns whether a journey was added for the
+/// data recorded before.
+pub fn start_manual_journey(title: String) -> Result<bool> {
+    let split_options = get().storage.get_journey_split_options();
+    reset_gps_preprocessor_if_finalized(|txn| txn.start_manual_journey(title, &split_options))
+}
+
+/// Returns `false` if there was no data to discard.
+pub fn discard_ongoing_journey() -> Result<bool> {
+    let state = get();
+    let mut gps_preprocessor = state.gps_preprocessor.lock().unwrap();
+    let discarded = state
+        .storage
+        .with_db_txn(|txn| txn.discard_ongoing_journey())?;
+    gps_preprocessor.reset();
+    // the current journey layer is based on the ongoing journey
+    let mut main_map_state = state.main_map_state.lock().unwrap();
+    reload_main_map_bitmap(&state.storage, &mut main_map_state)?;
+    Ok(discarded)
+}
+
+#[frb(sync)]
+pub fn get_journey_split_options() -> JourneySplitOptions {
+    get().storage.get_journey_split_options()
+}
+
+pub fn set_journey_split_options(options: JourneySplitOptions) -> Result<()> {
+    get().storage.set_journey_split_options(&options)
+}
+
+#[frb(sync)]
+pub fn get_auto_finalize_policy() -> AutoFinalizePolicy {
+    get().storage.get_auto_finalize_policy()
+}
+
+pub fn set_auto_finalize_policy(policy: AutoFinalizePolicy) -> Result<()> {
+    get().storage.set_auto_finalize_policy(&policy)
+}
+
+/// A dry run of `try_auto_finalize_journey` with the current policy, nothing
+/// is changed.
+pub fn explain_auto_finalize() -> Result<AutoFinalizeExplanation> {
+    let policy = get().storage.get_auto_finalize_policy();
+    get()
+        .storage
+        .with_db_txn(|txn| txn.explain_auto_finalize(&policy, &Local::now()))
 }
 
 pub fn has_ongoing_journey() -> Result<bool> {
@@ -547,12 +797,94 @@ pub fn list_all_journeys() -> Result<Vec<JourneyHeader>> {
         .with_db_txn(|txn| txn.query_journeys(None, None))
 }
 
-pub fn generate_full_archive(target_filepath: String) -> Result<()> {
-    info!("generating full archive");
-    let mut file = File::create(target_filepath)?;
+/// The places visited in `[start, end]` as a timeline, ordered by arrival
+/// time.
+pub fn list_place_visits(start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<PlaceVisit>> {
     get()
         .storage
-        .with_db_txn(|txn| archive::export_as_mldx(&archive::WhatToExport::All, txn, &mut file))?;
+        .with_db_txn(|txn| txn.query_place_visits(start, end))
+}
+
+/// Same as `list_place_visits`, for a local date.
+pub fn list_place_visits_on_date(year: i32, month: u32, day: u32) -> Result<Vec<PlaceVisit>> {
+    let date = NaiveDate::from_ymd_opt(year, month, day)
+        .ok_or_else(|| anyhow!("Invalid date: {year}-{month}-{day}"))?;
+    let start_of_date = |date: NaiveDate| {
+        utils::start_of_date(date, &Local)
+            .map(|x| x.with_timezone(&Utc))
+            .ok_or_else(|| anyhow!("Date out of range: {date}"))
+    };
+    let next_date = date
+        .succ_opt()
+        .ok_or_else(|| anyhow!("Date out of range: {date}"))?;
+    let start = start_of_date(date)?;
+    let end = start_of_date(next_date)? - Duration::seconds(1);
+    list_place_visits(start, end)
+}
+
+pub fn list_privacy_zones() -> Result<Vec<PrivacyZone>> {
+    get().storage.with_db_txn(|txn| txn.list_privacy_zones())
+}
+
+/// Returns the id of the new zone.
+pub fn add_privacy_zone(name: String, shape: PrivacyZoneShape) -> Result<String> {
+    get()
+        .storage
+        .with_db_txn(|txn| txn.add_privacy_zone(&name, &shape))
+}
+
+pub fn update_privacy_zone(id: String, name: String, shape: PrivacyZoneShape) -> Result<()> {
+    get()
+        .storage
+        .with_db_txn(|txn| txn.update_privacy_zone(&id, &name, &shape))
+}
+
+pub fn delete_privacy_zone(id: String) -> Result<()> {
+    get()
+        .storage
+        .with_db_txn(|txn| txn.delete_privacy_zone(&id))
+}
+
+#[frb(sync)]
+pub fn is_db_encrypted() -> bool {
+    get().storage.is_db_encrypted()
+}
+
+/// `None` disables the encryption. The new key should be persisted by the
+/// caller before calling this, and passed to `init` afterwards.
+pub fn rotate_db_key(new_key: Option<Vec<u8>>) -> Result<()> {
+    info!("rotating db key");
+    get().storage.rotate_db_key(new_key.as_deref())
+}
+
+pub fn list_all_places() -> Result<Vec<Place>> {
+    get().storage.with_db_txn(|txn| txn.list_places())
+}
+
+/// The archive is encrypted if `passphrase` is set. A full archive is meant to
+/// restore everything, so privacy zones are only applied if
+/// `apply_privacy_zones` is set, i.e. the archive is for sharing.
+pub fn generate_full_archive(
+    target_filepath: String,
+    passphrase: Option<String>,
+    apply_privacy_zones: bool,
+) -> Result<()> {
+    info!("generating full archive");
+    let mut file = File::create(target_filepath)?;
+    get().storage.with_db_txn(|txn| {
+        let privacy_zones = if apply_privacy_zones {
+            txn.list_privacy_zones()?
+        } else {
+            vec![]
+        };
+        archive::export_as_mldx(
+            &archive::WhatToExport::All,
+            txn,
+            &privacy_zones,
+            passphrase.as_deref(),
+            &mut file,
+        )
+    })?;
     drop(file);
     Ok(())
 }
@@ -561,7 +893,14 @@ pub fn generate_single_archive(journey_id: String, target_filepath: String) -> R
     info!("generating single journey archive");
     let mut file = File::create(target_filepath)?;
     get().storage.with_db_txn(|txn| {
-        archive::export_as_mldx(&archive::WhatToExport::Just(journey_id), txn, &mut file)
+        let privacy_zones = txn.list_privacy_zones()?;
+        archive::export_as_mldx(
+            &archive::WhatToExport::Just(journey_id),
+            txn,
+            &privacy_zones,
+            None,
+            &mut file,
+        )
     })?;
     drop(file);
     Ok(())
@@ -578,12 +917,16 @@ pub fn export_journey(
     journey_id: String,
     export_type: ExportType,
 ) -> Result<()> {
-    let journey_data = get()
-        .storage
-        .with_db_txn(|txn| txn.get_journey_data(&journey_id))?;
+    let (journey_data, privacy_zones) = get().storage.with_db_txn(|txn| {
+        Ok((
+            txn.get_journey_data(&journey_id)?,
+            txn.list_privacy_zones()?,
+        ))
+    })?;
     match journey_data {
         JourneyData::Bitmap(_bitmap) => Err(anyhow!("Data type error")),
         JourneyData::Vector(vector) => {
+            let vector = privacy_zone::trim_journey_vector(&privacy_zones, &vector);
             let mut file = File::create(target_filepath)?;
             match export_type {
                 ExportType::GPX => {
@@ -601,9 +944,7 @@ pub fn export_journey(
 #[auto_context]
 pub fn export_raw_data_gpx_file(csv_filepath: String) -> Result<String> {
     let csv_path = Path::new(&csv_filepath);
-    let file_name = csv_path
-        .file_stem()
-        .and_then(|s| s.to_str())
+    let file_name = raw_data_store::name_of_path(csv_path)
         .ok_or_else(|| anyhow::anyhow!("Failed to parse filename: {csv_filepath}"))?;
 
     let target_dir = Path::new(&get().storage.cache_dir).join("raw_data");
@@ -619,9 +960,8 @@ pub fn export_raw_data_gpx_file(csv_filepath: String) -> Result<String> {
         return Ok(gpx_path_str);
     }
 
-    let csv_file = File::open(csv_path)
+    let mut reader = raw_data_store::open_csv_reader(csv_path)
         .with_context(|| format!("Failed to open source CSV file: {csv_filepath}"))?;
-    let mut reader = Reader::from_reader(BufReader::new(csv_file));
 
     let gpx_file = File::create(&gpx_path)
         .with_context(|| format!("Failed to create target GPX file: {gpx_path_str}"))?;
@@ -634,17 +974,66 @@ pub fn export_raw_data_gpx_file(csv_filepath: String) -> Result<String> {
     Ok(gpx_path_str)
 }
 
+/// Raw data files are compressed once they are closed, this gives a plain CSV
+/// file in the cache dir for sharing.
+#[auto_context]
+pub fn export_raw_data_csv_file(csv_filepath: String) -> Result<String> {
+    let csv_path = Path::new(&csv_filepath);
+    let file_name = raw_data_store::name_of_path(csv_path)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse filename: {csv_filepath}"))?;
+
+    let target_dir = Path::new(&get().storage.cache_dir).join("raw_data");
+    std::fs::create_dir_all(&target_dir)?;
+    let target_path = target_dir.join(file_name).with_extension("csv");
+
+    let mut reader = raw_data_store::open_csv_reader(csv_path)
+        .with_context(|| format!("Failed to open source CSV file: {csv_filepath}"))?;
+    let mut writer = csv::Writer::from_path(&target_path)?;
+    writer.write_record(reader.headers()?)?;
+    for record in reader.records() {
+        writer.write_record(&record?)?;
+    }
+    writer.flush()?;
+
+    Ok(target_path.to_string_lossy().to_string())
+}
+
+#[frb(sync)]
+pub fn get_raw_data_retention() -> RawDataRetention {
+    get().storage.get_raw_data_retention()
+}
+
+/// The new retention is applied right away.
+#[auto_context]
+pub fn set_raw_data_retention(retention: RawDataRetention) -> Result<()> {
+    let storage = &get().storage;
+    storage.set_raw_data_retention(&retention)?;
+    storage.maintain_raw_data()
+}
+
 pub fn delete_all_journeys() -> Result<()> {
     info!("Delete all journeys");
     get().storage.with_db_txn(|txn| txn.delete_all_journeys())
 }
 
-pub fn import_archive(mldx_file_path: String) -> Result<()> {
+pub fn archive_requires_passphrase(mldx_file_path: String) -> Result<bool> {
+    archive::mldx_requires_passphrase(&mldx_file_path)
+}
+
+pub fn import_archive(
+    mldx_file_path: String,
+    passphrase: Option<String>,
+) -> Result<Vec<DuplicateJourneyPair>> {
     info!("Import Archived Data");
     get()
         .storage
-        .with_db_txn(|txn| archive::import_mldx(txn, &mldx_file_path))?;
-    Ok(())
+        .with_db_txn(|txn| archive::import_mldx(txn, &mldx_file_path, passphrase.as_deref()))
+}
+
+pub fn find_duplicate_journeys() -> Result<Vec<DuplicateJourneyPair>> {
+    get()
+        .storage
+        .with_db_txn(|txn| duplicate_detector::find_all_duplicates(txn))
 }
 
 pub fn update_journey_metadata(id: &str, journey_info: JourneyInfo) -> Result<()> {
@@ -656,7 +1045,8 @@ pub fn update_journey_metadata(id: &str, journey_info: JourneyInfo) -> Result<()
             journey_info.end_time,
             journey_info.note,
             journey_info.journey_kind,
-        )
+        )?;
+        txn.set_journey_transport_mode(id, journey_info.transport_mode)
     })?;
     Ok(())
 }
@@ -696,6 +1086,36 @@ pub fn export_logs(target_file_path: String) -> Result<()> {
 
 pub fn ten_minutes_heartbeat() {
     info!("10 minutes heartbeat");
+    if let Err(e) = get().storage.maintain_raw_data() {
+        error!("Failed to maintain raw data: {e:?}");
+    }
+    if let Err(e) = get().storage.maintain_backups(Local::now()) {
+        error!("Failed to maintain backups: {e:?}");
+    }
+}
+
+/// Newest first.
+pub fn list_backups() -> Result<Vec<BackupFile>> {
+    get().storage.list_backups()
+}
+
+pub fn create_backup() -> Result<BackupFile> {
+    get().storage.create_backup(Local::now().naive_local())
+}
+
+/// Journeys in the backup that were deleted are brought back, existing ones
+/// are kept as is.
+pub fn restore_backup(name: String) -> Result<Vec<DuplicateJourneyPair>> {
+    get().storage.restore_backup(&name)
+}
+
+#[frb(sync)]
+pub fn get_backup_policy() -> BackupPolicy {
+    get().storage.get_backup_policy()
+}
+
+pub fn set_backup_policy(policy: BackupPolicy) -> Result<()> {
+    get().storage.set_backup_policy(&policy)
 }
 
 pub fn main_db_require_optimization() -> Result<bool> {
@@ -746,10 +1166,15 @@ pub mod for_testing {
     use std::sync::{Arc, Mutex};
 
     use super::MainMapState;
+    use crate::storage::Storage;
 
     pub fn get_main_map_state() -> Arc<Mutex<MainMapState>> {
         super::get().main_map_state.clone()
     }
+
+    pub fn get_storage() -> &'static Storage {
+        &super::get().storage
+    }
 }
 
 #[frb(sync)]
@@ -4,16 +4,25 @@ use anyhow::{Context, Result};
 use auto_context::auto_context;
 use chrono::{DateTime, Local, NaiveDate, Utc};
 use flutter_rust_bridge::frb;
+use itertools::Itertools;
 
-use super::api;
-use crate::gps_processor::SegmentGapRule;
+use super::api::{self, CameraOption, MapRendererProxy};
+use crate::airports::{self, Airport};
+use crate::duplicate_detector::{self, DuplicateJourneyPair};
+use crate::flight_detector::{self, TrackPart};
+use crate::gps_processor::{
+    GpsFilter, PreprocessingProfile, PreprocessingThresholds, SegmentGapRule,
+};
 use crate::journey_vector::JourneyVector;
+use crate::places::{self, StayPoint};
+use crate::preprocessor_detector::{self, PreprocessorRecommendation};
+use crate::transport_mode::{self, TimedPoint, TransportMode};
 use crate::{
     flight_track_processor,
-    gps_processor::RawData,
+    gps_processor::{Point, RawData},
     import_data::{self, journey_info_from_raw_vector_data},
     journey_data::JourneyData,
-    journey_header::JourneyKind,
+    journey_header::{JourneyHeader, JourneyKind, JourneyType},
 };
 
 #[derive(Debug)]
@@ -24,6 +33,7 @@ pub struct JourneyInfo {
     pub end_time: Option<DateTime<Utc>>,
     pub journey_kind: JourneyKind,
     pub note: Option<String>,
+    pub transport_mode: Option<TransportMode>,
 }
 
 #[frb(opaque)]
@@ -50,22 +60,36 @@ pub fn load_fow_data(file_path: String) -> Result<(JourneyInfo, JourneyData)> {
         end_time: None,
         note: None,
         journey_kind: JourneyKind::DefaultKind,
+        transport_mode: None,
     };
     Ok((journey_info, JourneyData::Bitmap(journey_bitmap)))
 }
 
-#[auto_context]
-pub fn load_gpx_or_kml(
-    file_path: String,
-) -> Result<(JourneyInfo, RawVectorData, ImportPreprocessor)> {
-    let (raw_vector_data, import_preprocessor) = match Path::new(&file_path)
+fn extension_of(file_path: &str) -> Option<String> {
+    Path::new(file_path)
         .extension()
         .and_then(OsStr::to_str)
         .map(|x| x.to_lowercase())
-        .as_deref()
+}
+
+/// The last one is the warnings about the data that can't be imported as is,
+/// e.g. invalid timestamps.
+#[auto_context]
+pub fn load_gpx_or_kml(
+    file_path: String,
+) -> Result<(
+    JourneyInfo,
+    RawVectorData,
+    ImportPreprocessor,
+    Option<String>,
+)> {
+    let (raw_vector_data, import_preprocessor, warnings) = match extension_of(&file_path).as_deref()
     {
-        Some("gpx") => import_data::load_gpx(&file_path)?,
-        Some("kml") => import_data::load_kml(&file_path)?,
+        Some("gpx") => import_data::load_gpx_with_warnings(&file_path)?,
+        Some("kml" | "kmz") => {
+            let (raw_vector_data, import_preprocessor) = import_data::load_kml(&file_path)?;
+            (raw_vector_data, import_preprocessor, None)
+        }
         extension => return Err(anyhow!("Unknown extension: {extension:?}")),
     };
 
@@ -75,13 +99,85 @@ pub fn load_gpx_or_kml(
             data: raw_vector_data,
         },
         import_preprocessor,
+        warnings,
     ))
 }
 
+// Larger files are imported with `import_gpx_by_local_day` since loading all
+// points for the preview could use too much memory on phones.
+const STREAMING_IMPORT_MIN_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Whether the file should be imported with `import_gpx_by_local_day` instead
+/// of being loaded with `load_gpx_or_kml`.
+#[frb(sync)]
+#[auto_context]
+pub fn should_import_by_local_day(file_path: String) -> Result<bool> {
+    Ok(extension_of(&file_path).as_deref() == Some("gpx")
+        && std::fs::metadata(&file_path)?.len() >= STREAMING_IMPORT_MIN_FILE_SIZE)
+}
+
+#[frb(non_opaque)]
+pub struct LocalDayImportResult {
+    pub imported_journey_count: u64,
+    pub duplicates: Vec<DuplicateJourneyPair>,
+    pub warnings: Option<String>,
+}
+
+/// Imports a large GPX file without preview, one journey per local day (and
+/// per flight, see `import_vector_data_with_flights`) with the preprocessor
+/// recommended for each day. Only one day of data is in memory at a time.
+#[auto_context]
+pub fn import_gpx_by_local_day(file_path: String) -> Result<LocalDayImportResult> {
+    let mut imported_journey_count = 0;
+    let mut duplicates = Vec::new();
+    let warnings = import_data::load_gpx_by_local_day(&file_path, |data, import_processor| {
+        let journey_info = journey_info_from_raw_vector_data(&data);
+        let (count, mut part_duplicates) = import_non_empty_vector_data_with_flights(
+            journey_info,
+            &RawVectorData { data },
+            import_processor,
+        )?;
+        imported_journey_count += count;
+        duplicates.append(&mut part_duplicates);
+        Ok(())
+    })?;
+    Ok(LocalDayImportResult {
+        imported_journey_count,
+        duplicates,
+        warnings,
+    })
+}
+
+/// Returns existing journeys that are likely duplicates of the imported one.
+/// The journey is imported regardless, it is up to the user to decide what to
+/// do with the duplicates.
+#[auto_context]
+pub fn import_journey_data(
+    journey_info: JourneyInfo,
+    journey_data: JourneyData,
+) -> Result<Vec<DuplicateJourneyPair>> {
+    import_journey_data_with_stay_points(journey_info, journey_data, &[])
+}
+
+/// Same as `import_journey_data`, but also records the places visited based
+/// on the timestamps in `vector_data`, which `journey_data` is processed from.
 #[auto_context]
-pub fn import_journey_data(journey_info: JourneyInfo, journey_data: JourneyData) -> Result<()> {
-    let _id = api::get().storage.with_db_txn(|txn| {
-        txn.create_and_insert_journey(
+pub fn import_journey_data_with_places(
+    journey_info: JourneyInfo,
+    journey_data: JourneyData,
+    vector_data: &RawVectorData,
+) -> Result<Vec<DuplicateJourneyPair>> {
+    let stay_points = places::detect_stay_points_from_raw_data(&vector_data.data);
+    import_journey_data_with_stay_points(journey_info, journey_data, &stay_points)
+}
+
+fn import_journey_data_with_stay_points(
+    journey_info: JourneyInfo,
+    journey_data: JourneyData,
+    stay_points: &[StayPoint],
+) -> Result<Vec<DuplicateJourneyPair>> {
+    api::get().storage.with_db_txn(|txn| {
+        let id = txn.create_and_insert_journey(
             journey_info.journey_date,
             journey_info.start_time,
             journey_info.end_time,
@@ -89,11 +185,284 @@ pub fn import_journey_data(journey_info: JourneyInfo, journey_data: JourneyData)
             journey_info.journey_kind,
             journey_info.note,
             journey_data,
-        )
+        )?;
+        txn.add_place_visits(Some(&id), stay_points)?;
+        txn.set_journey_transport_mode(&id, journey_info.transport_mode)?;
+        duplicate_detector::find_duplicates_of(txn, &id)
+    })
+}
+
+/// Processes `vector_data` with `import_processor` and imports it, like
+/// `import_journey_data_with_places`, except that flights found in the data
+/// (see `flight_detector`) are imported as separate `JourneyKind::Flight`
+/// journeys following the great circle. The data around the flights is split
+/// into journeys as well, with the time range and date of their own, so only
+/// the note of `journey_info` is kept (use `count_flights` to tell the user
+/// beforehand). If there is no flight, `journey_info` is used as is.
+#[auto_context]
+pub fn import_vector_data_with_flights(
+    journey_info: JourneyInfo,
+    vector_data: &RawVectorData,
+    import_processor: ImportPreprocessor,
+) -> Result<Vec<DuplicateJourneyPair>> {
+    let (_, duplicates) =
+        import_non_empty_vector_data_with_flights(journey_info, vector_data, import_processor)?;
+    Ok(duplicates)
+}
+
+/// The number of flights `import_vector_data_with_flights` finds in
+/// `vector_data`.
+pub fn count_flights(vector_data: &RawVectorData) -> u32 {
+    flight_detector::split_flights(&vector_data.data, TimedPoint::of_raw_data)
+        .iter()
+        .filter(|x| matches!(x, TrackPart::Flight(_)))
+        .count() as u32
+}
+
+/// Same as `import_vector_data_with_flights`, but journeys with empty data are
+/// skipped. Also returns the number of imported journeys.
+fn import_non_empty_vector_data_with_flights(
+    journey_info: JourneyInfo,
+    vector_data: &RawVectorData,
+    import_processor: ImportPreprocessor,
+) -> Result<(u64, Vec<DuplicateJourneyPair>)> {
+    let parts = flight_detector::split_flights(&vector_data.data, TimedPoint::of_raw_data);
+    if !parts.iter().any(|x| matches!(x, TrackPart::Flight(_))) {
+        let journey_data = process_vector_data(vector_data, import_processor)?;
+        if is_journey_data_empty(&journey_data) {
+            return Ok((0, vec![]));
+        }
+        let duplicates = import_journey_data_with_places(journey_info, journey_data, vector_data)?;
+        return Ok((1, duplicates));
+    }
+
+    let mut journeys = Vec::new();
+    for part in parts {
+        match part {
+            TrackPart::Ground(data) => {
+                let vector_data = RawVectorData { data };
+                let journey_data = process_vector_data(&vector_data, import_processor)?;
+                if is_journey_data_empty(&journey_data) {
+                    continue;
+                }
+                journeys.push((
+                    journey_info_from_raw_vector_data(&vector_data.data),
+                    journey_data,
+                    places::detect_stay_points_from_raw_data(&vector_data.data),
+                ));
+            }
+            TrackPart::Flight(data) => {
+                let points: Vec<Point> = data.iter().map(|x| x.point.clone()).collect();
+                if let Some(journey_vector) = flight_track_processor::process_great_circle(&points)
+                {
+                    let data = vec![data];
+                    journeys.push((
+                        JourneyInfo {
+                            journey_kind: JourneyKind::Flight,
+                            transport_mode: Some(TransportMode::Flight),
+                            ..journey_info_from_raw_vector_data(&data)
+                        },
+                        JourneyData::Vector(journey_vector),
+                        vec![],
+                    ));
+                }
+            }
+        }
+    }
+
+    let journey_count = journeys.len() as u64;
+    let duplicates = api::get().storage.with_db_txn(|txn| {
+        let mut duplicates = Vec::new();
+        for (info, journey_data, stay_points) in journeys {
+            let id = txn.create_and_insert_journey(
+                info.journey_date,
+                info.start_time,
+                info.end_time,
+                None,
+                info.journey_kind,
+                journey_info.note.clone(),
+                journey_data,
+            )?;
+            txn.add_place_visits(Some(&id), &stay_points)?;
+            txn.set_journey_transport_mode(&id, info.transport_mode)?;
+            duplicates.extend(duplicate_detector::find_duplicates_of(txn, &id)?);
+        }
+        Ok(duplicates)
+    })?;
+    Ok((journey_count, duplicates))
+}
+
+#[frb(sync)]
+pub fn lookup_airport(code: String) -> Option<Airport> {
+    airports::find_airport(&code).cloned()
+}
+
+/// Creates a flight journey on `journey_date` from the airport codes (see
+/// `airports::find_airport`), for flights without any recorded data. The path
+/// follows the great circle between the two airports. The note defaults to
+/// the route, e.g. "PVG → HND".
+#[auto_context]
+pub fn create_flight_journey(
+    origin: String,
+    destination: String,
+    journey_date: NaiveDate,
+    note: Option<String>,
+) -> Result<Vec<DuplicateJourneyPair>> {
+    let (journey_info, journey_data) =
+        flight_journey_of_airport_codes(&origin, &destination, journey_date, note)?;
+    import_journey_data(journey_info, journey_data)
+}
+
+fn flight_journey_of_airport_codes(
+    origin: &str,
+    destination: &str,
+    journey_date: NaiveDate,
+    note: Option<String>,
+) -> Result<(JourneyInfo, JourneyData)> {
+    let find_airport =
+        |code: &str| airports::find_airport(code).ok_or_else(|| anyhow!("Unknown airport: {code}"));
+    let origin = find_airport(origin)?;
+    let destination = find_airport(destination)?;
+    if origin == destination {
+        bail!(
+            "The origin and the destination are the same: {}",
+            origin.iata
+        );
+    }
+    let journey_vector = flight_track_processor::process_great_circle(&[
+        Point {
+            latitude: origin.latitude,
+            longitude: origin.longitude,
+        },
+        Point {
+            latitude: destination.latitude,
+            longitude: destination.longitude,
+        },
+    ])
+    .ok_or_else(|| anyhow!("Failed to build the flight path"))?;
+
+    Ok((
+        JourneyInfo {
+            journey_date,
+            start_time: None,
+            end_time: None,
+            journey_kind: JourneyKind::Flight,
+            note: Some(note.unwrap_or_else(|| format!("{} → {}", origin.iata, destination.iata))),
+            transport_mode: Some(TransportMode::Flight),
+        },
+        JourneyData::Vector(journey_vector),
+    ))
+}
+
+#[derive(Debug, Clone)]
+#[frb(non_opaque)]
+pub struct FlightLogEntry {
+    pub date: NaiveDate,
+    /// Airport codes, see `airports::find_airport`.
+    pub origin: String,
+    pub destination: String,
+    pub airline: Option<String>,
+    pub flight_number: Option<String>,
+}
+
+/// See `import_data::load_flight_log_csv`.
+#[auto_context]
+pub fn load_flight_log_csv(file_path: String) -> Result<Vec<FlightLogEntry>> {
+    import_data::load_flight_log_csv(&file_path)
+}
+
+#[frb(non_opaque)]
+pub struct FlightLogImportResult {
+    pub imported_count: u32,
+    /// Entries with unknown airports.
+    pub skipped_entries: Vec<FlightLogEntry>,
+    pub duplicates: Vec<DuplicateJourneyPair>,
+}
+
+/// Creates one flight journey for each entry (see `create_flight_journey`)
+/// in a single transaction. The note is the route, followed by the flight
+/// number and the airline if there is any.
+#[auto_context]
+pub fn import_flight_log(entries: Vec<FlightLogEntry>) -> Result<FlightLogImportResult> {
+    let mut journeys = Vec::new();
+    let mut skipped_entries = Vec::new();
+    for entry in entries {
+        let note = [
+            Some(format!("{} → {}", entry.origin, entry.destination)),
+            entry.flight_number.clone(),
+            entry.airline.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .join(", ");
+        match flight_journey_of_airport_codes(
+            &entry.origin,
+            &entry.destination,
+            entry.date,
+            Some(note),
+        ) {
+            Ok(journey) => journeys.push(journey),
+            Err(error) => {
+                warn!("[import_flight_log] Skipping {entry:?}: {error}");
+                skipped_entries.push(entry);
+            }
+        }
+    }
+
+    let imported_count = journeys.len() as u32;
+    let duplicates = api::get().storage.with_db_txn(|txn| {
+        let mut duplicates = Vec::new();
+        for (journey_info, journey_data) in journeys {
+            let id = txn.create_and_insert_journey(
+                journey_info.journey_date,
+                journey_info.start_time,
+                journey_info.end_time,
+                None,
+                journey_info.journey_kind,
+                journey_info.note,
+                journey_data,
+            )?;
+            txn.set_journey_transport_mode(&id, journey_info.transport_mode)?;
+            duplicates.extend(duplicate_detector::find_duplicates_of(txn, &id)?);
+        }
+        Ok(duplicates)
     })?;
-    Ok(())
+    Ok(FlightLogImportResult {
+        imported_count,
+        skipped_entries,
+        duplicates,
+    })
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum ImportSplitRule {
+    /// Start a new journey when the local date changes.
+    LocalDay,
+    /// Start a new journey when there is no data for more than `gap_minutes`.
+    TimeGap { gap_minutes: u32 },
+    /// Start a new journey when the transport mode changes (see
+    /// `transport_mode`).
+    TransportMode,
+}
+
+/// Split the loaded data into multiple journeys, each with its own
+/// `JourneyInfo`. Each of them can then be processed and imported separately.
+pub fn split_raw_vector_data(
+    vector_data: &RawVectorData,
+    split_rule: ImportSplitRule,
+) -> Vec<(JourneyInfo, RawVectorData)> {
+    import_data::split_raw_vector_data(&vector_data.data, split_rule)
+        .into_iter()
+        .map(|data| {
+            (
+                journey_info_from_raw_vector_data(&data),
+                RawVectorData { data },
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ImportPreprocessor {
     None,
     Generic,
@@ -105,22 +474,70 @@ pub enum ImportPreprocessor {
 pub fn process_vector_data(
     vector_data: &RawVectorData,
     import_processor: ImportPreprocessor,
+) -> Result<JourneyData> {
+    process_vector_data_with_gps_filter(vector_data, import_processor, GpsFilter::None)
+}
+
+/// Same as `process_vector_data`, with an extra `GpsFilter` to correct the
+/// data. It only applies to `Generic` and `Spare`.
+#[auto_context]
+pub fn process_vector_data_with_gps_filter(
+    vector_data: &RawVectorData,
+    import_processor: ImportPreprocessor,
+    gps_filter: GpsFilter,
+) -> Result<JourneyData> {
+    process_vector_data_with_thresholds(
+        vector_data,
+        import_processor,
+        PreprocessingThresholds::from_segment_gap_rule(SegmentGapRule::Default),
+        gps_filter,
+    )
+}
+
+/// Same as `process_vector_data_with_gps_filter`, with thresholds from a
+/// `PreprocessingProfile`. `Generic` uses all of them, `Spare` keeps its own
+/// segment gap thresholds.
+#[auto_context]
+pub fn process_vector_data_with_profile(
+    vector_data: &RawVectorData,
+    import_processor: ImportPreprocessor,
+    profile: PreprocessingProfile,
+    gps_filter: GpsFilter,
+) -> Result<JourneyData> {
+    // only the custom one needs the storage
+    let thresholds = match profile.builtin_thresholds() {
+        Some(thresholds) => thresholds,
+        None => api::get().storage.get_preprocessing_thresholds(profile),
+    };
+    process_vector_data_with_thresholds(vector_data, import_processor, thresholds, gps_filter)
+}
+
+fn process_vector_data_with_thresholds(
+    vector_data: &RawVectorData,
+    import_processor: ImportPreprocessor,
+    thresholds: PreprocessingThresholds,
+    gps_filter: GpsFilter,
 ) -> Result<JourneyData> {
     let journey_vector_opt = match import_processor {
         ImportPreprocessor::None => {
             import_data::journey_vector_from_raw_data_with_gps_preprocessor(&vector_data.data, None)
         }
         ImportPreprocessor::Generic => {
-            import_data::journey_vector_from_raw_data_with_gps_preprocessor(
+            import_data::journey_vector_from_raw_data_with_gps_preprocessor_and_filter(
                 &vector_data.data,
-                Some(SegmentGapRule::Default),
+                Some(&thresholds),
+                gps_filter,
             )
         }
         ImportPreprocessor::FlightTrack => flight_track_processor::process(&vector_data.data),
         ImportPreprocessor::Spare => {
-            import_data::journey_vector_from_raw_data_with_gps_preprocessor(
+            import_data::journey_vector_from_raw_data_with_gps_preprocessor_and_filter(
                 &vector_data.data,
-                Some(SegmentGapRule::Spare),
+                Some(&PreprocessingThresholds {
+                    segment_gap_thresholds: SegmentGapRule::Spare.segment_gap_thresholds().to_vec(),
+                    ..thresholds
+                }),
+                gps_filter,
             )
         }
     };
@@ -131,6 +548,277 @@ pub fn process_vector_data(
     Ok(JourneyData::Vector(journey_vector))
 }
 
+/// Loads CSV files recorded in raw data mode (see `api::list_all_raw_data`),
+/// so they can be replayed. Files are concatenated in the given order as a
+/// single segment, the same way they were recorded.
+#[auto_context]
+pub fn load_raw_data_csv_files(csv_file_paths: Vec<String>) -> Result<RawVectorData> {
+    let mut segment = Vec::new();
+    for csv_file_path in csv_file_paths {
+        segment.append(&mut import_data::load_raw_data_csv(&csv_file_path)?);
+    }
+    let data = if segment.is_empty() {
+        vec![]
+    } else {
+        vec![segment]
+    };
+    Ok(RawVectorData { data })
+}
+
+/// Loads the data recorded in raw data mode in `[start, end]`, across all raw
+/// data files.
+#[auto_context]
+pub fn load_raw_data_in_time_range(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<RawVectorData> {
+    let segment = api::get().storage.query_raw_data(start, end)?;
+    let data = if segment.is_empty() {
+        vec![]
+    } else {
+        vec![segment]
+    };
+    Ok(RawVectorData { data })
+}
+
+#[frb(non_opaque)]
+pub struct ReplayedJourney {
+    pub journey_info: JourneyInfo,
+    pub journey_data: JourneyData,
+    /// Existing recorded journeys (`JourneyKind::DefaultKind` vector
+    /// journeys) in the same time range, they are the ones recorded from the
+    /// same data and will be deleted by `replace_with_replayed_journey`. The
+    /// app should show them to the user before that.
+    pub replaced_journeys: Vec<JourneyHeader>,
+    pub stay_points: Vec<StayPoint>,
+}
+
+/// Rebuilds journeys from the raw data by running it through the GPS
+/// preprocessor again, e.g. to recover from a preprocessor bug. It is split
+/// into journeys by `split_rule`. Nothing is written until
+/// `replace_with_replayed_journey`.
+#[auto_context]
+pub fn replay_raw_vector_data(
+    vector_data: &RawVectorData,
+    split_rule: ImportSplitRule,
+    profile: PreprocessingProfile,
+    gps_filter: GpsFilter,
+) -> Result<Vec<ReplayedJourney>> {
+    let mut replayed_journeys = Vec::new();
+    for (journey_info, vector_data) in split_raw_vector_data(vector_data, split_rule) {
+        let journey_data = process_vector_data_with_profile(
+            &vector_data,
+            ImportPreprocessor::Generic,
+            profile,
+            gps_filter,
+        )?;
+        if is_journey_data_empty(&journey_data) {
+            continue;
+        }
+        let replaced_journeys = match (journey_info.start_time, journey_info.end_time) {
+            (Some(start), Some(end)) => api::get().storage.with_db_txn(|txn| {
+                let mut headers = Vec::new();
+                for id in txn.query_journey_ids_in_time_range(start, end)? {
+                    // flights, imported bitmaps etc. are not from the raw data
+                    match txn.get_journey_header(&id)? {
+                        Some(header)
+                            if header.journey_kind == JourneyKind::DefaultKind
+                                && header.journey_type == JourneyType::Vector =>
+                        {
+                            headers.push(header)
+                        }
+                        _ => (),
+                    }
+                }
+                Ok(headers)
+            })?,
+            _ => vec![],
+        };
+        replayed_journeys.push(ReplayedJourney {
+            journey_info,
+            journey_data,
+            replaced_journeys,
+            stay_points: places::detect_stay_points_from_raw_data(&vector_data.data),
+        });
+    }
+    Ok(replayed_journeys)
+}
+
+/// Deletes `replaced_journeys` and inserts the replayed journey (with the
+/// places visited) in a single transaction. The note of the replaced journeys
+/// is kept if the replayed one does not have one. Returns the id of the new
+/// journey.
+#[auto_context]
+pub fn replace_with_replayed_journey(replayed_journey: ReplayedJourney) -> Result<String> {
+    let ReplayedJourney {
+        journey_info,
+        journey_data,
+        replaced_journeys,
+        stay_points,
+    } = replayed_journey;
+    api::get().storage.with_db_txn(|txn| {
+        let mut note = journey_info.note;
+        for header in &replaced_journeys {
+            if note.is_none() {
+                note = txn
+                    .get_journey_header(&header.id)?
+                    .and_then(|header| header.note);
+            }
+            txn.delete_journey(&header.id)?;
+        }
+        let id = txn.create_and_insert_journey(
+            journey_info.journey_date,
+            journey_info.start_time,
+            journey_info.end_time,
+            None,
+            journey_info.journey_kind,
+            note,
+            journey_data,
+        )?;
+        txn.add_place_visits(Some(&id), &stay_points)?;
+        txn.set_journey_transport_mode(&id, journey_info.transport_mode)?;
+        Ok(id)
+    })
+}
+
+#[frb(non_opaque)]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub max_latitude: f64,
+    pub min_longitude: f64,
+    pub max_longitude: f64,
+}
+
+#[frb(non_opaque)]
+pub struct ImportPreprocessorPreview {
+    pub import_preprocessor: ImportPreprocessor,
+    pub point_count: u64,
+    pub segment_count: u64,
+    pub distance_m: f64,
+    /// Number of raw points not kept in the result. The flight track
+    /// preprocessor interpolates the path so it may add points instead.
+    pub dropped_point_count: u64,
+    pub map_renderer_proxy: MapRendererProxy,
+    pub camera_option: Option<CameraOption>,
+}
+
+#[frb(non_opaque)]
+pub struct ImportPreview {
+    pub point_count: u64,
+    pub segment_count: u64,
+    pub distance_m: f64,
+    pub bounding_box: Option<BoundingBox>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub transport_mode: Option<TransportMode>,
+    /// One for each segment of the loaded data.
+    pub segment_transport_modes: Vec<Option<TransportMode>>,
+    pub recommendation: PreprocessorRecommendation,
+    pub preprocessors: Vec<ImportPreprocessorPreview>,
+}
+
+fn distance_of_segment_in_m(points: impl Iterator<Item = Point>) -> f64 {
+    points
+        .tuple_windows()
+        .map(|(a, b)| a.haversine_distance(&b))
+        .sum()
+}
+
+/// Statistics of the loaded data and what the result would look like with
+/// each `ImportPreprocessor`, so the user could pick one before importing.
+#[auto_context]
+pub fn preview_import(vector_data: &RawVectorData) -> Result<ImportPreview> {
+    let raw_points = || vector_data.data.iter().flatten();
+    let point_count = raw_points().count() as u64;
+
+    let bounding_box = raw_points().fold(None, |bounding_box: Option<BoundingBox>, x| {
+        let (latitude, longitude) = (x.point.latitude, x.point.longitude);
+        Some(match bounding_box {
+            None => BoundingBox {
+                min_latitude: latitude,
+                max_latitude: latitude,
+                min_longitude: longitude,
+                max_longitude: longitude,
+            },
+            Some(b) => BoundingBox {
+                min_latitude: b.min_latitude.min(latitude),
+                max_latitude: b.max_latitude.max(latitude),
+                min_longitude: b.min_longitude.min(longitude),
+                max_longitude: b.max_longitude.max(longitude),
+            },
+        })
+    });
+    let journey_info = journey_info_from_raw_vector_data(&vector_data.data);
+
+    let mut preprocessors = Vec::new();
+    for import_preprocessor in [
+        ImportPreprocessor::None,
+        ImportPreprocessor::Generic,
+        ImportPreprocessor::FlightTrack,
+        ImportPreprocessor::Spare,
+    ] {
+        let journey_data = process_vector_data(vector_data, import_preprocessor)?;
+        let (processed_point_count, segment_count, distance_m) = match &journey_data {
+            JourneyData::Vector(journey_vector) => (
+                journey_vector
+                    .track_segments
+                    .iter()
+                    .map(|x| x.track_points.len() as u64)
+                    .sum(),
+                journey_vector.track_segments.len() as u64,
+                journey_vector
+                    .track_segments
+                    .iter()
+                    .map(|x| {
+                        distance_of_segment_in_m(x.track_points.iter().map(|x| Point {
+                            latitude: x.latitude,
+                            longitude: x.longitude,
+                        }))
+                    })
+                    .sum(),
+            ),
+            JourneyData::Bitmap(_) => bail!("Unexpected bitmap data"),
+        };
+        let (map_renderer_proxy, camera_option) =
+            api::get_map_renderer_proxy_for_journey_data_internal(journey_data)?;
+        preprocessors.push(ImportPreprocessorPreview {
+            import_preprocessor,
+            point_count: processed_point_count,
+            segment_count,
+            distance_m,
+            dropped_point_count: point_count.saturating_sub(processed_point_count),
+            map_renderer_proxy,
+            camera_option,
+        });
+    }
+
+    Ok(ImportPreview {
+        point_count,
+        segment_count: vector_data.data.len() as u64,
+        distance_m: vector_data
+            .data
+            .iter()
+            .map(|x| distance_of_segment_in_m(x.iter().map(|x| x.point.clone())))
+            .sum(),
+        bounding_box,
+        start_time: journey_info.start_time,
+        end_time: journey_info.end_time,
+        transport_mode: journey_info.transport_mode,
+        segment_transport_modes: import_data::timed_segments(&vector_data.data)
+            .iter()
+            .map(|x| transport_mode::classify(x))
+            .collect(),
+        recommendation: preprocessor_detector::recommend_preprocessor(&vector_data.data),
+        preprocessors,
+    })
+}
+
+/// Analyze the loaded data and recommend a preprocessor, with the confidence
+/// and the reasons behind it.
+pub fn recommend_import_preprocessor(vector_data: &RawVectorData) -> PreprocessorRecommendation {
+    preprocessor_detector::recommend_preprocessor(&vector_data.data)
+}
+
 #[auto_context]
 pub fn is_journey_data_empty(journey_data: &JourneyData) -> bool {
     match journey_data {
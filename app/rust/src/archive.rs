@@ -5,6 +5,7 @@ use hex::ToHex;
 use integer_encoding::*;
 use protobuf::{EnumOrUnknown, Message};
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
 use std::{
     cmp::Ordering,
     collections::HashMap,
@@ -13,9 +14,11 @@ use std::{
 };
 
 use crate::{
+    duplicate_detector::{self, DuplicateJourneyPair},
     journey_data::{self, JourneyData},
     journey_header::JourneyHeader,
     main_db,
+    privacy_zone::{self, PrivacyZone},
     protos::archive::{metadata, Metadata, SectionHeader},
 };
 
@@ -32,16 +35,19 @@ use crate::{
 const METADATA_MAGIC_HEADER: [u8; 3] = [b'M', b'L', b'M'];
 const SECTION_MAGIC_HEADER: [u8; 3] = [b'M', b'L', b'S'];
 
+// https://cheatsheetseries.owasp.org/cheatsheets/Password_Storage_Cheat_Sheet.html
+const KDF_ITERATIONS: u32 = 600_000;
+// Leaves room for raising `KDF_ITERATIONS` later, but a crafted archive can't
+// make the import hang.
+const MAX_KDF_ITERATIONS: u32 = 10 * KDF_ITERATIONS;
+const KDF_SALT_LEN: usize = 16;
+
 // TODO: support incremetnal archiving by loading the previous metadata, we need
 // this for syncing.
 
 // TODO: support archive/export a seleted set of journeys instead of everything.
 
-// TODO: consider return more detail about this import: e.g. how many journeys
-// are added, how many are skipped.
-#[auto_context]
-pub fn import_mldx(txn: &mut main_db::Txn, mldx_file: &str) -> Result<()> {
-    let mut zip = zip::ZipArchive::new(File::open(mldx_file)?)?;
+fn read_metadata<R: Read + Seek>(zip: &mut zip::ZipArchive<R>) -> Result<Metadata> {
     let mut file = zip.by_name("metadata.xxm")?;
     let mut magic_header: [u8; 3] = [0; 3];
     file.read_exact(&mut magic_header)?;
@@ -58,10 +64,103 @@ pub fn import_mldx(txn: &mut main_db::Txn, mldx_file: &str) -> Result<()> {
     let len: u64 = file.read_varint()?;
     let mut decoder = zstd::Decoder::new(file.take(len))?;
     let metadata_proto: Metadata = Message::parse_from_reader(&mut decoder)?;
-    drop(decoder);
+    Ok(metadata_proto)
+}
+
+// The derived key is used as the password of zip AES encryption.
+fn derive_section_password(encryption: &metadata::Encryption, passphrase: &str) -> Result<String> {
+    match encryption.kdf.enum_value() {
+        std::result::Result::Ok(metadata::encryption::Kdf::PBKDF2_HMAC_SHA256) => (),
+        Err(kdf) => bail!("Unsupported kdf: {kdf}"),
+    }
+    if encryption.kdf_iterations == 0 || encryption.kdf_iterations > MAX_KDF_ITERATIONS {
+        bail!("Invalid kdf iterations: {}", encryption.kdf_iterations);
+    }
+    let key = pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(
+        passphrase.as_bytes(),
+        &encryption.salt,
+        encryption.kdf_iterations,
+    );
+    Ok(key.encode_hex())
+}
+
+// The metadata is not authenticated, so the section files are checked as well.
+fn has_encrypted_section<R: Read + Seek>(
+    zip: &mut zip::ZipArchive<R>,
+    metadata: &Metadata,
+) -> Result<bool> {
+    for section_info in &metadata.section_infos {
+        if let Some(index) = zip.index_for_name(&section_info.section_id) {
+            if zip.by_index_raw(index)?.encrypted() {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Whether `import_mldx` needs a passphrase for this archive.
+#[auto_context]
+pub fn mldx_requires_passphrase(mldx_file: &str) -> Result<bool> {
+    let mut zip = zip::ZipArchive::new(File::open(mldx_file)?)?;
+    let metadata_proto = read_metadata(&mut zip)?;
+    Ok(metadata_proto.encryption.is_some() || has_encrypted_section(&mut zip, &metadata_proto)?)
+}
+
+// TODO: consider return more detail about this import: e.g. how many journeys
+// are added, how many are skipped.
+/// Returns journeys in the archive that are likely duplicates of other journeys.
+/// With a `passphrase`, the archive must be encrypted: the metadata is not
+/// authenticated, so a plaintext archive can't be told apart from an encrypted
+/// one with its sections replaced.
+#[auto_context]
+pub fn import_mldx(
+    txn: &mut main_db::Txn,
+    mldx_file: &str,
+    passphrase: Option<&str>,
+) -> Result<Vec<DuplicateJourneyPair>> {
+    let mut zip = zip::ZipArchive::new(File::open(mldx_file)?)?;
+    let metadata_proto = read_metadata(&mut zip)?;
+    let section_password = match metadata_proto.encryption.as_ref() {
+        None => {
+            if passphrase.is_some() || has_encrypted_section(&mut zip, &metadata_proto)? {
+                bail!("The archive is expected to be encrypted but it is not");
+            }
+            None
+        }
+        Some(encryption) => {
+            let passphrase = passphrase
+                .ok_or_else(|| anyhow!("The archive is encrypted, a passphrase is required"))?;
+            Some(derive_section_password(encryption, passphrase)?)
+        }
+    };
 
+    let mut imported_journey_ids = Vec::new();
     for section_info in metadata_proto.section_infos {
-        let mut file = zip.by_name(&section_info.section_id)?;
+        // Reading the whole file also verifies the authentication code of
+        // encrypted files.
+        let mut buf = Vec::new();
+        let index = zip
+            .index_for_name(&section_info.section_id)
+            .with_context(|| format!("Missing section: {}", section_info.section_id))?;
+        match &section_password {
+            None => zip.by_index(index)?.read_to_end(&mut buf)?,
+            Some(password) => {
+                // Only AES is authenticated, other sections are returned as
+                // is by the zip reader.
+                if zip.get_aes_verification_key_and_salt(index)?.is_none() {
+                    bail!(
+                        "Section {} of the encrypted archive is not AES encrypted",
+                        section_info.section_id
+                    );
+                }
+                match zip.by_index_decrypt(index, password.as_bytes()) {
+                    Err(zip::result::ZipError::InvalidPassword) => bail!("Incorrect passphrase"),
+                    result => result?.read_to_end(&mut buf)?,
+                }
+            }
+        };
+        let mut file = buf.as_slice();
         let mut magic_header: [u8; 3] = [0; 3];
         file.read_exact(&mut magic_header)?;
         if magic_header != SECTION_MAGIC_HEADER {
@@ -86,10 +185,26 @@ pub fn import_mldx(txn: &mut main_db::Txn, mldx_file: &str) -> Result<()> {
             let journey_header = JourneyHeader::of_proto(header)?;
             let journey_data =
                 JourneyData::deserialize(buf.as_slice(), journey_header.journey_type)?;
+            if txn.get_journey_header(&journey_header.id)?.is_none() {
+                imported_journey_ids.push(journey_header.id.clone());
+            }
             txn.insert_journey(journey_header, journey_data)?;
         }
     }
-    Ok(())
+
+    let mut duplicates: Vec<DuplicateJourneyPair> = Vec::new();
+    for journey_id in imported_journey_ids {
+        for pair in duplicate_detector::find_duplicates_of(txn, &journey_id)? {
+            // two journeys in the same archive could be duplicates of each other
+            if !duplicates
+                .iter()
+                .any(|x| x.journey_id == pair.duplicate_of && x.duplicate_of == pair.journey_id)
+            {
+                duplicates.push(pair);
+            }
+        }
+    }
+    Ok(duplicates)
 }
 
 // TODO: support conflict resolvation by asking user what to do.
@@ -127,10 +242,14 @@ pub enum WhatToExport {
     Just(String),
 }
 
+/// Data inside `privacy_zones` is removed from the exported journeys, see
+/// `privacy_zone`. If `passphrase` is set, all journey data is encrypted.
 #[auto_context]
 pub fn export_as_mldx<T: Write + Seek>(
     what_to_export: &WhatToExport,
     txn: &main_db::Txn,
+    privacy_zones: &[PrivacyZone],
+    passphrase: Option<&str>,
     writer: &mut T,
 ) -> Result<()> {
     let journey_to_export = match what_to_export {
@@ -194,6 +313,25 @@ pub fn export_as_mldx<T: Write + Seek>(
     metadata_proto.created_at_timestamp_sec = Utc::now().timestamp();
     metadata_proto.kind = Some(EnumOrUnknown::new(metadata::Kind::FULL_ARCHIVE));
     metadata_proto.note = None;
+    let section_password = match passphrase {
+        None => None,
+        Some(passphrase) => {
+            if passphrase.is_empty() {
+                bail!("The passphrase is empty");
+            }
+            let mut encryption = metadata::Encryption::new();
+            encryption.kdf = EnumOrUnknown::new(metadata::encryption::Kdf::PBKDF2_HMAC_SHA256);
+            encryption.kdf_iterations = KDF_ITERATIONS;
+            encryption.salt = rand::random::<[u8; KDF_SALT_LEN]>().to_vec();
+            let password = derive_section_password(&encryption, passphrase)?;
+            metadata_proto.encryption = Some(encryption).into();
+            Some(password)
+        }
+    };
+    let section_options = match &section_password {
+        None => default_options,
+        Some(password) => default_options.with_aes_encryption(zip::AesMode::Aes256, password),
+    };
     for (_, section_id, journeys) in &to_process {
         let mut section_info = metadata::SectionInfo::new();
         section_info.section_id.clone_from(section_id);
@@ -218,7 +356,7 @@ pub fn export_as_mldx<T: Write + Seek>(
             section_header.journey_headers.push(j.clone().to_proto());
         }
 
-        zip.start_file(section_id.clone(), default_options)?;
+        zip.start_file(section_id.clone(), section_options)?;
         zip.write_all(&SECTION_MAGIC_HEADER)?;
         // version num
         zip.write_all(&[1])?;
@@ -229,7 +367,8 @@ pub fn export_as_mldx<T: Write + Seek>(
         for j in journeys {
             // TODO: maybe we want to just take the bytes from db without doing
             // a roundtrip.
-            let journey_data = txn.get_journey_data(&j.id)?;
+            let journey_data =
+                privacy_zone::apply_to_journey_data(privacy_zones, txn.get_journey_data(&j.id)?);
             let mut buf = Vec::new();
             journey_data.serialize(&mut buf)?;
             write_bytes_with_size_header(&mut zip, &buf)?;
@@ -242,7 +381,10 @@ pub fn export_as_mldx<T: Write + Seek>(
 
 #[cfg(test)]
 mod tests {
-    use crate::archive::YearMonth;
+    use crate::archive::{
+        derive_section_password, metadata, YearMonth, KDF_ITERATIONS, MAX_KDF_ITERATIONS,
+    };
+    use protobuf::EnumOrUnknown;
 
     #[test]
     fn order() {
@@ -251,4 +393,19 @@ mod tests {
         assert!(ym(2000, 10) > ym(2000, 9));
         assert!(ym(1999, 12) < ym(2000, 9));
     }
+
+    #[test]
+    fn kdf_iterations() {
+        let derive = |kdf_iterations| {
+            let mut encryption = metadata::Encryption::new();
+            encryption.kdf = EnumOrUnknown::new(metadata::encryption::Kdf::PBKDF2_HMAC_SHA256);
+            encryption.kdf_iterations = kdf_iterations;
+            encryption.salt = vec![0; 16];
+            derive_section_password(&encryption, "correct horse")
+        };
+        assert!(derive(KDF_ITERATIONS).is_ok());
+        assert!(derive(0).is_err());
+        assert!(derive(MAX_KDF_ITERATIONS + 1).is_err());
+        assert!(derive(u32::MAX).is_err());
+    }
 }
@@ -0,0 +1,219 @@
+/* When to finalize the ongoing journey automatically.
+
+We only finalize when there is no new data for a while (the "gap"), and the
+required gap depends on the situation:
+- Recording for too long: finalize (almost) right away, see
+  `recording_length_rules`.
+- Stationary at a known place (see `places`), e.g. back at home: the user is
+  unlikely to continue the journey.
+- Otherwise it depends on whether the local day changed since the start of
+  the recording. The day starts at `day_start_hour` so people who are active
+  around midnight (e.g. night shifts) don't get their journeys split in the
+  middle. Right after the day changed, or for a short recording, we are more
+  patient.
+
+The policy is stored as a setting, the default one is what we had before it
+became configurable.
+*/
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use auto_context::auto_context;
+use chrono::{DateTime, Duration, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordingLengthRule {
+    pub min_recording_length_hours: u32,
+    pub required_gap_mins: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AutoFinalizePolicy {
+    /// The first matching rule wins, so they should be ordered by
+    /// `min_recording_length_hours` descending.
+    pub recording_length_rules: Vec<RecordingLengthRule>,
+    /// `false` means we never consider the day changed, only
+    /// `same_day_gap_mins` applies.
+    pub split_at_local_midnight: bool,
+    /// In local time, 0 means the day starts at midnight.
+    pub day_start_hour: u32,
+    pub same_day_gap_mins: u32,
+    pub day_changed_gap_mins: u32,
+    /// Used instead of `day_changed_gap_mins` within the first
+    /// `day_changed_patient_hours` hours of the new day, or when the recording
+    /// is not longer than `short_recording_hours`.
+    pub day_changed_patient_gap_mins: u32,
+    pub day_changed_patient_hours: u32,
+    pub short_recording_hours: u32,
+    /// `None` to disable.
+    pub stationary_at_known_place_mins: Option<u32>,
+}
+
+impl Default for AutoFinalizePolicy {
+    fn default() -> Self {
+        Self {
+            recording_length_rules: vec![
+                RecordingLengthRule {
+                    min_recording_length_hours: 48,
+                    required_gap_mins: 0,
+                },
+                RecordingLengthRule {
+                    min_recording_length_hours: 24,
+                    required_gap_mins: 2,
+                },
+            ],
+            split_at_local_midnight: true,
+            day_start_hour: 0,
+            same_day_gap_mins: 6 * 60,
+            day_changed_gap_mins: 5,
+            day_changed_patient_gap_mins: 20,
+            day_changed_patient_hours: 5,
+            short_recording_hours: 8,
+            stationary_at_known_place_mins: None,
+        }
+    }
+}
+
+impl AutoFinalizePolicy {
+    #[auto_context]
+    pub fn validate(&self) -> Result<()> {
+        if self.day_start_hour >= 24 {
+            bail!("`day_start_hour` must be less than 24");
+        }
+        if self.day_changed_patient_hours > 24 {
+            bail!("`day_changed_patient_hours` must not be more than 24");
+        }
+        if !self
+            .recording_length_rules
+            .windows(2)
+            .all(|x| x[0].min_recording_length_hours > x[1].min_recording_length_hours)
+        {
+            bail!(
+                "Recording length rules must be ordered by `min_recording_length_hours` descending"
+            );
+        }
+        Ok(())
+    }
+}
+
+// Stored as JSON in settings.
+impl FromStr for AutoFinalizePolicy {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl fmt::Display for AutoFinalizePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(self).map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoFinalizeReason {
+    NoOngoingJourney,
+    /// A manual journey is only finalized on demand.
+    ManualJourney,
+    RecordingLength,
+    StationaryAtKnownPlace,
+    SameDay,
+    DayChanged,
+    DayChangedPatient,
+}
+
+/// What `try_auto_finalize_journey` would do (or did) and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoFinalizeExplanation {
+    pub reason: AutoFinalizeReason,
+    pub recording_length_mins: i64,
+    pub gap_mins: i64,
+    pub required_gap_mins: Option<i64>,
+    pub finalize: bool,
+}
+
+impl AutoFinalizeExplanation {
+    pub fn no_ongoing_journey() -> Self {
+        Self {
+            reason: AutoFinalizeReason::NoOngoingJourney,
+            recording_length_mins: 0,
+            gap_mins: 0,
+            required_gap_mins: None,
+            finalize: false,
+        }
+    }
+
+    pub fn manual_journey() -> Self {
+        Self {
+            reason: AutoFinalizeReason::ManualJourney,
+            ..Self::no_ongoing_journey()
+        }
+    }
+}
+
+/// `start` and `end` are the time range of the ongoing journey, the local day
+/// is based on the timezone of `now`. `at_known_place` is whether the last
+/// point of the ongoing journey is at a known place.
+pub fn explain<Tz: TimeZone>(
+    policy: &AutoFinalizePolicy,
+    now: &DateTime<Tz>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    at_known_place: bool,
+) -> AutoFinalizeExplanation {
+    let recording_length_mins = (now.timestamp() - start.timestamp()) / 60;
+    let recording_length_hours = recording_length_mins / 60;
+    let gap_mins = (now.timestamp() - end.timestamp()).max(0) / 60;
+
+    let (reason, required_gap_mins) = match policy
+        .recording_length_rules
+        .iter()
+        .find(|x| recording_length_hours >= x.min_recording_length_hours as i64)
+    {
+        Some(rule) => (
+            AutoFinalizeReason::RecordingLength,
+            rule.required_gap_mins as i64,
+        ),
+        None => {
+            // shifting the time so the day starts at 0 o'clock
+            let day_start = Duration::hours(policy.day_start_hour as i64);
+            let shifted_now = now.clone() - day_start;
+            let same_day = !policy.split_at_local_midnight
+                || (start.with_timezone(&now.timezone()) - day_start).date_naive()
+                    == shifted_now.date_naive();
+            let by_day = if same_day {
+                (AutoFinalizeReason::SameDay, policy.same_day_gap_mins)
+            } else if shifted_now.hour() < policy.day_changed_patient_hours
+                || recording_length_hours <= policy.short_recording_hours as i64
+            {
+                (
+                    AutoFinalizeReason::DayChangedPatient,
+                    policy.day_changed_patient_gap_mins,
+                )
+            } else {
+                (AutoFinalizeReason::DayChanged, policy.day_changed_gap_mins)
+            };
+            match policy.stationary_at_known_place_mins {
+                Some(mins) if at_known_place && mins < by_day.1 => {
+                    (AutoFinalizeReason::StationaryAtKnownPlace, mins as i64)
+                }
+                _ => (by_day.0, by_day.1 as i64),
+            }
+        }
+    };
+
+    AutoFinalizeExplanation {
+        reason,
+        recording_length_mins,
+        gap_mins,
+        required_gap_mins: Some(required_gap_mins),
+        finalize: gap_mins >= required_gap_mins,
+    }
+}
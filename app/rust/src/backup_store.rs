@@ -0,0 +1,254 @@
+/* Automatic local backups, so a corrupted main db or a bad bulk delete is not
+a permanent data loss.
+
+A backup is a full MLDX archive (see `archive`) named `backup-{local time}.mldx`
+in the `backups` dir. `Storage::maintain_backups` is called periodically and
+creates a new backup if there is none for the current local date yet, then old
+backups are rotated based on `BackupPolicy`: we keep the newest backup of each
+of the last `keep_daily` days, `keep_weekly` ISO weeks and `keep_monthly`
+months (a backup can count for all of them), plus the newest one overall.
+
+Backups only have finalized journeys, and ignore privacy zones so all of them
+can be restored. When the main db is encrypted, backups are encrypted as well
+(see `db_encryption`). Backups that don't match the current key are deleted
+once a backup with the current key is written: all of them when the key is
+rotated, and plaintext ones when an encrypted db is opened.
+
+The export reads a snapshot of the main db, so recording is not blocked while
+the archive is written. A corrupted main db can't be opened at all, so
+`Storage::restore_latest_backup_before_init` restores the newest backup before
+`Storage` is created. The app asks the user first, as everything else in the
+main db is lost.
+*/
+use anyhow::Result;
+use auto_context::auto_context;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const FILE_NAME_PREFIX: &str = "backup-";
+const MLDX_SUFFIX: &str = ".mldx";
+const TEMP_SUFFIX: &str = ".tmp";
+const TIME_FORMAT: &str = "%Y-%m-%d-%H%M%S";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[frb(non_opaque)]
+pub struct BackupPolicy {
+    pub enabled: bool,
+    pub keep_daily: u32,
+    pub keep_weekly: u32,
+    pub keep_monthly: u32,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            // at most 5 backups since the newest one counts for all, each one is a
+            // full archive
+            keep_daily: 3,
+            keep_weekly: 2,
+            keep_monthly: 2,
+        }
+    }
+}
+
+// Stored as JSON in settings.
+impl FromStr for BackupPolicy {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl fmt::Display for BackupPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(self).map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupEntry {
+    /// File name without the extension.
+    pub name: String,
+    pub path: PathBuf,
+    /// In local time.
+    pub created_at: NaiveDateTime,
+    pub size_bytes: u64,
+}
+
+fn created_at_of_name(name: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(name.strip_prefix(FILE_NAME_PREFIX)?, TIME_FORMAT).ok()
+}
+
+/// Names of the backups kept by `policy`, `entries` must be newest first.
+fn names_to_keep<'a>(entries: &'a [BackupEntry], policy: &BackupPolicy) -> HashSet<&'a str> {
+    let mut keep = HashSet::new();
+    if let Some(newest) = entries.first() {
+        keep.insert(newest.name.as_str());
+    }
+    let mut keep_newest_per_bucket = |count: u32, bucket: &dyn Fn(NaiveDate) -> (i32, u32)| {
+        let mut last_bucket = None;
+        let mut kept = 0;
+        for entry in entries {
+            if kept >= count {
+                break;
+            }
+            let bucket = bucket(entry.created_at.date());
+            if last_bucket != Some(bucket) {
+                last_bucket = Some(bucket);
+                keep.insert(entry.name.as_str());
+                kept += 1;
+            }
+        }
+    };
+    keep_newest_per_bucket(policy.keep_daily, &|date| (date.year(), date.ordinal()));
+    keep_newest_per_bucket(policy.keep_weekly, &|date| {
+        let week = date.iso_week();
+        (week.year(), week.week())
+    });
+    keep_newest_per_bucket(policy.keep_monthly, &|date| (date.year(), date.month()));
+    keep
+}
+
+pub struct BackupStore {
+    dir: PathBuf,
+}
+
+impl BackupStore {
+    pub fn new(support_dir: &str) -> Self {
+        BackupStore {
+            dir: Path::new(support_dir).join("backups"),
+        }
+    }
+
+    /// Newest first.
+    #[auto_context]
+    pub fn list(&self) -> Result<Vec<BackupEntry>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        if !self.dir.is_dir() {
+            bail!("backups path exists but is not a directory: {:?}", self.dir);
+        }
+
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let dir_entry = dir_entry?;
+            let path = dir_entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Some(name) = path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .and_then(|x| x.strip_suffix(MLDX_SUFFIX))
+            else {
+                continue;
+            };
+            let Some(created_at) = created_at_of_name(name) else {
+                continue;
+            };
+            entries.push(BackupEntry {
+                name: name.to_string(),
+                created_at,
+                path,
+                size_bytes: dir_entry.metadata()?.len(),
+            });
+        }
+        entries.sort_by_key(|x| std::cmp::Reverse(x.created_at));
+        Ok(entries)
+    }
+
+    #[auto_context]
+    pub fn path_of(&self, name: &str) -> Result<PathBuf> {
+        let path = self.dir.join(format!("{name}{MLDX_SUFFIX}"));
+        if created_at_of_name(name).is_none() || !path.is_file() {
+            bail!("backup not found: {name}");
+        }
+        Ok(path)
+    }
+
+    /// Creates a backup named after `created_at` with the content written by
+    /// `write`. The file only shows up in `list` once `write` succeeded.
+    #[auto_context]
+    pub fn create<F>(&self, created_at: NaiveDateTime, write: F) -> Result<BackupEntry>
+    where
+        F: FnOnce(&mut File) -> Result<()>,
+    {
+        // the name only has second precision
+        let created_at = created_at.with_nanosecond(0).unwrap_or(created_at);
+        fs::create_dir_all(&self.dir)?;
+        let name = format!("{FILE_NAME_PREFIX}{}", created_at.format(TIME_FORMAT));
+        let path = self.dir.join(format!("{name}{MLDX_SUFFIX}"));
+        if path.exists() {
+            bail!("backup already exists: {name}");
+        }
+        let temp_path = self.dir.join(format!("{name}{MLDX_SUFFIX}{TEMP_SUFFIX}"));
+
+        let result = (|| -> Result<u64> {
+            let mut file = File::create(&temp_path)?;
+            write(&mut file)?;
+            file.sync_all()?;
+            Ok(file.metadata()?.len())
+        })();
+        let size_bytes = match result {
+            Ok(size_bytes) => size_bytes,
+            Err(error) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(error);
+            }
+        };
+        fs::rename(&temp_path, &path)?;
+        Ok(BackupEntry {
+            name,
+            path,
+            created_at,
+            size_bytes,
+        })
+    }
+
+    #[auto_context]
+    pub fn delete(&self, name: &str) -> Result<()> {
+        info!("[backup_store] deleting {name}");
+        fs::remove_file(self.path_of(name)?)?;
+        Ok(())
+    }
+
+    /// Deletes backups that are not kept by `policy` and the partial output
+    /// of interrupted backups. Returns the names of the deleted backups.
+    #[auto_context]
+    pub fn apply_rotation(&self, policy: &BackupPolicy) -> Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.to_str().is_some_and(|x| x.ends_with(TEMP_SUFFIX)) {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        let entries = self.list()?;
+        let keep = names_to_keep(&entries, policy);
+        let mut deleted_names = Vec::new();
+        for entry in &entries {
+            if !keep.contains(entry.name.as_str()) {
+                info!("[backup_store] deleting {} for rotation", entry.name);
+                fs::remove_file(&entry.path)?;
+                deleted_names.push(entry.name.clone());
+            }
+        }
+        Ok(deleted_names)
+    }
+}
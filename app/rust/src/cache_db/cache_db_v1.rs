@@ -9,13 +9,17 @@ use super::{CacheDb, CacheEntry, LayerKind};
 use strum::IntoEnumIterator;
 
 use crate::{
-    journey_bitmap::JourneyBitmap, journey_data, journey_data::JourneyData,
-    journey_header::JourneyKind, main_db, utils,
+    db_encryption::{self, DbCipher},
+    journey_bitmap::JourneyBitmap,
+    journey_data,
+    journey_data::JourneyData,
+    journey_header::JourneyKind,
+    main_db, utils,
 };
 
 const TABLE_FULL: &str = "journey_cache__full";
 
-fn open_db(cache_dir: &str, file_name: &str) -> Result<Connection> {
+fn open_db(cache_dir: &str, file_name: &str, cipher: Option<&DbCipher>) -> Result<Connection> {
     debug!("opening cache db for {file_name}");
     let mut conn = Connection::open(Path::new(cache_dir).join(file_name))?;
 
@@ -45,22 +49,46 @@ fn open_db(cache_dir: &str, file_name: &str) -> Result<Connection> {
             utils::db::set_version_in_metadata(&tx, target_version)?;
         }
     }
+
+    // The cache can always be rebuilt, so just drop it if the key is changed.
+    let key_check = utils::db::get_metadata(&tx, db_encryption::KEY_CHECK_METADATA_KEY)?;
+    let key_changed = !db_encryption::matches_key_check(cipher, key_check.as_deref());
+    if key_changed {
+        info!("The key of the cache db is changed, clearing the cache");
+        tx.execute(&format!("DELETE FROM `{TABLE_FULL}`;"), ())?;
+        utils::db::set_metadata(
+            &tx,
+            db_encryption::KEY_CHECK_METADATA_KEY,
+            db_encryption::key_check(cipher)?.as_deref(),
+        )?;
+    }
     tx.commit()?;
+    if key_changed {
+        conn.execute("VACUUM;", ())?;
+    }
     Ok(conn)
 }
 
+fn layer_kind_aad(layer_kind: &LayerKind) -> Vec<u8> {
+    format!("cache.{}", layer_kind.to_sql()).into_bytes()
+}
+
 fn query_bitmap(
     conn: &Connection,
+    cipher: Option<&DbCipher>,
+    aad: &[u8],
     sql: &str,
     params: impl rusqlite::Params,
 ) -> Result<Option<JourneyBitmap>> {
     let mut stmt = conn.prepare(sql)?;
-    stmt.query_row(params, |row| {
-        let data = row.get_ref(0)?.as_blob()?;
-        Ok(journey_data::deserialize_journey_bitmap(data))
-    })
-    .optional()?
-    .transpose()
+    let data: Option<Vec<u8>> = stmt.query_row(params, |row| row.get(0)).optional()?;
+    match data {
+        None => Ok(None),
+        Some(data) => {
+            let data = db_encryption::decrypt_if_enabled(cipher, &data, aad)?;
+            Ok(Some(journey_data::deserialize_journey_bitmap(data.as_ref())?))
+        }
+    }
 }
 
 fn serialize_bitmap(bitmap: &JourneyBitmap) -> Result<Vec<u8>> {
@@ -97,31 +125,41 @@ fn compute_range_from_txn(
 ///
 /// Only full-range queries (`from: None, to: None`) are cached. Explicit date
 /// range queries are always computed directly from the main DB without caching.
+///
+/// Cached bitmaps are encrypted if a cipher is provided, see `db_encryption`.
 pub struct CacheDbV1 {
     conn: Connection,
+    cipher: Option<DbCipher>,
 }
 
 impl CacheDbV1 {
     pub fn open(cache_dir: &str) -> CacheDbV1 {
-        let conn = open_db(cache_dir, "cache.db").expect("failed to open cache db");
-        CacheDbV1 { conn }
+        CacheDbV1::open_with_cipher(cache_dir, None)
+    }
+
+    pub fn open_with_cipher(cache_dir: &str, cipher: Option<DbCipher>) -> CacheDbV1 {
+        let conn =
+            open_db(cache_dir, "cache.db", cipher.as_ref()).expect("failed to open cache db");
+        CacheDbV1 { conn, cipher }
     }
 
-    fn get_full(conn: &Connection, layer_kind: &LayerKind) -> Result<Option<JourneyBitmap>> {
+    fn get_full(&self, layer_kind: &LayerKind) -> Result<Option<JourneyBitmap>> {
         query_bitmap(
-            conn,
+            &self.conn,
+            self.cipher.as_ref(),
+            &layer_kind_aad(layer_kind),
             &format!("SELECT data FROM `{TABLE_FULL}` WHERE kind = ?1;"),
             (layer_kind.to_sql(),),
         )
     }
 
-    fn set_full(
-        conn: &Connection,
-        layer_kind: &LayerKind,
-        journey_bitmap: &JourneyBitmap,
-    ) -> Result<()> {
-        let data = serialize_bitmap(journey_bitmap)?;
-        conn.execute(
+    fn set_full(&self, layer_kind: &LayerKind, journey_bitmap: &JourneyBitmap) -> Result<()> {
+        let data = db_encryption::encrypt_if_enabled(
+            self.cipher.as_ref(),
+            serialize_bitmap(journey_bitmap)?,
+            &layer_kind_aad(layer_kind),
+        )?;
+        self.conn.execute(
             &format!("INSERT OR REPLACE INTO `{TABLE_FULL}` (kind, data) VALUES (?1, ?2)"),
             (layer_kind.to_sql(), &data),
         )?;
@@ -154,7 +192,7 @@ impl CacheDb for CacheDbV1 {
             }
             (None, None) => {
                 // Full range: use cache.
-                if let Some(bm) = Self::get_full(&self.conn, layer_kind)? {
+                if let Some(bm) = self.get_full(layer_kind)? {
                     return Ok(bm);
                 }
 
@@ -180,7 +218,7 @@ impl CacheDb for CacheDbV1 {
                     }
                 };
 
-                Self::set_full(&self.conn, layer_kind, &result)?;
+                self.set_full(layer_kind, &result)?;
                 Ok(result)
             }
             _ => bail!("from and to must both be Some or both be None"),
@@ -195,9 +233,9 @@ impl CacheDb for CacheDbV1 {
         Self::delete_full(&self.conn, &LayerKind::All)?;
 
         // Merge into the per-kind full cache if it exists.
-        if let Some(mut bm) = Self::get_full(&self.conn, &layer_kind)? {
+        if let Some(mut bm) = self.get_full(&layer_kind)? {
             data.merge_into_with_partial_clone(&mut bm);
-            Self::set_full(&self.conn, &layer_kind, &bm)?;
+            self.set_full(&layer_kind, &bm)?;
         }
 
         Ok(())
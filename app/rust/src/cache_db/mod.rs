@@ -1,7 +1,8 @@
 use chrono::NaiveDate;
 
 use crate::{
-    journey_bitmap::JourneyBitmap, journey_data::JourneyData, journey_header::JourneyKind, main_db,
+    db_encryption::DbCipher, journey_bitmap::JourneyBitmap, journey_data::JourneyData,
+    journey_header::JourneyKind, main_db,
 };
 
 mod cache_db_v1;
@@ -67,6 +68,6 @@ pub trait CacheDb {
     // to improve UX after add/edit/delete large amount of data.
 }
 
-pub fn new(cache_dir: &str) -> impl CacheDb {
-    CacheDbV1::open(cache_dir)
+pub fn new(cache_dir: &str, cipher: Option<DbCipher>) -> impl CacheDb {
+    CacheDbV1::open_with_cipher(cache_dir, cipher)
 }
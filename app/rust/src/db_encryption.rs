@@ -0,0 +1,156 @@
+use anyhow::Result;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+/* Optional encryption at rest for location data in `main_db` and `cache_db`.
+   The key is provided by the app (e.g. from the platform keystore) and never
+   stored. Each value is encrypted separately with XChaCha20-Poly1305, the
+   random nonce is prepended to the ciphertext. The associated data ties a
+   value to where it is stored (e.g. the journey id), so values cannot be
+   swapped around.
+
+   Only the location data is encrypted: journey headers and data, points of
+   the ongoing journey, places, privacy zones (including their names) and
+   cached bitmaps. Journey fingerprints are keyed hashes instead. Columns used for indexing or ordering are kept as is, e.g.
+   `journey_date`, `timestamp_sec` and the time of place visits, so it is
+   visible when the user was recording or staying somewhere, but not where.
+
+   Local backups (see `backup_store`) are not db files, they are MLDX archives
+   encrypted with `DbCipher::backup_passphrase`, which is derived from the key.
+*/
+
+pub const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+const BACKUP_PASSPHRASE_CONTEXT: &[u8] = b"MemoLanes backup passphrase";
+const FINGERPRINT_KEY_CONTEXT: &[u8] = b"MemoLanes journey fingerprint";
+
+const KEY_CHECK_PLAINTEXT: &[u8] = b"MemoLanes";
+const KEY_CHECK_AAD: &[u8] = b"key_check";
+pub const KEY_CHECK_METADATA_KEY: &str = "encryption_key_check";
+
+#[derive(Clone)]
+pub struct DbCipher {
+    cipher: XChaCha20Poly1305,
+    backup_passphrase: String,
+    fingerprint_key: [u8; KEY_LEN],
+}
+
+impl DbCipher {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        if key.len() != KEY_LEN {
+            bail!("Invalid key length, expect: {KEY_LEN}, got: {}", key.len());
+        }
+        let backup_passphrase = hex::encode(
+            Sha256::new()
+                .chain_update(BACKUP_PASSPHRASE_CONTEXT)
+                .chain_update(key)
+                .finalize(),
+        );
+        let fingerprint_key = Sha256::new()
+            .chain_update(FINGERPRINT_KEY_CONTEXT)
+            .chain_update(key)
+            .finalize()
+            .into();
+        Ok(DbCipher {
+            cipher: XChaCha20Poly1305::new_from_slice(key)?,
+            backup_passphrase,
+            fingerprint_key,
+        })
+    }
+
+    /// Passphrase for MLDX backups of the encrypted data. It changes with the
+    /// key, so backups made before rotating the key can't be restored with
+    /// the new one.
+    pub fn backup_passphrase(&self) -> &str {
+        &self.backup_passphrase
+    }
+
+    /// Key of the journey fingerprints (see `duplicate_detector::fingerprint`).
+    pub fn fingerprint_key(&self) -> &[u8] {
+        &self.fingerprint_key
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| anyhow!("Failed to encrypt"))?;
+        let mut output = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            bail!("Invalid encrypted data, length: {}", data.len());
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| anyhow!("Failed to decrypt, the key is wrong or the data is corrupted"))
+    }
+}
+
+/// The key check is stored alongside the encrypted data, so a wrong key is
+/// detected when opening the db instead of on the first read. `None` means
+/// the data is not encrypted.
+pub fn key_check(cipher: Option<&DbCipher>) -> Result<Option<String>> {
+    match cipher {
+        None => Ok(None),
+        Some(cipher) => Ok(Some(hex::encode(
+            cipher.encrypt(KEY_CHECK_PLAINTEXT, KEY_CHECK_AAD)?,
+        ))),
+    }
+}
+
+/// Whether data stored with `key_check` can be read with `cipher`.
+pub fn matches_key_check(cipher: Option<&DbCipher>, key_check: Option<&str>) -> bool {
+    match (cipher, key_check) {
+        (None, None) => true,
+        (Some(cipher), Some(key_check)) => hex::decode(key_check).is_ok_and(|key_check| {
+            cipher
+                .decrypt(&key_check, KEY_CHECK_AAD)
+                .is_ok_and(|x| x == KEY_CHECK_PLAINTEXT)
+        }),
+        _ => false,
+    }
+}
+
+/// Encrypts `data` if `cipher` is set, otherwise returns it as is.
+pub fn encrypt_if_enabled(cipher: Option<&DbCipher>, data: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
+    match cipher {
+        None => Ok(data),
+        Some(cipher) => cipher.encrypt(&data, aad),
+    }
+}
+
+/// Decrypts `data` if `cipher` is set, otherwise returns it as is.
+pub fn decrypt_if_enabled<'a>(
+    cipher: Option<&DbCipher>,
+    data: &'a [u8],
+    aad: &[u8],
+) -> Result<Cow<'a, [u8]>> {
+    match cipher {
+        None => Ok(Cow::Borrowed(data)),
+        Some(cipher) => Ok(Cow::Owned(cipher.decrypt(data, aad)?)),
+    }
+}
@@ -0,0 +1,300 @@
+/* Detecting journeys that are likely the same data stored more than once, e.g.
+the same GPX imported twice or an MLDX archive overlapping local data.
+
+There are two levels of checks:
+- Fingerprint: a hash of the normalized journey content. Journeys with the same
+  fingerprint are exact duplicates. It is stored in main db so the lookup is
+  cheap. When the db is encrypted it is an HMAC keyed by the db key, otherwise
+  a known track could be confirmed by its plain hash.
+- Similarity: a fuzzy check based on the overlap of time ranges and how much
+  the bitmaps of the two journeys intersect. This catches cases like the same
+  file imported with a different preprocessor.
+*/
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use auto_context::auto_context;
+use chrono::{DateTime, Duration, Utc};
+use flutter_rust_bridge::frb;
+use hex::ToHex;
+use hmac::{Hmac, Mac};
+use itertools::Itertools;
+use sha1::digest::Update;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::db_encryption::DbCipher;
+use crate::journey_bitmap::JourneyBitmap;
+use crate::journey_data::JourneyData;
+use crate::journey_header::JourneyHeader;
+use crate::main_db;
+
+// The bitmap of the smaller journey needs to be mostly covered by the other one.
+const MIN_BITMAP_INTERSECTION_RATIO: f64 = 0.8;
+// The shorter time range needs to be mostly covered by the other one.
+const MIN_TIME_RANGE_OVERLAP_RATIO: f64 = 0.5;
+// Roughly 0.1m, we don't want floating point noise to affect the fingerprint.
+const COORDINATE_PRECISION: f64 = 1e6;
+
+#[derive(Debug, Clone, PartialEq)]
+#[frb(non_opaque)]
+pub struct DuplicateJourneyPair {
+    pub journey_id: String,
+    pub duplicate_of: String,
+    /// `true` if the two journeys have exactly the same content.
+    pub exact_match: bool,
+    /// In [0, 1], how much the bitmap of the smaller journey is covered by the
+    /// other one.
+    pub similarity: f64,
+}
+
+// Returns `false` for empty journeys.
+fn hash_content<H: Update>(hasher: &mut H, journey_data: &JourneyData) -> bool {
+    match journey_data {
+        JourneyData::Vector(journey_vector) => {
+            if journey_vector
+                .track_segments
+                .iter()
+                .all(|x| x.track_points.is_empty())
+            {
+                return false;
+            }
+            hasher.update(b"V");
+            for track_segment in &journey_vector.track_segments {
+                hasher.update(&(track_segment.track_points.len() as u64).to_be_bytes());
+                for track_point in &track_segment.track_points {
+                    let normalize = |x: f64| (x * COORDINATE_PRECISION).round() as i64;
+                    hasher.update(&normalize(track_point.latitude).to_be_bytes());
+                    hasher.update(&normalize(track_point.longitude).to_be_bytes());
+                }
+            }
+        }
+        JourneyData::Bitmap(journey_bitmap) => {
+            if journey_bitmap.tiles.is_empty() {
+                return false;
+            }
+            hasher.update(b"B");
+            for (x, y) in journey_bitmap.tiles.keys().sorted() {
+                let tile = journey_bitmap.tiles.get(&(*x, *y)).unwrap();
+                hasher.update(&x.to_be_bytes());
+                hasher.update(&y.to_be_bytes());
+                for (block_key, block) in tile.iter() {
+                    hasher.update(&(block_key.index() as u16).to_be_bytes());
+                    hasher.update(&block.data);
+                }
+            }
+        }
+    }
+    true
+}
+
+/// `None` for empty journeys, they are not meaningful for duplicate detection.
+/// Keyed by `cipher` if the db is encrypted.
+pub fn fingerprint(journey_data: &JourneyData, cipher: Option<&DbCipher>) -> Option<String> {
+    match cipher {
+        None => {
+            let mut hasher = Sha1::new();
+            if !hash_content(&mut hasher, journey_data) {
+                return None;
+            }
+            Some(hasher.finalize().encode_hex::<String>())
+        }
+        Some(cipher) => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(cipher.fingerprint_key())
+                .expect("HMAC can take a key of any size");
+            if !hash_content(&mut mac, journey_data) {
+                return None;
+            }
+            Some(mac.finalize().into_bytes().encode_hex::<String>())
+        }
+    }
+}
+
+fn bit_count(journey_bitmap: &JourneyBitmap) -> u64 {
+    journey_bitmap
+        .tiles
+        .values()
+        .flat_map(|tile| tile.iter())
+        .map(|(_, block)| block.count() as u64)
+        .sum()
+}
+
+pub fn bitmap_intersection_ratio(a: &JourneyBitmap, b: &JourneyBitmap) -> f64 {
+    let smaller_count = bit_count(a).min(bit_count(b));
+    if smaller_count == 0 {
+        return 0.;
+    }
+    let mut intersection = a.clone();
+    intersection.intersection(b);
+    bit_count(&intersection) as f64 / smaller_count as f64
+}
+
+/// `None` if any of the two journeys does not have a complete time range.
+pub fn time_range_overlap_ratio(a: &JourneyHeader, b: &JourneyHeader) -> Option<f64> {
+    let (a_start, a_end) = a.start.zip(a.end)?;
+    let (b_start, b_end) = b.start.zip(b.end)?;
+    let overlap = a_end.min(b_end) - a_start.max(b_start);
+    if overlap < Duration::zero() {
+        return Some(0.);
+    }
+    let shorter = (a_end - a_start).min(b_end - b_start);
+    if shorter <= Duration::zero() {
+        // single point in time and it is covered by the other one
+        return Some(1.);
+    }
+    Some(overlap.num_seconds() as f64 / shorter.num_seconds().max(1) as f64)
+}
+
+fn time_range(header: &JourneyHeader) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    header.start.zip(header.end)
+}
+
+// Journeys without a time range (e.g. FoW data or KML without timestamps) can
+// only be compared with journeys of the same type that also lack it.
+fn should_compare(a: &JourneyHeader, b: &JourneyHeader) -> bool {
+    match time_range_overlap_ratio(a, b) {
+        Some(ratio) => ratio >= MIN_TIME_RANGE_OVERLAP_RATIO,
+        None => {
+            time_range(a).is_none() && time_range(b).is_none() && a.journey_type == b.journey_type
+        }
+    }
+}
+
+struct BitmapLoader<'a, 'b> {
+    txn: &'a main_db::Txn<'b>,
+    cache: HashMap<String, JourneyBitmap>,
+}
+
+impl<'a, 'b> BitmapLoader<'a, 'b> {
+    fn new(txn: &'a main_db::Txn<'b>) -> Self {
+        Self {
+            txn,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, journey_id: &str) -> Result<()> {
+        if !self.cache.contains_key(journey_id) {
+            let mut journey_bitmap = JourneyBitmap::new();
+            self.txn
+                .get_journey_data(journey_id)?
+                .merge_into(&mut journey_bitmap);
+            self.cache.insert(journey_id.to_string(), journey_bitmap);
+        }
+        Ok(())
+    }
+
+    fn similarity(&mut self, a: &str, b: &str) -> Result<f64> {
+        self.load(a)?;
+        self.load(b)?;
+        Ok(bitmap_intersection_ratio(&self.cache[a], &self.cache[b]))
+    }
+}
+
+/// Find existing journeys that are likely duplicates of the given journey.
+#[auto_context]
+pub fn find_duplicates_of(
+    txn: &main_db::Txn,
+    journey_id: &str,
+) -> Result<Vec<DuplicateJourneyPair>> {
+    let header = txn
+        .get_journey_header(journey_id)?
+        .ok_or_else(|| anyhow!("Failed to find journey, journey_id = {journey_id}"))?;
+
+    let mut results = Vec::new();
+    let mut seen = HashSet::new();
+    seen.insert(journey_id.to_string());
+
+    if let Some(fingerprint) = txn.get_journey_fingerprint(journey_id)? {
+        for other_id in txn.query_journey_ids_by_fingerprint(&fingerprint)? {
+            if seen.insert(other_id.clone()) {
+                results.push(DuplicateJourneyPair {
+                    journey_id: journey_id.to_string(),
+                    duplicate_of: other_id,
+                    exact_match: true,
+                    similarity: 1.,
+                });
+            }
+        }
+    }
+
+    // `journey_date` is picked in local time, so a real duplicate could be off by a day.
+    let candidates = match time_range(&header) {
+        Some(_) => txn.query_journeys(
+            header.journey_date.pred_opt(),
+            header.journey_date.succ_opt(),
+        )?,
+        None => txn.query_journeys(None, None)?,
+    };
+
+    let mut bitmap_loader = BitmapLoader::new(txn);
+    for candidate in candidates {
+        if seen.contains(&candidate.id) || !should_compare(&header, &candidate) {
+            continue;
+        }
+        let similarity = bitmap_loader.similarity(journey_id, &candidate.id)?;
+        if similarity >= MIN_BITMAP_INTERSECTION_RATIO {
+            seen.insert(candidate.id.clone());
+            results.push(DuplicateJourneyPair {
+                journey_id: journey_id.to_string(),
+                duplicate_of: candidate.id,
+                exact_match: false,
+                similarity,
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Find all pairs of journeys in main db that are likely duplicates. This
+/// could be slow as it needs to load the data of every journey that has a
+/// potential match.
+#[auto_context]
+pub fn find_all_duplicates(txn: &main_db::Txn) -> Result<Vec<DuplicateJourneyPair>> {
+    let mut results = Vec::new();
+    let mut seen_pairs = HashSet::new();
+
+    for journey_ids in txn.journey_ids_grouped_by_duplicated_fingerprint()? {
+        for (a, b) in journey_ids.iter().tuple_combinations() {
+            seen_pairs.insert((a.clone(), b.clone()));
+            results.push(DuplicateJourneyPair {
+                journey_id: a.clone(),
+                duplicate_of: b.clone(),
+                exact_match: true,
+                similarity: 1.,
+            });
+        }
+    }
+
+    // Sort by start time so we only need to look at journeys until the start
+    // time is passed the end time of the current one.
+    let mut headers = txn.query_journeys(None, None)?;
+    headers.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.id.cmp(&b.id)));
+
+    let mut bitmap_loader = BitmapLoader::new(txn);
+    for (i, a) in headers.iter().enumerate() {
+        for b in &headers[(i + 1)..] {
+            if let (Some((_, a_end)), Some((b_start, _))) = (time_range(a), time_range(b)) {
+                if b_start > a_end {
+                    break;
+                }
+            }
+            if seen_pairs.contains(&(a.id.clone(), b.id.clone()))
+                || seen_pairs.contains(&(b.id.clone(), a.id.clone()))
+                || !should_compare(a, b)
+            {
+                continue;
+            }
+            let similarity = bitmap_loader.similarity(&a.id, &b.id)?;
+            if similarity >= MIN_BITMAP_INTERSECTION_RATIO {
+                results.push(DuplicateJourneyPair {
+                    journey_id: a.id.clone(),
+                    duplicate_of: b.id.clone(),
+                    exact_match: false,
+                    similarity,
+                });
+            }
+        }
+    }
+    Ok(results)
+}
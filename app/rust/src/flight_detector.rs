@@ -0,0 +1,158 @@
+/* Finding flights in timestamped data, so they could become separate
+`JourneyKind::Flight` journeys instead of a straight line (or nothing at all)
+in the middle of a normal journey.
+
+Between two consecutive points (including the ones across segments, a flight
+usually starts a new segment because of the time gap), we consider the user
+is flying if:
+- Ground speed: faster than any ground transport (and not too fast to be
+  real, which is usually just bad data).
+- Great-circle jump: no data for a while and the next point is far away, e.g.
+  the phone is in flight mode. Averaged over the whole gap (taxiing, waiting,
+  etc.) the speed is lower so the threshold is lower as well.
+- Altitude: nothing but planes goes that high.
+
+Consecutive flying pairs form a flight if it covers enough distance. Points
+before and after a flight are kept as ground parts, the first/last point of
+a flight is shared with the ground part next to it.
+*/
+use std::ops::Range;
+
+use crate::transport_mode::TimedPoint;
+
+const MIN_FLIGHT_SPEED_IN_M_PER_SEC: f64 = 100.0;
+const MAX_FLIGHT_SPEED_IN_M_PER_SEC: f64 = 350.0;
+const MIN_JUMP_GAP_IN_SEC: i64 = 10 * 60;
+const MIN_JUMP_DISTANCE_IN_M: f64 = 150_000.0;
+const MIN_JUMP_SPEED_IN_M_PER_SEC: f64 = 70.0;
+const MIN_FLIGHT_ALTITUDE_IN_M: f32 = 6000.0;
+const MIN_FLIGHT_DISTANCE_IN_M: f64 = 100_000.0;
+
+pub enum TrackPart<T> {
+    /// The segments are kept as they are.
+    Ground(Vec<Vec<T>>),
+    Flight(Vec<T>),
+}
+
+fn is_flying(a: &TimedPoint, b: &TimedPoint) -> bool {
+    let duration_sec = b.timestamp_sec - a.timestamp_sec;
+    if duration_sec <= 0 {
+        return false;
+    }
+    if a.altitude.is_some_and(|x| x >= MIN_FLIGHT_ALTITUDE_IN_M)
+        && b.altitude.is_some_and(|x| x >= MIN_FLIGHT_ALTITUDE_IN_M)
+    {
+        return true;
+    }
+    let distance = a.point.haversine_distance(&b.point);
+    let speed = distance / duration_sec as f64;
+    if speed > MAX_FLIGHT_SPEED_IN_M_PER_SEC {
+        return false;
+    }
+    speed >= MIN_FLIGHT_SPEED_IN_M_PER_SEC
+        || (duration_sec >= MIN_JUMP_GAP_IN_SEC
+            && distance >= MIN_JUMP_DISTANCE_IN_M
+            && speed >= MIN_JUMP_SPEED_IN_M_PER_SEC)
+}
+
+/// `points` should be ordered by time. Returns the ranges of points of each
+/// flight, including the last point before taking off and the first point
+/// after landing.
+pub fn detect_flights(points: &[TimedPoint]) -> Vec<Range<usize>> {
+    let mut flights: Vec<Range<usize>> = Vec::new();
+    let mut current: Option<Range<usize>> = None;
+    for i in 1..=points.len() {
+        if i < points.len() && is_flying(&points[i - 1], &points[i]) {
+            match current.as_mut() {
+                Some(range) => range.end = i + 1,
+                None => current = Some(i - 1..i + 1),
+            }
+        } else if let Some(range) = current.take() {
+            let distance: f64 = points[range.clone()]
+                .windows(2)
+                .map(|x| x[0].point.haversine_distance(&x[1].point))
+                .sum();
+            if distance >= MIN_FLIGHT_DISTANCE_IN_M {
+                flights.push(range);
+            }
+        }
+    }
+    flights
+}
+
+/// Splits `segments` into ground parts and flights, ordered by time. Data
+/// without timestamp (`timed_point` returns `None`) is never considered as
+/// flying. Returns the whole data as a single ground part if there is no
+/// flight.
+pub fn split_flights<T: Clone>(
+    segments: &[Vec<T>],
+    timed_point: impl Fn(&T) -> Option<TimedPoint>,
+) -> Vec<TrackPart<T>> {
+    // `(segment index, data)` of all segments
+    let data: Vec<(usize, &T)> = segments
+        .iter()
+        .enumerate()
+        .flat_map(|(i, segment)| segment.iter().map(move |x| (i, x)))
+        .collect();
+
+    // detecting within each run of data with timestamp
+    let mut flights: Vec<Range<usize>> = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let mut points = Vec::new();
+        let mut end = start;
+        while end < data.len() {
+            match timed_point(data[end].1) {
+                Some(point) => points.push(point),
+                None => break,
+            }
+            end += 1;
+        }
+        flights.extend(
+            detect_flights(&points)
+                .into_iter()
+                .map(|range| range.start + start..range.end + start),
+        );
+        start = end + 1;
+    }
+    if flights.is_empty() {
+        return if segments.is_empty() {
+            vec![]
+        } else {
+            vec![TrackPart::Ground(segments.to_vec())]
+        };
+    }
+
+    let ground = |range: Range<usize>| -> Option<TrackPart<T>> {
+        // a single point is just the shared end of a flight
+        if range.len() < 2 {
+            return None;
+        }
+        let mut ground_segments: Vec<Vec<T>> = Vec::new();
+        let mut last_segment_index = None;
+        for (segment_index, x) in &data[range] {
+            if last_segment_index != Some(*segment_index) {
+                ground_segments.push(Vec::new());
+                last_segment_index = Some(*segment_index);
+            }
+            if let Some(segment) = ground_segments.last_mut() {
+                segment.push((*x).clone());
+            }
+        }
+        Some(TrackPart::Ground(ground_segments))
+    };
+    let mut parts = Vec::new();
+    let mut ground_start = 0;
+    for flight in flights {
+        parts.extend(ground(ground_start..flight.start + 1));
+        parts.push(TrackPart::Flight(
+            data[flight.clone()]
+                .iter()
+                .map(|(_, x)| (*x).clone())
+                .collect(),
+        ));
+        ground_start = flight.end - 1;
+    }
+    parts.extend(ground(ground_start..data.len()));
+    parts
+}
@@ -30,6 +30,62 @@ pub fn process(raw_data: &[Vec<RawData>]) -> Option<JourneyVector> {
     }
 }
 
+/// Same as `process`, but the gap between two points further apart than the
+/// interpolation step follows the great circle. It is for flights with only a
+/// few points (e.g. the departure and the arrival) where a spline would not
+/// look like the actual route.
+pub fn process_great_circle(points: &[Point]) -> Option<JourneyVector> {
+    const STEP_LENGTH: f64 = 1000.;
+
+    let mut filled_points: Vec<Point> = Vec::new();
+    for (i, point) in points.iter().enumerate() {
+        if i > 0 {
+            filled_points.extend(great_circle_points(&points[i - 1], point, STEP_LENGTH));
+        }
+        filled_points.push(point.clone());
+    }
+
+    let track_segments: Vec<TrackSegment> =
+        PathInterpolator::split_trajectory_at_180(&filled_points)
+            .iter()
+            .filter_map(|seg| PathInterpolator::interpolate_one_seg(seg, STEP_LENGTH))
+            .collect();
+    if track_segments.is_empty() {
+        None
+    } else {
+        Some(JourneyVector { track_segments })
+    }
+}
+
+/// Points along the great circle from `from` to `to`, roughly every
+/// `step_length` meters. Both ends are excluded.
+pub fn great_circle_points(from: &Point, to: &Point, step_length: f64) -> Vec<Point> {
+    let distance = from.haversine_distance(to);
+    let num_of_steps = (distance / step_length).ceil() as usize;
+    if num_of_steps < 2 {
+        return vec![];
+    }
+
+    let (x1, y1, z1) = from.to_cartesian();
+    let (x2, y2, z2) = to.to_cartesian();
+    // the angle between the two points as seen from the center of the earth
+    let omega = (x1 * x2 + y1 * y2 + z1 * z2).clamp(-1., 1.).acos();
+    let sin_omega = omega.sin();
+    if sin_omega.abs() < 1e-12 {
+        // the same point or antipodal points, there is no single great circle
+        return vec![];
+    }
+
+    (1..num_of_steps)
+        .map(|i| {
+            let fraction = i as f64 / num_of_steps as f64;
+            let a = ((1. - fraction) * omega).sin() / sin_omega;
+            let b = (fraction * omega).sin() / sin_omega;
+            Point::to_geographic(a * x1 + b * x2, a * y1 + b * y2, a * z1 + b * z2)
+        })
+        .collect()
+}
+
 struct PathInterpolator {}
 
 impl PathInterpolator {
@@ -1,4 +1,5 @@
 use crate::{
+    journey_bitmap::{BITMAP_WIDTH_OFFSET, MAP_WIDTH_OFFSET, TILE_WIDTH_OFFSET},
     journey_date_picker::JourneyDatePicker,
     journey_header::{JourneyHeader, JourneyType},
     journey_vector::{JourneyVector, TrackPoint, TrackSegment},
@@ -6,9 +7,12 @@ use crate::{
 use anyhow::{Context, Result};
 use auto_context::auto_context;
 use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use strum_macros::{Display, EnumIter, EnumString};
 
 // TODO: This is the same as `TrackPoint`, we should unify them.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub latitude: f64,
     pub longitude: f64,
@@ -76,6 +80,32 @@ pub struct RawData {
     pub accuracy: Option<f32>,
     pub altitude: Option<f32>,
     pub speed: Option<f32>,
+    /// In degrees, clockwise from north.
+    pub bearing: Option<f32>,
+    pub vertical_accuracy: Option<f32>,
+    pub speed_accuracy: Option<f32>,
+    /// Where the location comes from, e.g. `gps`, `network` or `fused` on
+    /// Android. Only used for diagnosing.
+    pub provider: Option<String>,
+    pub satellite_count: Option<u32>,
+}
+
+impl RawData {
+    /// Without any of the optional fields.
+    pub fn new(point: Point, timestamp_ms: Option<i64>) -> Self {
+        RawData {
+            point,
+            timestamp_ms,
+            accuracy: None,
+            altitude: None,
+            speed: None,
+            bearing: None,
+            vertical_accuracy: None,
+            speed_accuracy: None,
+            provider: None,
+            satellite_count: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -166,15 +196,9 @@ impl BadDataDetector {
         }
     }
 
-    fn is_bad_data(&mut self, curr_data: &RawData) -> bool {
-        const ACCURACY_THRESHOLD: f32 = 50.;
-        const ACCELERATION_THRESHOLD: f32 = 10.;
-        // We mostly don't care deceleration, but just in case we had a very bad
-        // data that bring the speed down a lot.
-        const DECELERATION_THRESHOLD: f32 = -20.;
-
+    fn is_bad_data(&mut self, curr_data: &RawData, thresholds: &PreprocessingThresholds) -> bool {
         if let Some(accuracy) = curr_data.accuracy {
-            if accuracy > ACCURACY_THRESHOLD {
+            if accuracy > thresholds.max_accuracy_m {
                 return true;
             }
         }
@@ -204,7 +228,10 @@ impl BadDataDetector {
                     let acceleration = (speed - last_speed) / time_span_in_sec;
                     // We only care about acceleration, not deceleration.
                     // Maybe we should also consider direction.
-                    if !(DECELERATION_THRESHOLD..=ACCELERATION_THRESHOLD).contains(&acceleration) {
+                    if !(thresholds.min_acceleration_m_per_s2
+                        ..=thresholds.max_acceleration_m_per_s2)
+                        .contains(&acceleration)
+                    {
                         return true;
                     }
                 }
@@ -218,6 +245,170 @@ impl BadDataDetector {
     }
 }
 
+/* A constant velocity Kalman filter. Positions are projected to a local plane
+in meters (x for east, y for north) and each axis is filtered independently
+with the state being (position, velocity).
+- Position is measured directly, the variance comes from the accuracy.
+- Speed doesn't have a direction, we assume the direction of the estimated
+  velocity and use it as a velocity measurement. It is most useful when
+  stopped, so the jitter doesn't look like movement.
+*/
+#[derive(Clone, Copy)]
+struct KalmanAxisState {
+    position: f64,
+    velocity: f64,
+    // covariance matrix of (position, velocity)
+    p: [[f64; 2]; 2],
+}
+
+impl KalmanAxisState {
+    fn predict(&mut self, dt: f64, acceleration_variance: f64) {
+        self.position += self.velocity * dt;
+        let [[p00, p01], [p10, p11]] = self.p;
+        let dt2 = dt * dt;
+        self.p = [
+            [
+                p00 + dt * (p01 + p10) + dt2 * p11 + dt2 * dt2 / 4.0 * acceleration_variance,
+                p01 + dt * p11 + dt2 * dt / 2.0 * acceleration_variance,
+            ],
+            [
+                p10 + dt * p11 + dt2 * dt / 2.0 * acceleration_variance,
+                p11 + dt2 * acceleration_variance,
+            ],
+        ];
+    }
+
+    // `index` 0 for measuring the position, 1 for measuring the velocity.
+    fn update(&mut self, index: usize, measurement: f64, variance: f64) {
+        let innovation = measurement
+            - if index == 0 {
+                self.position
+            } else {
+                self.velocity
+            };
+        let s = self.p[index][index] + variance;
+        let k = [self.p[0][index] / s, self.p[1][index] / s];
+        self.position += k[0] * innovation;
+        self.velocity += k[1] * innovation;
+        let p = self.p;
+        self.p = [
+            [p[0][0] - k[0] * p[index][0], p[0][1] - k[0] * p[index][1]],
+            [p[1][0] - k[1] * p[index][0], p[1][1] - k[1] * p[index][1]],
+        ];
+    }
+}
+
+struct KalmanFilterState {
+    origin: Point,
+    last_timestamp_ms: i64,
+    x: KalmanAxisState,
+    y: KalmanAxisState,
+}
+
+struct KalmanFilter {
+    state: Option<KalmanFilterState>,
+}
+
+impl KalmanFilter {
+    // Could handle normal driving, or a sudden stop.
+    const ACCELERATION_STD_IN_M_PER_S2: f64 = 3.0;
+    const SPEED_STD_IN_M_PER_S: f64 = 1.0;
+    const DEFAULT_ACCURACY_IN_M: f64 = 30.0;
+    // Same as `BadDataDetector`, the prediction is meaningless after a long
+    // time.
+    const MAX_TIME_SPAN_IN_MS: i64 = 10 * 1000;
+
+    fn new() -> Self {
+        Self { state: None }
+    }
+
+    // The inverse of `to_local_x_y_in_m`.
+    fn from_local_x_y(origin: &Point, x: f64, y: f64) -> Point {
+        Point {
+            latitude: origin.latitude + (y / EARTH_RADIUS_IN_M).to_degrees(),
+            longitude: Point::normalize_longitude(
+                origin.longitude
+                    + (x / (EARTH_RADIUS_IN_M * origin.latitude.to_radians().cos())).to_degrees(),
+            ),
+        }
+    }
+
+    fn filter(&mut self, curr_data: &RawData) -> RawData {
+        let timestamp_ms = match curr_data.timestamp_ms {
+            // can't predict anything without timestamp
+            None => {
+                self.state = None;
+                return curr_data.clone();
+            }
+            Some(timestamp_ms) => timestamp_ms,
+        };
+        let accuracy = curr_data
+            .accuracy
+            .map(|x| x as f64)
+            .unwrap_or(Self::DEFAULT_ACCURACY_IN_M);
+        let position_variance = accuracy * accuracy;
+
+        match &mut self.state {
+            Some(state)
+                if timestamp_ms > state.last_timestamp_ms
+                    && timestamp_ms - state.last_timestamp_ms <= Self::MAX_TIME_SPAN_IN_MS =>
+            {
+                let dt = (timestamp_ms - state.last_timestamp_ms) as f64 / 1000.0;
+                let acceleration_variance = Self::ACCELERATION_STD_IN_M_PER_S2.powi(2);
+                state.x.predict(dt, acceleration_variance);
+                state.y.predict(dt, acceleration_variance);
+
+                let (x, y) = to_local_x_y_in_m(&state.origin, &curr_data.point);
+                state.x.update(0, x, position_variance);
+                state.y.update(0, y, position_variance);
+
+                if let Some(speed) = curr_data.speed {
+                    let speed = speed as f64;
+                    let estimated_speed = state.x.velocity.hypot(state.y.velocity);
+                    let (velocity_x, velocity_y) = if estimated_speed > 0.0 {
+                        (
+                            state.x.velocity / estimated_speed * speed,
+                            state.y.velocity / estimated_speed * speed,
+                        )
+                    } else {
+                        (0.0, 0.0)
+                    };
+                    // without a direction, only a zero speed is useful
+                    if estimated_speed > 0.0 || speed == 0.0 {
+                        let speed_variance = Self::SPEED_STD_IN_M_PER_S.powi(2);
+                        state.x.update(1, velocity_x, speed_variance);
+                        state.y.update(1, velocity_y, speed_variance);
+                    }
+                }
+                state.last_timestamp_ms = timestamp_ms;
+
+                RawData {
+                    point: Self::from_local_x_y(&state.origin, state.x.position, state.y.position),
+                    ..curr_data.clone()
+                }
+            }
+            _ => {
+                // (re)starting from the current point
+                let axis_state = KalmanAxisState {
+                    position: 0.0,
+                    velocity: 0.0,
+                    p: [
+                        [position_variance, 0.0],
+                        [0.0, Self::SPEED_STD_IN_M_PER_S.powi(2) * 100.0],
+                    ],
+                };
+                self.state = Some(KalmanFilterState {
+                    origin: curr_data.point.clone(),
+                    last_timestamp_ms: timestamp_ms,
+                    x: axis_state,
+                    y: axis_state,
+                });
+                curr_data.clone()
+            }
+        }
+    }
+}
+
 enum GpsPreprocessorState {
     Empty,
     Moving {
@@ -233,21 +424,231 @@ enum GpsPreprocessorState {
     },
 }
 
-struct SegmentGapThreshold {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum GpsFilter {
+    None,
+    /// Correcting positions with `KalmanFilter`, useful when the signal is
+    /// noisy, e.g. zigzags between tall buildings.
+    Kalman,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SegmentGapThreshold {
+    pub distance_m: f64,
+    pub max_gap_sec: i64,
+}
+
+// Thresholds must be ordered by `distance_m` in ascending order.
+// The first matching one is applied.
+fn is_segment_gap(
+    thresholds: &[SegmentGapThreshold],
     distance_m: f64,
-    max_gap_sec: i64,
+    time_diff_in_ms: i64,
+) -> bool {
+    for threshold in thresholds {
+        if distance_m <= threshold.distance_m {
+            return time_diff_in_ms > threshold.max_gap_sec * 1000;
+        }
+    }
+    // Too far, start a new segment
+    true
 }
 
+const DEFAULT_SEGMENT_GAP_THRESHOLDS: [SegmentGapThreshold; 3] = [
+    SegmentGapThreshold {
+        distance_m: 5.0,
+        max_gap_sec: 3600,
+    },
+    SegmentGapThreshold {
+        distance_m: 50.0,
+        max_gap_sec: 20,
+    },
+    SegmentGapThreshold {
+        distance_m: 1000.0,
+        max_gap_sec: 4,
+    },
+];
+
+const SPARE_SEGMENT_GAP_THRESHOLDS: [SegmentGapThreshold; 3] = [
+    SegmentGapThreshold {
+        distance_m: 5.0,
+        max_gap_sec: 3600,
+    },
+    SegmentGapThreshold {
+        distance_m: 150.0,
+        max_gap_sec: 240,
+    },
+    SegmentGapThreshold {
+        distance_m: 1000.0,
+        max_gap_sec: 120,
+    },
+];
+
 #[derive(Clone, Copy, Debug)]
 pub enum SegmentGapRule {
     Default,
     Spare,
 }
 
+impl SegmentGapRule {
+    pub fn segment_gap_thresholds(&self) -> &'static [SegmentGapThreshold] {
+        match self {
+            SegmentGapRule::Default => &DEFAULT_SEGMENT_GAP_THRESHOLDS,
+            SegmentGapRule::Spare => &SPARE_SEGMENT_GAP_THRESHOLDS,
+        }
+    }
+
+    /// Whether two points that are `distance_m` apart with `time_diff_in_ms`
+    /// in between should be in different segments.
+    pub fn is_gap(&self, distance_m: f64, time_diff_in_ms: i64) -> bool {
+        is_segment_gap(self.segment_gap_thresholds(), distance_m, time_diff_in_ms)
+    }
+}
+
+/// All the thresholds used by `GpsPreprocessor`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PreprocessingThresholds {
+    /// Data with a worse (higher) accuracy is ignored.
+    pub max_accuracy_m: f32,
+    pub max_acceleration_m_per_s2: f32,
+    // We mostly don't care deceleration, but just in case we had a very bad
+    // data that bring the speed down a lot.
+    pub min_acceleration_m_per_s2: f32,
+    pub segment_gap_thresholds: Vec<SegmentGapThreshold>,
+}
+
+impl PreprocessingThresholds {
+    pub fn from_segment_gap_rule(rule: SegmentGapRule) -> Self {
+        Self {
+            max_accuracy_m: 50.,
+            max_acceleration_m_per_s2: 10.,
+            min_acceleration_m_per_s2: -20.,
+            segment_gap_thresholds: rule.segment_gap_thresholds().to_vec(),
+        }
+    }
+
+    pub fn is_gap(&self, distance_m: f64, time_diff_in_ms: i64) -> bool {
+        is_segment_gap(&self.segment_gap_thresholds, distance_m, time_diff_in_ms)
+    }
+
+    #[auto_context]
+    pub fn validate(&self) -> Result<()> {
+        let is_positive = |x: f64| x.is_finite() && x > 0.;
+        if !is_positive(self.max_accuracy_m as f64) {
+            bail!("`max_accuracy_m` must be positive");
+        }
+        if !is_positive(self.max_acceleration_m_per_s2 as f64) {
+            bail!("`max_acceleration_m_per_s2` must be positive");
+        }
+        if !is_positive(-self.min_acceleration_m_per_s2 as f64) {
+            bail!("`min_acceleration_m_per_s2` must be negative");
+        }
+        if self
+            .segment_gap_thresholds
+            .iter()
+            .any(|x| !is_positive(x.distance_m) || x.max_gap_sec < 0)
+        {
+            bail!("Invalid segment gap threshold");
+        }
+        if !self
+            .segment_gap_thresholds
+            .windows(2)
+            .all(|x| x[0].distance_m < x[1].distance_m)
+        {
+            bail!("Segment gap thresholds must be ordered by `distance_m`");
+        }
+        Ok(())
+    }
+}
+
+// Stored as JSON in settings.
+impl FromStr for PreprocessingThresholds {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl fmt::Display for PreprocessingThresholds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(self).map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum PreprocessingProfile {
+    Default,
+    Walking,
+    Cycling,
+    Driving,
+    /// Thresholds are provided by the user.
+    Custom,
+}
+
+impl PreprocessingProfile {
+    /// `None` for `Custom`.
+    pub fn builtin_thresholds(&self) -> Option<PreprocessingThresholds> {
+        let segment_gap_thresholds = |x: &[(f64, i64)]| {
+            x.iter()
+                .map(|(distance_m, max_gap_sec)| SegmentGapThreshold {
+                    distance_m: *distance_m,
+                    max_gap_sec: *max_gap_sec,
+                })
+                .collect()
+        };
+        match self {
+            Self::Default => Some(PreprocessingThresholds::from_segment_gap_rule(
+                SegmentGapRule::Default,
+            )),
+            // Slow, so we could be stricter on the data quality and more
+            // willing to connect points that are a bit far apart in time.
+            Self::Walking => Some(PreprocessingThresholds {
+                max_accuracy_m: 30.,
+                max_acceleration_m_per_s2: 6.,
+                min_acceleration_m_per_s2: -12.,
+                segment_gap_thresholds: segment_gap_thresholds(&[
+                    (5., 3600),
+                    (50., 60),
+                    (200., 30),
+                ]),
+            }),
+            Self::Cycling => Some(PreprocessingThresholds {
+                max_accuracy_m: 40.,
+                max_acceleration_m_per_s2: 8.,
+                min_acceleration_m_per_s2: -15.,
+                segment_gap_thresholds: segment_gap_thresholds(&[
+                    (5., 3600),
+                    (50., 30),
+                    (500., 10),
+                ]),
+            }),
+            Self::Driving => Some(PreprocessingThresholds {
+                max_accuracy_m: 50.,
+                max_acceleration_m_per_s2: 10.,
+                min_acceleration_m_per_s2: -20.,
+                segment_gap_thresholds: segment_gap_thresholds(&[
+                    (5., 3600),
+                    (100., 20),
+                    (1000., 10),
+                ]),
+            }),
+            Self::Custom => None,
+        }
+    }
+}
+
 pub struct GpsPreprocessor {
     state: GpsPreprocessorState,
     bad_data_detector: BadDataDetector,
-    rule: SegmentGapRule,
+    kalman_filter: Option<KalmanFilter>,
+    thresholds: PreprocessingThresholds,
 }
 
 impl GpsPreprocessor {
@@ -256,13 +657,62 @@ impl GpsPreprocessor {
     }
 
     pub fn new_with_rule(rule: SegmentGapRule) -> Self {
-        Self {
+        Self::new_with_rule_and_filter(rule, GpsFilter::None)
+    }
+
+    pub fn new_with_rule_and_filter(rule: SegmentGapRule, filter: GpsFilter) -> Self {
+        Self::new_with_thresholds_and_filter(
+            PreprocessingThresholds::from_segment_gap_rule(rule),
+            filter,
+        )
+    }
+
+    pub fn new_with_thresholds_and_filter(
+        thresholds: PreprocessingThresholds,
+        filter: GpsFilter,
+    ) -> Self {
+        let mut gps_preprocessor = Self {
             state: GpsPreprocessorState::Empty,
             bad_data_detector: BadDataDetector::new(),
-            rule,
+            kalman_filter: None,
+            thresholds,
+        };
+        gps_preprocessor.set_filter(filter);
+        gps_preprocessor
+    }
+
+    pub fn thresholds(&self) -> &PreprocessingThresholds {
+        &self.thresholds
+    }
+
+    /// Similar to `set_filter`, the current segment can continue.
+    pub fn set_thresholds(&mut self, thresholds: PreprocessingThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    pub fn filter(&self) -> GpsFilter {
+        match self.kalman_filter {
+            None => GpsFilter::None,
+            Some(_) => GpsFilter::Kalman,
+        }
+    }
+
+    /// Changing the filter without resetting the rest of the state, so the
+    /// current segment can continue.
+    pub fn set_filter(&mut self, filter: GpsFilter) {
+        if self.filter() != filter {
+            self.kalman_filter = match filter {
+                GpsFilter::None => None,
+                GpsFilter::Kalman => Some(KalmanFilter::new()),
+            };
         }
     }
 
+    /// Forgetting all previous data but keeping the configuration.
+    pub fn reset(&mut self) {
+        *self = Self::new_with_thresholds_and_filter(self.thresholds.clone(), self.filter());
+    }
+
     pub fn last_kept_point(&self) -> Option<Point> {
         use GpsPreprocessorState::*;
         match &self.state {
@@ -278,43 +728,11 @@ impl GpsPreprocessor {
     }
 
     fn process_moving_data(
-        rule: SegmentGapRule,
+        thresholds: &PreprocessingThresholds,
         last_point: &Point,
         last_timestamp_ms: Option<i64>,
         curr_data: &RawData,
     ) -> ProcessResult {
-        // Rules must be ordered by `distance_m` in ascending order.
-        // The first matching rule is applied.
-        type SegmentGapProfile = &'static [SegmentGapThreshold; 3];
-        const DEFAULT_SEGMENT_GAP_RULES: SegmentGapProfile = &[
-            SegmentGapThreshold {
-                distance_m: 5.0,
-                max_gap_sec: 3600,
-            },
-            SegmentGapThreshold {
-                distance_m: 50.0,
-                max_gap_sec: 20,
-            },
-            SegmentGapThreshold {
-                distance_m: 1000.0,
-                max_gap_sec: 4,
-            },
-        ];
-        const SPARE_SEGMENT_GAP_RULES: SegmentGapProfile = &[
-            SegmentGapThreshold {
-                distance_m: 5.0,
-                max_gap_sec: 3600,
-            },
-            SegmentGapThreshold {
-                distance_m: 150.0,
-                max_gap_sec: 240,
-            },
-            SegmentGapThreshold {
-                distance_m: 1000.0,
-                max_gap_sec: 120,
-            },
-        ];
-
         const TOO_CLOSE_DISTANCE_IN_M: f64 = 0.1;
 
         let distance_in_m = curr_data.point.haversine_distance(last_point);
@@ -335,28 +753,40 @@ impl GpsPreprocessor {
                     // in normal condition, we should have 1 data per sec
                     // we should mostly trust the data here and try to
                     // filter out bad ones in `BadDataDetector`.
-                    for rule in match rule {
-                        SegmentGapRule::Default => DEFAULT_SEGMENT_GAP_RULES,
-                        SegmentGapRule::Spare => SPARE_SEGMENT_GAP_RULES,
-                    }
-                    .iter()
-                    {
-                        if distance_in_m <= rule.distance_m {
-                            return if time_diff_in_ms <= rule.max_gap_sec * 1000 {
-                                ProcessResult::Append
-                            } else {
-                                ProcessResult::NewSegment
-                            };
-                        }
+                    if thresholds.is_gap(distance_in_m, time_diff_in_ms) {
+                        ProcessResult::NewSegment
+                    } else {
+                        ProcessResult::Append
                     }
-                    // Too far, start a new segment
-                    ProcessResult::NewSegment
                 }
             }
         }
     }
 
     pub fn preprocess(&mut self, curr_data: &RawData) -> ProcessResult {
+        self.preprocess_and_filter(curr_data).0
+    }
+
+    /// Same as `preprocess`, but also returns the data that should be kept,
+    /// which is the corrected one if there is a filter.
+    pub fn preprocess_and_filter(&mut self, curr_data: &RawData) -> (ProcessResult, RawData) {
+        // We don't update our state if the data is bad.
+        if self
+            .bad_data_detector
+            .is_bad_data(curr_data, &self.thresholds)
+        {
+            return (ProcessResult::Ignore, curr_data.clone());
+        };
+
+        let curr_data = match &mut self.kalman_filter {
+            None => curr_data.clone(),
+            Some(kalman_filter) => kalman_filter.filter(curr_data),
+        };
+        let process_result = self.process_good_data(&curr_data);
+        (process_result, curr_data)
+    }
+
+    fn process_good_data(&mut self, curr_data: &RawData) -> ProcessResult {
         // Something to note:
         // * Accuracy is not well defined. The unit is meters but: On android,
         //  it is the radius of this location at the 68th percentile confidence
@@ -376,11 +806,6 @@ impl GpsPreprocessor {
         const FALLBACK_NUM_OF_DATA_TO_WAIT_BEFORE_BEGINING_STATIONARY: i64 = 60;
         const DEFAULT_ACCURACY_OF_POINT: f32 = 30.0;
 
-        // We don't update our state if the data is bad.
-        if self.bad_data_detector.is_bad_data(curr_data) {
-            return ProcessResult::Ignore;
-        };
-
         let start_moving = |curr_data: &RawData| Moving {
             last_point: curr_data.point.clone(),
             last_timestamp_ms: curr_data.timestamp_ms,
@@ -401,8 +826,12 @@ impl GpsPreprocessor {
                 timestamp_ms_when_center_point_picked,
                 num_of_data_since_center_point_picked,
             } => {
-                let result =
-                    Self::process_moving_data(self.rule, last_point, *last_timestamp_ms, curr_data);
+                let result = Self::process_moving_data(
+                    &self.thresholds,
+                    last_point,
+                    *last_timestamp_ms,
+                    curr_data,
+                );
                 if result != ProcessResult::Ignore {
                     *last_point = curr_data.point.clone();
                     *last_timestamp_ms = curr_data.timestamp_ms;
@@ -459,7 +888,7 @@ impl GpsPreprocessor {
                 } else {
                     //then ending stationary change to move mode
                     let result = Self::process_moving_data(
-                        self.rule,
+                        &self.thresholds,
                         center_point,
                         *last_timestamp_ms,
                         curr_data,
@@ -472,6 +901,36 @@ impl GpsPreprocessor {
     }
 }
 
+/// Location updates could be delivered in batches and out of order (e.g. on
+/// Android in the background), but `GpsPreprocessor` treats any data that is
+/// not newer than the previous one as bad data. So a batch is ordered by
+/// timestamp first, and for data with the same timestamp only the most
+/// accurate one is kept. Data without timestamp is kept at the end in the
+/// original order.
+///
+/// Only the batch itself is reordered, data older than what has been processed
+/// before is still dropped by the preprocessor.
+pub fn reorder_batch(mut raw_data_list: Vec<RawData>) -> Vec<RawData> {
+    // stable, so the original order is kept for data without timestamp
+    raw_data_list.sort_by_key(|x| (x.timestamp_ms.is_none(), x.timestamp_ms));
+    let mut result: Vec<RawData> = Vec::with_capacity(raw_data_list.len());
+    for raw_data in raw_data_list {
+        match result.last_mut() {
+            Some(last)
+                if raw_data.timestamp_ms.is_some()
+                    && last.timestamp_ms == raw_data.timestamp_ms =>
+            {
+                let accuracy = |x: &RawData| x.accuracy.unwrap_or(f32::MAX);
+                if accuracy(&raw_data) < accuracy(last) {
+                    *last = raw_data;
+                }
+            }
+            _ => result.push(raw_data),
+        }
+    }
+    result
+}
+
 pub struct PreprocessedData {
     pub timestamp_sec: Option<i64>,
     pub track_point: TrackPoint,
@@ -522,15 +981,194 @@ pub fn build_journey_vector(
     }
 }
 
+/* Cleaning up a journey vector before storing it. The input is usually
+already handled by `GpsPreprocessor`, but the preprocessor only sees one point
+at a time (and imports may skip it), so we do another pass with the whole
+journey:
+1. Removing outliers: a single point jumping far away and then coming back.
+2. Smoothing jitter: when staying at the same place, points drift around
+   within a small radius, we replace them with their center.
+3. Simplification: Douglas-Peucker with a tolerance of half a pixel of the
+   bitmap at the highest zoom level, so the rendered result stays the same.
+*/
+const EARTH_RADIUS_IN_M: f64 = 6371e3;
+// The number of pixels around the equator is `2^BITMAP_ZOOM`.
+const BITMAP_ZOOM: i32 = (MAP_WIDTH_OFFSET + TILE_WIDTH_OFFSET + BITMAP_WIDTH_OFFSET) as i32;
+const SIMPLIFICATION_TOLERANCE_IN_PIXEL: f64 = 0.5;
+const OUTLIER_MIN_DISTANCE_IN_M: f64 = 200.0;
+// The point is an outlier if skipping it makes the path this much shorter.
+const OUTLIER_MAX_SHORTCUT_RATIO: f64 = 0.25;
+const STATIONARY_RADIUS_IN_M: f64 = 10.0;
+const STATIONARY_MIN_NUM_OF_POINTS: usize = 10;
+
+fn track_point_distance(a: &TrackPoint, b: &TrackPoint) -> f64 {
+    Point {
+        latitude: a.latitude,
+        longitude: a.longitude,
+    }
+    .haversine_distance(&Point {
+        latitude: b.latitude,
+        longitude: b.longitude,
+    })
+}
+
+fn pixel_size_in_m(latitude: f64) -> f64 {
+    2.0 * std::f64::consts::PI * EARTH_RADIUS_IN_M * latitude.to_radians().cos()
+        / f64::powi(2.0, BITMAP_ZOOM)
+}
+
+// Equirectangular projection around `origin`, good enough for short distances.
+fn to_local_x_y_in_m(origin: &Point, point: &Point) -> (f64, f64) {
+    let delta_longitude = Point::normalize_longitude(point.longitude - origin.longitude);
+    (
+        delta_longitude.to_radians() * origin.latitude.to_radians().cos() * EARTH_RADIUS_IN_M,
+        (point.latitude - origin.latitude).to_radians() * EARTH_RADIUS_IN_M,
+    )
+}
+
+fn distance_to_line_segment_in_m(point: &TrackPoint, start: &TrackPoint, end: &TrackPoint) -> f64 {
+    let to_point = |x: &TrackPoint| Point {
+        latitude: x.latitude,
+        longitude: x.longitude,
+    };
+    let start = to_point(start);
+    let (x, y) = to_local_x_y_in_m(&start, &to_point(point));
+    let (end_x, end_y) = to_local_x_y_in_m(&start, &to_point(end));
+    let length_squared = end_x * end_x + end_y * end_y;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        ((x * end_x + y * end_y) / length_squared).clamp(0.0, 1.0)
+    };
+    (x - t * end_x).hypot(y - t * end_y)
+}
+
 pub struct GpsPostprocessor {}
 
 impl GpsPostprocessor {
     pub fn process(journey_vector: JourneyVector) -> JourneyVector {
-        journey_vector
+        JourneyVector {
+            track_segments: journey_vector
+                .track_segments
+                .into_iter()
+                .map(|segment| {
+                    let track_points = Self::remove_outliers(segment.track_points);
+                    let track_points = Self::smooth_stationary_jitter(track_points);
+                    TrackSegment {
+                        track_points: Self::simplify(track_points),
+                    }
+                })
+                .filter(|segment| !segment.track_points.is_empty())
+                .collect(),
+        }
+    }
+
+    fn remove_outliers(track_points: Vec<TrackPoint>) -> Vec<TrackPoint> {
+        let mut result: Vec<TrackPoint> = Vec::with_capacity(track_points.len());
+        let mut iter = track_points.into_iter().peekable();
+        while let Some(curr) = iter.next() {
+            let is_outlier = match (result.last(), iter.peek()) {
+                (Some(prev), Some(next)) => {
+                    let distance_to_curr = track_point_distance(prev, &curr);
+                    let distance_from_curr = track_point_distance(&curr, next);
+                    distance_to_curr >= OUTLIER_MIN_DISTANCE_IN_M
+                        && distance_from_curr >= OUTLIER_MIN_DISTANCE_IN_M
+                        && track_point_distance(prev, next)
+                            <= distance_to_curr.min(distance_from_curr) * OUTLIER_MAX_SHORTCUT_RATIO
+                }
+                _ => false,
+            };
+            if !is_outlier {
+                result.push(curr);
+            }
+        }
+        result
+    }
+
+    fn smooth_stationary_jitter(track_points: Vec<TrackPoint>) -> Vec<TrackPoint> {
+        let mut result = Vec::with_capacity(track_points.len());
+        let mut i = 0;
+        while i < track_points.len() {
+            let center = &track_points[i];
+            let mut end = i + 1;
+            let mut path_length = 0.0;
+            while end < track_points.len()
+                && track_point_distance(center, &track_points[end]) <= STATIONARY_RADIUS_IN_M
+            {
+                path_length += track_point_distance(&track_points[end - 1], &track_points[end]);
+                end += 1;
+            }
+            // Moving slowly also keeps points within the radius for a while,
+            // it is only jitter if the path goes back and forth.
+            if end - i >= STATIONARY_MIN_NUM_OF_POINTS && path_length > 4.0 * STATIONARY_RADIUS_IN_M
+            {
+                let cluster = &track_points[i..end];
+                let (sum_latitude, sum_delta_longitude) =
+                    cluster.iter().fold((0.0, 0.0), |(latitude, longitude), x| {
+                        (
+                            latitude + x.latitude,
+                            longitude + Point::normalize_longitude(x.longitude - center.longitude),
+                        )
+                    });
+                let n = cluster.len() as f64;
+                result.push(TrackPoint {
+                    latitude: sum_latitude / n,
+                    longitude: Point::normalize_longitude(
+                        center.longitude + sum_delta_longitude / n,
+                    ),
+                });
+                i = end;
+            } else {
+                result.push(track_points[i].clone());
+                i += 1;
+            }
+        }
+        result
+    }
+
+    fn simplify(track_points: Vec<TrackPoint>) -> Vec<TrackPoint> {
+        if track_points.len() <= 2 {
+            return track_points;
+        }
+        let last = track_points.len() - 1;
+        let mut keep = vec![false; track_points.len()];
+        keep[0] = true;
+        keep[last] = true;
+        // not using recursion, a journey could have a lot of points
+        let mut ranges = vec![(0, last)];
+        while let Some((start, end)) = ranges.pop() {
+            if end <= start + 1 {
+                continue;
+            }
+            let (farthest, max_distance) = (start + 1..end)
+                .map(|i| {
+                    (
+                        i,
+                        distance_to_line_segment_in_m(
+                            &track_points[i],
+                            &track_points[start],
+                            &track_points[end],
+                        ),
+                    )
+                })
+                .fold((start, -1.0), |acc, x| if x.1 > acc.1 { x } else { acc });
+            let tolerance =
+                pixel_size_in_m(track_points[start].latitude) * SIMPLIFICATION_TOLERANCE_IN_PIXEL;
+            if max_distance > tolerance {
+                keep[farthest] = true;
+                ranges.push((start, farthest));
+                ranges.push((farthest, end));
+            }
+        }
+        track_points
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(track_point, keep)| keep.then_some(track_point))
+            .collect()
     }
 
     pub fn current_algo() -> String {
-        "0".to_string()
+        "1".to_string()
     }
 
     // When introducing a new algorithm, remember to update the
@@ -538,11 +1176,9 @@ impl GpsPostprocessor {
     pub fn outdated_algo(journey_header: &JourneyHeader) -> bool {
         match journey_header.journey_type {
             JourneyType::Bitmap => false,
-            #[allow(clippy::redundant_pattern_matching)]
-            JourneyType::Vector => match journey_header.postprocessor_algo {
-                None => true,
-                Some(_) => false,
-            },
+            JourneyType::Vector => {
+                journey_header.postprocessor_algo.as_deref() != Some(Self::current_algo().as_str())
+            }
         }
     }
 }
@@ -1,94 +1,278 @@
-use crate::api::import::ImportPreprocessor;
 use crate::export_data::JOURNEY_TYPE_NAME;
-use anyhow::Result;
-use chrono::Datelike;
-use quick_xml::events::{BytesText, Event};
-use quick_xml::{Reader, Writer};
+use crate::gps_processor::{Point, RawData};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Utc};
+use itertools::Itertools;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::{BufRead, BufReader, Cursor, Read};
 
 type TimeNormalizer = fn(&str) -> Option<String>;
 
-pub fn analyze_and_prepare_gpx(xml: &str) -> Result<(String, ImportPreprocessor)> {
-    let preprocessor = detect_gpx_preprocessor(xml);
+// Only the beginning of the file is checked for knowing if it is exported by us.
+const PROBE_LIMIT: usize = 8 * 1024;
+// Only a few invalid values are kept for the warnings, the file may have
+// millions of them.
+const MAX_INVALID_TIME_EXAMPLES: usize = 3;
 
-    // TODO: `normalize_gpx_time_with` is actually doing a full XML parse + rewrite.
-    // We should try to avoid calling it unless necessary.
-    let xml = match preprocessor {
-        ImportPreprocessor::Spare => normalize_gpx_time_with(xml, normalize_step_of_my_world_time)?,
-        _ => normalize_gpx_time_with(xml, normalize_generic_time)?,
-    };
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpxSegmentKind {
+    Track,
+    Route,
+}
 
-    Ok((xml, preprocessor))
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GpxReadSummary {
+    /// The file is a journey exported by us, which means the data is already
+    /// processed.
+    pub exported_journey: bool,
+    /// Points with a `<time>` we failed to parse, they are kept without
+    /// timestamp.
+    pub invalid_time_count: usize,
+    /// The first few of the invalid `<time>` values.
+    pub invalid_time_examples: Vec<String>,
 }
 
-fn detect_gpx_preprocessor(xml: &str) -> ImportPreprocessor {
-    // TODO: This is pretty hacky: trying to match some known strings at the
-    // beginning of the file.
-    // Better approach could be parsing the XML / actually detecting the
-    // properties of the data in it (is it actually spare?)
-    const PROBE_LIMIT: usize = 8 * 1024;
-
-    let head = xml
-        .chars()
-        .take(PROBE_LIMIT)
-        .collect::<String>()
-        .to_ascii_lowercase();
-
-    // stepofmyworld (一生足迹), yourapp (灵感足迹)
-    if head.contains("stepofmyworld") || head.contains("yourapp") {
-        ImportPreprocessor::Spare
-    } else if head.contains(&JOURNEY_TYPE_NAME.to_ascii_lowercase()) {
-        ImportPreprocessor::None
-    } else {
-        ImportPreprocessor::Generic
+impl GpxReadSummary {
+    /// Human readable warnings, similar to the ones of the FoW importers.
+    pub fn warnings(&self) -> Option<String> {
+        if self.invalid_time_count == 0 {
+            return None;
+        }
+        Some(format!(
+            "Points with an invalid time, imported without it: {}, e.g. {}",
+            self.invalid_time_count,
+            self.invalid_time_examples
+                .iter()
+                .map(|x| format!("`{x}`"))
+                .join(", ")
+        ))
     }
 }
 
-fn normalize_gpx_time_with(xml: &str, normalizer: TimeNormalizer) -> Result<String> {
-    let mut reader = Reader::from_str(xml);
-    reader.config_mut().trim_text(true);
+/// Returns `true` if the file is a journey exported by us, only the
+/// beginning of the file is read.
+pub fn is_exported_journey<R: Read>(reader: R) -> Result<bool> {
+    let mut head = Vec::with_capacity(PROBE_LIMIT);
+    reader.take(PROBE_LIMIT as u64).read_to_end(&mut head)?;
+    Ok(is_exported_journey_head(&head))
+}
 
-    let mut writer = Writer::new(Vec::new());
-    let mut buf = Vec::new();
+fn is_exported_journey_head(head: &[u8]) -> bool {
+    String::from_utf8_lossy(head)
+        .to_ascii_lowercase()
+        .contains(&JOURNEY_TYPE_NAME.to_ascii_lowercase())
+}
 
-    let mut modified = false;
+/// Streaming GPX reader, the file is never fully loaded into memory.
+/// `on_point` is called for every point in `trkseg` and `rte` in the order of
+/// the file, `new_segment` is `true` for the first point of each segment.
+/// Reading stops at the first error returned by `on_point`.
+pub fn read_gpx<R: Read>(
+    mut reader: R,
+    on_point: impl FnMut(GpxSegmentKind, bool, RawData) -> Result<()>,
+) -> Result<GpxReadSummary> {
+    let mut head = Vec::with_capacity(PROBE_LIMIT);
+    reader
+        .by_ref()
+        .take(PROBE_LIMIT as u64)
+        .read_to_end(&mut head)?;
+    let mut summary = GpxReadSummary {
+        exported_journey: is_exported_journey_head(&head),
+        ..GpxReadSummary::default()
+    };
 
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"time" => {
-                let raw = reader.read_text(e.name())?;
-                let normalized = match normalizer(&raw) {
-                    Some(v) => {
-                        modified = true;
-                        v
-                    }
-                    None => raw.parse()?,
-                };
+    let reader = BufReader::new(Cursor::new(head).chain(reader));
+    read_gpx_points(reader, normalize_time, &mut summary, on_point)?;
+    Ok(summary)
+}
+
+fn normalize_time(input: &str) -> Option<String> {
+    normalize_step_of_my_world_time(input).or_else(|| normalize_generic_time(input))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PointField {
+    Time,
+    Elevation,
+    Hdop,
+    Speed,
+}
+
+struct PointBuilder {
+    latitude: f64,
+    longitude: f64,
+    time: Option<String>,
+    elevation: Option<String>,
+    hdop: Option<String>,
+    speed: Option<String>,
+    // nesting level inside the point element
+    depth: usize,
+    field: Option<PointField>,
+    text: String,
+}
+
+impl PointBuilder {
+    fn new(element: &BytesStart) -> Result<Self> {
+        let get_coordinate = |name: &str| -> Result<f64> {
+            let attribute = element
+                .try_get_attribute(name)?
+                .ok_or_else(|| anyhow!("Missing `{name}` in GPX point"))?;
+            Ok(attribute.unescape_value()?.trim().parse::<f64>()?)
+        };
+        Ok(PointBuilder {
+            latitude: get_coordinate("lat")?,
+            longitude: get_coordinate("lon")?,
+            time: None,
+            elevation: None,
+            hdop: None,
+            speed: None,
+            depth: 0,
+            field: None,
+            text: String::new(),
+        })
+    }
 
-                writer.write_event(Event::Start(e.to_owned()))?;
-                writer.write_event(Event::Text(BytesText::new(&normalized)))?;
-                writer.write_event(Event::End(e.to_end().to_owned()))?;
+    // Similar to the GPX parser we used before, invalid optional fields are
+    // treated as missing instead of failing the whole file. Invalid times are
+    // recorded in `summary` since losing them changes the result a lot.
+    fn build(self, time_normalizer: TimeNormalizer, summary: &mut GpxReadSummary) -> RawData {
+        let timestamp_ms = self.time.and_then(|time| {
+            let normalized_time = time_normalizer(&time).unwrap_or_else(|| time.clone());
+            let timestamp_ms = DateTime::parse_from_rfc3339(normalized_time.trim())
+                .ok()
+                .map(|x| x.with_timezone(&Utc).timestamp_millis());
+            if timestamp_ms.is_none() {
+                summary.invalid_time_count += 1;
+                if summary.invalid_time_examples.len() < MAX_INVALID_TIME_EXAMPLES {
+                    summary.invalid_time_examples.push(time.trim().to_string());
+                }
             }
+            timestamp_ms
+        });
+        let parse_f32 = |x: Option<String>| x.and_then(|x| x.trim().parse::<f32>().ok());
+        RawData {
+            point: Point {
+                latitude: self.latitude,
+                longitude: self.longitude,
+            },
+            timestamp_ms,
+            accuracy: parse_f32(self.hdop),
+            altitude: parse_f32(self.elevation),
+            speed: parse_f32(self.speed),
+            bearing: None,
+            vertical_accuracy: None,
+            speed_accuracy: None,
+            provider: None,
+            satellite_count: None,
+        }
+    }
+}
+
+fn read_gpx_points<R: BufRead>(
+    reader: R,
+    time_normalizer: TimeNormalizer,
+    summary: &mut GpxReadSummary,
+    mut on_point: impl FnMut(GpxSegmentKind, bool, RawData) -> Result<()>,
+) -> Result<()> {
+    let mut reader = Reader::from_reader(reader);
+    reader.config_mut().trim_text(true);
+    let mut buf = Vec::new();
 
-            Ok(Event::Start(e)) => writer.write_event(Event::Start(e.into_owned()))?,
-            Ok(Event::Empty(e)) => writer.write_event(Event::Empty(e.into_owned()))?,
-            Ok(Event::End(e)) => writer.write_event(Event::End(e.into_owned()))?,
-            Ok(Event::Text(e)) => writer.write_event(Event::Text(e.into_owned()))?,
-            Ok(Event::CData(e)) => writer.write_event(Event::CData(e.into_owned()))?,
-            Ok(Event::Decl(e)) => writer.write_event(Event::Decl(e.into_owned()))?,
-            Ok(Event::Eof) => break,
+    // the kind of the current segment and whether we are still waiting for
+    // its first point.
+    let mut segment: Option<(GpxSegmentKind, bool)> = None;
+    let mut point: Option<PointBuilder> = None;
 
-            Ok(_) => {}
-            Err(e) => anyhow::bail!("XML parse error during GPX time normalize: {e:?}"),
+    let mut emit = |segment: &mut Option<(GpxSegmentKind, bool)>, point: PointBuilder| {
+        if let Some((kind, new_segment)) = segment {
+            on_point(*kind, *new_segment, point.build(time_normalizer, summary))?;
+            *new_segment = false;
         }
+        Ok::<(), anyhow::Error>(())
+    };
 
+    loop {
+        match reader
+            .read_event_into(&mut buf)
+            .context("XML parse error during GPX reading")?
+        {
+            Event::Start(e) => match &mut point {
+                Some(point) => {
+                    point.depth += 1;
+                    if point.depth == 1 {
+                        point.field = match e.local_name().as_ref() {
+                            b"time" => Some(PointField::Time),
+                            b"ele" => Some(PointField::Elevation),
+                            b"hdop" => Some(PointField::Hdop),
+                            b"speed" => Some(PointField::Speed),
+                            _ => None,
+                        };
+                        point.text.clear();
+                    }
+                }
+                None => match (e.local_name().as_ref(), segment) {
+                    (b"trkseg", _) => segment = Some((GpxSegmentKind::Track, true)),
+                    (b"rte", _) => segment = Some((GpxSegmentKind::Route, true)),
+                    (b"trkpt", Some((GpxSegmentKind::Track, _)))
+                    | (b"rtept", Some((GpxSegmentKind::Route, _))) => {
+                        point = Some(PointBuilder::new(&e)?)
+                    }
+                    _ => (),
+                },
+            },
+            Event::Empty(e) if point.is_none() => match (e.local_name().as_ref(), segment) {
+                (b"trkpt", Some((GpxSegmentKind::Track, _)))
+                | (b"rtept", Some((GpxSegmentKind::Route, _))) => {
+                    emit(&mut segment, PointBuilder::new(&e)?)?
+                }
+                _ => (),
+            },
+            Event::Text(e) => {
+                if let Some(point) = &mut point {
+                    if point.field.is_some() {
+                        point.text.push_str(&e.decode()?);
+                    }
+                }
+            }
+            Event::CData(e) => {
+                if let Some(point) = &mut point {
+                    if point.field.is_some() {
+                        point.text.push_str(&e.decode()?);
+                    }
+                }
+            }
+            Event::End(e) => match point.take() {
+                Some(mut current_point) => {
+                    if current_point.depth == 0 {
+                        emit(&mut segment, current_point)?;
+                    } else {
+                        if current_point.depth == 1 {
+                            let text = Some(std::mem::take(&mut current_point.text));
+                            match current_point.field.take() {
+                                Some(PointField::Time) => current_point.time = text,
+                                Some(PointField::Elevation) => current_point.elevation = text,
+                                Some(PointField::Hdop) => current_point.hdop = text,
+                                Some(PointField::Speed) => current_point.speed = text,
+                                None => (),
+                            }
+                        }
+                        current_point.depth -= 1;
+                        point = Some(current_point);
+                    }
+                }
+                None => {
+                    if matches!(e.local_name().as_ref(), b"trkseg" | b"rte") {
+                        segment = None;
+                    }
+                }
+            },
+            Event::Eof => break,
+            _ => (),
+        }
         buf.clear();
     }
-
-    if !modified {
-        Ok(xml.to_owned())
-    } else {
-        Ok(String::from_utf8(writer.into_inner())?)
-    }
+    Ok(())
 }
 
 /// Step Of My World：
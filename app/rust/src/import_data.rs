@@ -1,27 +1,32 @@
-use crate::api::import::{ImportPreprocessor, JourneyInfo};
+use crate::api::import::{FlightLogEntry, ImportPreprocessor, ImportSplitRule, JourneyInfo};
 use crate::flight_track_processor;
-use crate::gps_processor::{Point, PreprocessedData, ProcessResult, RawData, SegmentGapRule};
-use crate::gpx_file_utils::analyze_and_prepare_gpx;
+use crate::gps_processor::{
+    GpsFilter, Point, PreprocessedData, PreprocessingThresholds, ProcessResult, RawData,
+    SegmentGapRule,
+};
+use crate::gpx_file_utils::{self, GpxSegmentKind};
 use crate::journey_bitmap::{
     self, Block, BlockKey, JourneyBitmap, BITMAP_SIZE, MAP_WIDTH, TILE_WIDTH,
 };
 use crate::journey_date_picker::JourneyDatePicker;
 use crate::journey_header::JourneyKind;
+use crate::preprocessor_detector;
+use crate::raw_data_store;
+use crate::storage::RawCsvRow;
+use crate::transport_mode::{self, TimedPoint};
 use crate::{
     gps_processor::{self, GpsPreprocessor},
     journey_vector::{JourneyVector, TrackPoint},
 };
 use anyhow::{Context, Result};
 use auto_context::auto_context;
-use chrono::{DateTime, Local, TimeZone, Utc};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use flate2::read::ZlibDecoder;
-use gpx::{read, Waypoint};
 use kml::types::{Element, Geometry};
 use kml::Kml::Placemark;
 use kml::{Kml, KmlReader};
 use quick_xml::events::Event;
 use quick_xml::{Reader, Writer};
-use std::cell::RefCell;
 use std::io::Cursor;
 use std::result::Result::Ok;
 use std::{fs, vec};
@@ -179,74 +184,131 @@ pub fn load_fow_snapshot_data(fwss_file_path: &str) -> Result<(JourneyBitmap, Op
     }
 }
 
+/// Same as `load_gpx_with_warnings`, the warnings are only logged.
 #[auto_context]
 pub fn load_gpx(file_path: &str) -> Result<(Vec<Vec<RawData>>, ImportPreprocessor)> {
-    // TODO: it is pretty inefficient to read the whole file into memory first.
-    // Some of the GPX files can be very large. Probably we want streaming.
-    let xml = fs::read_to_string(file_path)?;
-    let (xml, preprocessor) = analyze_and_prepare_gpx(&xml)?;
-    let gpx = read(xml.as_bytes())?;
-    let raw_data = load_gpx_raw_data(&gpx)?;
-    Ok((raw_data, preprocessor))
+    let (raw_vector_data, preprocessor, warnings) = load_gpx_with_warnings(file_path)?;
+    if let Some(warnings) = warnings {
+        warn!("[import_data] {file_path}: {warnings}");
+    }
+    Ok((raw_vector_data, preprocessor))
 }
 
-pub fn load_gpx_raw_data(gpx_data: &gpx::Gpx) -> Result<Vec<Vec<RawData>>> {
-    let convert_to_timestamp = |time: &Option<gpx::Time>| -> Result<Option<i64>> {
-        match time {
-            Some(t) => {
-                let s = t.format()?;
-                let dt = DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&s)?);
-                Ok(Some(dt.timestamp_millis()))
+/// The file is parsed in a streaming way, but all points are kept in memory,
+/// see `load_gpx_by_local_day` for files too large for that.
+#[auto_context]
+pub fn load_gpx_with_warnings(
+    file_path: &str,
+) -> Result<(Vec<Vec<RawData>>, ImportPreprocessor, Option<String>)> {
+    let mut track_data: Vec<Vec<RawData>> = Vec::new();
+    let mut route_data: Vec<Vec<RawData>> = Vec::new();
+    let summary = gpx_file_utils::read_gpx(
+        File::open(file_path)?,
+        |segment_kind, new_segment, raw_data| {
+            let data = match segment_kind {
+                GpxSegmentKind::Track => &mut track_data,
+                GpxSegmentKind::Route => &mut route_data,
+            };
+            if new_segment {
+                data.push(Vec::new());
             }
-            None => Ok(None),
-        }
-    };
+            if let Some(segment) = data.last_mut() {
+                segment.push(raw_data);
+            }
+            Ok(())
+        },
+    )?;
+    track_data.append(&mut route_data);
 
-    let waypoint_to_rawdata = |point: &Waypoint| -> Result<RawData> {
-        Ok(RawData {
-            point: Point {
-                latitude: point.point().y(),
-                longitude: point.point().x(),
-            },
-            timestamp_ms: convert_to_timestamp(&point.time)?,
-            accuracy: point.hdop.map(|v| v as f32),
-            altitude: point.elevation.map(|v| v as f32),
-            speed: point.speed.map(|v| v as f32),
-        })
+    let preprocessor = if summary.exported_journey {
+        ImportPreprocessor::None
+    } else {
+        let recommendation = preprocessor_detector::recommend_preprocessor(&track_data);
+        info!("Recommended preprocessor for GPX: {recommendation:?}");
+        recommendation.preprocessor
     };
+    Ok((track_data, preprocessor, summary.warnings()))
+}
 
-    let track_data = gpx_data
-        .tracks
-        .iter()
-        .flat_map(|t| t.segments.iter())
-        .map(|s| {
-            s.points
-                .iter()
-                .map(waypoint_to_rawdata)
-                .collect::<Result<Vec<_>>>()
-        })
-        .filter_map(Result::ok)
-        .filter(|v| !v.is_empty());
+// A day of 1Hz data is ~86k points, this only matters for files without
+// timestamps.
+const MAX_POINTS_PER_LOCAL_DAY_PART: usize = 1_000_000;
 
-    let route_data = gpx_data
-        .routes
-        .iter()
-        .map(|r| {
-            r.points
-                .iter()
-                .map(waypoint_to_rawdata)
-                .collect::<Result<Vec<_>>>()
-        })
-        .filter_map(Result::ok)
-        .filter(|v| !v.is_empty());
+/// For GPX files too large to be kept in memory, e.g. a multi-year export.
+/// The data is split by local day (like `ImportSplitRule::LocalDay`) while
+/// reading and `on_part` is called with each part as soon as it ends, with the
+/// preprocessor recommended for that part, so at most one day of data is in
+/// memory. Unlike `load_gpx`, routes are not moved after the tracks. Returns
+/// the warnings.
+#[auto_context]
+pub fn load_gpx_by_local_day(
+    file_path: &str,
+    mut on_part: impl FnMut(Vec<Vec<RawData>>, ImportPreprocessor) -> Result<()>,
+) -> Result<Option<String>> {
+    let exported_journey = gpx_file_utils::is_exported_journey(File::open(file_path)?)?;
+    let mut flush = |part: Vec<Vec<RawData>>| {
+        let part: Vec<Vec<RawData>> = part.into_iter().filter(|x| !x.is_empty()).collect();
+        if part.is_empty() {
+            return Ok(());
+        }
+        let preprocessor = if exported_journey {
+            ImportPreprocessor::None
+        } else {
+            preprocessor_detector::recommend_preprocessor(&part).preprocessor
+        };
+        on_part(part, preprocessor)
+    };
 
-    Ok(track_data.chain(route_data).collect())
+    let mut part: Vec<Vec<RawData>> = Vec::new();
+    let mut part_point_count = 0;
+    let mut part_date: Option<NaiveDate> = None;
+    let summary = gpx_file_utils::read_gpx(
+        File::open(file_path)?,
+        |_segment_kind, new_segment, raw_data| {
+            let date = raw_data.timestamp_ms.and_then(|timestamp_ms| {
+                Local
+                    .timestamp_millis_opt(timestamp_ms)
+                    .single()
+                    .map(|x| x.date_naive())
+            });
+            let new_day = date.is_some() && part_date.is_some() && date != part_date;
+            if new_day || part_point_count >= MAX_POINTS_PER_LOCAL_DAY_PART {
+                // the segment continues in the next part
+                flush(std::mem::take(&mut part))?;
+                part_point_count = 0;
+                part.push(Vec::new());
+            }
+            if date.is_some() {
+                part_date = date;
+            }
+            if new_segment || part.is_empty() {
+                part.push(Vec::new());
+            }
+            if let Some(segment) = part.last_mut() {
+                segment.push(raw_data);
+                part_point_count += 1;
+            }
+            Ok(())
+        },
+    )?;
+    flush(part)?;
+    Ok(summary.warnings())
 }
 
 /// Load and parse KML safely, skipping invalid <description> blocks.
+/// KMZ (zipped KML) is also supported.
 #[auto_context]
 pub fn load_kml(file_path: &str) -> Result<(Vec<Vec<RawData>>, ImportPreprocessor)> {
-    let xml = fs::read_to_string(file_path)?;
+    let is_kmz = Path::new(file_path)
+        .extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| x.eq_ignore_ascii_case("kmz"));
+    let xml = if is_kmz {
+        read_kml_from_kmz(file_path)?
+    } else {
+        fs::read_to_string(file_path)?
+    };
+    let known_preprocessor = detect_kml_preprocessor(&xml);
     let (cleaned_xml, _descriptions) = read_kml_description_and_remove(&xml)?;
     // TODO: pass _descriptions to journey_info if needed later
     let mut kml_reader = KmlReader::<_, f64>::from_reader(Cursor::new(cleaned_xml));
@@ -257,8 +319,87 @@ pub fn load_kml(file_path: &str) -> Result<(Vec<Vec<RawData>>, ImportPreprocesso
         raw_vector_data = read_line_string(&flatten_data)?
     }
 
-    // TODO: we currently do not have preprocessor detection for KML
-    Ok((raw_vector_data, ImportPreprocessor::Generic))
+    let preprocessor = match known_preprocessor {
+        Some(preprocessor) => preprocessor,
+        None => preprocessor_detector::recommend_preprocessor(&raw_vector_data).preprocessor,
+    };
+    Ok((raw_vector_data, preprocessor))
+}
+
+/// A KMZ is a zip file with a main KML file (usually `doc.kml`) and other
+/// resources like images. We only care about the main KML file.
+#[auto_context]
+fn read_kml_from_kmz(file_path: &str) -> Result<String> {
+    let mut zip = zip::ZipArchive::new(File::open(file_path)?)?;
+    let kml_file_names: Vec<String> = zip
+        .file_names()
+        .filter(|name| name.to_lowercase().ends_with(".kml"))
+        .map(|name| name.to_string())
+        .collect();
+    // according to the KMZ spec, the first KML file is the main one, but
+    // `doc.kml` is the conventional name so let's prefer it.
+    let kml_file_name = kml_file_names
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case("doc.kml"))
+        .or(kml_file_names.first())
+        .ok_or_else(|| anyhow!("No KML file found in KMZ"))?;
+
+    let mut xml = String::new();
+    zip.by_name(kml_file_name)?.read_to_string(&mut xml)?;
+    Ok(xml)
+}
+
+// Some producers are known to be a specific kind of data, e.g. flight trackers
+// or our own export, for others we need to look at the data.
+// Signatures are matched against the lowercased beginning of the file, usually
+// they are in the producer's `<snippet>`, `<description>` or links.
+const KNOWN_KML_PRODUCERS: &[(&str, ImportPreprocessor)] = &[
+    ("flightaware", ImportPreprocessor::FlightTrack),
+    ("flightradar24", ImportPreprocessor::FlightTrack),
+    ("generated by memolanes", ImportPreprocessor::None),
+    // 两步路
+    ("2bulu.com", ImportPreprocessor::Generic),
+    ("tbulukml", ImportPreprocessor::Generic),
+    // 六只脚
+    ("foooooot.com", ImportPreprocessor::Generic),
+    // 行者
+    ("imxingzhe.com", ImportPreprocessor::Generic),
+    ("oruxmaps", ImportPreprocessor::Generic),
+    // 一生足迹, only records a point when the user moved far enough.
+    ("stepofmyworld", ImportPreprocessor::Spare),
+    ("一生足迹", ImportPreprocessor::Spare),
+];
+
+fn detect_kml_preprocessor(xml: &str) -> Option<ImportPreprocessor> {
+    const PROBE_LIMIT: usize = 8 * 1024;
+
+    let head = xml
+        .chars()
+        .take(PROBE_LIMIT)
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    KNOWN_KML_PRODUCERS
+        .iter()
+        .find(|(signature, _)| head.contains(signature))
+        .map(|(_, preprocessor)| *preprocessor)
+}
+
+// KML uses `xsd:dateTime`, the timezone part is optional and we treat it as
+// UTC if it is missing.
+fn parse_kml_time(when: &str) -> Result<DateTime<Utc>> {
+    let when = when.trim();
+    match DateTime::parse_from_rfc3339(when) {
+        Ok(datetime) => Ok(datetime.with_timezone(&Utc)),
+        Err(_) => Ok(NaiveDateTime::parse_from_str(when, "%Y-%m-%dT%H:%M:%S%.f")
+            .with_context(|| format!("Invalid KML time: {when}"))?
+            .and_utc()),
+    }
+}
+
+// The KML parser may or may not keep the namespace prefix (e.g. `gx:Track`).
+fn is_kml_element(element: &Element, name: &str) -> bool {
+    element.name.rsplit(':').next() == Some(name)
 }
 
 /// 2bulu generated KML contains HTML tags in <description>, which breaks the KML parser.
@@ -294,158 +435,216 @@ fn read_kml_description_and_remove(xml: &str) -> Result<(String, Vec<String>)> {
 }
 
 #[auto_context]
-fn read_track(flatten_data: &[Kml]) -> Result<Vec<Vec<RawData>>> {
-    let parse_line = |coord: &Option<String>, when: &Option<String>| -> Result<Option<RawData>> {
-        let coord: Vec<&str> = match coord {
-            Some(coord) => coord.split_whitespace().collect(),
-            None => return Ok(None),
-        };
+fn read_gx_track(track: &Element) -> Result<Vec<RawData>> {
+    let parse_line =
+        |coord: &Option<String>, when: Option<&Option<String>>| -> Result<Option<RawData>> {
+            let coord: Vec<&str> = match coord {
+                Some(coord) => coord.split_whitespace().collect(),
+                None => return Ok(None),
+            };
+            if coord.len() < 2 {
+                return Ok(None);
+            }
+
+            let timestamp = match when {
+                Some(Some(when)) => Some(parse_kml_time(when)?),
+                _ => None,
+            };
 
-        let timestamp = match when {
-            None => None,
-            Some(when) => Some(DateTime::<Utc>::from(DateTime::parse_from_rfc3339(when)?)),
+            Ok(Some(gps_processor::RawData {
+                point: Point {
+                    latitude: coord[1].parse::<f64>()?,
+                    longitude: coord[0].parse::<f64>()?,
+                },
+                timestamp_ms: timestamp.map(|x| x.timestamp_millis()),
+                accuracy: None,
+                altitude: if coord.len() >= 3 {
+                    Some(coord[2].parse::<f32>()?)
+                } else {
+                    None
+                },
+                speed: None,
+                bearing: None,
+                vertical_accuracy: None,
+                speed_accuracy: None,
+                provider: None,
+                satellite_count: None,
+            }))
         };
 
-        Ok(Some(gps_processor::RawData {
-            point: Point {
-                latitude: coord[1].parse::<f64>()?,
-                longitude: coord[0].parse::<f64>()?,
-            },
-            timestamp_ms: timestamp.map(|x| x.timestamp_millis()),
-            accuracy: None,
-            altitude: if coord.len() >= 3 {
-                Some(coord[2].parse::<f32>()?)
-            } else {
-                None
-            },
-            speed: None,
-        }))
-    };
+    let mut when_list = Vec::new();
+    let mut coord_list = Vec::new();
+    track.children.iter().for_each(|e| {
+        if is_kml_element(e, "when") {
+            when_list.push(&e.content);
+        } else if is_kml_element(e, "coord") {
+            coord_list.push(&e.content);
+        }
+    });
 
-    let segments = flatten_data
+    let missing_timestamp = when_list.is_empty();
+    if !missing_timestamp && when_list.len() != coord_list.len() {
+        return Err(anyhow!(
+            "number of `when` does not match number of `coord`. when = {}, coord = {}",
+            when_list.len(),
+            coord_list.len()
+        ));
+    }
+
+    let mut raw_vector_data_segment: Vec<RawData> = Vec::new();
+    for (i, coord) in coord_list.into_iter().enumerate() {
+        if let Some(raw_data) = parse_line(coord, when_list.get(i).copied())? {
+            raw_vector_data_segment.push(raw_data);
+        }
+    }
+    Ok(raw_vector_data_segment)
+}
+
+/// Reads `gx:Track` and `gx:MultiTrack`. Each track is a segment, unless it is
+/// in a `gx:MultiTrack` with `gx:interpolate` enabled, which means the tracks
+/// should be connected.
+#[auto_context]
+fn read_track(flatten_data: &[Kml]) -> Result<Vec<Vec<RawData>>> {
+    let elements = flatten_data
         .iter()
         .filter_map(|k| match k {
             Kml::Placemark(p) => Some(&p.children),
             _ => None,
         })
-        .flat_map(|arr| arr.iter().filter(|e| e.name == "Track"));
+        .flatten();
 
     let mut raw_vector_data: Vec<Vec<RawData>> = Vec::new();
+    let mut push_segment = |segment: Vec<RawData>| {
+        if !segment.is_empty() {
+            raw_vector_data.push(segment);
+        }
+    };
 
-    for segment in segments {
-        let mut when_list = Vec::new();
-        let mut coord_list = Vec::new();
-        segment.children.iter().for_each(|e| {
-            if e.name == "when" {
-                when_list.push(&e.content);
-            } else if e.name == "coord" {
-                coord_list.push(&e.content);
+    for element in elements {
+        if is_kml_element(element, "Track") {
+            push_segment(read_gx_track(element)?);
+        } else if is_kml_element(element, "MultiTrack") {
+            let interpolate = element
+                .children
+                .iter()
+                .find(|e| is_kml_element(e, "interpolate"))
+                .and_then(|e| e.content.as_deref())
+                .is_some_and(|x| matches!(x.trim(), "1" | "true"));
+            let tracks = element
+                .children
+                .iter()
+                .filter(|e| is_kml_element(e, "Track"))
+                .map(read_gx_track)
+                .collect::<Result<Vec<_>>>()?;
+            if interpolate {
+                push_segment(tracks.into_iter().flatten().collect());
+            } else {
+                tracks.into_iter().for_each(&mut push_segment);
             }
-        });
-
-        let missing_timestamp = when_list.is_empty();
-        if !missing_timestamp && when_list.len() != coord_list.len() {
-            return Err(anyhow!(
-                "number of `when` does not match number of `coord`. when = {}, coord = {}",
-                when_list.len(),
-                coord_list.len()
-            ));
         }
+    }
 
-        let mut raw_vector_data_segment: Vec<RawData> = Vec::new();
-        for i in 0..coord_list.len() {
-            let parse_result = parse_line(
-                coord_list[i],
-                if missing_timestamp {
-                    &None
-                } else {
-                    when_list[i]
+    Ok(raw_vector_data)
+}
+
+// `Point`s are connected as a track, while each `LineString` is a separate
+// segment.
+fn read_geometry(
+    geometry: &Geometry,
+    timestamp_ms: Option<i64>,
+    point_segment: &mut Vec<RawData>,
+    raw_vector_data: &mut Vec<Vec<RawData>>,
+) {
+    match geometry {
+        Geometry::Point(point) => {
+            point_segment.push(RawData {
+                point: Point {
+                    latitude: point.coord.y,
+                    longitude: point.coord.x,
                 },
-            )?;
-            match parse_result {
-                None => (),
-                Some(raw_data) => raw_vector_data_segment.push(raw_data),
+                timestamp_ms,
+                accuracy: None,
+                altitude: None,
+                speed: None,
+                bearing: None,
+                vertical_accuracy: None,
+                speed_accuracy: None,
+                provider: None,
+                satellite_count: None,
+            });
+        }
+        Geometry::LineString(line_string) => {
+            let segment: Vec<RawData> = line_string
+                .coords
+                .iter()
+                .map(|coord| RawData {
+                    point: Point {
+                        latitude: coord.y,
+                        longitude: coord.x,
+                    },
+                    timestamp_ms: None,
+                    accuracy: None,
+                    altitude: None,
+                    speed: None,
+                    bearing: None,
+                    vertical_accuracy: None,
+                    speed_accuracy: None,
+                    provider: None,
+                    satellite_count: None,
+                })
+                .collect();
+            if !segment.is_empty() {
+                raw_vector_data.push(segment);
             }
         }
-        if !raw_vector_data_segment.is_empty() {
-            raw_vector_data.push(raw_vector_data_segment);
+        Geometry::MultiGeometry(multi_geometry) => {
+            for geometry in &multi_geometry.geometries {
+                read_geometry(geometry, timestamp_ms, point_segment, raw_vector_data);
+            }
         }
+        _ => (),
     }
-
-    Ok(raw_vector_data)
 }
 
 fn read_line_string(flatten_data: &[Kml]) -> Result<Vec<Vec<RawData>>> {
     let mut raw_vector_data: Vec<Vec<RawData>> = Vec::new();
 
-    let convert_to_timestamp = |when: Option<String>| -> Option<i64> {
-        match when {
-            None => None,
-            Some(when) => {
-                let datetime = DateTime::parse_from_rfc3339(&when).ok()?;
-                Some(datetime.timestamp_millis())
-            }
-        }
-    };
-
     let extract_time_from_children = |timestamp_element: &Element| -> Option<String> {
         timestamp_element
             .children
             .iter()
-            .find(|e| e.name == "when")
+            .find(|e| is_kml_element(e, "when"))
             .and_then(|when_element| when_element.content.clone())
     };
 
-    let raw_vector_data_segment: RefCell<Vec<RawData>> = RefCell::new(Vec::new());
+    let mut point_segment: Vec<RawData> = Vec::new();
 
-    flatten_data.iter().for_each(|k| {
+    for k in flatten_data {
         if let Placemark(p) = k {
             if let Some(geometry) = &p.geometry {
-                match geometry {
-                    Geometry::Point(point) => {
-                        let timestamp_ms = convert_to_timestamp(
-                            p.children
-                                .iter()
-                                .find(|e| e.name == "TimeStamp")
-                                .and_then(extract_time_from_children),
-                        );
-                        raw_vector_data_segment.borrow_mut().push(RawData {
-                            point: Point {
-                                latitude: point.coord.y,
-                                longitude: point.coord.x,
-                            },
-                            timestamp_ms,
-                            accuracy: None,
-                            altitude: None,
-                            speed: None,
-                        });
-                    }
-                    Geometry::LineString(line_string) => {
-                        line_string.coords.iter().for_each(|coord| {
-                            raw_vector_data_segment.borrow_mut().push(RawData {
-                                point: Point {
-                                    latitude: coord.y,
-                                    longitude: coord.x,
-                                },
-                                timestamp_ms: None,
-                                accuracy: None,
-                                altitude: None,
-                                speed: None,
-                            });
-                        });
-
-                        // we treat different `LineString` as different segments
-                        if !raw_vector_data_segment.borrow().is_empty() {
-                            raw_vector_data.push(raw_vector_data_segment.replace(Vec::new()));
-                        }
-                    }
-                    _ => (),
+                let timestamp_ms = p
+                    .children
+                    .iter()
+                    .find(|e| is_kml_element(e, "TimeStamp"))
+                    .and_then(extract_time_from_children)
+                    .and_then(|when| parse_kml_time(&when).ok())
+                    .map(|x| x.timestamp_millis());
+                let segment_count = raw_vector_data.len();
+                read_geometry(
+                    geometry,
+                    timestamp_ms,
+                    &mut point_segment,
+                    &mut raw_vector_data,
+                );
+                // a line in this placemark splits the track of points
+                if raw_vector_data.len() != segment_count && !point_segment.is_empty() {
+                    raw_vector_data.insert(segment_count, std::mem::take(&mut point_segment));
                 }
             }
         }
-    });
-    if !raw_vector_data_segment.borrow().is_empty() {
-        raw_vector_data.push(raw_vector_data_segment.into_inner());
+    }
+    if !point_segment.is_empty() {
+        raw_vector_data.push(point_segment);
     }
     Ok(raw_vector_data)
 }
@@ -460,27 +659,124 @@ fn flatten_kml(kml: Kml) -> Vec<Kml> {
         k => vec![k],
     }
 }
+
+/// Loads a CSV file recorded in raw data mode, rows are kept in the recorded
+/// order. It could be compressed or not.
+#[auto_context]
+pub fn load_raw_data_csv(file_path: &str) -> Result<Vec<RawData>> {
+    let mut reader = raw_data_store::open_csv_reader(Path::new(file_path))?;
+    reader
+        .deserialize::<RawCsvRow>()
+        .map(|row| Ok(row?.to_raw_data()))
+        .collect()
+}
+
+// The airport could be just the code (OpenFlights) or something like
+// "Shanghai / Pudong (PVG/ZSPD)" (flightdiary).
+fn airport_code_of_flight_log(field: &str) -> Option<String> {
+    let field = field.trim();
+    let code = match (field.rfind('('), field.rfind(')')) {
+        (Some(start), Some(end)) if start < end => field[start + 1..end].split('/').next()?,
+        _ => field,
+    }
+    .trim();
+    if (3..=4).contains(&code.len()) && code.chars().all(|x| x.is_ascii_alphanumeric()) {
+        Some(code.to_ascii_uppercase())
+    } else {
+        None
+    }
+}
+
+/// Loads a flight history CSV, e.g. exported from OpenFlights or flightdiary.
+/// Columns are matched by the header (case insensitive): `date`, `from`, `to`
+/// are required, `airline` and `flight number` are optional. Rows that cannot
+/// be parsed are skipped.
+#[auto_context]
+pub fn load_flight_log_csv(file_path: &str) -> Result<Vec<FlightLogEntry>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(file_path)?;
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|x| x.trim().to_lowercase().replace('_', " "))
+        .collect();
+    let column = |names: &[&str]| headers.iter().position(|x| names.contains(&x.as_str()));
+    let (date_column, from_column, to_column) =
+        match (column(&["date"]), column(&["from"]), column(&["to"])) {
+            (Some(date), Some(from), Some(to)) => (date, from, to),
+            _ => bail!("Missing columns, headers: {headers:?}"),
+        };
+    let airline_column = column(&["airline"]);
+    let flight_number_column = column(&["flight number", "flight"]);
+
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|x| record.get(x))
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+        };
+        let date = field(Some(date_column))
+            .and_then(|x| NaiveDate::parse_from_str(x.get(..10)?, "%Y-%m-%d").ok());
+        let origin = field(Some(from_column)).and_then(|x| airport_code_of_flight_log(&x));
+        let destination = field(Some(to_column)).and_then(|x| airport_code_of_flight_log(&x));
+        match (date, origin, destination) {
+            (Some(date), Some(origin), Some(destination)) => entries.push(FlightLogEntry {
+                date,
+                origin,
+                destination,
+                airline: field(airline_column),
+                flight_number: field(flight_number_column),
+            }),
+            _ => warn!("[load_flight_log_csv] Skipping invalid row: {record:?}"),
+        }
+    }
+    Ok(entries)
+}
+
 /// `segment_gap_rule_for_preprocessor = None` meaning disable preprocessor
 pub fn journey_vector_from_raw_data_with_gps_preprocessor(
     raw_data: &[Vec<RawData>],
     segment_gap_rule_for_preprocessor: Option<SegmentGapRule>,
+) -> Option<JourneyVector> {
+    journey_vector_from_raw_data_with_gps_preprocessor_and_filter(
+        raw_data,
+        segment_gap_rule_for_preprocessor
+            .map(PreprocessingThresholds::from_segment_gap_rule)
+            .as_ref(),
+        GpsFilter::None,
+    )
+}
+
+/// Same as `journey_vector_from_raw_data_with_gps_preprocessor` but with all
+/// the thresholds, the `filter` is ignored when the preprocessor is disabled.
+pub fn journey_vector_from_raw_data_with_gps_preprocessor_and_filter(
+    raw_data: &[Vec<RawData>],
+    thresholds_for_preprocessor: Option<&PreprocessingThresholds>,
+    filter: GpsFilter,
 ) -> Option<JourneyVector> {
     let processed_data = raw_data.iter().flat_map(move |x| {
         // we handle each segment separately
-        let mut gps_preprocessor =
-            segment_gap_rule_for_preprocessor.map(GpsPreprocessor::new_with_rule);
+        let mut gps_preprocessor = thresholds_for_preprocessor.map(|thresholds| {
+            GpsPreprocessor::new_with_thresholds_and_filter(thresholds.clone(), filter)
+        });
 
         let mut first = true;
         x.iter().map(move |raw_data| {
-            let process_result = match &mut gps_preprocessor {
-                Some(preprocessor) => preprocessor.preprocess(raw_data),
+            let (process_result, raw_data) = match &mut gps_preprocessor {
+                Some(preprocessor) => preprocessor.preprocess_and_filter(raw_data),
                 None => {
-                    if first {
+                    let process_result = if first {
                         first = false;
                         ProcessResult::NewSegment
                     } else {
                         ProcessResult::Append
-                    }
+                    };
+                    (process_result, raw_data.clone())
                 }
             };
 
@@ -505,6 +801,71 @@ pub fn journey_vector_from_raw_data_with_flight_track_processor(
     flight_track_processor::process(raw_data)
 }
 
+/// Split raw data into multiple journeys, e.g. for a multi-day trip recorded in
+/// a single file. Data without timestamp stays with the previous data.
+pub fn split_raw_vector_data(
+    raw_vector_data: &[Vec<RawData>],
+    split_rule: ImportSplitRule,
+) -> Vec<Vec<Vec<RawData>>> {
+    let local_date = |timestamp_ms: i64| {
+        Local
+            .timestamp_millis_opt(timestamp_ms)
+            .single()
+            .map(|x| x.date_naive())
+    };
+    // the first timestamp of each run of the same mode, except the first one
+    let transport_mode_split_timestamps_sec: Vec<i64> = match split_rule {
+        ImportSplitRule::TransportMode => {
+            let points: Vec<TimedPoint> = timed_segments(raw_vector_data).concat();
+            transport_mode::split_by_transport_mode(&points)
+                .iter()
+                .skip(1)
+                .map(|(_, range)| points[range.start].timestamp_sec)
+                .collect()
+        }
+        ImportSplitRule::LocalDay | ImportSplitRule::TimeGap { .. } => vec![],
+    };
+    let should_split = |last_timestamp_ms: i64, timestamp_ms: i64| match split_rule {
+        ImportSplitRule::LocalDay => local_date(last_timestamp_ms) != local_date(timestamp_ms),
+        ImportSplitRule::TimeGap { gap_minutes } => {
+            timestamp_ms - last_timestamp_ms > gap_minutes as i64 * 60 * 1000
+        }
+        ImportSplitRule::TransportMode => transport_mode_split_timestamps_sec
+            .iter()
+            .any(|x| last_timestamp_ms / 1000 < *x && *x <= timestamp_ms / 1000),
+    };
+
+    let mut results = Vec::new();
+    let mut current_journey: Vec<Vec<RawData>> = Vec::new();
+    let mut last_timestamp_ms: Option<i64> = None;
+    for segment in raw_vector_data {
+        let mut current_segment = Vec::new();
+        for raw_data in segment {
+            if let Some(timestamp_ms) = raw_data.timestamp_ms {
+                if let Some(last_timestamp_ms) = last_timestamp_ms {
+                    if should_split(last_timestamp_ms, timestamp_ms) {
+                        if !current_segment.is_empty() {
+                            current_journey.push(std::mem::take(&mut current_segment));
+                        }
+                        if !current_journey.is_empty() {
+                            results.push(std::mem::take(&mut current_journey));
+                        }
+                    }
+                }
+                last_timestamp_ms = Some(timestamp_ms);
+            }
+            current_segment.push(raw_data.clone());
+        }
+        if !current_segment.is_empty() {
+            current_journey.push(current_segment);
+        }
+    }
+    if !current_journey.is_empty() {
+        results.push(current_journey);
+    }
+    results
+}
+
 pub fn journey_info_from_raw_vector_data(raw_vector_data: &[Vec<RawData>]) -> JourneyInfo {
     let time_from_raw_data = |raw_data: &RawData| {
         raw_data
@@ -537,5 +898,14 @@ pub fn journey_info_from_raw_vector_data(raw_vector_data: &[Vec<RawData>]) -> Jo
         end_time: journey_date_picker.max_time(),
         note: None,
         journey_kind: JourneyKind::DefaultKind,
+        transport_mode: transport_mode::classify_journey(&timed_segments(raw_vector_data)),
     }
 }
+
+/// Data without timestamp is skipped.
+pub fn timed_segments(raw_vector_data: &[Vec<RawData>]) -> Vec<Vec<TimedPoint>> {
+    raw_vector_data
+        .iter()
+        .map(|segment| segment.iter().filter_map(TimedPoint::of_raw_data).collect())
+        .collect()
+}
@@ -3,8 +3,10 @@ use auto_context::auto_context;
 use chrono::{DateTime, NaiveDate, Utc};
 use flutter_rust_bridge::frb;
 use protobuf::EnumOrUnknown;
+use std::str::FromStr;
 use strum_macros::EnumIter;
 
+use crate::transport_mode::TransportMode;
 use crate::{protos, utils};
 
 #[derive(Copy, Clone, Debug, EnumIter, PartialEq, Eq, Hash)]
@@ -104,6 +106,7 @@ pub struct JourneyHeader {
     pub journey_kind: JourneyKind,
     pub note: Option<String>,
     pub postprocessor_algo: Option<String>,
+    pub transport_mode: Option<TransportMode>,
 }
 
 impl JourneyHeader {
@@ -134,6 +137,10 @@ impl JourneyHeader {
             }),
             note: proto.note,
             postprocessor_algo: proto.postprocessor_algo,
+            // unknown modes (e.g. from a newer version) are ignored
+            transport_mode: proto
+                .transport_mode
+                .and_then(|x| TransportMode::from_str(&x).ok()),
         })
     }
 
@@ -150,6 +157,7 @@ impl JourneyHeader {
             journey_kind,
             note,
             postprocessor_algo,
+            transport_mode,
         } = self;
         let mut proto = protos::journey::Header::new();
         proto.id = id;
@@ -163,6 +171,7 @@ impl JourneyHeader {
         proto.kind.0 = Some(Box::new(journey_kind.to_proto()));
         proto.note = note;
         proto.postprocessor_algo = postprocessor_algo;
+        proto.transport_mode = transport_mode.map(|x| x.to_string());
         proto
     }
 }
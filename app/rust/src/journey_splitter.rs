@@ -0,0 +1,105 @@
+/* Splitting the ongoing journey into multiple journeys when finalizing, so a
+long recording (e.g. a 40-hour road trip) shows up on every day it covers
+instead of a single day picked by `JourneyDatePicker`.
+
+We split between two consecutive points (including the ones across segments)
+if they are on different local dates or the time gap between them is too
+long. Points are not shared between the resulting journeys, so the line
+between the two points where we split is dropped, which is usually too short
+to notice.
+
+Disabled by default, which keeps the whole recording as one journey.
+*/
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use auto_context::auto_context;
+use chrono::{DateTime, NaiveDate, TimeZone};
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+use crate::transport_mode::TimedPoint;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[frb(non_opaque)]
+pub struct JourneySplitOptions {
+    pub split_at_local_date: bool,
+    /// `None` to disable.
+    pub max_gap_mins: Option<u32>,
+}
+
+impl JourneySplitOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.split_at_local_date || self.max_gap_mins.is_some()
+    }
+
+    #[auto_context]
+    pub fn validate(&self) -> Result<()> {
+        if self.max_gap_mins == Some(0) {
+            bail!("`max_gap_mins` must be positive");
+        }
+        Ok(())
+    }
+}
+
+// Stored as JSON in settings.
+impl FromStr for JourneySplitOptions {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl fmt::Display for JourneySplitOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(self).map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+fn local_date<Tz: TimeZone>(point: &TimedPoint, tz: &Tz) -> Option<NaiveDate> {
+    DateTime::from_timestamp(point.timestamp_sec, 0).map(|x| x.with_timezone(tz).date_naive())
+}
+
+/// `segments` should be ordered by time, the local dates are based on `tz`.
+/// Returns the segments of each journey, ordered by time.
+pub fn split_segments<Tz: TimeZone>(
+    segments: &[Vec<TimedPoint>],
+    options: &JourneySplitOptions,
+    tz: &Tz,
+) -> Vec<Vec<Vec<TimedPoint>>> {
+    let should_split = |a: &TimedPoint, b: &TimedPoint| {
+        (options.split_at_local_date && local_date(a, tz) != local_date(b, tz))
+            || options
+                .max_gap_mins
+                .is_some_and(|x| b.timestamp_sec - a.timestamp_sec > x as i64 * 60)
+    };
+
+    let mut journeys: Vec<Vec<Vec<TimedPoint>>> = Vec::new();
+    let mut last_point: Option<&TimedPoint> = None;
+    for segment in segments {
+        let mut new_segment = true;
+        for point in segment {
+            if last_point.is_none_or(|x| should_split(x, point)) {
+                journeys.push(Vec::new());
+                new_segment = true;
+            }
+            if let Some(journey) = journeys.last_mut() {
+                if new_segment {
+                    journey.push(Vec::new());
+                    new_segment = false;
+                }
+                if let Some(segment) = journey.last_mut() {
+                    segment.push(point.clone());
+                }
+            }
+            last_point = Some(point);
+        }
+    }
+    journeys
+}
@@ -12,10 +12,16 @@ mod frb_generated; /* AUTO INJECTED BY flutter_rust_bridge. This line may not be
 #[rustfmt::skip]
 pub mod build_info;
 
+pub mod airports;
 pub mod api;
 pub mod archive;
+pub mod auto_finalize;
+pub mod backup_store;
 pub mod cache_db;
+pub mod db_encryption;
+pub mod duplicate_detector;
 pub mod export_data;
+pub mod flight_detector;
 pub mod flight_track_processor;
 pub mod gps_processor;
 pub mod gpx_file_utils;
@@ -25,11 +31,17 @@ pub mod journey_bitmap;
 pub mod journey_data;
 pub mod journey_date_picker;
 pub mod journey_header;
+pub mod journey_splitter;
 pub mod journey_vector;
 mod logs;
 pub mod main_db;
 pub mod merged_journey_builder;
+pub mod places;
+pub mod preprocessor_detector;
+pub mod privacy_zone;
 mod protos;
+pub mod raw_data_store;
 pub mod renderer;
 pub mod storage;
+pub mod transport_mode;
 pub mod utils;
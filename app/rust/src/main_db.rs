@@ -1,21 +1,33 @@
 extern crate simplelog;
 use anyhow::{Context, Result};
 use auto_context::auto_context;
-use chrono::{DateTime, Local, NaiveDate, Timelike, Utc};
+use chrono::{DateTime, Days, Local, NaiveDate, TimeZone, Utc};
+use flutter_rust_bridge::frb;
 use protobuf::Message;
 use rusqlite::{Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::error::Error;
+use std::fmt;
 use std::path::Path;
 use std::str::FromStr;
 use uuid::Uuid;
 
+use crate::auto_finalize::{self, AutoFinalizeExplanation, AutoFinalizePolicy, AutoFinalizeReason};
 pub use crate::cache_db::CacheEntry;
+use crate::db_encryption::{self, DbCipher};
+use crate::duplicate_detector;
+use crate::flight_detector::{self, TrackPart};
+use crate::flight_track_processor;
 use crate::gps_processor::{self, GpsPostprocessor, PreprocessedData, ProcessResult};
 use crate::journey_data::JourneyData;
 use crate::journey_date_picker::JourneyDatePicker;
 use crate::journey_header::{JourneyHeader, JourneyKind, JourneyType};
-use crate::journey_vector::{JourneyVector, TrackPoint};
+use crate::journey_splitter::{self, JourneySplitOptions};
+use crate::journey_vector::{JourneyVector, TrackPoint, TrackSegment};
+use crate::places::{self, Place, PlaceVisit, StayPoint};
+use crate::privacy_zone::{PrivacyZone, PrivacyZoneShape};
+use crate::transport_mode::{self, TimedPoint, TransportMode};
 use crate::{protos, utils};
 
 /* The main database, we are likely to store a lot of protobuf bytes in it,
@@ -29,7 +41,21 @@ when finalizing the journey.
 `journey` keeps all finalized journeys. It stores most data as raw protobuf
 bytes and some index for faster lookup. Instead of storing a single blob, it has
 two parts: header and data, so most common operation only need to fetch and
-deserialize the header.
+deserialize the header. It also keeps a `fingerprint` of the data for finding
+duplicated journeys (see `duplicate_detector`).
+
+`place` and `place_visit` keep the places the user spent time at (see
+`places`). Visits are detected from the data of a journey, so they are deleted
+together with the journey, and places without any visit are deleted too.
+
+`privacy_zone` keeps the areas hidden from exports (see `privacy_zone`), the
+shape is stored as JSON.
+
+If a key is provided, journey headers and data, points of the ongoing journey,
+places and privacy zones are encrypted (see `db_encryption`). For the ongoing
+journey and places, `lat` and `lng` are NULL and the encrypted point is stored
+in `point` instead. For privacy zones, `name` and `shape` are NULL and `data`
+is the encrypted JSON of both.
 */
 
 // 3 is the zstd default
@@ -69,9 +95,24 @@ fn open_db_and_run_migration(
     Ok(conn)
 }
 
+/// Whether the error is from the db file being damaged, as opposed to e.g. a
+/// wrong key or a failed migration. Only then restoring a backup makes sense.
+pub fn is_corruption_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| {
+        matches!(
+            e.downcast_ref::<rusqlite::Error>()
+                .and_then(|e| e.sqlite_error_code()),
+            Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+        )
+    })
+}
+
 pub struct Txn<'a> {
     db_txn: rusqlite::Transaction<'a>,
+    cipher: Option<&'a DbCipher>,
     pub action: Option<Action>,
+    /// The new `RecorderState::paused` if the recorder state is changed.
+    pub recorder_paused: Option<bool>,
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -91,9 +132,124 @@ fn generate_random_revision() -> String {
     random_string::generate(8, random_string::charsets::ALPHANUMERIC)
 }
 
+fn journey_header_aad(id: &str) -> Vec<u8> {
+    format!("journey.header.{id}").into_bytes()
+}
+
+fn journey_data_aad(id: &str) -> Vec<u8> {
+    format!("journey.data.{id}").into_bytes()
+}
+
+const ONGOING_JOURNEY_POINT_AAD: &[u8] = b"ongoing_journey.point";
+
+fn place_point_aad(id: &str) -> Vec<u8> {
+    format!("place.point.{id}").into_bytes()
+}
+
+fn privacy_zone_aad(id: &str) -> Vec<u8> {
+    format!("privacy_zone.{id}").into_bytes()
+}
+
+// The `lat`, `lng` and `point` columns of `ongoing_journey` and `place`.
+type PointColumns = (Option<f64>, Option<f64>, Option<Vec<u8>>);
+
+fn encode_point(
+    cipher: Option<&DbCipher>,
+    latitude: f64,
+    longitude: f64,
+    aad: &[u8],
+) -> Result<PointColumns> {
+    match cipher {
+        None => Ok((Some(latitude), Some(longitude), None)),
+        Some(cipher) => {
+            let mut buf = Vec::with_capacity(16);
+            buf.extend_from_slice(&latitude.to_le_bytes());
+            buf.extend_from_slice(&longitude.to_le_bytes());
+            let point = cipher.encrypt(&buf, aad)?;
+            Ok((None, None, Some(point)))
+        }
+    }
+}
+
+fn decode_point(
+    cipher: Option<&DbCipher>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    point: Option<&[u8]>,
+    aad: &[u8],
+) -> Result<gps_processor::Point> {
+    match (cipher, latitude, longitude, point) {
+        (None, Some(latitude), Some(longitude), None) => Ok(gps_processor::Point {
+            latitude,
+            longitude,
+        }),
+        (Some(cipher), None, None, Some(point)) => {
+            let buf = cipher.decrypt(point, aad)?;
+            if buf.len() != 16 {
+                bail!("Invalid point, length: {}", buf.len());
+            }
+            Ok(gps_processor::Point {
+                latitude: f64::from_le_bytes(buf[0..8].try_into()?),
+                longitude: f64::from_le_bytes(buf[8..16].try_into()?),
+            })
+        }
+        _ => bail!("Invalid point, encrypted: {}", cipher.is_some()),
+    }
+}
+
+// The `name`, `shape` and `data` columns of `privacy_zone`. When encrypted,
+// `data` is the encrypted JSON of `(name, shape)`.
+type PrivacyZoneColumns = (Option<String>, Option<String>, Option<Vec<u8>>);
+
+fn encode_privacy_zone(
+    cipher: Option<&DbCipher>,
+    id: &str,
+    name: &str,
+    shape: &PrivacyZoneShape,
+) -> Result<PrivacyZoneColumns> {
+    match cipher {
+        None => Ok((
+            Some(name.to_string()),
+            Some(serde_json::to_string(shape)?),
+            None,
+        )),
+        Some(cipher) => {
+            let buf = serde_json::to_vec(&(name, shape))?;
+            Ok((
+                None,
+                None,
+                Some(cipher.encrypt(&buf, &privacy_zone_aad(id))?),
+            ))
+        }
+    }
+}
+
+fn decode_privacy_zone(
+    cipher: Option<&DbCipher>,
+    id: String,
+    (name, shape, data): PrivacyZoneColumns,
+) -> Result<PrivacyZone> {
+    let (name, shape) = match (cipher, name, shape, data) {
+        (None, Some(name), Some(shape), None) => (name, serde_json::from_str(&shape)?),
+        (Some(cipher), None, None, Some(data)) => {
+            serde_json::from_slice(&cipher.decrypt(&data, &privacy_zone_aad(&id))?)?
+        }
+        _ => bail!(
+            "Invalid privacy zone, id = {id}, encrypted: {}",
+            cipher.is_some()
+        ),
+    };
+    Ok(PrivacyZone { id, name, shape })
+}
+
 // NOTE: the `Txn` here is not only for making operation atomic, the `storage`
 // will also use this to make sure the `cache_db` is in sync.
 impl Txn<'_> {
+    /// `None` means the data is not encrypted.
+    pub fn cipher(&self) -> Option<&DbCipher> {
+        self.cipher
+    }
+
     fn set_invalidate_action(&mut self, new_entries: Vec<CacheEntry>) -> Result<()> {
         self.action = Some(match self.action.take() {
             Some(Action::CompleteRebuilt) => Action::CompleteRebuilt,
@@ -121,24 +277,37 @@ impl Txn<'_> {
     ) -> Result<Option<JourneyVector>> {
         // `id` in `ongoing_journey` is auto incremented.
         let mut query = self.db_txn.prepare(
-            "SELECT timestamp_sec, lat, lng, process_result FROM ongoing_journey ORDER BY id;",
+            "SELECT timestamp_sec, lat, lng, point, process_result FROM ongoing_journey ORDER BY id;",
         )?;
         let results = query
             .query_map((), |row| {
                 let timestamp_sec: Option<i64> = row.get(0)?;
-                let process_result: i8 = row.get(3)?;
+                let latitude: Option<f64> = row.get(1)?;
+                let longitude: Option<f64> = row.get(2)?;
+                let point: Option<Vec<u8>> = row.get(3)?;
+                let process_result: i8 = row.get(4)?;
+                Ok((timestamp_sec, latitude, longitude, point, process_result))
+            })
+            .context("get_onging_journey")?;
+        gps_processor::build_journey_vector(
+            results.map(|x| {
+                let (timestamp_sec, latitude, longitude, point, process_result) = x?;
+                let point = decode_point(
+                    self.cipher,
+                    latitude,
+                    longitude,
+                    point.as_deref(),
+                    ONGOING_JOURNEY_POINT_AAD,
+                )?;
                 Ok(PreprocessedData {
                     timestamp_sec,
                     track_point: TrackPoint {
-                        latitude: row.get(1)?,
-                        longitude: row.get(2)?,
+                        latitude: point.latitude,
+                        longitude: point.longitude,
                     },
                     process_result: process_result.into(),
                 })
-            })
-            .context("get_onging_journey")?;
-        gps_processor::build_journey_vector(
-            results.map(|x| x.map_err(|x| x.into())),
+            }),
             journey_date_picker,
         )
     }
@@ -174,6 +343,8 @@ impl Txn<'_> {
     pub fn delete_all_journeys(&mut self) -> Result<()> {
         info!("Deleting all journeys");
         self.db_txn.execute("DELETE FROM journey;", ())?;
+        self.db_txn.execute("DELETE FROM place_visit;", ())?;
+        self.db_txn.execute("DELETE FROM place;", ())?;
         self.action = Some(Action::CompleteRebuilt);
         Ok(())
     }
@@ -190,6 +361,7 @@ impl Txn<'_> {
         if changes != 1 {
             return Err(anyhow!("Failed to delete journey with id = {id}"));
         }
+        self.delete_place_visits_of_journey(id)?;
         self.set_invalidate_action(vec![CacheEntry {
             date: header.journey_date,
             kind: header.journey_kind,
@@ -232,11 +404,18 @@ impl Txn<'_> {
         // use start time first, then fallback to endtime
         let timestamp_for_ordering = header.start.or(header.end).map(|x| x.timestamp());
 
-        let header_bytes = header.to_proto().write_to_bytes()?;
+        let header_bytes = db_encryption::encrypt_if_enabled(
+            self.cipher,
+            header.to_proto().write_to_bytes()?,
+            &journey_header_aad(&id),
+        )?;
         let mut data_bytes = Vec::new();
         data.serialize(&mut data_bytes)?;
+        let data_bytes =
+            db_encryption::encrypt_if_enabled(self.cipher, data_bytes, &journey_data_aad(&id))?;
+        let fingerprint = duplicate_detector::fingerprint(&data, self.cipher);
 
-        let sql = "INSERT INTO journey (id, journey_date, timestamp_for_ordering, type, header, data) VALUES (?1, ?2, ?3, ?4, ?5, ?6);";
+        let sql = "INSERT INTO journey (id, journey_date, timestamp_for_ordering, type, header, data, fingerprint) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);";
         self.db_txn.execute(
             sql,
             (
@@ -246,6 +425,7 @@ impl Txn<'_> {
                 journey_type.to_int(),
                 header_bytes,
                 data_bytes,
+                fingerprint,
             ),
         )?;
 
@@ -304,6 +484,7 @@ impl Txn<'_> {
             journey_kind,
             note,
             postprocessor_algo,
+            transport_mode: None,
         };
         self.insert_journey(header, journey_data)?;
         Ok(id)
@@ -340,7 +521,11 @@ impl Txn<'_> {
         // update
         let journey_date = utils::date_to_days_since_epoch(header.journey_date);
         let timestamp_for_ordering = header.start.or(header.end).map(|x| x.timestamp());
-        let header_bytes = header.to_proto().write_to_bytes()?;
+        let header_bytes = db_encryption::encrypt_if_enabled(
+            self.cipher,
+            header.to_proto().write_to_bytes()?,
+            &journey_header_aad(id),
+        )?;
         let sql = "UPDATE journey SET journey_date = ?1, timestamp_for_ordering = ?2, header = ?3 WHERE id = ?4;";
         self.db_txn.execute(
             sql,
@@ -363,6 +548,33 @@ impl Txn<'_> {
         Ok(())
     }
 
+    /// The transport mode is a guess from the data instead of something the
+    /// user edits, so `updated_at` and `revision` are kept.
+    #[auto_context]
+    pub fn set_journey_transport_mode(
+        &mut self,
+        id: &str,
+        transport_mode: Option<TransportMode>,
+    ) -> Result<()> {
+        let mut header = self
+            .get_journey_header(id)?
+            .ok_or_else(|| anyhow!("Updating non existent journey, journey id = {id}"))?;
+        if header.transport_mode == transport_mode {
+            return Ok(());
+        }
+        header.transport_mode = transport_mode;
+        let header_bytes = db_encryption::encrypt_if_enabled(
+            self.cipher,
+            header.to_proto().write_to_bytes()?,
+            &journey_header_aad(id),
+        )?;
+        self.db_txn.execute(
+            "UPDATE journey SET header = ?1 WHERE id = ?2;",
+            (header_bytes, id),
+        )?;
+        Ok(())
+    }
+
     #[auto_context]
     pub fn update_journey_data_with_latest_postprocessor(
         &mut self,
@@ -391,14 +603,28 @@ impl Txn<'_> {
 
         let journey_date = header.journey_date;
         let journey_kind = header.journey_kind;
-        let header_bytes = header.to_proto().write_to_bytes()?;
+        let header_bytes = db_encryption::encrypt_if_enabled(
+            self.cipher,
+            header.to_proto().write_to_bytes()?,
+            &journey_header_aad(id),
+        )?;
         let mut data_bytes = Vec::new();
         journey_data.serialize(&mut data_bytes)?;
+        let data_bytes =
+            db_encryption::encrypt_if_enabled(self.cipher, data_bytes, &journey_data_aad(id))?;
+        let fingerprint = duplicate_detector::fingerprint(&journey_data, self.cipher);
 
-        let sql = "UPDATE journey SET type = ?2, header = ?3, data = ?4 WHERE id =?1;";
+        let sql =
+            "UPDATE journey SET type = ?2, header = ?3, data = ?4, fingerprint = ?5 WHERE id =?1;";
         self.db_txn.execute(
             sql,
-            (&id, journey_data.type_().to_int(), header_bytes, data_bytes),
+            (
+                &id,
+                journey_data.type_().to_int(),
+                header_bytes,
+                data_bytes,
+                fingerprint,
+            ),
         )?;
 
         self.set_invalidate_action(vec![CacheEntry {
@@ -410,40 +636,233 @@ impl Txn<'_> {
 
     #[auto_context]
     pub fn finalize_ongoing_journey(&mut self) -> Result<bool> {
-        let mut journey_date_picker = JourneyDatePicker::new();
-        let new_journey_added = match self.get_ongoing_journey(Some(&mut journey_date_picker))? {
-            None => false,
-            Some(journey_vector) => {
-                // TODO: allow user to set this when recording?
-                let journey_kind = JourneyKind::DefaultKind;
-
-                self.create_and_insert_journey(
-                    // In practice, `end` could never be none but just in case ...
-                    // TODO: Maybe we want better journey date strategy
-                    journey_date_picker
-                        .pick_journey_date()
-                        .unwrap_or_else(|| Local::now().date_naive()),
-                    journey_date_picker.min_time(),
-                    journey_date_picker.max_time(),
-                    None,
-                    journey_kind,
-                    None,
-                    JourneyData::Vector(journey_vector),
-                )?;
-                true
+        self.finalize_ongoing_journey_with_split(&JourneySplitOptions::default())
+    }
+
+    /// Same as `finalize_ongoing_journey` but the ground parts (i.e. not
+    /// flights) are further split by `split_options`.
+    #[auto_context]
+    pub fn finalize_ongoing_journey_with_split(
+        &mut self,
+        split_options: &JourneySplitOptions,
+    ) -> Result<bool> {
+        let recorder_state = self.get_recorder_state()?;
+        // timestamps are dropped in the journey, so these have to be done
+        // before that
+        let timed_segments = self.get_ongoing_journey_timed_segments()?;
+        // A manual journey is kept as a single journey, the user decided where
+        // it starts and ends.
+        let parts: Vec<TrackPart<TimedPoint>> = if recorder_state.manual_journey_title.is_some() {
+            vec![]
+        } else {
+            flight_detector::split_flights(&timed_segments, |x| Some(x.clone()))
+                .into_iter()
+                .flat_map(|part| match part {
+                    TrackPart::Ground(segments) if split_options.is_enabled() => {
+                        journey_splitter::split_segments(&segments, split_options, &Local)
+                            .into_iter()
+                            .map(TrackPart::Ground)
+                            .collect()
+                    }
+                    part => vec![part],
+                })
+                .collect()
+        };
+        let new_journey_added = if parts.len() > 1
+            || parts.iter().any(|x| matches!(x, TrackPart::Flight(_)))
+        {
+            // Flights and split parts become their own journeys. The journeys
+            // are built from the timed segments, data without timestamp is
+            // dropped but the app always records one.
+            for part in parts {
+                match part {
+                    TrackPart::Ground(segments) => {
+                        let journey_vector = JourneyVector {
+                            track_segments: segments
+                                .iter()
+                                .map(|segment| TrackSegment {
+                                    track_points: segment
+                                        .iter()
+                                        .map(|x| TrackPoint {
+                                            latitude: x.point.latitude,
+                                            longitude: x.point.longitude,
+                                        })
+                                        .collect(),
+                                })
+                                .collect(),
+                        };
+                        self.insert_finalized_journey(
+                            JourneyKind::DefaultKind,
+                            None,
+                            journey_vector,
+                            &segments,
+                        )?;
+                    }
+                    TrackPart::Flight(points) => {
+                        if let Some(journey_vector) = flight_track_processor::process_great_circle(
+                            &points.iter().map(|x| x.point.clone()).collect::<Vec<_>>(),
+                        ) {
+                            self.insert_finalized_journey(
+                                JourneyKind::Flight,
+                                None,
+                                journey_vector,
+                                &[points],
+                            )?;
+                        }
+                    }
+                }
+            }
+            true
+        } else {
+            match self.get_ongoing_journey(None)? {
+                None => false,
+                Some(journey_vector) => {
+                    // TODO: allow user to set this when recording?
+                    self.insert_finalized_journey(
+                        JourneyKind::DefaultKind,
+                        recorder_state.manual_journey_title,
+                        journey_vector,
+                        &timed_segments,
+                    )?;
+                    true
+                }
             }
         };
 
+        self.clear_ongoing_journey()?;
+
+        info!("Ongoing journey finalized: new_journey_added={new_journey_added}");
+        Ok(new_journey_added)
+    }
+
+    // Also ends the manual journey. The pause is kept, it could be finalized
+    // automatically while paused and recording must not resume by itself.
+    fn clear_ongoing_journey(&mut self) -> Result<()> {
         self.db_txn.execute("DELETE FROM ongoing_journey;", ())?;
         self.db_txn.execute(
             "DELETE FROM sqlite_sequence WHERE name='ongoing_journey';",
             (),
         )?;
+        let recorder_state = self.get_recorder_state()?;
+        self.set_recorder_state(&RecorderState {
+            manual_journey_title: None,
+            ..recorder_state
+        })
+    }
 
-        info!("Ongoing journey finalized: new_journey_added={new_journey_added}");
+    /// Drops the ongoing journey without adding any journey. Returns `false`
+    /// if there was no data.
+    #[auto_context]
+    pub fn discard_ongoing_journey(&mut self) -> Result<bool> {
+        let has_data = self.get_ongoing_journey_timestamp_range()?.is_some();
+        self.clear_ongoing_journey()?;
+        info!("Ongoing journey discarded: has_data={has_data}");
+        Ok(has_data)
+    }
+
+    #[auto_context]
+    pub fn get_recorder_state(&self) -> Result<RecorderState> {
+        get_recorder_state(&self.db_txn)
+    }
+
+    #[auto_context]
+    fn set_recorder_state(&mut self, recorder_state: &RecorderState) -> Result<()> {
+        self.db_txn.execute(
+            "INSERT OR REPLACE INTO setting (key, value) VALUES (?1, ?2);",
+            (
+                Setting::RecorderState.to_db_key(),
+                recorder_state.to_string(),
+            ),
+        )?;
+        self.recorder_paused = Some(recorder_state.paused);
+        Ok(())
+    }
+
+    /// New data is ignored until `resume_recording`.
+    #[auto_context]
+    pub fn pause_recording(&mut self) -> Result<()> {
+        let mut recorder_state = self.get_recorder_state()?;
+        recorder_state.paused = true;
+        self.set_recorder_state(&recorder_state)
+    }
+
+    #[auto_context]
+    pub fn resume_recording(&mut self) -> Result<()> {
+        let mut recorder_state = self.get_recorder_state()?;
+        recorder_state.paused = false;
+        self.set_recorder_state(&recorder_state)
+    }
+
+    /// Starts a journey named `title` which is never finalized automatically,
+    /// only by `finalize_ongoing_journey`. Data recorded before is finalized
+    /// first (by `split_options`) so it is not part of the new journey.
+    /// Returns whether a journey was added for the data recorded before.
+    #[auto_context]
+    pub fn start_manual_journey(
+        &mut self,
+        title: String,
+        split_options: &JourneySplitOptions,
+    ) -> Result<bool> {
+        let title = title.trim().to_string();
+        if title.is_empty() {
+            bail!("Journey title must not be empty");
+        }
+        let new_journey_added = self.finalize_ongoing_journey_with_split(split_options)?;
+        self.set_recorder_state(&RecorderState {
+            paused: false,
+            manual_journey_title: Some(title),
+        })?;
         Ok(new_journey_added)
     }
 
+    fn insert_finalized_journey(
+        &mut self,
+        journey_kind: JourneyKind,
+        note: Option<String>,
+        journey_vector: JourneyVector,
+        timed_segments: &[Vec<TimedPoint>],
+    ) -> Result<()> {
+        let mut journey_date_picker = JourneyDatePicker::new();
+        for x in timed_segments.iter().flatten() {
+            if let Some(time) = DateTime::from_timestamp(x.timestamp_sec, 0) {
+                journey_date_picker.add_point(
+                    time,
+                    &TrackPoint {
+                        latitude: x.point.latitude,
+                        longitude: x.point.longitude,
+                    },
+                );
+            }
+        }
+        let stay_points = places::detect_stay_points(
+            &timed_segments
+                .iter()
+                .flatten()
+                .map(|x| (x.timestamp_sec, x.point.clone()))
+                .collect::<Vec<_>>(),
+        );
+        let transport_mode = match journey_kind {
+            JourneyKind::Flight => Some(TransportMode::Flight),
+            _ => transport_mode::classify_journey(timed_segments),
+        };
+        let id = self.create_and_insert_journey(
+            // In practice, `end` could never be none but just in case ...
+            // TODO: Maybe we want better journey date strategy
+            journey_date_picker
+                .pick_journey_date()
+                .unwrap_or_else(|| Local::now().date_naive()),
+            journey_date_picker.min_time(),
+            journey_date_picker.max_time(),
+            None,
+            journey_kind,
+            note,
+            JourneyData::Vector(journey_vector),
+        )?;
+        self.add_place_visits(Some(&id), &stay_points)?;
+        self.set_journey_transport_mode(&id, transport_mode)?;
+        Ok(())
+    }
+
     // TODO: we should consider disallow unbounded queries. Keeping all
     // `JourneyHeader` in memory might be a little bit too much.
     // Actually, header is pretty small so it should be fine but still an iterator
@@ -455,7 +874,7 @@ impl Txn<'_> {
         to_date_inclusive: Option<NaiveDate>,
     ) -> Result<Vec<JourneyHeader>> {
         let mut query = self.db_txn.prepare(
-            "SELECT header, type FROM journey WHERE journey_date >= (?1) AND journey_date <= (?2) ORDER BY journey_date DESC, timestamp_for_ordering DESC, id;",
+            "SELECT id, header, type FROM journey WHERE journey_date >= (?1) AND journey_date <= (?2) ORDER BY journey_date DESC, timestamp_for_ordering DESC, id;",
             // use `id` to break tie
         )?;
         let from = match from_date_inclusive {
@@ -469,10 +888,15 @@ impl Txn<'_> {
         let mut rows = query.query((from, to))?;
         let mut results = Vec::new();
         while let Some(row) = rows.next()? {
-            let header_bytes = row.get_ref(0)?.as_blob()?;
-            let journey_type = JourneyType::of_int(row.get(1)?)?;
+            let id: String = row.get(0)?;
+            let header_bytes = db_encryption::decrypt_if_enabled(
+                self.cipher,
+                row.get_ref(1)?.as_blob()?,
+                &journey_header_aad(&id),
+            )?;
+            let journey_type = JourneyType::of_int(row.get(2)?)?;
             let header =
-                JourneyHeader::of_proto(protos::journey::Header::parse_from_bytes(header_bytes)?)?;
+                JourneyHeader::of_proto(protos::journey::Header::parse_from_bytes(&header_bytes)?)?;
             if header.journey_type != journey_type {
                 bail!(
                     "Invalid DB state, `journey_type` miss match. id: {}.",
@@ -484,22 +908,54 @@ impl Txn<'_> {
         Ok(results)
     }
 
+    /// Journeys with a time range overlapping `[start, end]`, journeys without
+    /// any time are ignored.
+    #[auto_context]
+    pub fn query_journey_ids_in_time_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<String>> {
+        // `journey_date` is a local date, one day of margin is enough for any
+        // timezone.
+        let headers = self.query_journeys(
+            start.date_naive().checked_sub_days(Days::new(1)),
+            end.date_naive().checked_add_days(Days::new(1)),
+        )?;
+        Ok(headers
+            .into_iter()
+            .filter(
+                |header| match (header.start.or(header.end), header.end.or(header.start)) {
+                    (Some(journey_start), Some(journey_end)) => {
+                        journey_start <= end && journey_end >= start
+                    }
+                    _ => false,
+                },
+            )
+            .map(|header| header.id)
+            .collect())
+    }
+
     pub fn get_journey_header(&self, id: &str) -> Result<Option<JourneyHeader>> {
         let mut query = self
             .db_txn
             .prepare("SELECT header FROM journey WHERE id = ?1;")?;
 
-        let header_proto_result = query
-            .query_row([id], |row| {
-                let header_bytes = row.get_ref(0)?.as_blob()?;
-                Ok(protos::journey::Header::parse_from_bytes(header_bytes))
-            })
+        let header_bytes: Option<Vec<u8>> = query
+            .query_row([id], |row| row.get(0))
             .optional()
             .context("get_journey_header")?;
 
-        match header_proto_result {
-            Some(header_proto_result) => {
-                let header = JourneyHeader::of_proto(header_proto_result?)?;
+        match header_bytes {
+            Some(header_bytes) => {
+                let header_bytes = db_encryption::decrypt_if_enabled(
+                    self.cipher,
+                    &header_bytes,
+                    &journey_header_aad(id),
+                )?;
+                let header = JourneyHeader::of_proto(protos::journey::Header::parse_from_bytes(
+                    &header_bytes,
+                )?)?;
                 Ok(Some(header))
             }
             None => Ok(None),
@@ -516,14 +972,63 @@ impl Txn<'_> {
                 let type_ = row.get_ref(0)?.as_i64()?;
                 let f = || {
                     let journey_type = JourneyType::of_int(i8::try_from(type_)?)?;
-                    let data = row.get_ref(1)?.as_blob()?;
-                    JourneyData::deserialize(data, journey_type)
+                    let data = db_encryption::decrypt_if_enabled(
+                        self.cipher,
+                        row.get_ref(1)?.as_blob()?,
+                        &journey_data_aad(id),
+                    )?;
+                    JourneyData::deserialize(data.as_ref(), journey_type)
                 };
                 Ok(f())
             })
             .context("get_journey_data")?
     }
 
+    pub fn get_journey_fingerprint(&self, id: &str) -> Result<Option<String>> {
+        let mut query = self
+            .db_txn
+            .prepare("SELECT fingerprint FROM journey WHERE id = ?1;")?;
+        let fingerprint: Option<Option<String>> = query
+            .query_row([id], |row| row.get(0))
+            .optional()
+            .context("get_journey_fingerprint")?;
+        Ok(fingerprint.flatten())
+    }
+
+    #[auto_context]
+    pub fn query_journey_ids_by_fingerprint(&self, fingerprint: &str) -> Result<Vec<String>> {
+        let mut query = self
+            .db_txn
+            .prepare("SELECT id FROM journey WHERE fingerprint = ?1 ORDER BY id;")?;
+        let mut ids = Vec::new();
+        for row in query.query_map([fingerprint], |row| row.get(0))? {
+            ids.push(row?);
+        }
+        Ok(ids)
+    }
+
+    // Each group contains ids of journeys that share the same fingerprint.
+    #[auto_context]
+    pub fn journey_ids_grouped_by_duplicated_fingerprint(&self) -> Result<Vec<Vec<String>>> {
+        let mut query = self.db_txn.prepare(
+            "SELECT fingerprint, id FROM journey WHERE fingerprint IN (SELECT fingerprint FROM journey WHERE fingerprint IS NOT NULL GROUP BY fingerprint HAVING COUNT(*) > 1) ORDER BY fingerprint, id;",
+        )?;
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        let mut last_fingerprint: Option<String> = None;
+        let mut rows = query.query(())?;
+        while let Some(row) = rows.next()? {
+            let fingerprint: String = row.get(0)?;
+            let id: String = row.get(1)?;
+            if last_fingerprint.as_ref() == Some(&fingerprint) {
+                groups.last_mut().unwrap().push(id);
+            } else {
+                groups.push(vec![id]);
+                last_fingerprint = Some(fingerprint);
+            }
+        }
+        Ok(groups)
+    }
+
     #[auto_context]
     pub fn years_with_journey(&self) -> Result<Vec<i32>> {
         let mut query = self
@@ -560,45 +1065,364 @@ impl Txn<'_> {
         Ok(days)
     }
 
-    // TODO: consider moving this to `storage.rs`
+    /// What `try_auto_finalize_journey` would do at `now`, without changing
+    /// anything.
     #[auto_context]
-    pub fn try_auto_finalize_journey(&mut self) -> Result<bool> {
+    pub fn explain_auto_finalize<Tz: TimeZone>(
+        &self,
+        policy: &AutoFinalizePolicy,
+        now: &DateTime<Tz>,
+    ) -> Result<AutoFinalizeExplanation> {
+        if self.get_recorder_state()?.manual_journey_title.is_some() {
+            return Ok(AutoFinalizeExplanation::manual_journey());
+        }
         match self.get_ongoing_journey_timestamp_range()? {
-            None => Ok(false),
+            None => Ok(AutoFinalizeExplanation::no_ongoing_journey()),
             Some((start, end)) => {
                 // NOTE: this logic is not called very frequently
-
-                let now = Local::now();
-                let recording_length_hours = (now.timestamp() - start.timestamp()) / 60 / 60;
-                let required_gap_mins = if recording_length_hours >= 48 {
-                    0 // let's just finalize it
-                } else if recording_length_hours >= 24 {
-                    2
-                } else {
-                    // if the local date changed since start, we should try to finalize it, otherwise we don't want that unless there is a huge gap (6h)
-                    if start.with_timezone(&Local).date_naive() == now.date_naive() {
-                        6 * 60
-                    } else if now.hour() <= 4 || recording_length_hours <= 8 {
-                        20
-                    } else {
-                        5
-                    }
+                let at_known_place = match policy.stationary_at_known_place_mins {
+                    None => false,
+                    Some(_) => self.is_ongoing_journey_at_known_place()?,
                 };
+                Ok(auto_finalize::explain(
+                    policy,
+                    now,
+                    start,
+                    end,
+                    at_known_place,
+                ))
+            }
+        }
+    }
 
-                let gap_mins = (now.timestamp() - end.timestamp()).max(0) / 60;
+    #[auto_context]
+    pub fn try_auto_finalize_journey(
+        &mut self,
+        policy: &AutoFinalizePolicy,
+        split_options: &JourneySplitOptions,
+    ) -> Result<bool> {
+        let explanation = self.explain_auto_finalize(policy, &Local::now())?;
+        if matches!(
+            explanation.reason,
+            AutoFinalizeReason::NoOngoingJourney | AutoFinalizeReason::ManualJourney
+        ) {
+            return Ok(false);
+        }
+        info!("Auto finalize ongoing journey: {explanation:?}");
+        if explanation.finalize {
+            self.finalize_ongoing_journey_with_split(split_options)
+        } else {
+            Ok(false)
+        }
+    }
 
-                let try_finalize = gap_mins >= required_gap_mins;
+    fn is_ongoing_journey_at_known_place(&self) -> Result<bool> {
+        let last_point = self
+            .db_txn
+            .query_row(
+                "SELECT lat, lng, point FROM ongoing_journey ORDER BY id DESC LIMIT 1;",
+                (),
+                |row| {
+                    let latitude: Option<f64> = row.get(0)?;
+                    let longitude: Option<f64> = row.get(1)?;
+                    let point: Option<Vec<u8>> = row.get(2)?;
+                    Ok((latitude, longitude, point))
+                },
+            )
+            .optional()?
+            .map(|(latitude, longitude, point)| {
+                decode_point(
+                    self.cipher,
+                    latitude,
+                    longitude,
+                    point.as_deref(),
+                    ONGOING_JOURNEY_POINT_AAD,
+                )
+            })
+            .transpose()?;
+        Ok(match last_point {
+            None => false,
+            Some(last_point) => self.list_places()?.iter().any(|place| {
+                last_point.haversine_distance(&gps_processor::Point {
+                    latitude: place.latitude,
+                    longitude: place.longitude,
+                }) <= places::PLACE_RADIUS_IN_M
+            }),
+        })
+    }
 
-                info!(
-                    "Auto finalize ongoing journey: recording_length_hours={recording_length_hours}, gap_mins={gap_mins}, required_gap_mins={required_gap_mins}, try_finalize={try_finalize}"
-                );
-                if try_finalize {
-                    self.finalize_ongoing_journey()
-                } else {
-                    Ok(false)
+    // Data without timestamp is skipped.
+    fn get_ongoing_journey_timed_segments(&self) -> Result<Vec<Vec<TimedPoint>>> {
+        let mut query = self.db_txn.prepare(
+            "SELECT timestamp_sec, lat, lng, point, process_result FROM ongoing_journey WHERE timestamp_sec IS NOT NULL ORDER BY id;",
+        )?;
+        let mut rows = query.query(())?;
+        let mut segments: Vec<Vec<TimedPoint>> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let process_result: i8 = row.get(4)?;
+            if segments.is_empty()
+                || ProcessResult::from(process_result) == ProcessResult::NewSegment
+            {
+                segments.push(Vec::new());
+            }
+            if let Some(segment) = segments.last_mut() {
+                segment.push(TimedPoint {
+                    timestamp_sec: row.get(0)?,
+                    point: decode_point(
+                        self.cipher,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get_ref(3)?.as_blob_or_null()?,
+                        ONGOING_JOURNEY_POINT_AAD,
+                    )?,
+                    altitude: None,
+                });
+            }
+        }
+        Ok(segments)
+    }
+
+    /// Each stay point is added as a visit of the closest place within
+    /// `places::PLACE_RADIUS_IN_M`, a new place is created if there is none.
+    #[auto_context]
+    pub fn add_place_visits(
+        &mut self,
+        journey_id: Option<&str>,
+        stay_points: &[StayPoint],
+    ) -> Result<()> {
+        for stay_point in stay_points {
+            let point = &stay_point.point;
+            // filtering by latitude first, 1 degree of latitude is ~111km. The
+            // latitude is NULL when encrypted, then all places are checked.
+            let delta_latitude = places::PLACE_RADIUS_IN_M / 111_000.0 * 2.0;
+            let mut query = self.db_txn.prepare_cached(
+                "SELECT place.id, place.lat, place.lng, place.point, COUNT(*) FROM place JOIN place_visit ON place.id = place_visit.place_id WHERE place.lat IS NULL OR place.lat BETWEEN ?1 AND ?2 GROUP BY place.id;",
+            )?;
+            let rows = query
+                .query_map(
+                    (
+                        point.latitude - delta_latitude,
+                        point.latitude + delta_latitude,
+                    ),
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            (row.get(1)?, row.get(2)?, row.get(3)?),
+                            row.get::<_, i64>(4)?,
+                        ))
+                    },
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            drop(query);
+            let mut closest_place: Option<(f64, String, gps_processor::Point, i64)> = None;
+            for (id, columns, visit_count) in rows {
+                let center = self.decode_place_point(&id, columns)?;
+                let distance = center.haversine_distance(point);
+                if distance <= places::PLACE_RADIUS_IN_M
+                    && closest_place
+                        .as_ref()
+                        .is_none_or(|(closest_distance, ..)| distance < *closest_distance)
+                {
+                    closest_place = Some((distance, id, center, visit_count));
                 }
             }
+
+            let place_id = match closest_place {
+                None => {
+                    let id = Uuid::new_v4().as_hyphenated().to_string();
+                    let (lat, lng, point) = encode_point(
+                        self.cipher,
+                        point.latitude,
+                        point.longitude,
+                        &place_point_aad(&id),
+                    )?;
+                    self.db_txn.execute(
+                        "INSERT INTO place (id, lat, lng, point) VALUES (?1, ?2, ?3, ?4);",
+                        (&id, lat, lng, point),
+                    )?;
+                    id
+                }
+                Some((_, id, center, visit_count)) => {
+                    // the center of the place is the average of all visits
+                    let n = visit_count as f64;
+                    let (lat, lng, point) = encode_point(
+                        self.cipher,
+                        (center.latitude * n + point.latitude) / (n + 1.0),
+                        (center.longitude * n + point.longitude) / (n + 1.0),
+                        &place_point_aad(&id),
+                    )?;
+                    self.db_txn.execute(
+                        "UPDATE place SET lat = ?2, lng = ?3, point = ?4 WHERE id = ?1;",
+                        (&id, lat, lng, point),
+                    )?;
+                    id
+                }
+            };
+            self.db_txn.execute(
+                "INSERT INTO place_visit (place_id, journey_id, arrival_timestamp_sec, departure_timestamp_sec) VALUES (?1, ?2, ?3, ?4);",
+                (
+                    place_id,
+                    journey_id,
+                    stay_point.arrival.timestamp(),
+                    stay_point.departure.timestamp(),
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn decode_place_point(&self, id: &str, columns: PointColumns) -> Result<gps_processor::Point> {
+        let (latitude, longitude, point) = columns;
+        decode_point(
+            self.cipher,
+            latitude,
+            longitude,
+            point.as_deref(),
+            &place_point_aad(id),
+        )
+    }
+
+    #[auto_context]
+    fn delete_place_visits_of_journey(&mut self, journey_id: &str) -> Result<()> {
+        self.db_txn.execute(
+            "DELETE FROM place_visit WHERE journey_id = ?1;",
+            (journey_id,),
+        )?;
+        self.db_txn.execute(
+            "DELETE FROM place WHERE id NOT IN (SELECT place_id FROM place_visit);",
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Visits overlapping with `[start, end]`, ordered by arrival time.
+    #[auto_context]
+    pub fn query_place_visits(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<PlaceVisit>> {
+        let mut query = self.db_txn.prepare(
+            "SELECT place.id, place.lat, place.lng, place.point, place_visit.arrival_timestamp_sec, place_visit.departure_timestamp_sec, place_visit.journey_id FROM place_visit JOIN place ON place.id = place_visit.place_id WHERE place_visit.departure_timestamp_sec >= ?1 AND place_visit.arrival_timestamp_sec <= ?2 ORDER BY place_visit.arrival_timestamp_sec;",
+        )?;
+        let rows = query.query_map((start.timestamp(), end.timestamp()), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                (row.get(1)?, row.get(2)?, row.get(3)?),
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, Option<String>>(6)?,
+            ))
+        })?;
+        let mut visits = Vec::new();
+        for row in rows {
+            let (place_id, columns, arrival, departure, journey_id) = row?;
+            let point = self.decode_place_point(&place_id, columns)?;
+            visits.push(PlaceVisit {
+                place_id,
+                latitude: point.latitude,
+                longitude: point.longitude,
+                arrival: DateTime::from_timestamp(arrival, 0).unwrap_or_default(),
+                departure: DateTime::from_timestamp(departure, 0).unwrap_or_default(),
+                journey_id,
+            });
+        }
+        Ok(visits)
+    }
+
+    /// Ordered by the total duration of visits, the most visited first.
+    #[auto_context]
+    pub fn list_places(&self) -> Result<Vec<Place>> {
+        let mut query = self.db_txn.prepare(
+            "SELECT place.id, place.lat, place.lng, place.point, COUNT(*), SUM(place_visit.departure_timestamp_sec - place_visit.arrival_timestamp_sec) AS total_duration_sec, MAX(place_visit.departure_timestamp_sec) FROM place JOIN place_visit ON place.id = place_visit.place_id GROUP BY place.id ORDER BY total_duration_sec DESC;",
+        )?;
+        let rows = query.query_map((), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                (row.get(1)?, row.get(2)?, row.get(3)?),
+                row.get::<_, u32>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, i64>(6)?,
+            ))
+        })?;
+        let mut places = Vec::new();
+        for row in rows {
+            let (id, columns, visit_count, total_duration_sec, last_departure) = row?;
+            let point = self.decode_place_point(&id, columns)?;
+            places.push(Place {
+                id,
+                latitude: point.latitude,
+                longitude: point.longitude,
+                visit_count,
+                total_duration_sec,
+                last_departure: DateTime::from_timestamp(last_departure, 0).unwrap_or_default(),
+            });
+        }
+        Ok(places)
+    }
+
+    #[auto_context]
+    pub fn list_privacy_zones(&self) -> Result<Vec<PrivacyZone>> {
+        let mut query = self
+            .db_txn
+            .prepare("SELECT id, name, shape, data FROM privacy_zone;")?;
+        let rows = query.query_map((), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                (row.get(1)?, row.get(2)?, row.get(3)?),
+            ))
+        })?;
+        let mut zones = Vec::new();
+        for row in rows {
+            let (id, columns) = row?;
+            zones.push(decode_privacy_zone(self.cipher, id, columns)?);
+        }
+        // sorted here since the name may be encrypted
+        zones.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+        Ok(zones)
+    }
+
+    /// Returns the id of the new zone.
+    #[auto_context]
+    pub fn add_privacy_zone(&mut self, name: &str, shape: &PrivacyZoneShape) -> Result<String> {
+        shape.validate()?;
+        let id = Uuid::new_v4().as_hyphenated().to_string();
+        let (name, shape, data) = encode_privacy_zone(self.cipher, &id, name, shape)?;
+        self.db_txn.execute(
+            "INSERT INTO privacy_zone (id, name, shape, data) VALUES (?1, ?2, ?3, ?4);",
+            (&id, name, shape, data),
+        )?;
+        Ok(id)
+    }
+
+    #[auto_context]
+    pub fn update_privacy_zone(
+        &mut self,
+        id: &str,
+        name: &str,
+        shape: &PrivacyZoneShape,
+    ) -> Result<()> {
+        shape.validate()?;
+        let (name, shape, data) = encode_privacy_zone(self.cipher, id, name, shape)?;
+        let changes = self.db_txn.execute(
+            "UPDATE privacy_zone SET name = ?2, shape = ?3, data = ?4 WHERE id = ?1;",
+            (id, name, shape, data),
+        )?;
+        if changes != 1 {
+            bail!("Privacy zone not found, id = {id}");
         }
+        Ok(())
+    }
+
+    #[auto_context]
+    pub fn delete_privacy_zone(&mut self, id: &str) -> Result<()> {
+        let changes = self
+            .db_txn
+            .execute("DELETE FROM privacy_zone WHERE id = ?1;", (id,))?;
+        if changes != 1 {
+            bail!("Privacy zone not found, id = {id}");
+        }
+        Ok(())
     }
 
     pub fn earliest_journey_date(&self) -> Result<Option<NaiveDate>> {
@@ -649,7 +1473,7 @@ impl Txn<'_> {
                 match self.get_journey_data(&journey_header.id)? {
                     JourneyData::Bitmap(_) => (),
                     JourneyData::Vector(journey_vector) => {
-                        let journey_vector = GpsPostprocessor::process(journey_vector);
+                        // the latest postprocessor is applied during the update
                         self.update_journey_data_with_latest_postprocessor(
                             &journey_header.id,
                             JourneyData::Vector(journey_vector),
@@ -663,18 +1487,43 @@ impl Txn<'_> {
     }
 }
 
+#[auto_context]
+fn get_recorder_state(tx: &Transaction) -> Result<RecorderState> {
+    let value: Option<String> = tx
+        .query_row(
+            "SELECT value FROM setting WHERE key = ?1;",
+            [Setting::RecorderState.to_db_key()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(match value {
+        None => RecorderState::default(),
+        Some(value) => value.parse()?,
+    })
+}
+
 pub struct MainDb {
     conn: Connection,
+    cipher: Option<DbCipher>,
 }
 
 impl MainDb {
     pub fn open(support_dir: &str) -> MainDb {
         // TODO: better error handling
+        MainDb::open_with_key(support_dir, None).expect("failed to open main db")
+    }
+
+    /// Data is encrypted with `key` (see `db_encryption`). Existing data is
+    /// migrated if the db was not encrypted before, use `rotate_key` to change
+    /// the key of an encrypted db.
+    #[auto_context]
+    pub fn open_with_key(support_dir: &str, key: Option<&[u8]>) -> Result<MainDb> {
         let conn = open_db_and_run_migration(
             support_dir,
             "main.db",
-            &[&|tx| {
-                let sql = "
+            &[
+                &|tx| {
+                    let sql = "
                 CREATE TABLE ongoing_journey (
                     id             INTEGER PRIMARY KEY AUTOINCREMENT
                                         UNIQUE
@@ -705,14 +1554,326 @@ impl MainDb {
                     value             TEXT
                 );
                 ";
-                for s in sql_split::split(sql) {
-                    tx.execute(&s, ())?;
+                    for s in sql_split::split(sql) {
+                        tx.execute(&s, ())?;
+                    }
+                    Ok(())
+                },
+                &|tx| {
+                    let sql = "
+                ALTER TABLE journey ADD COLUMN fingerprint TEXT;
+                CREATE INDEX journey_fingerprint_index ON journey (
+                    fingerprint
+                );
+                ";
+                    for s in sql_split::split(sql) {
+                        tx.execute(&s, ())?;
+                    }
+                    // backfill fingerprints for existing journeys
+                    let mut fingerprints = Vec::new();
+                    let mut query = tx.prepare("SELECT id, type, data FROM journey;")?;
+                    let mut rows = query.query(())?;
+                    while let Some(row) = rows.next()? {
+                        let id: String = row.get(0)?;
+                        let journey_type = JourneyType::of_int(row.get(1)?)?;
+                        let data = row.get_ref(2)?.as_blob()?;
+                        let journey_data = JourneyData::deserialize(data, journey_type)?;
+                        fingerprints
+                            .push((id, duplicate_detector::fingerprint(&journey_data, None)));
+                    }
+                    drop(rows);
+                    drop(query);
+                    for (id, fingerprint) in fingerprints {
+                        tx.execute(
+                            "UPDATE journey SET fingerprint = ?1 WHERE id = ?2;",
+                            (fingerprint, id),
+                        )?;
+                    }
+                    Ok(())
+                },
+                &|tx| {
+                    let sql = "
+                CREATE TABLE place (
+                    id                TEXT    PRIMARY KEY
+                                              NOT NULL
+                                              UNIQUE,
+                    lat               REAL    NOT NULL,
+                    lng               REAL    NOT NULL
+                );
+                CREATE INDEX place_lat_index ON place (
+                    lat
+                );
+                CREATE TABLE place_visit (
+                    id                INTEGER PRIMARY KEY AUTOINCREMENT
+                                              UNIQUE
+                                              NOT NULL,
+                    place_id          TEXT    NOT NULL,
+                    journey_id        TEXT,
+                    arrival_timestamp_sec
+                                      INTEGER NOT NULL,
+                    departure_timestamp_sec
+                                      INTEGER NOT NULL
+                );
+                CREATE INDEX place_visit_place_id_index ON place_visit (
+                    place_id
+                );
+                CREATE INDEX place_visit_journey_id_index ON place_visit (
+                    journey_id
+                );
+                CREATE INDEX place_visit_arrival_index ON place_visit (
+                    arrival_timestamp_sec
+                );
+                ";
+                    for s in sql_split::split(sql) {
+                        tx.execute(&s, ())?;
+                    }
+                    Ok(())
+                },
+                &|tx| {
+                    let sql = "
+                CREATE TABLE privacy_zone (
+                    id                TEXT    PRIMARY KEY
+                                              NOT NULL
+                                              UNIQUE,
+                    name              TEXT    NOT NULL,
+                    shape             TEXT    NOT NULL
+                );
+                ";
+                    for s in sql_split::split(sql) {
+                        tx.execute(&s, ())?;
+                    }
+                    Ok(())
+                },
+                &|tx| {
+                    // make `lat` and `lng` nullable and add `point` for encryption
+                    let sql = "
+                CREATE TABLE ongoing_journey_new (
+                    id             INTEGER PRIMARY KEY AUTOINCREMENT
+                                        UNIQUE
+                                        NOT NULL,
+                    timestamp_sec  INTEGER,
+                    lat            REAL,
+                    lng            REAL,
+                    point          BLOB,
+                    process_result INTEGER NOT NULL
+                );
+                INSERT INTO ongoing_journey_new (id, timestamp_sec, lat, lng, process_result)
+                    SELECT id, timestamp_sec, lat, lng, process_result FROM ongoing_journey;
+                DROP TABLE ongoing_journey;
+                ALTER TABLE ongoing_journey_new RENAME TO ongoing_journey;
+                ";
+                    for s in sql_split::split(sql) {
+                        tx.execute(&s, ())?;
+                    }
+                    Ok(())
+                },
+                &|tx| {
+                    // make the location columns of `place` and `privacy_zone`
+                    // nullable and add the encrypted columns
+                    let sql = "
+                CREATE TABLE place_new (
+                    id                TEXT    PRIMARY KEY
+                                              NOT NULL
+                                              UNIQUE,
+                    lat               REAL,
+                    lng               REAL,
+                    point             BLOB
+                );
+                INSERT INTO place_new (id, lat, lng) SELECT id, lat, lng FROM place;
+                DROP TABLE place;
+                ALTER TABLE place_new RENAME TO place;
+                CREATE INDEX place_lat_index ON place (
+                    lat
+                );
+                CREATE TABLE privacy_zone_new (
+                    id                TEXT    PRIMARY KEY
+                                              NOT NULL
+                                              UNIQUE,
+                    name              TEXT,
+                    shape             TEXT,
+                    data              BLOB
+                );
+                INSERT INTO privacy_zone_new (id, name, shape)
+                    SELECT id, name, shape FROM privacy_zone;
+                DROP TABLE privacy_zone;
+                ALTER TABLE privacy_zone_new RENAME TO privacy_zone;
+                ";
+                    for s in sql_split::split(sql) {
+                        tx.execute(&s, ())?;
+                    }
+                    Ok(())
+                },
+            ],
+        )?;
+        let mut main_db = MainDb { conn, cipher: None };
+
+        let key_check = {
+            let tx = main_db.conn.transaction()?;
+            utils::db::get_metadata(&tx, db_encryption::KEY_CHECK_METADATA_KEY)?
+        };
+        match (key_check, key) {
+            (None, None) => (),
+            (Some(_), None) => bail!("The main db is encrypted but no key is provided"),
+            (None, Some(key)) => {
+                info!("Encrypting the main db");
+                main_db.rotate_key(Some(key))?;
+            }
+            (Some(key_check), Some(key)) => {
+                let cipher = DbCipher::new(key)?;
+                if !db_encryption::matches_key_check(Some(&cipher), Some(&key_check)) {
+                    bail!("Wrong key for the encrypted main db");
                 }
-                Ok(())
-            }],
-        )
-        .expect("failed to open main db");
-        MainDb { conn }
+                main_db.cipher = Some(cipher);
+            }
+        }
+        Ok(main_db)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Re-encrypts all data with `new_key`, `None` decrypts the db. The db is
+    /// vacuumed afterwards so the old data does not stay in free pages.
+    #[auto_context]
+    pub fn rotate_key(&mut self, new_key: Option<&[u8]>) -> Result<()> {
+        let old_cipher = self.cipher.as_ref();
+        let new_cipher = new_key.map(DbCipher::new).transpose()?;
+
+        let tx = self.conn.transaction()?;
+        // journeys are processed one by one since the data could be large
+        let journey_ids = {
+            let mut query = tx.prepare("SELECT id FROM journey;")?;
+            let ids = query
+                .query_map((), |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            ids
+        };
+        for id in journey_ids {
+            let (journey_type, header, data): (i8, Vec<u8>, Vec<u8>) = tx.query_row(
+                "SELECT type, header, data FROM journey WHERE id = ?1;",
+                [&id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+            let header_aad = journey_header_aad(&id);
+            let data_aad = journey_data_aad(&id);
+            let header = db_encryption::encrypt_if_enabled(
+                new_cipher.as_ref(),
+                db_encryption::decrypt_if_enabled(old_cipher, &header, &header_aad)?.into_owned(),
+                &header_aad,
+            )?;
+            let data = db_encryption::decrypt_if_enabled(old_cipher, &data, &data_aad)?;
+            // the fingerprint is keyed as well
+            let fingerprint = duplicate_detector::fingerprint(
+                &JourneyData::deserialize(&*data, JourneyType::of_int(journey_type)?)?,
+                new_cipher.as_ref(),
+            );
+            let data = db_encryption::encrypt_if_enabled(
+                new_cipher.as_ref(),
+                data.into_owned(),
+                &data_aad,
+            )?;
+            tx.execute(
+                "UPDATE journey SET header = ?1, data = ?2, fingerprint = ?3 WHERE id = ?4;",
+                (header, data, fingerprint, &id),
+            )?;
+        }
+
+        let points: Vec<(i64, PointColumns)> = {
+            let mut query = tx.prepare("SELECT id, lat, lng, point FROM ongoing_journey;")?;
+            let points = query
+                .query_map((), |row| {
+                    Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            points
+        };
+        for (id, (latitude, longitude, point)) in points {
+            let point = decode_point(
+                old_cipher,
+                latitude,
+                longitude,
+                point.as_deref(),
+                ONGOING_JOURNEY_POINT_AAD,
+            )?;
+            let (latitude, longitude, point) = encode_point(
+                new_cipher.as_ref(),
+                point.latitude,
+                point.longitude,
+                ONGOING_JOURNEY_POINT_AAD,
+            )?;
+            tx.execute(
+                "UPDATE ongoing_journey SET lat = ?1, lng = ?2, point = ?3 WHERE id = ?4;",
+                (latitude, longitude, point, id),
+            )?;
+        }
+
+        let places: Vec<(String, PointColumns)> = {
+            let mut query = tx.prepare("SELECT id, lat, lng, point FROM place;")?;
+            let places = query
+                .query_map((), |row| {
+                    Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            places
+        };
+        for (id, (latitude, longitude, point)) in places {
+            let aad = place_point_aad(&id);
+            let point = decode_point(old_cipher, latitude, longitude, point.as_deref(), &aad)?;
+            let (latitude, longitude, point) =
+                encode_point(new_cipher.as_ref(), point.latitude, point.longitude, &aad)?;
+            tx.execute(
+                "UPDATE place SET lat = ?1, lng = ?2, point = ?3 WHERE id = ?4;",
+                (latitude, longitude, point, &id),
+            )?;
+        }
+
+        let privacy_zones: Vec<(String, PrivacyZoneColumns)> = {
+            let mut query = tx.prepare("SELECT id, name, shape, data FROM privacy_zone;")?;
+            let privacy_zones = query
+                .query_map((), |row| {
+                    Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            privacy_zones
+        };
+        for (id, columns) in privacy_zones {
+            let zone = decode_privacy_zone(old_cipher, id, columns)?;
+            let (name, shape, data) =
+                encode_privacy_zone(new_cipher.as_ref(), &zone.id, &zone.name, &zone.shape)?;
+            tx.execute(
+                "UPDATE privacy_zone SET name = ?1, shape = ?2, data = ?3 WHERE id = ?4;",
+                (name, shape, data, &zone.id),
+            )?;
+        }
+
+        utils::db::set_metadata(
+            &tx,
+            db_encryption::KEY_CHECK_METADATA_KEY,
+            db_encryption::key_check(new_cipher.as_ref())?.as_deref(),
+        )?;
+        tx.commit()?;
+        self.cipher = new_cipher;
+
+        self.conn.execute("VACUUM;", ())?;
+        Ok(())
+    }
+
+    /// The cipher used for the data, `cache_db` should use the same one.
+    pub fn cipher(&self) -> Option<&DbCipher> {
+        self.cipher.as_ref()
+    }
+
+    /// A consistent copy of the db at `path`, so slow reads (e.g. backups)
+    /// don't block writes. `path` must not exist yet.
+    #[auto_context]
+    pub fn snapshot(&self, path: &Path) -> Result<MainDb> {
+        let path_str = path.to_str().context("invalid snapshot path")?;
+        self.conn.execute("VACUUM INTO ?1;", (path_str,))?;
+        Ok(MainDb {
+            conn: Connection::open(path)?,
+            cipher: self.cipher.clone(),
+        })
     }
 
     #[auto_context]
@@ -722,7 +1883,9 @@ impl MainDb {
     {
         let mut txn = Txn {
             db_txn: self.conn.transaction()?,
+            cipher: self.cipher.as_ref(),
             action: None,
+            recorder_paused: None,
         };
         let output = f(&mut txn)?;
         txn.db_txn.commit()?;
@@ -740,38 +1903,54 @@ impl MainDb {
       `cache_db` can be put outside `Txn`. Be extra careful.
     */
 
+    /// Data is dropped while recording is paused (see `RecorderState`).
     #[auto_context]
-    fn append_ongoing_journey(
+    pub fn record(
         &mut self,
         raw_data: &gps_processor::RawData,
         process_result: ProcessResult,
     ) -> Result<()> {
-        let process_result = process_result.to_int();
-        assert!(process_result >= 0);
-        let tx = self.conn.transaction()?;
-        let sql = "INSERT INTO ongoing_journey (timestamp_sec, lat, lng, process_result) VALUES (?1, ?2, ?3, ?4);";
-        tx.prepare_cached(sql)?.execute((
-            raw_data.timestamp_ms.map(|x| x / 1000),
-            raw_data.point.latitude,
-            raw_data.point.longitude,
-            process_result,
-        ))?;
-        tx.commit()?;
-        Ok(())
+        self.record_batch([(raw_data, process_result)])
     }
 
+    /// Same as `record` but all the data is written in a single transaction.
     #[auto_context]
-    pub fn record(
+    pub fn record_batch<'a>(
         &mut self,
-        raw_data: &gps_processor::RawData,
-        process_result: ProcessResult,
+        data: impl IntoIterator<Item = (&'a gps_processor::RawData, ProcessResult)>,
     ) -> Result<()> {
-        match process_result {
-            ProcessResult::Ignore => (),
-            ProcessResult::Append | ProcessResult::NewSegment => {
-                self.append_ongoing_journey(raw_data, process_result)?;
+        let tx = self.conn.transaction()?;
+        // checked in the same transaction so it is never stale
+        if get_recorder_state(&tx)?.paused {
+            return Ok(());
+        }
+        {
+            let sql = "INSERT INTO ongoing_journey (timestamp_sec, lat, lng, point, process_result) VALUES (?1, ?2, ?3, ?4, ?5);";
+            let mut query = tx.prepare_cached(sql)?;
+            for (raw_data, process_result) in data {
+                match process_result {
+                    ProcessResult::Ignore => (),
+                    ProcessResult::Append | ProcessResult::NewSegment => {
+                        let process_result = process_result.to_int();
+                        assert!(process_result >= 0);
+                        let (lat, lng, point) = encode_point(
+                            self.cipher.as_ref(),
+                            raw_data.point.latitude,
+                            raw_data.point.longitude,
+                            ONGOING_JOURNEY_POINT_AAD,
+                        )?;
+                        query.execute((
+                            raw_data.timestamp_ms.map(|x| x / 1000),
+                            lat,
+                            lng,
+                            point,
+                            process_result,
+                        ))?;
+                    }
+                }
             }
         }
+        tx.commit()?;
         Ok(())
     }
 
@@ -823,12 +2002,61 @@ pub enum Setting {
     // TODO: We should consider making the flutter part handle this, similar to
     // `GpsManager.isRecording`.
     RawDataMode,
+    GpsFilter,
+    PreprocessingProfile,
+    CustomPreprocessingThresholds,
+    RawDataMaxAgeDays,
+    RawDataMaxTotalSizeMb,
+    AutoFinalizePolicy,
+    JourneySplitOptions,
+    RecorderState,
+    BackupPolicy,
 }
 
 impl Setting {
     fn to_db_key(self) -> &'static str {
         match self {
             Self::RawDataMode => "RAW_DATA_MODE",
+            Self::GpsFilter => "GPS_FILTER",
+            Self::PreprocessingProfile => "PREPROCESSING_PROFILE",
+            Self::CustomPreprocessingThresholds => "CUSTOM_PREPROCESSING_THRESHOLDS",
+            Self::RawDataMaxAgeDays => "RAW_DATA_MAX_AGE_DAYS",
+            Self::RawDataMaxTotalSizeMb => "RAW_DATA_MAX_TOTAL_SIZE_MB",
+            Self::AutoFinalizePolicy => "AUTO_FINALIZE_POLICY",
+            Self::JourneySplitOptions => "JOURNEY_SPLIT_OPTIONS",
+            Self::RecorderState => "RECORDER_STATE",
+            Self::BackupPolicy => "BACKUP_POLICY",
         }
     }
 }
+
+/// The explicit recording controls on top of the ongoing journey, stored in
+/// `setting`. The manual journey ends when the ongoing journey is finalized
+/// or discarded, the pause only ends with `Txn::resume_recording`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[frb(non_opaque)]
+pub struct RecorderState {
+    /// New data is ignored while paused.
+    pub paused: bool,
+    /// Set for a manual journey, see `Txn::start_manual_journey`.
+    pub manual_journey_title: Option<String>,
+}
+
+// Stored as JSON in settings.
+impl FromStr for RecorderState {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl fmt::Display for RecorderState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(self).map_err(|_| fmt::Error)?
+        )
+    }
+}
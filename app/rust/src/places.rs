@@ -0,0 +1,105 @@
+/* Places the user spent time at.
+
+A stay point is a period of time that the user stays within a small area. It is
+detected from timestamped points: the ongoing journey when finalizing it, or
+imported data with timestamps. Note that `GpsPreprocessor` ignores data once it
+becomes stationary, so in the ongoing journey a stay usually shows up as two
+nearby points with a large time gap in between, while imported data tends to
+have lots of points around the same spot. The detection handles both.
+
+Stay points are then clustered into places (see `main_db`): a stay point joins
+the closest existing place within `PLACE_RADIUS_IN_M` or creates a new one.
+Each stay point becomes a visit of a place.
+*/
+use chrono::{DateTime, Utc};
+
+use crate::gps_processor::{Point, RawData};
+
+const STAY_RADIUS_IN_M: f64 = 100.0;
+const STAY_MIN_DURATION_IN_SEC: i64 = 10 * 60;
+pub const PLACE_RADIUS_IN_M: f64 = 150.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StayPoint {
+    pub point: Point,
+    pub arrival: DateTime<Utc>,
+    pub departure: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Place {
+    pub id: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub visit_count: u32,
+    pub total_duration_sec: i64,
+    pub last_departure: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaceVisit {
+    pub place_id: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub arrival: DateTime<Utc>,
+    pub departure: DateTime<Utc>,
+    /// The journey that the visit is detected from.
+    pub journey_id: Option<String>,
+}
+
+fn centroid(points: &[(i64, Point)]) -> Point {
+    let n = points.len() as f64;
+    Point {
+        latitude: points.iter().map(|(_, x)| x.latitude).sum::<f64>() / n,
+        longitude: points.iter().map(|(_, x)| x.longitude).sum::<f64>() / n,
+    }
+}
+
+/// `points` are `(timestamp_sec, point)` ordered by time.
+pub fn detect_stay_points(points: &[(i64, Point)]) -> Vec<StayPoint> {
+    let mut stay_points: Vec<StayPoint> = Vec::new();
+    let mut i = 0;
+    while i < points.len() {
+        let (arrival_sec, anchor) = &points[i];
+        let mut j = i + 1;
+        while j < points.len() && points[j].1.haversine_distance(anchor) <= STAY_RADIUS_IN_M {
+            j += 1;
+        }
+        let departure_sec = points[j - 1].0;
+        if departure_sec - arrival_sec < STAY_MIN_DURATION_IN_SEC {
+            i += 1;
+            continue;
+        }
+
+        let stay_point = StayPoint {
+            point: centroid(&points[i..j]),
+            arrival: DateTime::from_timestamp(*arrival_sec, 0).unwrap_or_default(),
+            departure: DateTime::from_timestamp(departure_sec, 0).unwrap_or_default(),
+        };
+        // A short trip away (or a single bad point) could break a stay into
+        // two, merge them back.
+        match stay_points.last_mut() {
+            Some(last)
+                if last.point.haversine_distance(&stay_point.point) <= STAY_RADIUS_IN_M
+                    && (stay_point.arrival - last.departure).num_seconds()
+                        < STAY_MIN_DURATION_IN_SEC =>
+            {
+                last.departure = stay_point.departure;
+            }
+            _ => stay_points.push(stay_point),
+        }
+        i = j;
+    }
+    stay_points
+}
+
+/// Data without timestamp is skipped.
+pub fn detect_stay_points_from_raw_data(raw_data: &[Vec<RawData>]) -> Vec<StayPoint> {
+    let mut points: Vec<(i64, Point)> = raw_data
+        .iter()
+        .flatten()
+        .filter_map(|x| Some((x.timestamp_ms? / 1000, x.point.clone())))
+        .collect();
+    points.sort_by_key(|(timestamp_sec, _)| *timestamp_sec);
+    detect_stay_points(&points)
+}
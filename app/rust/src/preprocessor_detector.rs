@@ -0,0 +1,201 @@
+/* Recommending an `ImportPreprocessor` by looking at the imported data instead
+of guessing by the app that produced the file.
+
+We look at:
+- Timestamp quality: without reliable timestamps, we can't tell much.
+- Speed and altitude: flights are much faster and higher than anything else.
+- Sparsity: some apps only record a point every ~100m or when the user moves,
+  most pairs of points are too far apart for the default segment gap rule,
+  but fine for the spare one.
+- Sampling interval: just for the reasons, dense data is the normal case.
+*/
+use flutter_rust_bridge::frb;
+
+use crate::api::import::ImportPreprocessor;
+use crate::gps_processor::{RawData, SegmentGapRule};
+
+// Below this, there isn't enough data to say anything meaningful.
+const MIN_TIMED_PAIRS: usize = 10;
+const MIN_TIMESTAMP_COVERAGE: f64 = 0.5;
+// ~290 km/h, faster than most trains and way faster than cars.
+const FLIGHT_MIN_MEDIAN_SPEED_M_PER_S: f64 = 80.;
+// Higher than any road in the world.
+const FLIGHT_MIN_MEDIAN_ALTITUDE_M: f32 = 6000.;
+const SPARE_MIN_DEFAULT_BREAK_RATIO: f64 = 0.3;
+
+/// Explanations of the recommendation, they are localized by the app.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreprocessorReason {
+    /// Only possible for flights.
+    HighAltitude {
+        median_altitude_m: f32,
+    },
+    /// In [0, 1].
+    LowTimestampCoverage {
+        coverage: f64,
+    },
+    NotEnoughTimedPoints,
+    /// Duplicated or going backward, in [0, 1].
+    BadTimestamps {
+        ratio: f64,
+    },
+    SamplingStats {
+        median_interval_sec: f64,
+        median_speed_km_per_h: f64,
+    },
+    /// Only possible for flights.
+    HighSpeed,
+    /// Most points are too far apart to be connected by the default segment
+    /// gap rule, in [0, 1].
+    Sparse {
+        default_break_ratio: f64,
+    },
+    NormalTrack,
+}
+
+#[derive(Debug, Clone)]
+#[frb(non_opaque)]
+pub struct PreprocessorRecommendation {
+    pub preprocessor: ImportPreprocessor,
+    /// In [0, 1].
+    pub confidence: f64,
+    pub reasons: Vec<PreprocessorReason>,
+}
+
+fn median<T: PartialOrd + Copy>(values: &mut [T]) -> Option<T> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    Some(values[values.len() / 2])
+}
+
+pub fn recommend_preprocessor(raw_vector_data: &[Vec<RawData>]) -> PreprocessorRecommendation {
+    let mut reasons = Vec::new();
+    let points = || raw_vector_data.iter().flatten();
+    let point_count = points().count();
+
+    let mut altitudes: Vec<f32> = points().filter_map(|x| x.altitude).collect();
+    if altitudes.len() * 2 >= point_count {
+        if let Some(median_altitude) = median(&mut altitudes) {
+            if median_altitude >= FLIGHT_MIN_MEDIAN_ALTITUDE_M {
+                reasons.push(PreprocessorReason::HighAltitude {
+                    median_altitude_m: median_altitude,
+                });
+                return PreprocessorRecommendation {
+                    preprocessor: ImportPreprocessor::FlightTrack,
+                    confidence: 0.9,
+                    reasons,
+                };
+            }
+        }
+    }
+
+    let timestamp_count = points().filter(|x| x.timestamp_ms.is_some()).count();
+    let timestamp_coverage = if point_count == 0 {
+        0.
+    } else {
+        timestamp_count as f64 / point_count as f64
+    };
+    if timestamp_coverage < MIN_TIMESTAMP_COVERAGE {
+        reasons.push(PreprocessorReason::LowTimestampCoverage {
+            coverage: timestamp_coverage,
+        });
+        return PreprocessorRecommendation {
+            preprocessor: ImportPreprocessor::Generic,
+            confidence: 0.5,
+            reasons,
+        };
+    }
+
+    let mut intervals_sec = Vec::new();
+    let mut speeds_m_per_s = Vec::new();
+    let mut backward_count: usize = 0;
+    let mut duplicated_count: usize = 0;
+    let mut default_break_count: usize = 0;
+    let mut spare_break_count: usize = 0;
+    for segment in raw_vector_data {
+        for (prev, curr) in segment.iter().zip(segment.iter().skip(1)) {
+            let (Some(prev_ms), Some(curr_ms)) = (prev.timestamp_ms, curr.timestamp_ms) else {
+                continue;
+            };
+            let interval_ms = curr_ms - prev_ms;
+            if interval_ms < 0 {
+                backward_count += 1;
+                continue;
+            }
+            if interval_ms == 0 {
+                duplicated_count += 1;
+                continue;
+            }
+            let distance_m = prev.point.haversine_distance(&curr.point);
+            let interval_sec = interval_ms as f64 / 1000.;
+            intervals_sec.push(interval_sec);
+            speeds_m_per_s.push(distance_m / interval_sec);
+            if SegmentGapRule::Default.is_gap(distance_m, interval_ms) {
+                default_break_count += 1;
+            }
+            if SegmentGapRule::Spare.is_gap(distance_m, interval_ms) {
+                spare_break_count += 1;
+            }
+        }
+    }
+
+    let pair_count = intervals_sec.len() + backward_count + duplicated_count;
+    if intervals_sec.len() < MIN_TIMED_PAIRS {
+        reasons.push(PreprocessorReason::NotEnoughTimedPoints);
+        return PreprocessorRecommendation {
+            preprocessor: ImportPreprocessor::Generic,
+            confidence: 0.3,
+            reasons,
+        };
+    }
+
+    // Penalize the confidence if the timestamps look off.
+    let bad_timestamp_ratio = (backward_count + duplicated_count) as f64 / pair_count as f64;
+    if bad_timestamp_ratio > 0.05 {
+        reasons.push(PreprocessorReason::BadTimestamps {
+            ratio: bad_timestamp_ratio,
+        });
+    }
+    let timestamp_quality = timestamp_coverage * (1. - bad_timestamp_ratio);
+
+    let median_interval_sec = median(&mut intervals_sec).unwrap_or_default();
+    let median_speed = median(&mut speeds_m_per_s).unwrap_or_default();
+    reasons.push(PreprocessorReason::SamplingStats {
+        median_interval_sec,
+        median_speed_km_per_h: median_speed * 3.6,
+    });
+
+    if median_speed >= FLIGHT_MIN_MEDIAN_SPEED_M_PER_S {
+        reasons.push(PreprocessorReason::HighSpeed);
+        return PreprocessorRecommendation {
+            preprocessor: ImportPreprocessor::FlightTrack,
+            confidence: 0.9 * timestamp_quality,
+            reasons,
+        };
+    }
+
+    let timed_pair_count = intervals_sec.len() as f64;
+    let default_break_ratio = default_break_count as f64 / timed_pair_count;
+    let spare_break_ratio = spare_break_count as f64 / timed_pair_count;
+    if default_break_ratio >= SPARE_MIN_DEFAULT_BREAK_RATIO
+        && spare_break_ratio <= default_break_ratio / 2.
+    {
+        reasons.push(PreprocessorReason::Sparse {
+            default_break_ratio,
+        });
+        return PreprocessorRecommendation {
+            preprocessor: ImportPreprocessor::Spare,
+            confidence: (default_break_ratio - spare_break_ratio).min(1.) * timestamp_quality,
+            reasons,
+        };
+    }
+
+    reasons.push(PreprocessorReason::NormalTrack);
+    PreprocessorRecommendation {
+        preprocessor: ImportPreprocessor::Generic,
+        confidence: (1. - default_break_ratio) * timestamp_quality,
+        reasons,
+    }
+}
@@ -0,0 +1,236 @@
+/* Privacy zones hide places like home or work from what leaves the app for
+sharing: exported files (GPX/KML/MLDX) and renders. Full archives are meant
+to restore everything so they only apply the zones when asked to, backups never
+do. The data in the app is never changed.
+
+For vector data, points inside a zone are removed and the segment is split
+there, so no line is drawn into the zone. For bitmap data (and renders), the
+zones are rasterized into a `JourneyBitmap` at the bitmap resolution and then
+subtracted via `JourneyBitmap::difference`.
+*/
+use anyhow::Result;
+use auto_context::auto_context;
+use serde::{Deserialize, Serialize};
+
+use crate::gps_processor::Point;
+use crate::journey_bitmap::{
+    JourneyBitmap, BITMAP_WIDTH_OFFSET, MAP_WIDTH_OFFSET, TILE_WIDTH_OFFSET,
+};
+use crate::journey_data::JourneyData;
+use crate::journey_vector::{JourneyVector, TrackPoint, TrackSegment};
+use crate::utils;
+
+const MAX_CIRCLE_RADIUS_IN_M: f64 = 10_000.0;
+// The width and height of the bounding box, same as the largest circle. Zones
+// are rasterized by their bounding box.
+const MAX_POLYGON_EXTENT_IN_M: f64 = 2.0 * MAX_CIRCLE_RADIUS_IN_M;
+// The zoom level of a single bit in `JourneyBitmap`.
+const BITMAP_ZOOM: i32 = (MAP_WIDTH_OFFSET + TILE_WIDTH_OFFSET + BITMAP_WIDTH_OFFSET) as i32;
+const EARTH_RADIUS_IN_M: f64 = 6371e3;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PrivacyZoneShape {
+    Circle {
+        center: Point,
+        radius_m: f64,
+    },
+    /// The polygon is closed automatically.
+    Polygon {
+        points: Vec<Point>,
+    },
+}
+
+impl PrivacyZoneShape {
+    #[auto_context]
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Circle { radius_m, .. } => {
+                if !(*radius_m > 0.0 && *radius_m <= MAX_CIRCLE_RADIUS_IN_M) {
+                    bail!("The radius must be in (0, {MAX_CIRCLE_RADIUS_IN_M}] meters");
+                }
+            }
+            Self::Polygon { points } => {
+                if points.len() < 3 {
+                    bail!("A polygon needs at least 3 points");
+                }
+                let (min_lng, min_lat, max_lng, max_lat) = self.bounding_box();
+                let center_lat = (min_lat + max_lat) / 2.0;
+                let width = Point {
+                    latitude: center_lat,
+                    longitude: min_lng,
+                }
+                .haversine_distance(&Point {
+                    latitude: center_lat,
+                    longitude: max_lng,
+                });
+                let height = Point {
+                    latitude: min_lat,
+                    longitude: min_lng,
+                }
+                .haversine_distance(&Point {
+                    latitude: max_lat,
+                    longitude: min_lng,
+                });
+                // also rejects NaN
+                if !(width <= MAX_POLYGON_EXTENT_IN_M && height <= MAX_POLYGON_EXTENT_IN_M) {
+                    bail!("A polygon must fit in {MAX_POLYGON_EXTENT_IN_M} x {MAX_POLYGON_EXTENT_IN_M} meters");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        match self {
+            Self::Circle { center, radius_m } => center.haversine_distance(point) <= *radius_m,
+            Self::Polygon { points } => {
+                // ray casting, zones are small so we treat lat/lng as planar
+                let mut inside = false;
+                let mut j = points.len() - 1;
+                for i in 0..points.len() {
+                    let (a, b) = (&points[i], &points[j]);
+                    if (a.latitude > point.latitude) != (b.latitude > point.latitude)
+                        && point.longitude
+                            < (b.longitude - a.longitude) * (point.latitude - a.latitude)
+                                / (b.latitude - a.latitude)
+                                + a.longitude
+                    {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+                inside
+            }
+        }
+    }
+
+    // (min_lng, min_lat, max_lng, max_lat)
+    fn bounding_box(&self) -> (f64, f64, f64, f64) {
+        match self {
+            Self::Circle { center, radius_m } => {
+                let delta_lat = (radius_m / EARTH_RADIUS_IN_M).to_degrees();
+                let delta_lng = delta_lat / center.latitude.to_radians().cos().max(0.01);
+                (
+                    center.longitude - delta_lng,
+                    center.latitude - delta_lat,
+                    center.longitude + delta_lng,
+                    center.latitude + delta_lat,
+                )
+            }
+            Self::Polygon { points } => points.iter().fold(
+                (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+                |(min_lng, min_lat, max_lng, max_lat), x| {
+                    (
+                        min_lng.min(x.longitude),
+                        min_lat.min(x.latitude),
+                        max_lng.max(x.longitude),
+                        max_lat.max(x.latitude),
+                    )
+                },
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrivacyZone {
+    pub id: String,
+    pub name: String,
+    pub shape: PrivacyZoneShape,
+}
+
+fn is_hidden(zones: &[PrivacyZone], point: &Point) -> bool {
+    zones.iter().any(|zone| zone.shape.contains(point))
+}
+
+/// Removes points inside any of the zones, segments are split at the removed
+/// points.
+pub fn trim_journey_vector(zones: &[PrivacyZone], journey_vector: &JourneyVector) -> JourneyVector {
+    let mut track_segments = Vec::new();
+    for track_segment in &journey_vector.track_segments {
+        let mut track_points: Vec<TrackPoint> = Vec::new();
+        for x in &track_segment.track_points {
+            let point = Point {
+                latitude: x.latitude,
+                longitude: x.longitude,
+            };
+            if is_hidden(zones, &point) {
+                if !track_points.is_empty() {
+                    track_segments.push(TrackSegment {
+                        track_points: std::mem::take(&mut track_points),
+                    });
+                }
+            } else {
+                track_points.push(x.clone());
+            }
+        }
+        if !track_points.is_empty() {
+            track_segments.push(TrackSegment { track_points });
+        }
+    }
+    JourneyVector { track_segments }
+}
+
+/// All the bits covered by any of the zones.
+pub fn zones_to_journey_bitmap(zones: &[PrivacyZone]) -> JourneyBitmap {
+    let mut journey_bitmap = JourneyBitmap::new();
+    let n = f64::powi(2.0, BITMAP_ZOOM);
+    let center_lng = |x: i32| (x as f64 + 0.5) / n * 360.0 - 180.0;
+    let center_lat = |y: i32| {
+        let (_, top) = utils::tile_x_y_to_lng_lat(0, y, BITMAP_ZOOM);
+        let (_, bottom) = utils::tile_x_y_to_lng_lat(0, y + 1, BITMAP_ZOOM);
+        (top + bottom) / 2.0
+    };
+    for zone in zones {
+        let (min_lng, min_lat, max_lng, max_lat) = zone.shape.bounding_box();
+        let (x0, y0) = utils::lng_lat_to_tile_x_y(min_lng, max_lat, BITMAP_ZOOM);
+        let (x1, y1) = utils::lng_lat_to_tile_x_y(max_lng, min_lat, BITMAP_ZOOM);
+        for y in y0..=y1 {
+            let latitude = center_lat(y);
+            // drawing each run of covered bits as a horizontal line
+            let mut run_start: Option<i32> = None;
+            for x in x0..=x1 + 1 {
+                let covered = x <= x1
+                    && zone.shape.contains(&Point {
+                        latitude,
+                        longitude: center_lng(x),
+                    });
+                match (covered, run_start) {
+                    (true, None) => run_start = Some(x),
+                    (false, Some(start)) => {
+                        journey_bitmap.add_line(
+                            center_lng(start),
+                            latitude,
+                            center_lng(x - 1),
+                            latitude,
+                        );
+                        run_start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+    journey_bitmap
+}
+
+pub fn apply_to_journey_bitmap(zones: &[PrivacyZone], journey_bitmap: &mut JourneyBitmap) {
+    if !zones.is_empty() {
+        journey_bitmap.difference(&zones_to_journey_bitmap(zones));
+    }
+}
+
+pub fn apply_to_journey_data(zones: &[PrivacyZone], journey_data: JourneyData) -> JourneyData {
+    if zones.is_empty() {
+        return journey_data;
+    }
+    match journey_data {
+        JourneyData::Vector(journey_vector) => {
+            JourneyData::Vector(trim_journey_vector(zones, &journey_vector))
+        }
+        JourneyData::Bitmap(mut journey_bitmap) => {
+            apply_to_journey_bitmap(zones, &mut journey_bitmap);
+            JourneyData::Bitmap(journey_bitmap)
+        }
+    }
+}
@@ -0,0 +1,385 @@
+/* Files recorded in raw data mode (see `storage::RawDataRecorder`).
+
+A new file `gps-{local date}-{i}.csv` is started every time the app starts or
+the local date changes, so a file never covers more than one local date. Once a
+file is closed, it is compressed into `.csv.zst` (raw data compresses really
+well) and old files are deleted based on `RawDataRetention`.
+
+The date in the file name is also what makes time range queries cheap, we only
+need to decompress the files of the dates we are interested in.
+*/
+use anyhow::{Context, Result};
+use auto_context::auto_context;
+use chrono::{DateTime, Days, Local, NaiveDate, Utc};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use crate::gps_processor::RawData;
+use crate::main_db::ZSTD_COMPRESS_LEVEL;
+use crate::storage::RawCsvRow;
+
+const FILE_NAME_PREFIX: &str = "gps-";
+const CSV_SUFFIX: &str = ".csv";
+const COMPRESSED_CSV_SUFFIX: &str = ".csv.zst";
+const TEMP_SUFFIX: &str = ".tmp";
+const VERSION_COLUMN: &str = "csv_version";
+
+/// Version of the CSV format, written in the `csv_version` column of every row
+/// so the files stay plain CSV. Adding optional columns doesn't need a new
+/// version since columns are matched by the header (see `RawCsvRow`), bump it
+/// when the meaning of existing columns changes. Files without the column are
+/// version 1.
+pub const CSV_VERSION: u32 = 2;
+
+pub const DEFAULT_MAX_AGE_DAYS: u32 = 0;
+pub const DEFAULT_MAX_TOTAL_SIZE_MB: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawDataRetention {
+    /// Files older than this are deleted, 0 means no limit.
+    pub max_age_days: u32,
+    /// The oldest files are deleted until the total size is below this, 0
+    /// means no limit.
+    pub max_total_size_mb: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawDataFileEntry {
+    /// File name without the extension, it is the same before and after
+    /// compression.
+    pub name: String,
+    pub path: PathBuf,
+    pub date: Option<NaiveDate>,
+    pub compressed: bool,
+    pub size_bytes: u64,
+}
+
+fn parse_file_name(file_name: &str) -> Option<(String, bool)> {
+    if let Some(name) = file_name.strip_suffix(COMPRESSED_CSV_SUFFIX) {
+        Some((name.to_string(), true))
+    } else {
+        file_name
+            .strip_suffix(CSV_SUFFIX)
+            .map(|name| (name.to_string(), false))
+    }
+}
+
+fn date_of_name(name: &str) -> Option<NaiveDate> {
+    let date = name.strip_prefix(FILE_NAME_PREFIX)?.get(0..10)?;
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+// For ordering, the index is not zero padded so names can't be compared as
+// strings, e.g. `gps-2025-08-09-10` is newer than `gps-2025-08-09-9`.
+fn date_and_index_of_name(name: &str) -> Option<(NaiveDate, u32)> {
+    let index = name
+        .strip_prefix(FILE_NAME_PREFIX)?
+        .get(10..)?
+        .strip_prefix('-')?
+        .parse()
+        .ok()?;
+    Some((date_of_name(name)?, index))
+}
+
+/// Whether the file may still be recorded to. `current_name` is the file
+/// being recorded when it was read, but the recorder could start a new file
+/// right after that (see `Storage::maintain_raw_data`). A new file is always
+/// newer than `current_name`, or of `today` or later if there was none.
+fn may_be_recording(name: &str, current_name: Option<&str>, today: NaiveDate) -> bool {
+    match current_name {
+        Some(current_name) => {
+            name == current_name
+                || matches!(
+                    (date_and_index_of_name(name), date_and_index_of_name(current_name)),
+                    (Some(key), Some(current_key)) if key > current_key
+                )
+        }
+        None => date_of_name(name).is_some_and(|date| date >= today),
+    }
+}
+
+/// Name of a raw data file without the extension, e.g. for naming exported
+/// files. Works for both compressed and uncompressed files.
+pub fn name_of_path(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    Some(match parse_file_name(file_name) {
+        Some((name, _)) => name,
+        None => path.file_stem()?.to_str()?.to_string(),
+    })
+}
+
+/// Works for both compressed and uncompressed files, of any version up to
+/// `CSV_VERSION`.
+#[auto_context]
+pub fn open_csv_reader(path: &Path) -> Result<csv::Reader<Box<dyn Read>>> {
+    let file = File::open(path)?;
+    let compressed = path
+        .to_str()
+        .is_some_and(|x| x.ends_with(COMPRESSED_CSV_SUFFIX));
+    let mut reader: Box<dyn BufRead> = if compressed {
+        Box::new(BufReader::new(zstd::Decoder::new(file)?))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+
+    // The version is checked on the first row, rows are written without line
+    // breaks in them.
+    let mut first_lines = String::new();
+    reader.read_line(&mut first_lines)?;
+    reader.read_line(&mut first_lines)?;
+    let mut peek_reader = csv::Reader::from_reader(first_lines.as_bytes());
+    let version_index = peek_reader
+        .headers()?
+        .iter()
+        .position(|x| x == VERSION_COLUMN);
+    // a partial row (without the line break) is left to the caller, same as
+    // the other rows
+    if let (Some(version_index), true) = (version_index, first_lines.ends_with('\n')) {
+        if let Some(Ok(record)) = peek_reader.records().next() {
+            let version = record.get(version_index).unwrap_or_default();
+            let version: u32 = version
+                .parse()
+                .with_context(|| format!("invalid raw data CSV version: {version}"))?;
+            if version > CSV_VERSION {
+                bail!("unsupported raw data CSV version: {version}");
+            }
+        }
+    }
+    let reader = Box::new(Cursor::new(first_lines).chain(reader));
+    Ok(csv::Reader::from_reader(reader))
+}
+
+pub struct RawDataStore {
+    dir: PathBuf,
+}
+
+impl RawDataStore {
+    pub fn new(support_dir: &str) -> Self {
+        RawDataStore {
+            dir: Path::new(support_dir).join("raw_data"),
+        }
+    }
+
+    /// Picks a file for `date` that does not exist yet. Returns the path and
+    /// the name of it.
+    #[auto_context]
+    pub fn new_file(&self, date: NaiveDate) -> Result<(PathBuf, String)> {
+        fs::create_dir_all(&self.dir)?;
+        let mut i = 0;
+        loop {
+            let name = format!("{FILE_NAME_PREFIX}{date}-{i}");
+            let path = self.dir.join(format!("{name}{CSV_SUFFIX}"));
+            let compressed_path = self.dir.join(format!("{name}{COMPRESSED_CSV_SUFFIX}"));
+            if !path.exists() && !compressed_path.exists() {
+                return Ok((path, name));
+            }
+            i += 1;
+        }
+    }
+
+    /// Newest first.
+    #[auto_context]
+    pub fn list(&self) -> Result<Vec<RawDataFileEntry>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        if !self.dir.is_dir() {
+            bail!(
+                "raw_data path exists but is not a directory: {:?}",
+                self.dir
+            );
+        }
+
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let dir_entry = dir_entry?;
+            let path = dir_entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Some((name, compressed)) = path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .and_then(parse_file_name)
+            else {
+                continue;
+            };
+            entries.push(RawDataFileEntry {
+                date: date_of_name(&name),
+                name,
+                path,
+                compressed,
+                size_bytes: dir_entry.metadata()?.len(),
+            });
+        }
+
+        // If the app is killed in the middle of compressing a file, both of
+        // them could exist. The compressed one is complete, so prefer it.
+        entries.sort_by(|a, b| {
+            date_and_index_of_name(&b.name)
+                .cmp(&date_and_index_of_name(&a.name))
+                .then_with(|| b.name.cmp(&a.name))
+                .then_with(|| b.compressed.cmp(&a.compressed))
+        });
+        entries.dedup_by(|a, b| a.name == b.name);
+        Ok(entries)
+    }
+
+    #[auto_context]
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let mut deleted = false;
+        for suffix in [CSV_SUFFIX, COMPRESSED_CSV_SUFFIX] {
+            let path = self.dir.join(format!("{name}{suffix}"));
+            if path.exists() {
+                fs::remove_file(&path).with_context(|| {
+                    format!("failed to remove raw data file: {}", path.display())
+                })?;
+                deleted = true;
+            }
+        }
+        if !deleted {
+            bail!("raw data file not found: {name}");
+        }
+        Ok(())
+    }
+
+    #[auto_context]
+    fn compress(&self, entry: &RawDataFileEntry) -> Result<()> {
+        let compressed_path = self
+            .dir
+            .join(format!("{}{COMPRESSED_CSV_SUFFIX}", entry.name));
+        let temp_path = self.dir.join(format!(
+            "{}{COMPRESSED_CSV_SUFFIX}{TEMP_SUFFIX}",
+            entry.name
+        ));
+
+        let mut encoder = zstd::Encoder::new(File::create(&temp_path)?, ZSTD_COMPRESS_LEVEL)?;
+        io::copy(&mut File::open(&entry.path)?, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        fs::rename(&temp_path, &compressed_path)?;
+        fs::remove_file(&entry.path)?;
+        Ok(())
+    }
+
+    /// Compresses all files except the ones that may still be recorded to,
+    /// `current_name` is the file being recorded.
+    #[auto_context]
+    pub fn compress_closed_files(
+        &self,
+        current_name: Option<&str>,
+        today: NaiveDate,
+    ) -> Result<()> {
+        if !self.dir.exists() {
+            return Ok(());
+        }
+        for entry in self.list()? {
+            if may_be_recording(&entry.name, current_name, today) {
+                continue;
+            }
+            if entry.compressed {
+                // clean up the original file if the compression was
+                // interrupted right before deleting it
+                let path = self.dir.join(format!("{}{CSV_SUFFIX}", entry.name));
+                if path.exists() {
+                    fs::remove_file(&path)?;
+                }
+            } else {
+                info!("[raw_data_store] compressing {}", entry.name);
+                self.compress(&entry)?;
+            }
+        }
+        // clean up the partial output of interrupted compression
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.to_str().is_some_and(|x| x.ends_with(TEMP_SUFFIX)) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the oldest files that are out of `retention`, except the ones
+    /// that may still be recorded to, `current_name` is the file being
+    /// recorded. Returns the names of the deleted files.
+    #[auto_context]
+    pub fn apply_retention(
+        &self,
+        retention: &RawDataRetention,
+        today: NaiveDate,
+        current_name: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let mut entries = self.list()?;
+        let mut total_size_bytes: u64 = entries.iter().map(|x| x.size_bytes).sum();
+        let max_total_size_bytes = retention.max_total_size_mb as u64 * 1024 * 1024;
+        let oldest_date = match retention.max_age_days {
+            0 => None,
+            max_age_days => today.checked_sub_days(Days::new(max_age_days as u64)),
+        };
+
+        let mut deleted_names = Vec::new();
+        // `entries` is newest first
+        while let Some(entry) = entries.pop() {
+            if may_be_recording(&entry.name, current_name, today) {
+                continue;
+            }
+            let too_old = matches!((entry.date, oldest_date), (Some(date), Some(oldest_date)) if date < oldest_date);
+            let too_large = max_total_size_bytes > 0 && total_size_bytes > max_total_size_bytes;
+            if too_old || too_large {
+                info!("[raw_data_store] deleting {} for retention", entry.name);
+                fs::remove_file(&entry.path)?;
+                total_size_bytes -= entry.size_bytes;
+                deleted_names.push(entry.name);
+            }
+        }
+        Ok(deleted_names)
+    }
+
+    /// All data in `[start, end]`, in the recorded order. Data without
+    /// timestamp uses the received timestamp instead.
+    #[auto_context]
+    pub fn query(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<RawData>> {
+        // The date in the file name is the local date when the data is
+        // received, one day of margin covers timezone changes and GPS
+        // timestamps being a bit off.
+        let from_date = start
+            .with_timezone(&Local)
+            .date_naive()
+            .checked_sub_days(Days::new(1));
+        let to_date = end
+            .with_timezone(&Local)
+            .date_naive()
+            .checked_add_days(Days::new(1));
+        let (start_ms, end_ms) = (start.timestamp_millis(), end.timestamp_millis());
+
+        let mut results = Vec::new();
+        for entry in self.list()?.into_iter().rev() {
+            if let Some(date) = entry.date {
+                if from_date.is_some_and(|x| date < x) || to_date.is_some_and(|x| date > x) {
+                    continue;
+                }
+            }
+            let mut reader = open_csv_reader(&entry.path)?;
+            let mut rows = reader.deserialize::<RawCsvRow>().peekable();
+            while let Some(row) = rows.next() {
+                let row = match row {
+                    Ok(row) => row,
+                    // the app could be killed in the middle of writing a row
+                    Err(e) if rows.peek().is_none() => {
+                        warn!(
+                            "[raw_data_store] skipping partial last row of {}: {e}",
+                            entry.name
+                        );
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                let timestamp_ms = row.timestamp_ms.unwrap_or(row.received_timestamp_ms);
+                if timestamp_ms >= start_ms && timestamp_ms <= end_ms {
+                    results.push(row.to_raw_data());
+                }
+            }
+        }
+        Ok(results)
+    }
+}
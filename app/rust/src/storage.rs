@@ -1,16 +1,26 @@
 extern crate simplelog;
+use crate::archive;
+use crate::auto_finalize::AutoFinalizePolicy;
+use crate::backup_store::{BackupPolicy, BackupStore};
 use crate::cache_db::{self, CacheDb, LayerKind};
-use crate::gps_processor::{self, ProcessResult};
+use crate::db_encryption::DbCipher;
+use crate::duplicate_detector::DuplicateJourneyPair;
+use crate::gps_processor::{
+    self, GpsFilter, PreprocessingProfile, PreprocessingThresholds, ProcessResult, SegmentGapRule,
+};
 use crate::journey_bitmap::JourneyBitmap;
 use crate::journey_header::JourneyKind;
+use crate::journey_splitter::JourneySplitOptions;
 use crate::main_db::{self, Action, MainDb};
 use crate::merged_journey_builder;
+use crate::raw_data_store::{self, RawDataRetention, RawDataStore};
 use anyhow::{Context, Ok, Result};
 use auto_context::auto_context;
-use chrono::{Local, NaiveDate};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
-use std::fs::{remove_file, File};
-use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 
 // TODO: error handling in this file is horrifying, we should think about what
@@ -21,9 +31,17 @@ pub struct RawDataFile {
     pub path: String,
 }
 
+pub struct BackupFile {
+    pub name: String,
+    pub path: String,
+    /// In local time.
+    pub created_at: NaiveDateTime,
+    pub size_bytes: u64,
+}
+
 struct CurrentRawDataFile {
     writer: csv::Writer<File>,
-    filename: String,
+    name: String,
     date: chrono::NaiveDate,
 }
 
@@ -36,6 +54,23 @@ pub struct RawCsvRow {
     pub accuracy: Option<f32>,
     pub altitude: Option<f32>,
     pub speed: Option<f32>,
+    // Columns are matched by the header, so columns can only be appended and
+    // new columns must be optional. Files written before these are added
+    // (i.e. only with the columns above) are still valid. Other changes need a
+    // new `raw_data_store::CSV_VERSION`.
+    #[serde(default)]
+    pub bearing: Option<f32>,
+    #[serde(default)]
+    pub vertical_accuracy: Option<f32>,
+    #[serde(default)]
+    pub speed_accuracy: Option<f32>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub satellite_count: Option<u32>,
+    /// `raw_data_store::CSV_VERSION` when written, `None` for version 1.
+    #[serde(default)]
+    pub csv_version: Option<u32>,
 }
 
 impl RawCsvRow {
@@ -51,6 +86,30 @@ impl RawCsvRow {
             accuracy: raw_data.accuracy,
             altitude: raw_data.altitude,
             speed: raw_data.speed,
+            bearing: raw_data.bearing,
+            vertical_accuracy: raw_data.vertical_accuracy,
+            speed_accuracy: raw_data.speed_accuracy,
+            provider: raw_data.provider.clone(),
+            satellite_count: raw_data.satellite_count,
+            csv_version: Some(raw_data_store::CSV_VERSION),
+        }
+    }
+
+    pub fn to_raw_data(&self) -> gps_processor::RawData {
+        gps_processor::RawData {
+            point: gps_processor::Point {
+                latitude: self.latitude,
+                longitude: self.longitude,
+            },
+            timestamp_ms: self.timestamp_ms,
+            accuracy: self.accuracy,
+            altitude: self.altitude,
+            speed: self.speed,
+            bearing: self.bearing,
+            vertical_accuracy: self.vertical_accuracy,
+            speed_accuracy: self.speed_accuracy,
+            provider: self.provider.clone(),
+            satellite_count: self.satellite_count,
         }
     }
 }
@@ -58,22 +117,16 @@ impl RawCsvRow {
 /* This is an optional feature that should be off by default: storing raw GPS
    data with detailed timestamp. It is designed for advanced user or debugging.
    It stores data in a simple csv format and will be using a new file every time
-   the app starts.
-
-   TODO: we should zstd all old data to reduce disk usage.
+   the app starts. Closed files are compressed and cleaned up by
+   `Storage::maintain_raw_data` (see `raw_data_store`).
 */
 struct RawDataRecorder {
-    dir: PathBuf,
     current_raw_data_file: Option<CurrentRawDataFile>,
 }
 
 impl RawDataRecorder {
-    fn init(support_dir: &str) -> RawDataRecorder {
-        // TODO: better error handling
-        let dir = Path::new(support_dir).join("raw_data/");
-        std::fs::create_dir_all(&dir).unwrap();
+    fn init() -> RawDataRecorder {
         RawDataRecorder {
-            dir,
             current_raw_data_file: None,
         }
     }
@@ -85,7 +138,12 @@ impl RawDataRecorder {
     }
 
     // TODO: better error handling
-    fn record(&mut self, raw_data: &gps_processor::RawData, received_timestamp_ms: i64) {
+    fn record(
+        &mut self,
+        raw_data_store: &RawDataStore,
+        raw_data: &gps_processor::RawData,
+        received_timestamp_ms: i64,
+    ) {
         let current_date = Local::now().date_naive();
         if let Some(current_raw_data_file) = &self.current_raw_data_file {
             if current_raw_data_file.date != current_date {
@@ -95,15 +153,7 @@ impl RawDataRecorder {
         }
 
         let current_raw_data_file = self.current_raw_data_file.get_or_insert_with(|| {
-            let mut i = 0;
-            let (path, filename) = loop {
-                let filename = format!("gps-{current_date}-{i}.csv");
-                let path = Path::new(&self.dir).join(&filename);
-                if std::fs::metadata(&path).is_err() {
-                    break (path, filename);
-                }
-                i += 1;
-            };
+            let (path, name) = raw_data_store.new_file(current_date).unwrap();
             let file = File::create(path).unwrap();
             let writer = csv::WriterBuilder::new()
                 .has_headers(true)
@@ -111,7 +161,7 @@ impl RawDataRecorder {
 
             CurrentRawDataFile {
                 writer,
-                filename,
+                name,
                 date: current_date,
             }
         });
@@ -121,10 +171,27 @@ impl RawDataRecorder {
     }
 }
 
+// Backups of an encrypted db are always encrypted, so the passphrase is given
+// even if the archive claims otherwise (see `archive::import_mldx`).
+fn backup_passphrase(cipher: Option<&DbCipher>, path: &str) -> Result<Option<String>> {
+    match cipher {
+        Some(cipher) => Ok(Some(cipher.backup_passphrase().to_string())),
+        None => {
+            if archive::mldx_requires_passphrase(path)? {
+                bail!("The backup is encrypted but the db is not");
+            }
+            Ok(None)
+        }
+    }
+}
+
 type FinalizedJourneyChangedCallback = Box<dyn Fn(&Storage) + Send + Sync + 'static>;
 
 pub struct Storage {
-    support_dir: String,
+    raw_data_store: RawDataStore,
+    backup_store: BackupStore,
+    // held while creating a backup, they share the snapshot file
+    backup_lock: Mutex<()>,
     raw_data_recorder: Mutex<Option<RawDataRecorder>>, // `None` means disabled
     pub cache_dir: String,
     // TODO: I feel the abstraction between `dbs`, `merged_journey_builder`, and
@@ -133,31 +200,85 @@ pub struct Storage {
     // NOTE: both db are deliberately hidden so all operations need to go
     // through `Storage` to make sure they are in sync.
     dbs: Mutex<(MainDb, Box<dyn CacheDb + Send>)>,
+    // `RecorderState::paused`, so location updates can be skipped without a
+    // db transaction. Kept in sync by `with_db_txn`.
+    recording_paused: AtomicBool,
     finalized_journey_changed_callback: FinalizedJourneyChangedCallback,
 }
 
 impl Storage {
+    #[auto_context]
     pub fn init(
+        temp_dir: String,
+        doc_dir: String,
+        support_dir: String,
+        cache_dir: String,
+    ) -> Result<Self> {
+        Self::init_with_db_key(temp_dir, doc_dir, support_dir, cache_dir, None)
+    }
+
+    /// Same as `init`, but location data in the dbs is encrypted with
+    /// `db_key`, see `db_encryption`. Fails if the key is wrong or missing for
+    /// an encrypted db.
+    #[auto_context]
+    pub fn init_with_db_key(
         _temp_dir: String,
         _doc_dir: String,
         support_dir: String,
         cache_dir: String,
-    ) -> Self {
-        let mut main_db = MainDb::open(&support_dir);
-        let cache_db: Box<dyn CacheDb + Send> = Box::new(cache_db::new(&cache_dir));
+        db_key: Option<&[u8]>,
+    ) -> Result<Self> {
+        let mut main_db = MainDb::open_with_key(&support_dir, db_key)?;
+        let cache_db: Box<dyn CacheDb + Send> =
+            Box::new(cache_db::new(&cache_dir, main_db.cipher().cloned()));
         let raw_data_recorder =
             if main_db.get_setting_with_default(crate::main_db::Setting::RawDataMode, false) {
-                Some(RawDataRecorder::init(&support_dir))
+                Some(RawDataRecorder::init())
             } else {
                 None
             };
-        Storage {
-            support_dir,
+        let recording_paused = main_db.with_txn(|txn| txn.get_recorder_state())?.paused;
+        let storage = Storage {
+            raw_data_store: RawDataStore::new(&support_dir),
+            backup_store: BackupStore::new(&support_dir),
+            backup_lock: Mutex::new(()),
             raw_data_recorder: Mutex::new(raw_data_recorder),
             cache_dir,
             dbs: Mutex::new((main_db, cache_db)),
+            recording_paused: AtomicBool::new(recording_paused),
             finalized_journey_changed_callback: Box::new(|_| {}),
+        };
+        if storage.is_db_encrypted() {
+            storage.replace_plaintext_backups()?;
         }
+        Ok(storage)
+    }
+
+    // The db could be encrypted just now, don't keep plaintext copies of it.
+    // They are only deleted once there is an encrypted backup to replace them,
+    // otherwise they are kept and this is tried again on the next init.
+    #[auto_context]
+    fn replace_plaintext_backups(&self) -> Result<()> {
+        let mut plaintext_backups = vec![];
+        for entry in self.backup_store.list()? {
+            let path = entry.path.to_str().context("invalid backup path")?;
+            match archive::mldx_requires_passphrase(path) {
+                std::result::Result::Ok(true) => (),
+                std::result::Result::Ok(false) => plaintext_backups.push(entry.name),
+                Err(e) => warn!("[storage] skipping unreadable backup {}: {e:?}", entry.name),
+            }
+        }
+        if plaintext_backups.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self.create_backup(Local::now().naive_local()) {
+            error!("[storage] failed to create backup to replace plaintext ones: {e:?}");
+            return Ok(());
+        }
+        for name in plaintext_backups {
+            self.backup_store.delete(&name)?;
+        }
+        Ok(())
     }
 
     #[auto_context]
@@ -169,9 +290,11 @@ impl Storage {
         let (ref mut main_db, ref cache_db) = *dbs;
 
         let mut finalized_journey_changed = false;
+        let mut recorder_paused = None;
 
         let output = main_db.with_txn(|txn| {
             let output = f(txn)?;
+            recorder_paused = txn.recorder_paused;
 
             match &txn.action {
                 None => (),
@@ -193,6 +316,10 @@ impl Storage {
 
             Ok(output)
         })?;
+        // still holding the lock so the order of changes is kept
+        if let Some(paused) = recorder_paused {
+            self.recording_paused.store(paused, Ordering::Relaxed);
+        }
 
         // Make using we are not holding the lock when calling the callback
         // TODO: This is still error-prone, and easy to cause deadlock. Consider
@@ -205,11 +332,41 @@ impl Storage {
         Ok(output)
     }
 
+    /// `None` disables the encryption. The cache is rebuilt with the new key.
+    /// An error can also mean the db was rotated but its backups were not, so
+    /// the caller should check `is_db_encrypted` before dropping either key.
+    #[auto_context]
+    pub fn rotate_db_key(&self, new_key: Option<&[u8]>) -> Result<()> {
+        let mut dbs = self.dbs.lock().unwrap();
+        let (ref mut main_db, ref mut cache_db) = *dbs;
+        main_db.rotate_key(new_key)?;
+        *cache_db = Box::new(cache_db::new(&self.cache_dir, main_db.cipher().cloned()));
+        drop(dbs);
+        // Existing backups are plaintext or use the old key, replace them with
+        // one for the new key. They are only deleted once the new one exists.
+        let new_backup = self.create_backup(Local::now().naive_local())?;
+        for entry in self.backup_store.list()? {
+            if entry.name != new_backup.name {
+                self.backup_store.delete(&entry.name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `RecorderState::paused`, without a db transaction.
+    pub fn is_recording_paused(&self) -> bool {
+        self.recording_paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_db_encrypted(&self) -> bool {
+        self.dbs.lock().unwrap().0.is_encrypted()
+    }
+
     pub fn toggle_raw_data_mode(&self, enable: bool) {
         let mut raw_data_recorder = self.raw_data_recorder.lock().unwrap();
         if enable {
             if raw_data_recorder.is_none() {
-                *raw_data_recorder = Some(RawDataRecorder::init(&self.support_dir));
+                *raw_data_recorder = Some(RawDataRecorder::init());
                 info!("[storage] raw data mod enabled");
                 let main_db = &mut self.dbs.lock().unwrap().0;
                 main_db
@@ -232,82 +389,377 @@ impl Storage {
         raw_data_recorder.is_some()
     }
 
+    /// `filename` could be with or without the extension.
     #[auto_context]
     pub fn delete_raw_data_file(&self, filename: String) -> Result<()> {
-        let filename = if Path::new(&filename).extension().is_some() {
-            filename
-        } else {
-            format!("{filename}.csv")
-        };
+        let name = raw_data_store::name_of_path(std::path::Path::new(&filename))
+            .ok_or_else(|| anyhow!("invalid raw data file name: {filename}"))?;
 
         let mut raw_data_recorder = self.raw_data_recorder.lock().unwrap();
 
         if let Some(ref mut x) = *raw_data_recorder {
             if let Some(current_raw_data_file) = &x.current_raw_data_file {
-                if current_raw_data_file.filename == filename {
+                if current_raw_data_file.name == name {
                     x.current_raw_data_file = None;
                 }
             }
         }
 
-        let path = Path::new(&self.support_dir)
-            .join("raw_data")
-            .join(&filename);
+        self.raw_data_store.delete(&name)
+    }
+
+    /// Compresses closed raw data files and applies the retention.
+    #[auto_context]
+    pub fn maintain_raw_data(&self) -> Result<()> {
+        let retention = self.get_raw_data_retention();
+        let today = Local::now().date_naive();
+        // Compression could take a while, the recorder should not be blocked
+        // by it. Files it starts after this are left alone by the raw data
+        // store.
+        let current_name = self
+            .raw_data_recorder
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|x| x.current_raw_data_file.as_ref())
+            .map(|x| x.name.clone());
+        self.raw_data_store
+            .compress_closed_files(current_name.as_deref(), today)?;
+        self.raw_data_store
+            .apply_retention(&retention, today, current_name.as_deref())?;
+        Ok(())
+    }
 
-        remove_file(&path)
-            .with_context(|| format!("failed to remove raw data file: {}", path.display()))?;
+    pub fn get_raw_data_retention(&self) -> RawDataRetention {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        RawDataRetention {
+            max_age_days: main_db.get_setting_with_default(
+                crate::main_db::Setting::RawDataMaxAgeDays,
+                raw_data_store::DEFAULT_MAX_AGE_DAYS,
+            ),
+            max_total_size_mb: main_db.get_setting_with_default(
+                crate::main_db::Setting::RawDataMaxTotalSizeMb,
+                raw_data_store::DEFAULT_MAX_TOTAL_SIZE_MB,
+            ),
+        }
+    }
 
+    #[auto_context]
+    pub fn set_raw_data_retention(&self, retention: &RawDataRetention) -> Result<()> {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.set_setting(
+            crate::main_db::Setting::RawDataMaxAgeDays,
+            retention.max_age_days,
+        )?;
+        main_db.set_setting(
+            crate::main_db::Setting::RawDataMaxTotalSizeMb,
+            retention.max_total_size_mb,
+        )
+    }
+
+    /// Creates a backup if there is none for the local date of `now` yet, then
+    /// rotates old backups. Nothing is done if backups are disabled.
+    #[auto_context]
+    pub fn maintain_backups(&self, now: DateTime<Local>) -> Result<()> {
+        let policy = self.get_backup_policy();
+        if !policy.enabled {
+            return Ok(());
+        }
+        let now = now.naive_local();
+        let has_backup_for_today = self
+            .backup_store
+            .list()?
+            .first()
+            .is_some_and(|x| x.created_at.date() >= now.date());
+        if !has_backup_for_today {
+            let has_data = self.with_db_txn(|txn| {
+                Ok(txn.earliest_journey_date()?.is_some()
+                    || txn.get_ongoing_journey_timestamp_range()?.is_some())
+            })?;
+            if has_data {
+                self.create_backup(now)?;
+            }
+        }
+        self.backup_store.apply_rotation(&policy)?;
         Ok(())
     }
 
+    /// A full archive of all journeys, ignoring privacy zones. It is encrypted
+    /// if the main db is encrypted. The dbs are only locked while taking a
+    /// snapshot of the main db, the archive is written from the snapshot.
+    #[auto_context]
+    pub fn create_backup(&self, now: NaiveDateTime) -> Result<BackupFile> {
+        info!("[storage] creating backup");
+        let _backup_lock = self.backup_lock.lock().unwrap();
+        let snapshot_path = Path::new(&self.cache_dir).join("backup_snapshot.db");
+        if snapshot_path.exists() {
+            fs::remove_file(&snapshot_path)?;
+        }
+        let result = (|| {
+            let mut snapshot = self.dbs.lock().unwrap().0.snapshot(&snapshot_path)?;
+            self.backup_store.create(now, |file| {
+                snapshot.with_txn(|txn| {
+                    let passphrase = txn.cipher().map(|x| x.backup_passphrase());
+                    archive::export_as_mldx(&archive::WhatToExport::All, txn, &[], passphrase, file)
+                })
+            })
+        })();
+        if snapshot_path.exists() {
+            fs::remove_file(&snapshot_path)?;
+        }
+        let entry = result?;
+        Ok(BackupFile {
+            name: entry.name,
+            path: entry.path.to_string_lossy().to_string(),
+            created_at: entry.created_at,
+            size_bytes: entry.size_bytes,
+        })
+    }
+
+    /// Newest first.
+    #[auto_context]
+    pub fn list_backups(&self) -> Result<Vec<BackupFile>> {
+        Ok(self
+            .backup_store
+            .list()?
+            .into_iter()
+            .map(|entry| BackupFile {
+                name: entry.name,
+                path: entry.path.to_string_lossy().to_string(),
+                created_at: entry.created_at,
+                size_bytes: entry.size_bytes,
+            })
+            .collect())
+    }
+
+    /// Imports the backup like `archive::import_mldx`: deleted journeys are
+    /// brought back and existing ones are kept as is.
+    #[auto_context]
+    pub fn restore_backup(&self, name: &str) -> Result<Vec<DuplicateJourneyPair>> {
+        let path = self.backup_store.path_of(name)?;
+        let path = path.to_str().context("invalid backup path")?;
+        info!("[storage] restoring backup {name}");
+        self.with_db_txn(|txn| {
+            let passphrase = backup_passphrase(txn.cipher(), path)?;
+            archive::import_mldx(txn, path, passphrase.as_deref())
+        })
+    }
+
+    /// For when the main db is corrupted (see `main_db::is_corruption_error`).
+    /// The newest backup is imported into a new main db, which replaces the
+    /// old one only if that worked. The old one is moved aside as
+    /// `main.db.corrupted-{local time}`. Backups only have finalized journeys,
+    /// the ongoing journey, places, privacy zones and settings are not
+    /// restored. Returns the name of the restored backup.
+    #[auto_context]
+    pub fn restore_latest_backup_before_init(
+        support_dir: &str,
+        cache_dir: &str,
+        db_key: Option<&[u8]>,
+    ) -> Result<String> {
+        let backup_store = BackupStore::new(support_dir);
+        let Some(entry) = backup_store.list()?.into_iter().next() else {
+            bail!("There is no backup to restore");
+        };
+        let path = entry.path.to_str().context("invalid backup path")?;
+        let cipher = db_key.map(DbCipher::new).transpose()?;
+        let passphrase = backup_passphrase(cipher.as_ref(), path)?;
+        info!("[storage] restoring backup {} before init", entry.name);
+
+        let support_dir = Path::new(support_dir);
+        let restore_dir = support_dir.join("restoring_backup");
+        if restore_dir.exists() {
+            fs::remove_dir_all(&restore_dir)?;
+        }
+        fs::create_dir_all(&restore_dir)?;
+        {
+            let mut main_db = MainDb::open_with_key(
+                restore_dir.to_str().context("invalid restore path")?,
+                db_key,
+            )?;
+            main_db.with_txn(|txn| archive::import_mldx(txn, path, passphrase.as_deref()))?;
+        }
+
+        // the journal belongs to the old db, it must not be applied to the new one
+        let suffix = format!(".corrupted-{}", Local::now().format("%Y-%m-%d-%H%M%S"));
+        for file_name in ["main.db", "main.db-journal"] {
+            let file_path = support_dir.join(file_name);
+            if file_path.exists() {
+                fs::rename(&file_path, support_dir.join(format!("{file_name}{suffix}")))?;
+            }
+        }
+        fs::rename(restore_dir.join("main.db"), support_dir.join("main.db"))?;
+        fs::remove_dir_all(&restore_dir)?;
+        // the cache was built from the old db
+        cache_db::new(cache_dir, cipher).clear_all()?;
+        Ok(entry.name)
+    }
+
+    pub fn get_backup_policy(&self) -> BackupPolicy {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.get_setting_with_default(
+            crate::main_db::Setting::BackupPolicy,
+            BackupPolicy::default(),
+        )
+    }
+
+    #[auto_context]
+    pub fn set_backup_policy(&self, policy: &BackupPolicy) -> Result<()> {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.set_setting(crate::main_db::Setting::BackupPolicy, policy)
+    }
+
+    /// Raw data in `[start, end]` across all raw data files.
+    #[auto_context]
+    pub fn query_raw_data(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<gps_processor::RawData>> {
+        self.raw_data_store.query(start, end)
+    }
+
+    // `raw_data` is what we received and `filtered_data` is what we keep, they
+    // are different when there is a `GpsFilter`.
     pub fn record_gps_data(
         &self,
         raw_data: &gps_processor::RawData,
+        filtered_data: &gps_processor::RawData,
         process_result: ProcessResult,
         received_timestamp_ms: i64,
     ) {
         let mut raw_data_recorder = self.raw_data_recorder.lock().unwrap();
         if let Some(ref mut x) = *raw_data_recorder {
-            x.record(raw_data, received_timestamp_ms);
+            x.record(&self.raw_data_store, raw_data, received_timestamp_ms);
         }
         drop(raw_data_recorder);
 
         let main_db = &mut self.dbs.lock().unwrap().0;
-        main_db.record(raw_data, process_result).unwrap();
+        main_db.record(filtered_data, process_result).unwrap();
     }
 
-    pub fn list_all_raw_data(&self) -> Result<Vec<RawDataFile>> {
-        let dir = Path::new(&self.support_dir).join("raw_data");
-
-        if !dir.exists() {
-            return Ok(Vec::new());
+    /// Same as `record_gps_data` but for a batch of `(raw_data, filtered_data,
+    /// process_result)`, the main db is only locked once.
+    pub fn record_gps_data_batch(
+        &self,
+        data: &[(
+            gps_processor::RawData,
+            gps_processor::RawData,
+            ProcessResult,
+        )],
+        received_timestamp_ms: i64,
+    ) {
+        let mut raw_data_recorder = self.raw_data_recorder.lock().unwrap();
+        if let Some(ref mut x) = *raw_data_recorder {
+            for (raw_data, _, _) in data {
+                x.record(&self.raw_data_store, raw_data, received_timestamp_ms);
+            }
         }
+        drop(raw_data_recorder);
 
-        if !dir.is_dir() {
-            anyhow::bail!("raw_data path exists but is not a directory: {dir:?}");
-        }
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db
+            .record_batch(
+                data.iter()
+                    .map(|(_, filtered_data, process_result)| (filtered_data, *process_result)),
+            )
+            .unwrap();
+    }
 
-        let mut result: Vec<RawDataFile> = std::fs::read_dir(&dir)?
-            .filter_map(|entry_res| {
-                let entry = entry_res.ok()?;
-                let path = entry.path();
-                if path.is_file() && path.extension()?.to_str()? == "csv" {
-                    let name = path
-                        .file_stem()
-                        .map(|s| s.to_string_lossy().to_string())
-                        .unwrap_or_default();
-                    Some(RawDataFile {
-                        name,
-                        path: path.to_string_lossy().to_string(),
-                    })
-                } else {
-                    None
-                }
-            })
-            .collect();
+    pub fn get_gps_filter(&self) -> GpsFilter {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.get_setting_with_default(crate::main_db::Setting::GpsFilter, GpsFilter::None)
+    }
+
+    #[auto_context]
+    pub fn set_gps_filter(&self, gps_filter: GpsFilter) -> Result<()> {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.set_setting(crate::main_db::Setting::GpsFilter, gps_filter)
+    }
+
+    pub fn get_preprocessing_profile(&self) -> PreprocessingProfile {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.get_setting_with_default(
+            crate::main_db::Setting::PreprocessingProfile,
+            PreprocessingProfile::Default,
+        )
+    }
+
+    #[auto_context]
+    pub fn set_preprocessing_profile(&self, profile: PreprocessingProfile) -> Result<()> {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.set_setting(crate::main_db::Setting::PreprocessingProfile, profile)
+    }
+
+    /// Thresholds of built-in profiles are fixed, the custom one falls back to
+    /// the default profile if the user never set it.
+    pub fn get_preprocessing_thresholds(
+        &self,
+        profile: PreprocessingProfile,
+    ) -> PreprocessingThresholds {
+        profile.builtin_thresholds().unwrap_or_else(|| {
+            let main_db = &mut self.dbs.lock().unwrap().0;
+            main_db.get_setting_with_default(
+                crate::main_db::Setting::CustomPreprocessingThresholds,
+                PreprocessingThresholds::from_segment_gap_rule(SegmentGapRule::Default),
+            )
+        })
+    }
 
-        result.sort_by(|a, b| b.name.cmp(&a.name));
-        Ok(result)
+    #[auto_context]
+    pub fn set_custom_preprocessing_thresholds(
+        &self,
+        thresholds: &PreprocessingThresholds,
+    ) -> Result<()> {
+        thresholds.validate()?;
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.set_setting(
+            crate::main_db::Setting::CustomPreprocessingThresholds,
+            thresholds,
+        )
+    }
+
+    pub fn get_auto_finalize_policy(&self) -> AutoFinalizePolicy {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.get_setting_with_default(
+            crate::main_db::Setting::AutoFinalizePolicy,
+            AutoFinalizePolicy::default(),
+        )
+    }
+
+    #[auto_context]
+    pub fn set_auto_finalize_policy(&self, policy: &AutoFinalizePolicy) -> Result<()> {
+        policy.validate()?;
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.set_setting(crate::main_db::Setting::AutoFinalizePolicy, policy)
+    }
+
+    pub fn get_journey_split_options(&self) -> JourneySplitOptions {
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.get_setting_with_default(
+            crate::main_db::Setting::JourneySplitOptions,
+            JourneySplitOptions::default(),
+        )
+    }
+
+    #[auto_context]
+    pub fn set_journey_split_options(&self, options: &JourneySplitOptions) -> Result<()> {
+        options.validate()?;
+        let main_db = &mut self.dbs.lock().unwrap().0;
+        main_db.set_setting(crate::main_db::Setting::JourneySplitOptions, options)
+    }
+
+    #[auto_context]
+    pub fn list_all_raw_data(&self) -> Result<Vec<RawDataFile>> {
+        Ok(self
+            .raw_data_store
+            .list()?
+            .into_iter()
+            .map(|entry| RawDataFile {
+                name: entry.name,
+                path: entry.path.to_string_lossy().to_string(),
+            })
+            .collect())
     }
 
     pub fn set_finalized_journey_changed_callback(
@@ -0,0 +1,283 @@
+/* Guessing how the user travels from timestamped data.
+
+This is a simple rule based classifier over a few features of a segment:
+- Speed distribution: the 95th percentile of the moving speed, weighted by
+  time so sparse data (e.g. nothing is recorded when stationary) does not
+  skew the result.
+- Acceleration: cars accelerate/brake much harder than bikes and trains.
+- Stop patterns: trains stop at stations for a while but rarely in between.
+- Altitude: nothing but planes goes that high.
+
+It is only a suggestion, so when in doubt we prefer the more common mode.
+Splitting a journey by mode works by classifying short windows and merging
+neighbours with the same mode, short runs are absorbed by their neighbours
+(e.g. walking to the car park is not worth its own journey).
+*/
+use std::ops::Range;
+
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::gps_processor::{Point, RawData};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumString, Display, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum TransportMode {
+    Walking,
+    Cycling,
+    Driving,
+    Train,
+    Flight,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedPoint {
+    pub timestamp_sec: i64,
+    pub point: Point,
+    pub altitude: Option<f32>,
+}
+
+impl TimedPoint {
+    pub fn of_raw_data(raw_data: &RawData) -> Option<Self> {
+        Some(TimedPoint {
+            timestamp_sec: raw_data.timestamp_ms? / 1000,
+            point: raw_data.point.clone(),
+            altitude: raw_data.altitude,
+        })
+    }
+}
+
+const MIN_NUM_OF_POINTS: usize = 3;
+const MIN_DURATION_IN_SEC: i64 = 60;
+const STOP_SPEED_IN_M_PER_SEC: f64 = 0.5;
+
+const MAX_WALKING_SPEED_IN_M_PER_SEC: f64 = 2.5;
+const MAX_CYCLING_SPEED_IN_M_PER_SEC: f64 = 8.5;
+const MAX_CYCLING_ACCELERATION_IN_M_PER_SEC2: f64 = 1.5;
+const MIN_TRAIN_SPEED_IN_M_PER_SEC: f64 = 28.0;
+const MAX_TRAIN_ACCELERATION_IN_M_PER_SEC2: f64 = 0.5;
+const MIN_TRAIN_STOP_RATIO: f64 = 0.1;
+// faster than any car on the road
+const MIN_HIGH_SPEED_TRAIN_SPEED_IN_M_PER_SEC: f64 = 42.0;
+const MIN_FLIGHT_SPEED_IN_M_PER_SEC: f64 = 70.0;
+// higher than any road
+const MIN_FLIGHT_ALTITUDE_IN_M: f32 = 6000.0;
+
+const SPLIT_WINDOW_IN_SEC: i64 = 3 * 60;
+const SPLIT_MIN_RUN_IN_SEC: i64 = 10 * 60;
+
+struct Features {
+    p95_speed: f64,
+    p95_acceleration: f64,
+    // the ratio of time spent stopping
+    stop_ratio: f64,
+    max_altitude: Option<f32>,
+}
+
+// `values` are `(value, weight)`.
+fn weighted_percentile(mut values: Vec<(f64, f64)>, percentile: f64) -> f64 {
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total_weight: f64 = values.iter().map(|(_, weight)| weight).sum();
+    let mut accumulated_weight = 0.0;
+    for (value, weight) in &values {
+        accumulated_weight += weight;
+        if accumulated_weight >= total_weight * percentile {
+            return *value;
+        }
+    }
+    values.last().map(|(value, _)| *value).unwrap_or(0.0)
+}
+
+fn features(points: &[TimedPoint]) -> Option<Features> {
+    if points.len() < MIN_NUM_OF_POINTS {
+        return None;
+    }
+    let duration_sec = points.last()?.timestamp_sec - points.first()?.timestamp_sec;
+    if duration_sec < MIN_DURATION_IN_SEC {
+        return None;
+    }
+
+    // `(speed, duration)` between each pair of points
+    let speeds: Vec<(f64, f64)> = points
+        .windows(2)
+        .filter_map(|x| {
+            let duration_sec = (x[1].timestamp_sec - x[0].timestamp_sec) as f64;
+            if duration_sec <= 0.0 {
+                None
+            } else {
+                Some((
+                    x[1].point.haversine_distance(&x[0].point) / duration_sec,
+                    duration_sec,
+                ))
+            }
+        })
+        .collect();
+    let accelerations: Vec<(f64, f64)> = speeds
+        .windows(2)
+        .map(|x| {
+            let duration_sec = (x[0].1 + x[1].1) / 2.0;
+            ((x[1].0 - x[0].0).abs() / duration_sec, duration_sec)
+        })
+        .collect();
+    let stop_duration_sec: f64 = speeds
+        .iter()
+        .filter(|(speed, _)| *speed < STOP_SPEED_IN_M_PER_SEC)
+        .map(|(_, duration_sec)| duration_sec)
+        .sum();
+    let moving_speeds: Vec<(f64, f64)> = speeds
+        .into_iter()
+        .filter(|(speed, _)| *speed >= STOP_SPEED_IN_M_PER_SEC)
+        .collect();
+
+    Some(Features {
+        p95_speed: weighted_percentile(moving_speeds, 0.95),
+        p95_acceleration: weighted_percentile(accelerations, 0.95),
+        stop_ratio: stop_duration_sec / duration_sec as f64,
+        max_altitude: points
+            .iter()
+            .filter_map(|x| x.altitude)
+            .max_by(|a, b| a.total_cmp(b)),
+    })
+}
+
+/// `points` should be a single segment ordered by time. Returns `None` if
+/// there is not enough data.
+pub fn classify(points: &[TimedPoint]) -> Option<TransportMode> {
+    let features = features(points)?;
+    let mode = if features.p95_speed >= MIN_FLIGHT_SPEED_IN_M_PER_SEC
+        || features
+            .max_altitude
+            .is_some_and(|x| x >= MIN_FLIGHT_ALTITUDE_IN_M)
+    {
+        TransportMode::Flight
+    } else if features.p95_speed >= MIN_HIGH_SPEED_TRAIN_SPEED_IN_M_PER_SEC
+        || (features.p95_speed >= MIN_TRAIN_SPEED_IN_M_PER_SEC
+            && features.p95_acceleration <= MAX_TRAIN_ACCELERATION_IN_M_PER_SEC2
+            && features.stop_ratio >= MIN_TRAIN_STOP_RATIO)
+    {
+        TransportMode::Train
+    } else if features.p95_speed <= MAX_WALKING_SPEED_IN_M_PER_SEC {
+        TransportMode::Walking
+    } else if features.p95_speed <= MAX_CYCLING_SPEED_IN_M_PER_SEC
+        && features.p95_acceleration <= MAX_CYCLING_ACCELERATION_IN_M_PER_SEC2
+    {
+        TransportMode::Cycling
+    } else {
+        TransportMode::Driving
+    };
+    debug!(
+        "[transport_mode] p95_speed={:.1}, p95_acceleration={:.2}, stop_ratio={:.2}, max_altitude={:?}, mode={mode}",
+        features.p95_speed,
+        features.p95_acceleration,
+        features.stop_ratio,
+        features.max_altitude
+    );
+    Some(mode)
+}
+
+fn distance_in_m(points: &[TimedPoint]) -> f64 {
+    points
+        .windows(2)
+        .map(|x| x[1].point.haversine_distance(&x[0].point))
+        .sum()
+}
+
+/// The mode that covers the longest distance among all segments.
+pub fn classify_journey(segments: &[Vec<TimedPoint>]) -> Option<TransportMode> {
+    let mut distance_by_mode: Vec<(TransportMode, f64)> = Vec::new();
+    for segment in segments {
+        if let Some(mode) = classify(segment) {
+            let distance = distance_in_m(segment);
+            match distance_by_mode.iter_mut().find(|(x, _)| *x == mode) {
+                Some((_, total_distance)) => *total_distance += distance,
+                None => distance_by_mode.push((mode, distance)),
+            }
+        }
+    }
+    distance_by_mode
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(mode, _)| mode)
+}
+
+/// Splits `points` (ordered by time) into continuous runs of the same mode.
+/// The ranges cover all points. Returns an empty list if nothing could be
+/// classified.
+pub fn split_by_transport_mode(points: &[TimedPoint]) -> Vec<(TransportMode, Range<usize>)> {
+    // classifying each window, the last point of a window is also the first
+    // point of the next one so the movement in between is not lost
+    let mut runs: Vec<(Option<TransportMode>, Range<usize>)> = Vec::new();
+    let mut start = 0;
+    while start < points.len() {
+        let mut end = start + 1;
+        while end < points.len()
+            && points[end - 1].timestamp_sec - points[start].timestamp_sec < SPLIT_WINDOW_IN_SEC
+        {
+            end += 1;
+        }
+        let mode = classify(&points[start.saturating_sub(1)..end]);
+        match runs.last_mut() {
+            Some((last_mode, range)) if *last_mode == mode => range.end = end,
+            _ => runs.push((mode, start..end)),
+        }
+        start = end;
+    }
+
+    // windows without enough data follow the previous one (or the next one if
+    // it is the first)
+    for i in 1..runs.len() {
+        if runs[i].0.is_none() {
+            runs[i].0 = runs[i - 1].0;
+        }
+    }
+    if let Some(first_mode) = runs.iter().find_map(|(mode, _)| *mode) {
+        for (mode, _) in runs.iter_mut() {
+            if mode.is_some() {
+                break;
+            }
+            *mode = Some(first_mode);
+        }
+    }
+    let mut runs: Vec<(TransportMode, Range<usize>)> = runs
+        .into_iter()
+        .filter_map(|(mode, range)| Some((mode?, range)))
+        .collect();
+
+    let duration_sec = |range: &Range<usize>| {
+        points[range.end - 1].timestamp_sec - points[range.start].timestamp_sec
+    };
+    loop {
+        // merging neighbours with the same mode
+        let mut merged: Vec<(TransportMode, Range<usize>)> = Vec::new();
+        for (mode, range) in runs {
+            match merged.last_mut() {
+                Some((last_mode, last_range)) if *last_mode == mode => last_range.end = range.end,
+                _ => merged.push((mode, range)),
+            }
+        }
+        runs = merged;
+        // absorbing the shortest run that is too short into its neighbour
+        let shortest = runs
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, range))| duration_sec(range) < SPLIT_MIN_RUN_IN_SEC)
+            .min_by_key(|(_, (_, range))| duration_sec(range))
+            .map(|(i, _)| i);
+        match shortest {
+            Some(i) if runs.len() > 1 => {
+                // the longer neighbour
+                let neighbour = if i == 0 {
+                    1
+                } else if i == runs.len() - 1
+                    || duration_sec(&runs[i - 1].1) >= duration_sec(&runs[i + 1].1)
+                {
+                    i - 1
+                } else {
+                    i + 1
+                };
+                runs[i].0 = runs[neighbour].0;
+            }
+            _ => break,
+        }
+    }
+    runs
+}
@@ -1,6 +1,6 @@
 use std::f64::consts::PI;
 
-use chrono::{Datelike, NaiveDate};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone};
 
 // https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames
 // TODO: remove this two duplicated function once we have the new rendering system.
@@ -37,11 +37,24 @@ pub fn date_of_days_since_epoch(days: i32) -> NaiveDate {
         .expect("Invalid num of days")
 }
 
+/// The first moment of `date` in `tz`. It is usually midnight, but some
+/// timezones skip midnight when DST starts, then it is the first valid time
+/// after the gap. `None` if there is no such time (out of range).
+pub fn start_of_date<Tz: TimeZone>(date: NaiveDate, tz: &Tz) -> Option<DateTime<Tz>> {
+    let midnight = date.and_hms_opt(0, 0, 0)?;
+    // DST gaps are whole minutes and never longer than a day.
+    (0..24 * 60).find_map(|minutes| {
+        (midnight + Duration::minutes(minutes))
+            .and_local_timezone(tz.clone())
+            .earliest()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::{FixedOffset, NaiveDate, TimeZone, Utc};
 
-    use crate::utils::{date_of_days_since_epoch, date_to_days_since_epoch};
+    use crate::utils::{date_of_days_since_epoch, date_to_days_since_epoch, start_of_date};
 
     #[test]
     fn days_since_epoch() {
@@ -65,6 +78,20 @@ mod tests {
         assert_eq!(utc.date_naive().to_string(), "2024-03-31");
         assert_eq!(plus8.date_naive().to_string(), "2024-04-01");
     }
+
+    #[test]
+    fn start_of_date_in_timezone() {
+        let date = NaiveDate::from_ymd_opt(2024, 4, 1).unwrap();
+        let plus8 = FixedOffset::east_opt(8 * 3600).unwrap();
+        assert_eq!(
+            start_of_date(date, &plus8).unwrap().to_rfc3339(),
+            "2024-04-01T00:00:00+08:00"
+        );
+        assert_eq!(
+            start_of_date(date, &Utc).unwrap().to_rfc3339(),
+            "2024-04-01T00:00:00+00:00"
+        );
+    }
 }
 
 pub mod db {
@@ -104,4 +131,29 @@ pub mod db {
         )?;
         Ok(())
     }
+
+    #[auto_context]
+    pub fn get_metadata(tx: &Transaction, key: &str) -> Result<Option<String>> {
+        let value = tx
+            .query_row(
+                "SELECT `value` FROM `db_metadata` WHERE key = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    /// `None` removes the entry.
+    #[auto_context]
+    pub fn set_metadata(tx: &Transaction, key: &str, value: Option<&str>) -> Result<()> {
+        match value {
+            None => tx.execute("DELETE FROM `db_metadata` WHERE key = ?1", [key])?,
+            Some(value) => tx.execute(
+                "INSERT OR REPLACE INTO `db_metadata` (key, value) VALUES (?1, ?2)",
+                (key, value),
+            )?,
+        };
+        Ok(())
+    }
 }
@@ -0,0 +1,35 @@
+use memolanes_core::{airports, flight_track_processor, gps_processor::Point};
+
+#[test]
+fn find_airport() {
+    let pvg = airports::find_airport("PVG").unwrap();
+    assert_eq!(pvg.icao, "ZSPD");
+    assert_eq!(pvg.country, "CN");
+    assert_eq!(airports::find_airport(" zspd "), Some(pvg));
+    assert_eq!(airports::find_airport("hnd").unwrap().icao, "RJTT");
+
+    assert_eq!(airports::find_airport("XXX"), None);
+    assert_eq!(airports::find_airport("PV"), None);
+    assert_eq!(airports::find_airport(""), None);
+}
+
+#[test]
+fn flight_across_antimeridian() {
+    let point = |code| {
+        let airport = airports::find_airport(code).unwrap();
+        Point {
+            latitude: airport.latitude,
+            longitude: airport.longitude,
+        }
+    };
+    let journey_vector =
+        flight_track_processor::process_great_circle(&[point("NRT"), point("HNL")]).unwrap();
+    // split at the antimeridian instead of going around the world
+    assert_eq!(journey_vector.track_segments.len(), 2);
+    let first = &journey_vector.track_segments[0].track_points;
+    let second = &journey_vector.track_segments[1].track_points;
+    assert!(first.iter().all(|x| x.longitude > 0.0));
+    assert!(second.iter().all(|x| x.longitude < 0.0));
+    assert_eq!(first.last().unwrap().longitude, 180.0);
+    assert_eq!(second.first().unwrap().longitude, -180.0);
+}
@@ -6,9 +6,27 @@ use memolanes_core::{
     archive, gps_processor, import_data, journey_data::JourneyData, journey_header::JourneyHeader,
     main_db::MainDb,
 };
-use std::{fs::File, io::Write};
+use std::{fs::File, io::Write, path::Path};
 use tempdir::TempDir;
 
+// As if the archive was tampered with: the metadata of `metadata_from` and
+// the sections of `sections_from`.
+fn mix_archives(metadata_from: &Path, sections_from: &Path, target: &Path) {
+    let mut writer = zip::ZipWriter::new(File::create(target).unwrap());
+    let mut metadata_zip = zip::ZipArchive::new(File::open(metadata_from).unwrap()).unwrap();
+    writer
+        .raw_copy_file(metadata_zip.by_name("metadata.xxm").unwrap())
+        .unwrap();
+    let mut sections_zip = zip::ZipArchive::new(File::open(sections_from).unwrap()).unwrap();
+    for i in 0..sections_zip.len() {
+        let file = sections_zip.by_index_raw(i).unwrap();
+        if file.name() != "metadata.xxm" {
+            writer.raw_copy_file(file).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+}
+
 fn add_vector_journeys(main_db: &mut MainDb) {
     let (raw_data, _preprocessor) =
         import_data::load_gpx("./tests/data/raw_gps_shanghai.gpx").unwrap();
@@ -72,13 +90,15 @@ fn archive_and_import() {
     let mldx_file_path = temp_dir.path().join("archive.mldx");
     let mut file = File::create(&mldx_file_path).unwrap();
     main_db
-        .with_txn(|txn| archive::export_as_mldx(&archive::WhatToExport::All, txn, &mut file))
+        .with_txn(|txn| {
+            archive::export_as_mldx(&archive::WhatToExport::All, txn, &[], None, &mut file)
+        })
         .unwrap();
     drop(file);
     main_db.with_txn(|txn| txn.delete_all_journeys()).unwrap();
 
     main_db
-        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path.to_str().unwrap()))
+        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path.to_str().unwrap(), None))
         .unwrap();
     assert_eq!(all_journeys_before, all_journeys(&mut main_db));
 }
@@ -113,7 +133,7 @@ fn import_broken_archive_and_roll_back() {
 
     // recover
     assert!(main_db
-        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path.to_str().unwrap()))
+        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path.to_str().unwrap(), None))
         .is_err());
     assert_eq!(all_journeys_before, all_journeys(&mut main_db));
 }
@@ -130,7 +150,9 @@ fn import_skips_existing_journeys() {
     let mldx_file_path = temp_dir.path().join("archive.mldx");
     let mut file = File::create(&mldx_file_path).unwrap();
     main_db
-        .with_txn(|txn| archive::export_as_mldx(&archive::WhatToExport::All, txn, &mut file))
+        .with_txn(|txn| {
+            archive::export_as_mldx(&archive::WhatToExport::All, txn, &[], None, &mut file)
+        })
         .unwrap();
     drop(file);
 
@@ -145,7 +167,95 @@ fn import_skips_existing_journeys() {
 
     // import the archive again, it should skip all exisiting journeys but import the deleted one
     main_db
-        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path.to_str().unwrap()))
+        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path.to_str().unwrap(), None))
+        .unwrap();
+    assert_eq!(all_journeys_before, all_journeys(&mut main_db));
+}
+
+#[test]
+fn encrypted_archive() {
+    let temp_dir = TempDir::new("archive-encrypted_archive").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    add_vector_journeys(&mut main_db);
+    add_bitmap_journey(&mut main_db);
+
+    let all_journeys_before = all_journeys(&mut main_db);
+    let mldx_file_path = temp_dir.path().join("archive.mldx");
+    let mldx_file_path_str = mldx_file_path.to_str().unwrap();
+    let mut file = File::create(&mldx_file_path).unwrap();
+    main_db
+        .with_txn(|txn| {
+            archive::export_as_mldx(
+                &archive::WhatToExport::All,
+                txn,
+                &[],
+                Some("correct horse"),
+                &mut file,
+            )
+        })
+        .unwrap();
+    drop(file);
+    assert!(archive::mldx_requires_passphrase(mldx_file_path_str).unwrap());
+    main_db.with_txn(|txn| txn.delete_all_journeys()).unwrap();
+
+    // missing or wrong passphrase
+    assert!(main_db
+        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path_str, None))
+        .is_err());
+    assert!(main_db
+        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path_str, Some("battery staple")))
+        .is_err());
+    assert!(all_journeys(&mut main_db).is_empty());
+
+    main_db
+        .with_txn(|txn| archive::import_mldx(txn, mldx_file_path_str, Some("correct horse")))
         .unwrap();
     assert_eq!(all_journeys_before, all_journeys(&mut main_db));
 }
+
+#[test]
+fn tampered_encrypted_archive() {
+    let temp_dir = TempDir::new("archive-tampered_encrypted_archive").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+    add_bitmap_journey(&mut main_db);
+    let export = |main_db: &mut MainDb, name: &str, passphrase| {
+        let path = temp_dir.path().join(name);
+        let mut file = File::create(&path).unwrap();
+        main_db
+            .with_txn(|txn| {
+                archive::export_as_mldx(
+                    &archive::WhatToExport::All,
+                    txn,
+                    &[],
+                    passphrase,
+                    &mut file,
+                )
+            })
+            .unwrap();
+        path
+    };
+    let plaintext = export(&mut main_db, "plaintext.mldx", None);
+    let encrypted = export(&mut main_db, "encrypted.mldx", Some("correct horse"));
+    main_db.with_txn(|txn| txn.delete_all_journeys()).unwrap();
+    let import = |main_db: &mut MainDb, path: &Path, passphrase| {
+        main_db.with_txn(|txn| archive::import_mldx(txn, path.to_str().unwrap(), passphrase))
+    };
+
+    // sections replaced by plaintext ones
+    let injected = temp_dir.path().join("injected.mldx");
+    mix_archives(&encrypted, &plaintext, &injected);
+    assert!(import(&mut main_db, &injected, Some("correct horse")).is_err());
+
+    // the encryption removed from the metadata
+    let stripped = temp_dir.path().join("stripped.mldx");
+    mix_archives(&plaintext, &encrypted, &stripped);
+    assert!(archive::mldx_requires_passphrase(stripped.to_str().unwrap()).unwrap());
+    assert!(import(&mut main_db, &stripped, None).is_err());
+    // a plaintext archive when an encrypted one is expected
+    assert!(import(&mut main_db, &plaintext, Some("correct horse")).is_err());
+    assert!(all_journeys(&mut main_db).is_empty());
+
+    import(&mut main_db, &plaintext, None).unwrap();
+    assert_eq!(all_journeys(&mut main_db).len(), 1);
+}
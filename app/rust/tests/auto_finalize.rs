@@ -0,0 +1,184 @@
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use memolanes_core::auto_finalize::{self, AutoFinalizePolicy, AutoFinalizeReason};
+
+// UTC+8
+fn local(s: &str) -> DateTime<FixedOffset> {
+    FixedOffset::east_opt(8 * 3600)
+        .unwrap()
+        .from_local_datetime(&chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+        .unwrap()
+}
+
+fn utc(s: &str) -> DateTime<Utc> {
+    local(s).with_timezone(&Utc)
+}
+
+fn explain(
+    policy: &AutoFinalizePolicy,
+    now: &str,
+    start: &str,
+    end: &str,
+    at_known_place: bool,
+) -> (AutoFinalizeReason, Option<i64>, bool) {
+    let explanation =
+        auto_finalize::explain(policy, &local(now), utc(start), utc(end), at_known_place);
+    (
+        explanation.reason,
+        explanation.required_gap_mins,
+        explanation.finalize,
+    )
+}
+
+#[test]
+fn default_policy() {
+    let policy = AutoFinalizePolicy::default();
+    policy.validate().unwrap();
+
+    // same day, waiting for a long gap
+    assert_eq!(
+        explain(
+            &policy,
+            "2024-03-01 18:00",
+            "2024-03-01 08:00",
+            "2024-03-01 17:00",
+            false
+        ),
+        (AutoFinalizeReason::SameDay, Some(360), false)
+    );
+    // the day changed, but it is still early
+    assert_eq!(
+        explain(
+            &policy,
+            "2024-03-02 01:10",
+            "2024-03-01 20:00",
+            "2024-03-02 01:00",
+            false
+        ),
+        (AutoFinalizeReason::DayChangedPatient, Some(20), false)
+    );
+    assert_eq!(
+        explain(
+            &policy,
+            "2024-03-02 10:00",
+            "2024-03-01 20:00",
+            "2024-03-02 09:50",
+            false
+        ),
+        (AutoFinalizeReason::DayChanged, Some(5), true)
+    );
+    // recording for too long
+    assert_eq!(
+        explain(
+            &policy,
+            "2024-03-03 10:00",
+            "2024-03-01 08:00",
+            "2024-03-03 10:00",
+            false
+        ),
+        (AutoFinalizeReason::RecordingLength, Some(0), true)
+    );
+    // disabled by default
+    assert_eq!(
+        explain(
+            &policy,
+            "2024-03-01 18:00",
+            "2024-03-01 08:00",
+            "2024-03-01 17:00",
+            true
+        ),
+        (AutoFinalizeReason::SameDay, Some(360), false)
+    );
+}
+
+#[test]
+fn custom_policy() {
+    // a night shift from 22:00 to 06:00
+    let night_shift = AutoFinalizePolicy {
+        day_start_hour: 12,
+        ..AutoFinalizePolicy::default()
+    };
+    night_shift.validate().unwrap();
+    assert_eq!(
+        explain(
+            &night_shift,
+            "2024-03-02 03:00",
+            "2024-03-01 21:30",
+            "2024-03-02 02:50",
+            false
+        ),
+        (AutoFinalizeReason::SameDay, Some(360), false)
+    );
+    assert_eq!(
+        explain(
+            &night_shift,
+            "2024-03-02 13:00",
+            "2024-03-01 21:30",
+            "2024-03-02 06:30",
+            false
+        ),
+        (AutoFinalizeReason::DayChangedPatient, Some(20), true)
+    );
+
+    let no_split = AutoFinalizePolicy {
+        split_at_local_midnight: false,
+        ..AutoFinalizePolicy::default()
+    };
+    assert_eq!(
+        explain(
+            &no_split,
+            "2024-03-02 10:00",
+            "2024-03-01 20:00",
+            "2024-03-02 09:50",
+            false
+        ),
+        (AutoFinalizeReason::SameDay, Some(360), false)
+    );
+
+    let known_place = AutoFinalizePolicy {
+        stationary_at_known_place_mins: Some(30),
+        ..AutoFinalizePolicy::default()
+    };
+    assert_eq!(
+        explain(
+            &known_place,
+            "2024-03-01 18:00",
+            "2024-03-01 08:00",
+            "2024-03-01 17:00",
+            true
+        ),
+        (AutoFinalizeReason::StationaryAtKnownPlace, Some(30), true)
+    );
+    // a shorter gap is required anyway
+    assert_eq!(
+        explain(
+            &known_place,
+            "2024-03-02 10:00",
+            "2024-03-01 20:00",
+            "2024-03-02 09:50",
+            true
+        ),
+        (AutoFinalizeReason::DayChanged, Some(5), true)
+    );
+}
+
+#[test]
+fn validate_and_persist() {
+    assert!(AutoFinalizePolicy {
+        day_start_hour: 24,
+        ..AutoFinalizePolicy::default()
+    }
+    .validate()
+    .is_err());
+    let mut policy = AutoFinalizePolicy::default();
+    policy.recording_length_rules.reverse();
+    assert!(policy.validate().is_err());
+
+    let policy = AutoFinalizePolicy {
+        stationary_at_known_place_mins: Some(30),
+        ..AutoFinalizePolicy::default()
+    };
+    assert_eq!(
+        policy.to_string().parse::<AutoFinalizePolicy>().unwrap(),
+        policy
+    );
+}
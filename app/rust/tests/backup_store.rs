@@ -0,0 +1,298 @@
+use chrono::{Duration, Local, NaiveDateTime, TimeZone};
+use memolanes_core::{
+    archive,
+    backup_store::{BackupPolicy, BackupStore},
+    gps_processor::ProcessResult,
+    import_data, main_db,
+    storage::Storage,
+};
+use std::fs;
+use std::io::Write;
+use tempdir::TempDir;
+
+fn time(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+}
+
+fn names(store: &BackupStore) -> Vec<String> {
+    store.list().unwrap().into_iter().map(|x| x.name).collect()
+}
+
+fn init_storage(temp_dir: &TempDir, db_key: Option<&[u8]>) -> Storage {
+    let sub_folder = |sub| {
+        let path = temp_dir.path().join(sub);
+        fs::create_dir_all(&path).unwrap();
+        path.into_os_string().into_string().unwrap()
+    };
+    Storage::init_with_db_key(
+        sub_folder("temp/"),
+        sub_folder("doc/"),
+        sub_folder("support/"),
+        sub_folder("cache/"),
+        db_key,
+    )
+    .unwrap()
+}
+
+fn record_and_finalize(storage: &Storage) {
+    let (raw_data, _preprocessor) =
+        import_data::load_gpx("./tests/data/raw_gps_shanghai.gpx").unwrap();
+    for raw_data in raw_data.iter().flatten().take(2000) {
+        storage.record_gps_data(
+            raw_data,
+            raw_data,
+            ProcessResult::Append,
+            raw_data.timestamp_ms.unwrap(),
+        );
+    }
+    storage
+        .with_db_txn(|txn| txn.finalize_ongoing_journey())
+        .unwrap();
+}
+
+#[test]
+fn create_and_rotate() {
+    let temp_dir = TempDir::new("backup_store-create_and_rotate").unwrap();
+    let store = BackupStore::new(temp_dir.path().to_str().unwrap());
+    assert!(store.list().unwrap().is_empty());
+    assert!(store
+        .apply_rotation(&BackupPolicy::default())
+        .unwrap()
+        .is_empty());
+
+    // one backup per day from 2025-01-01 to 2025-03-31, plus an extra one on
+    // the last day
+    let mut t = time("2025-01-01 03:00:00");
+    while t <= time("2025-03-31 03:00:00") {
+        store
+            .create(t, |file| Ok(file.write_all(b"hello")?))
+            .unwrap();
+        t += Duration::days(1);
+    }
+    let newest = store
+        .create(time("2025-03-31 15:30:00"), |file| {
+            Ok(file.write_all(b"hello")?)
+        })
+        .unwrap();
+    assert_eq!(newest.name, "backup-2025-03-31-153000");
+    assert_eq!(newest.size_bytes, 5);
+    assert!(store
+        .create(time("2025-03-31 15:30:00"), |_| Ok(()))
+        .is_err());
+
+    // a failed backup leaves nothing behind
+    assert!(store
+        .create(time("2025-03-31 16:00:00"), |_| Err(anyhow::anyhow!(
+            "oops"
+        )))
+        .is_err());
+    assert_eq!(store.list().unwrap().len(), 91);
+
+    store
+        .apply_rotation(&BackupPolicy {
+            enabled: true,
+            keep_daily: 3,
+            keep_weekly: 2,
+            keep_monthly: 3,
+        })
+        .unwrap();
+    assert_eq!(
+        names(&store),
+        vec![
+            // daily, 2025-03-31 (a Monday) and 2025-03-30 are also the
+            // newest of the last 2 weeks, 2025-03-31 is also the newest of
+            // the month
+            "backup-2025-03-31-153000",
+            "backup-2025-03-30-030000",
+            "backup-2025-03-29-030000",
+            // monthly
+            "backup-2025-02-28-030000",
+            "backup-2025-01-31-030000",
+        ]
+    );
+    assert!(store.path_of("backup-2025-02-28-030000").is_ok());
+    assert!(store.path_of("backup-2025-03-23-030000").is_err());
+
+    // the newest one is always kept
+    store
+        .apply_rotation(&BackupPolicy {
+            enabled: true,
+            keep_daily: 0,
+            keep_weekly: 0,
+            keep_monthly: 0,
+        })
+        .unwrap();
+    assert_eq!(names(&store), vec!["backup-2025-03-31-153000"]);
+}
+
+#[test]
+fn maintain_and_restore() {
+    for db_key in [None, Some([7; 32])] {
+        let temp_dir = TempDir::new("backup_store-maintain_and_restore").unwrap();
+        let storage = init_storage(&temp_dir, db_key.as_ref().map(|x| x.as_slice()));
+        let now = Local.with_ymd_and_hms(2025, 3, 31, 12, 0, 0).unwrap();
+
+        // nothing to back up yet
+        storage.maintain_backups(now).unwrap();
+        assert!(storage.list_backups().unwrap().is_empty());
+
+        record_and_finalize(&storage);
+        let journeys = storage
+            .with_db_txn(|txn| txn.query_journeys(None, None))
+            .unwrap();
+        assert_eq!(journeys.len(), 1);
+
+        storage.maintain_backups(now).unwrap();
+        // only one backup per day
+        storage.maintain_backups(now + Duration::hours(1)).unwrap();
+        let backups = storage.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].name, "backup-2025-03-31-120000");
+
+        // disabled
+        storage
+            .set_backup_policy(&BackupPolicy {
+                enabled: false,
+                ..BackupPolicy::default()
+            })
+            .unwrap();
+        storage.maintain_backups(now + Duration::days(1)).unwrap();
+        assert_eq!(storage.list_backups().unwrap().len(), 1);
+
+        storage
+            .with_db_txn(|txn| txn.delete_all_journeys())
+            .unwrap();
+        storage.restore_backup(&backups[0].name).unwrap();
+        assert_eq!(
+            storage
+                .with_db_txn(|txn| txn.query_journeys(None, None))
+                .unwrap(),
+            journeys
+        );
+        assert!(storage.restore_backup("backup-2025-01-01-000000").is_err());
+    }
+}
+
+#[test]
+fn backups_follow_db_key() {
+    let temp_dir = TempDir::new("backup_store-backups_follow_db_key").unwrap();
+    let storage = init_storage(&temp_dir, None);
+    record_and_finalize(&storage);
+    let plaintext = storage.create_backup(time("2025-03-30 12:00:00")).unwrap();
+    // the snapshot used for the export is cleaned up
+    assert!(!temp_dir.path().join("cache/backup_snapshot.db").exists());
+    drop(storage);
+
+    // an unreadable backup doesn't fail the init and is left alone
+    let backups_dir = temp_dir.path().join("support/backups");
+    let unreadable = backups_dir.join("backup-2025-03-29-120000.mldx");
+    fs::write(&unreadable, b"definitely not a zip").unwrap();
+
+    // plaintext backups are replaced by an encrypted one once the db is encrypted
+    let key = [7; 32];
+    let storage = init_storage(&temp_dir, Some(&key));
+    let backups = storage.list_backups().unwrap();
+    assert_eq!(backups.len(), 2);
+    assert!(!std::path::Path::new(&plaintext.path).exists());
+    assert!(unreadable.exists());
+    assert!(archive::mldx_requires_passphrase(&backups[0].path).unwrap());
+    storage.restore_backup(&backups[0].name).unwrap();
+    fs::remove_file(&unreadable).unwrap();
+
+    storage.create_backup(time("2025-03-31 12:00:00")).unwrap();
+
+    // the old backups are kept if the new one can't be created
+    let snapshot_path = temp_dir.path().join("cache/backup_snapshot.db");
+    fs::create_dir_all(&snapshot_path).unwrap();
+    assert!(storage.rotate_db_key(Some(&[9; 32])).is_err());
+    assert!(storage.is_db_encrypted());
+    let backups = storage.list_backups().unwrap();
+    assert_eq!(backups.len(), 2);
+    assert_eq!(backups[1].name, "backup-2025-03-31-120000");
+    fs::remove_dir(&snapshot_path).unwrap();
+
+    let other_key = [8; 32];
+    storage.rotate_db_key(Some(&other_key)).unwrap();
+    let backups = storage.list_backups().unwrap();
+    assert_eq!(backups.len(), 1);
+    assert_ne!(backups[0].name, "backup-2025-03-31-120000");
+    storage.restore_backup(&backups[0].name).unwrap();
+}
+
+#[test]
+fn restore_corrupted_main_db() {
+    for db_key in [None, Some([7; 32])] {
+        let db_key = db_key.as_ref().map(|x| x.as_slice());
+        let temp_dir = TempDir::new("backup_store-restore_corrupted_main_db").unwrap();
+        let support_dir = temp_dir.path().join("support");
+        let cache_dir = temp_dir.path().join("cache");
+        let storage = init_storage(&temp_dir, db_key);
+        record_and_finalize(&storage);
+        let journeys = storage
+            .with_db_txn(|txn| txn.query_journeys(None, None))
+            .unwrap();
+        let backup = storage.create_backup(time("2025-03-31 12:00:00")).unwrap();
+        drop(storage);
+        let init = |db_key| {
+            Storage::init_with_db_key(
+                temp_dir.path().join("temp").to_str().unwrap().to_string(),
+                temp_dir.path().join("doc").to_str().unwrap().to_string(),
+                support_dir.to_str().unwrap().to_string(),
+                cache_dir.to_str().unwrap().to_string(),
+                db_key,
+            )
+        };
+
+        // a wrong key is not a corrupted db
+        if db_key.is_some() {
+            let Err(e) = init(Some(&[8; 32])) else {
+                panic!("init with a wrong key should fail");
+            };
+            assert!(!main_db::is_corruption_error(&e));
+        }
+
+        fs::write(support_dir.join("main.db"), b"definitely not a sqlite db").unwrap();
+        let Err(e) = init(db_key) else {
+            panic!("init with a corrupted db should fail");
+        };
+        assert!(main_db::is_corruption_error(&e));
+
+        // a backup for another key is not restored and nothing is changed
+        if db_key.is_some() {
+            assert!(Storage::restore_latest_backup_before_init(
+                support_dir.to_str().unwrap(),
+                cache_dir.to_str().unwrap(),
+                Some(&[8; 32]),
+            )
+            .is_err());
+            assert_eq!(
+                fs::read(support_dir.join("main.db")).unwrap(),
+                b"definitely not a sqlite db"
+            );
+        }
+
+        assert_eq!(
+            Storage::restore_latest_backup_before_init(
+                support_dir.to_str().unwrap(),
+                cache_dir.to_str().unwrap(),
+                db_key,
+            )
+            .unwrap(),
+            backup.name
+        );
+        let corrupted: Vec<_> = fs::read_dir(&support_dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .filter(|x| x.starts_with("main.db.corrupted-"))
+            .collect();
+        assert_eq!(corrupted.len(), 1);
+
+        let storage = init_storage(&temp_dir, db_key);
+        assert_eq!(
+            storage
+                .with_db_txn(|txn| txn.query_journeys(None, None))
+                .unwrap(),
+            journeys
+        );
+    }
+}
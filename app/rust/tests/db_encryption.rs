@@ -0,0 +1,250 @@
+pub mod test_utils;
+
+use chrono::DateTime;
+use memolanes_core::{
+    cache_db::{CacheDb, CacheDbV1, LayerKind},
+    db_encryption::DbCipher,
+    duplicate_detector,
+    gps_processor::{self, Point, RawData},
+    import_data,
+    journey_data::JourneyData,
+    journey_header::{JourneyHeader, JourneyKind},
+    journey_vector::JourneyVector,
+    main_db::MainDb,
+    places::{Place, StayPoint},
+    privacy_zone::{PrivacyZone, PrivacyZoneShape},
+};
+use tempdir::TempDir;
+
+const KEY1: [u8; 32] = [1; 32];
+const KEY2: [u8; 32] = [2; 32];
+
+type Snapshot = (
+    Vec<(JourneyHeader, JourneyData)>,
+    Option<JourneyVector>,
+    Vec<Place>,
+    Vec<PrivacyZone>,
+);
+
+fn snapshot(main_db: &mut MainDb) -> Snapshot {
+    main_db
+        .with_txn(|txn| {
+            let mut journeys = Vec::new();
+            for header in txn.query_journeys(None, None)? {
+                let data = txn.get_journey_data(&header.id)?;
+                journeys.push((header, data));
+            }
+            Ok((
+                journeys,
+                txn.get_ongoing_journey(None)?,
+                txn.list_places()?,
+                txn.list_privacy_zones()?,
+            ))
+        })
+        .unwrap()
+}
+
+// The stored fingerprint of the first journey and the expected one for `key`.
+fn fingerprints(main_db: &mut MainDb, key: Option<&[u8]>) -> (Option<String>, Option<String>) {
+    main_db
+        .with_txn(|txn| {
+            let id = txn.query_journeys(None, None)?[0].id.clone();
+            let cipher = key.map(DbCipher::new).transpose()?;
+            Ok((
+                txn.get_journey_fingerprint(&id)?,
+                duplicate_detector::fingerprint(&txn.get_journey_data(&id)?, cipher.as_ref()),
+            ))
+        })
+        .unwrap()
+}
+
+fn open(dir: &TempDir, key: Option<&[u8]>) -> anyhow::Result<MainDb> {
+    MainDb::open_with_key(dir.path().to_str().unwrap(), key)
+}
+
+#[test]
+fn cipher() {
+    assert!(DbCipher::new(&[0; 16]).is_err());
+    let cipher = DbCipher::new(&KEY1).unwrap();
+    let encrypted = cipher.encrypt(b"hello", b"aad").unwrap();
+    assert_eq!(cipher.decrypt(&encrypted, b"aad").unwrap(), b"hello");
+    // random nonce
+    assert_ne!(encrypted, cipher.encrypt(b"hello", b"aad").unwrap());
+    assert!(cipher.decrypt(&encrypted, b"other").is_err());
+    assert!(DbCipher::new(&KEY2)
+        .unwrap()
+        .decrypt(&encrypted, b"aad")
+        .is_err());
+}
+
+#[test]
+fn migrate_and_rotate() {
+    let (raw_data, _preprocessor) =
+        import_data::load_gpx("./tests/data/raw_gps_shanghai.gpx").unwrap();
+    let raw_data: Vec<RawData> = raw_data.into_iter().flatten().collect();
+    let (finalized, ongoing) = raw_data.split_at(raw_data.len() / 2);
+
+    let temp_dir = TempDir::new("db_encryption-migrate_and_rotate").unwrap();
+    let mut main_db = open(&temp_dir, None).unwrap();
+    for raw_data in finalized {
+        main_db
+            .record(raw_data, gps_processor::ProcessResult::Append)
+            .unwrap();
+    }
+    main_db
+        .with_txn(|txn| txn.finalize_ongoing_journey())
+        .unwrap();
+    for raw_data in ongoing {
+        main_db
+            .record(raw_data, gps_processor::ProcessResult::Append)
+            .unwrap();
+    }
+    let place = Point {
+        latitude: 31.2123,
+        longitude: 121.4567,
+    };
+    main_db
+        .with_txn(|txn| {
+            txn.add_place_visits(
+                None,
+                &[StayPoint {
+                    point: place.clone(),
+                    arrival: DateTime::from_timestamp(1697349115, 0).unwrap(),
+                    departure: DateTime::from_timestamp(1697352715, 0).unwrap(),
+                }],
+            )?;
+            txn.add_privacy_zone(
+                "Secret Garden",
+                &PrivacyZoneShape::Circle {
+                    center: place.clone(),
+                    radius_m: 500.0,
+                },
+            )
+        })
+        .unwrap();
+    assert!(!main_db.is_encrypted());
+    let expected = snapshot(&mut main_db);
+    let (plain_fingerprint, expected_fingerprint) = fingerprints(&mut main_db, None);
+    assert!(plain_fingerprint.is_some());
+    assert_eq!(plain_fingerprint, expected_fingerprint);
+    assert_eq!(expected.2.len(), 1);
+    assert_eq!(expected.3.len(), 1);
+    drop(main_db);
+    let contains = |bytes: &[u8]| {
+        let db_file = std::fs::read(temp_dir.path().join("main.db")).unwrap();
+        db_file.windows(bytes.len()).any(|x| x == bytes)
+    };
+    // SQLite stores REAL as big endian
+    let contains_last_point = || contains(&ongoing.last().unwrap().point.latitude.to_be_bytes());
+    let contains_place = || contains(&place.latitude.to_be_bytes());
+    let contains_privacy_zone = || contains(b"Secret Garden");
+    assert!(contains_last_point());
+    assert!(contains_place());
+    assert!(contains_privacy_zone());
+
+    // existing data is encrypted
+    let mut main_db = open(&temp_dir, Some(&KEY1)).unwrap();
+    assert!(main_db.is_encrypted());
+    assert_eq!(snapshot(&mut main_db), expected);
+    // the fingerprint is keyed
+    let (fingerprint, expected_fingerprint) = fingerprints(&mut main_db, Some(&KEY1));
+    assert_eq!(fingerprint, expected_fingerprint);
+    assert_ne!(fingerprint, plain_fingerprint);
+    drop(main_db);
+    assert!(!contains_last_point());
+    assert!(!contains_place());
+    assert!(!contains_privacy_zone());
+
+    assert!(open(&temp_dir, None).is_err());
+    assert!(open(&temp_dir, Some(&KEY2)).is_err());
+
+    // new data is encrypted as well
+    let mut main_db = open(&temp_dir, Some(&KEY1)).unwrap();
+    main_db
+        .record(&raw_data[0], gps_processor::ProcessResult::NewSegment)
+        .unwrap();
+    main_db
+        .with_txn(|txn| {
+            // a visit of the same place
+            txn.add_place_visits(
+                None,
+                &[StayPoint {
+                    point: place.clone(),
+                    arrival: DateTime::from_timestamp(1697435515, 0).unwrap(),
+                    departure: DateTime::from_timestamp(1697439115, 0).unwrap(),
+                }],
+            )?;
+            txn.add_privacy_zone(
+                "Another Garden",
+                &PrivacyZoneShape::Circle {
+                    center: place.clone(),
+                    radius_m: 100.0,
+                },
+            )
+        })
+        .unwrap();
+    let expected = snapshot(&mut main_db);
+    assert_eq!(expected.2.len(), 1);
+    assert_eq!(expected.2[0].visit_count, 2);
+    assert_eq!(expected.3.len(), 2);
+
+    main_db.rotate_key(Some(&KEY2)).unwrap();
+    assert_eq!(snapshot(&mut main_db), expected);
+    drop(main_db);
+    assert!(open(&temp_dir, Some(&KEY1)).is_err());
+    let mut main_db = open(&temp_dir, Some(&KEY2)).unwrap();
+    assert_eq!(snapshot(&mut main_db), expected);
+    let (fingerprint, expected_fingerprint) = fingerprints(&mut main_db, Some(&KEY2));
+    assert_eq!(fingerprint, expected_fingerprint);
+
+    // disable
+    main_db.rotate_key(None).unwrap();
+    drop(main_db);
+    let mut main_db = open(&temp_dir, None).unwrap();
+    assert!(!main_db.is_encrypted());
+    assert_eq!(snapshot(&mut main_db), expected);
+    assert_eq!(fingerprints(&mut main_db, None).0, plain_fingerprint);
+}
+
+#[test]
+fn cache_is_cleared_when_key_changes() {
+    let (mut main_db, cache_db, main_dir, cache_dir) =
+        test_utils::setup_main_and_cache_db("db_encryption-cache");
+    drop(cache_db);
+    main_db
+        .with_txn(|txn| {
+            test_utils::insert_bitmap_journey(
+                txn,
+                chrono::Utc::now().date_naive(),
+                JourneyKind::DefaultKind,
+                test_utils::draw_sample_bitmap(),
+            );
+            Ok(())
+        })
+        .unwrap();
+    main_db.rotate_key(Some(&KEY1)).unwrap();
+    let cipher = main_db.cipher().cloned();
+
+    let cache_dir = cache_dir.path().to_str().unwrap();
+    let cache_db = CacheDbV1::open_with_cipher(cache_dir, cipher.clone());
+    let expected = main_db
+        .with_txn(|txn| cache_db.get_or_compute(txn, &LayerKind::All, None, None))
+        .unwrap();
+    drop(cache_db);
+
+    // cached with the same key
+    let cache_db = CacheDbV1::open_with_cipher(cache_dir, cipher);
+    let cached = main_db
+        .with_txn(|txn| cache_db.get_or_compute(txn, &LayerKind::All, None, None))
+        .unwrap();
+    assert_eq!(cached, expected);
+    drop(cache_db);
+
+    // a different key, the cache is dropped instead of failing
+    let cache_db = CacheDbV1::open_with_cipher(cache_dir, Some(DbCipher::new(&KEY2).unwrap()));
+    let rebuilt = main_db
+        .with_txn(|txn| cache_db.get_or_compute(txn, &LayerKind::All, None, None))
+        .unwrap();
+    assert_eq!(rebuilt, expected);
+    drop(main_dir);
+}
@@ -0,0 +1,111 @@
+pub mod test_utils;
+
+use memolanes_core::{
+    duplicate_detector, import_data, journey_data::JourneyData, journey_header::JourneyKind,
+    journey_vector::JourneyVector, main_db::MainDb,
+};
+use tempdir::TempDir;
+
+fn insert_gpx_journey(main_db: &mut MainDb, path: &str, modify: fn(&mut JourneyVector)) -> String {
+    let (raw_data, _preprocessor) = import_data::load_gpx(path).unwrap();
+    let info = import_data::journey_info_from_raw_vector_data(&raw_data);
+    let mut journey_vector =
+        import_data::journey_vector_from_raw_data_with_gps_preprocessor(&raw_data, None).unwrap();
+    modify(&mut journey_vector);
+    main_db
+        .with_txn(|txn| {
+            txn.create_and_insert_journey(
+                info.journey_date,
+                info.start_time,
+                info.end_time,
+                None,
+                JourneyKind::DefaultKind,
+                None,
+                JourneyData::Vector(journey_vector),
+            )
+        })
+        .unwrap()
+}
+
+#[test]
+fn fingerprint_of_empty_journey() {
+    assert_eq!(
+        duplicate_detector::fingerprint(
+            &JourneyData::Vector(JourneyVector {
+                track_segments: vec![]
+            }),
+            None
+        ),
+        None
+    );
+}
+
+#[test]
+fn exact_duplicate() {
+    let temp_dir = TempDir::new("duplicate_detector-exact_duplicate").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let id1 = insert_gpx_journey(&mut main_db, "./tests/data/raw_gps_laojunshan.gpx", |_| ());
+    let id2 = insert_gpx_journey(&mut main_db, "./tests/data/raw_gps_laojunshan.gpx", |_| ());
+
+    let duplicates = main_db
+        .with_txn(|txn| duplicate_detector::find_duplicates_of(txn, &id2))
+        .unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].journey_id, id2);
+    assert_eq!(duplicates[0].duplicate_of, id1);
+    assert!(duplicates[0].exact_match);
+
+    let all_duplicates = main_db
+        .with_txn(|txn| duplicate_detector::find_all_duplicates(txn))
+        .unwrap();
+    assert_eq!(all_duplicates.len(), 1);
+    assert!(all_duplicates[0].exact_match);
+}
+
+#[test]
+fn similar_duplicate() {
+    let temp_dir = TempDir::new("duplicate_detector-similar_duplicate").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let id1 = insert_gpx_journey(&mut main_db, "./tests/data/raw_gps_laojunshan.gpx", |_| ());
+    // same journey with the tail cut off
+    let id2 = insert_gpx_journey(
+        &mut main_db,
+        "./tests/data/raw_gps_laojunshan.gpx",
+        |journey_vector| {
+            let track_points = &mut journey_vector
+                .track_segments
+                .last_mut()
+                .unwrap()
+                .track_points;
+            track_points.truncate(track_points.len() * 9 / 10);
+        },
+    );
+
+    let duplicates = main_db
+        .with_txn(|txn| duplicate_detector::find_duplicates_of(txn, &id2))
+        .unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].duplicate_of, id1);
+    assert!(!duplicates[0].exact_match);
+    assert!(duplicates[0].similarity > 0.99);
+}
+
+#[test]
+fn no_duplicate() {
+    let temp_dir = TempDir::new("duplicate_detector-no_duplicate").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let id = insert_gpx_journey(&mut main_db, "./tests/data/raw_gps_laojunshan.gpx", |_| ());
+    let _ = insert_gpx_journey(&mut main_db, "./tests/data/raw_gps_shanghai.gpx", |_| ());
+
+    assert!(main_db
+        .with_txn(|txn| duplicate_detector::find_duplicates_of(txn, &id))
+        .unwrap()
+        .is_empty());
+    assert!(main_db
+        .with_txn(|txn| duplicate_detector::find_all_duplicates(txn))
+        .unwrap()
+        .is_empty());
+}
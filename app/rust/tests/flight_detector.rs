@@ -0,0 +1,140 @@
+use memolanes_core::{
+    flight_detector::{self, TrackPart},
+    flight_track_processor,
+    gps_processor::Point,
+    transport_mode::TimedPoint,
+};
+
+fn timed_point(timestamp_sec: i64, latitude: f64, longitude: f64) -> TimedPoint {
+    TimedPoint {
+        timestamp_sec,
+        point: Point {
+            latitude,
+            longitude,
+        },
+        altitude: None,
+    }
+}
+
+// ~9m/s to the north, one point per minute
+fn drive(start_sec: i64, latitude: f64, longitude: f64, n: i64) -> Vec<TimedPoint> {
+    (0..n)
+        .map(|i| timed_point(start_sec + i * 60, latitude + i as f64 * 0.005, longitude))
+        .collect()
+}
+
+#[test]
+fn detect_flights() {
+    // driving only
+    assert!(flight_detector::detect_flights(&drive(0, 31.0, 121.0, 100)).is_empty());
+
+    // a high-speed train is not a flight
+    let train: Vec<TimedPoint> = (0..100)
+        .map(|i| timed_point(i * 60, 31.0 + i as f64 * 0.04, 121.0))
+        .collect();
+    assert!(flight_detector::detect_flights(&train).is_empty());
+
+    // a single bad point far away
+    let mut points = drive(0, 31.0, 121.0, 10);
+    points.push(timed_point(601, 45.0, 121.0));
+    points.extend(drive(602, 31.05, 121.0, 10));
+    assert!(flight_detector::detect_flights(&points).is_empty());
+
+    // recorded during the flight
+    let mut points = drive(0, 31.0, 121.0, 10);
+    points.extend((1..=60).map(|i| timed_point(540 + i * 60, 31.045 + i as f64 * 0.12, 121.0)));
+    points.extend(drive(540 + 61 * 60, 38.245, 121.0, 10));
+    assert_eq!(flight_detector::detect_flights(&points), vec![9..70]);
+
+    // nothing recorded during the flight
+    let mut points = drive(0, 31.0, 121.0, 10);
+    points.extend(drive(3 * 3600, 40.0, 116.0, 10));
+    assert_eq!(flight_detector::detect_flights(&points), vec![9..11]);
+
+    // high but not far enough, e.g. bad altitude data
+    let mut points = drive(0, 31.0, 121.0, 10);
+    for point in &mut points[3..8] {
+        point.altitude = Some(10000.0);
+    }
+    assert!(flight_detector::detect_flights(&points).is_empty());
+}
+
+#[test]
+fn split_flights() {
+    // Shanghai -> Beijing -> Tokyo, starting a new segment after each flight
+    let segments = vec![
+        drive(0, 31.15, 121.80, 10),
+        drive(3 * 3600, 40.08, 116.58, 10),
+        drive(9 * 3600, 35.55, 139.78, 10),
+    ];
+    let parts = flight_detector::split_flights(&segments, |x| Some(x.clone()));
+    assert_eq!(parts.len(), 5);
+    match &parts[..] {
+        [TrackPart::Ground(a), TrackPart::Flight(b), TrackPart::Ground(c), TrackPart::Flight(d), TrackPart::Ground(e)] =>
+        {
+            assert_eq!(a, &vec![segments[0].clone()]);
+            assert_eq!(b, &vec![segments[0][9].clone(), segments[1][0].clone()]);
+            assert_eq!(c, &vec![segments[1].clone()]);
+            assert_eq!(d, &vec![segments[1][9].clone(), segments[2][0].clone()]);
+            assert_eq!(e, &vec![segments[2].clone()]);
+        }
+        _ => panic!("Unexpected parts"),
+    }
+
+    // data without timestamp is never a flight
+    let parts = flight_detector::split_flights(&segments, |x| {
+        if x == &segments[1][0] {
+            None
+        } else {
+            Some(x.clone())
+        }
+    });
+    assert_eq!(
+        parts
+            .iter()
+            .map(|x| matches!(x, TrackPart::Flight(_)))
+            .collect::<Vec<_>>(),
+        vec![false, true, false]
+    );
+
+    // no flight
+    let segments = vec![drive(0, 31.15, 121.80, 10)];
+    match &flight_detector::split_flights(&segments, |x| Some(x.clone()))[..] {
+        [TrackPart::Ground(x)] => assert_eq!(x, &segments),
+        _ => panic!("Unexpected parts"),
+    }
+}
+
+#[test]
+fn great_circle() {
+    // Tokyo -> Honolulu, crossing the antimeridian
+    let tokyo = Point {
+        latitude: 35.55,
+        longitude: 139.78,
+    };
+    let honolulu = Point {
+        latitude: 21.32,
+        longitude: -157.92,
+    };
+    let points = flight_track_processor::great_circle_points(&tokyo, &honolulu, 1000.0);
+    let distance = tokyo.haversine_distance(&honolulu);
+    assert_eq!(points.len(), (distance / 1000.0).ceil() as usize - 1);
+    // the great circle goes north of both ends
+    let max_latitude = points.iter().map(|x| x.latitude).fold(f64::MIN, f64::max);
+    assert!(max_latitude > tokyo.latitude, "{max_latitude}");
+    // evenly spaced
+    for x in points.windows(2) {
+        assert!((x[0].haversine_distance(&x[1]) - 1000.0).abs() < 10.0);
+    }
+
+    let journey_vector =
+        flight_track_processor::process_great_circle(&[tokyo.clone(), honolulu.clone()]).unwrap();
+    assert_eq!(journey_vector.track_segments.len(), 2);
+    for segment in &journey_vector.track_segments {
+        for x in &segment.track_points {
+            assert!(x.longitude.abs() <= 180.0);
+        }
+    }
+
+    assert!(flight_track_processor::great_circle_points(&tokyo, &tokyo, 1000.0).is_empty());
+}
@@ -0,0 +1,187 @@
+use chrono::{NaiveDate, Utc};
+use memolanes_core::{
+    gps_processor::GpsPostprocessor,
+    import_data,
+    journey_data::JourneyData,
+    journey_header::{JourneyHeader, JourneyKind, JourneyType},
+    journey_vector::{JourneyVector, TrackPoint, TrackSegment},
+    main_db::MainDb,
+};
+use tempdir::TempDir;
+
+fn track_point(latitude: f64, longitude: f64) -> TrackPoint {
+    TrackPoint {
+        latitude,
+        longitude,
+    }
+}
+
+fn single_segment(track_points: Vec<TrackPoint>) -> JourneyVector {
+    JourneyVector {
+        track_segments: vec![TrackSegment { track_points }],
+    }
+}
+
+// Going north, roughly 11m per point.
+fn straight_line(count: usize) -> Vec<TrackPoint> {
+    (0..count)
+        .map(|i| track_point(30.0 + i as f64 * 0.0001, 120.0))
+        .collect()
+}
+
+#[test]
+fn simplify_straight_line() {
+    let track_points = straight_line(100);
+    let result = GpsPostprocessor::process(single_segment(track_points.clone()));
+    assert_eq!(
+        result,
+        single_segment(vec![track_points[0].clone(), track_points[99].clone()])
+    );
+}
+
+#[test]
+fn keep_corners() {
+    let mut track_points = straight_line(50);
+    // then going east
+    track_points.extend((1..50).map(|i| track_point(30.0049, 120.0 + i as f64 * 0.0001)));
+    let result = GpsPostprocessor::process(single_segment(track_points.clone()));
+    assert_eq!(
+        result,
+        single_segment(vec![
+            track_points[0].clone(),
+            track_points[49].clone(),
+            track_points[98].clone()
+        ])
+    );
+}
+
+#[test]
+fn remove_outliers() {
+    let mut track_points = straight_line(20);
+    // ~1km away
+    track_points[10].longitude = 120.01;
+    let result = GpsPostprocessor::process(single_segment(track_points.clone()));
+    assert_eq!(
+        result,
+        single_segment(vec![track_points[0].clone(), track_points[19].clone()])
+    );
+}
+
+#[test]
+fn smooth_stationary_jitter() {
+    let mut track_points = straight_line(10);
+    // staying around the last point, jumping back and forth a few meters
+    for i in 0..60 {
+        let offset = if i % 2 == 0 { 0.00005 } else { -0.00005 };
+        track_points.push(track_point(30.0009 + offset, 120.0 - offset));
+    }
+    let result = GpsPostprocessor::process(single_segment(track_points));
+    let result_points = &result.track_segments[0].track_points;
+    assert!(result_points.len() <= 3, "{result_points:?}");
+    assert_eq!(result_points[0], track_point(30.0, 120.0));
+}
+
+#[test]
+fn empty_and_short_segments() {
+    let journey_vector = JourneyVector {
+        track_segments: vec![
+            TrackSegment {
+                track_points: vec![],
+            },
+            TrackSegment {
+                track_points: vec![track_point(30.0, 120.0)],
+            },
+        ],
+    };
+    assert_eq!(
+        GpsPostprocessor::process(journey_vector),
+        JourneyVector {
+            track_segments: vec![TrackSegment {
+                track_points: vec![track_point(30.0, 120.0)],
+            }]
+        }
+    );
+}
+
+#[test]
+fn real_data() {
+    let (raw_data, _preprocessor) =
+        import_data::load_gpx("./tests/data/raw_gps_laojunshan.gpx").unwrap();
+    let journey_vector =
+        import_data::journey_vector_from_raw_data_with_gps_preprocessor(&raw_data, None).unwrap();
+    let count = |journey_vector: &JourneyVector| -> usize {
+        journey_vector
+            .track_segments
+            .iter()
+            .map(|x| x.track_points.len())
+            .sum()
+    };
+    let result = GpsPostprocessor::process(journey_vector.clone());
+    assert_eq!(
+        result.track_segments.len(),
+        journey_vector.track_segments.len()
+    );
+    assert!(count(&result) * 10 < count(&journey_vector));
+    for (segment, result_segment) in journey_vector
+        .track_segments
+        .iter()
+        .zip(result.track_segments.iter())
+    {
+        assert_eq!(
+            segment.track_points.first(),
+            result_segment.track_points.first()
+        );
+        assert_eq!(
+            segment.track_points.last(),
+            result_segment.track_points.last()
+        );
+    }
+}
+
+#[test]
+fn optimize_main_db() {
+    let temp_dir = TempDir::new("gps_postprocessor-optimize_main_db").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let journey_vector = single_segment(straight_line(100));
+    let header = JourneyHeader {
+        id: "old-journey".to_string(),
+        revision: "old".to_string(),
+        journey_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        created_at: Utc::now(),
+        updated_at: None,
+        end: None,
+        start: None,
+        journey_type: JourneyType::Vector,
+        journey_kind: JourneyKind::DefaultKind,
+        note: None,
+        postprocessor_algo: Some("0".to_string()),
+        transport_mode: None,
+    };
+    main_db
+        .with_txn(|txn| txn.insert_journey(header.clone(), JourneyData::Vector(journey_vector)))
+        .unwrap();
+    assert!(GpsPostprocessor::outdated_algo(&header));
+    assert!(main_db.with_txn(|txn| txn.require_optimization()).unwrap());
+
+    main_db.with_txn(|txn| txn.optimize()).unwrap();
+    assert!(!main_db.with_txn(|txn| txn.require_optimization()).unwrap());
+
+    let header = main_db
+        .with_txn(|txn| txn.get_journey_header("old-journey"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        header.postprocessor_algo,
+        Some(GpsPostprocessor::current_algo())
+    );
+    match main_db
+        .with_txn(|txn| txn.get_journey_data("old-journey"))
+        .unwrap()
+    {
+        JourneyData::Vector(journey_vector) => {
+            assert_eq!(journey_vector.track_segments[0].track_points.len(), 2)
+        }
+        JourneyData::Bitmap(_) => panic!("invalid"),
+    }
+}
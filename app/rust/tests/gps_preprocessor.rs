@@ -1,25 +1,30 @@
 pub mod test_utils;
 
 use memolanes_core::gps_processor::{
-    GpsPreprocessor, Point, ProcessResult, RawData, SegmentGapRule,
+    self, GpsFilter, GpsPreprocessor, Point, PreprocessingProfile, PreprocessingThresholds,
+    ProcessResult, RawData, SegmentGapRule,
 };
 use memolanes_core::{export_data, import_data};
 use std::collections::HashMap;
 use std::fs::File;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
 
 #[test]
 fn first_data() {
     let mut gps_preprocessor = GpsPreprocessor::new();
     assert!(gps_preprocessor.last_kept_point().is_none());
     let data = RawData {
-        point: Point {
-            latitude: 120.163856,
-            longitude: 30.2719716,
-        },
-        timestamp_ms: Some(1697349116449),
         accuracy: Some(3.9),
         altitude: Some(10.),
         speed: Some(0.6028665),
+        ..RawData::new(
+            Point {
+                latitude: 120.163856,
+                longitude: 30.2719716,
+            },
+            Some(1697349116449),
+        )
     };
     assert_eq!(
         gps_preprocessor.preprocess(&data),
@@ -31,14 +36,16 @@ fn first_data() {
 fn ignore() {
     let mut gps_preprocessor = GpsPreprocessor::new();
     let data = RawData {
-        point: Point {
-            latitude: 120.163856,
-            longitude: 30.2719716,
-        },
-        timestamp_ms: Some(1697349116449),
         accuracy: Some(300.0),
         altitude: Some(10.),
         speed: Some(0.6028665),
+        ..RawData::new(
+            Point {
+                latitude: 120.163856,
+                longitude: 30.2719716,
+            },
+            Some(1697349116449),
+        )
     };
     assert_eq!(gps_preprocessor.preprocess(&data), ProcessResult::Ignore);
 }
@@ -48,14 +55,16 @@ fn time_difference() {
     let mut gps_preprocessor = GpsPreprocessor::new();
 
     gps_preprocessor.preprocess(&RawData {
-        point: Point {
-            latitude: 120.163856,
-            longitude: 30.2719716,
-        },
-        timestamp_ms: Some(1697349116449),
         accuracy: Some(3.9),
         altitude: Some(10.),
         speed: Some(0.6028665),
+        ..RawData::new(
+            Point {
+                latitude: 120.163856,
+                longitude: 30.2719716,
+            },
+            Some(1697349116449),
+        )
     });
 
     assert_eq!(
@@ -63,14 +72,16 @@ fn time_difference() {
         120.163856
     );
     let result = gps_preprocessor.preprocess(&RawData {
-        point: Point {
-            latitude: 120.1639266,
-            longitude: 30.271981,
-        },
-        timestamp_ms: Some(1697349117449),
         accuracy: Some(3.5),
         altitude: Some(20.),
         speed: Some(0.18825254),
+        ..RawData::new(
+            Point {
+                latitude: 120.1639266,
+                longitude: 30.271981,
+            },
+            Some(1697349117449),
+        )
     });
     assert_eq!(ProcessResult::Append, result);
 
@@ -79,14 +90,16 @@ fn time_difference() {
         120.1639266
     );
     let result = gps_preprocessor.preprocess(&RawData {
-        point: Point {
-            latitude: 120.163857,
-            longitude: 30.2719716,
-        },
-        timestamp_ms: Some(1698349116449),
         accuracy: Some(3.9),
         altitude: Some(30.),
         speed: Some(0.6028665),
+        ..RawData::new(
+            Point {
+                latitude: 120.163857,
+                longitude: 30.2719716,
+            },
+            Some(1698349116449),
+        )
     });
     assert_eq!(ProcessResult::NewSegment, result);
 
@@ -95,14 +108,16 @@ fn time_difference() {
         120.163857
     );
     let result = gps_preprocessor.preprocess(&RawData {
-        point: Point {
-            latitude: 120.163856,
-            longitude: 30.2719716,
-        },
-        timestamp_ms: Some(1697349116449),
         accuracy: Some(3.9),
         altitude: Some(10.),
         speed: Some(0.6028665),
+        ..RawData::new(
+            Point {
+                latitude: 120.163856,
+                longitude: 30.2719716,
+            },
+            Some(1697349116449),
+        )
     });
     assert_eq!(ProcessResult::Ignore, result);
 }
@@ -110,37 +125,64 @@ fn time_difference() {
 #[test]
 fn speed() {
     let mut gps_preprocessor = GpsPreprocessor::new();
-    let data = RawData {
-        point: Point {
+    let data = RawData::new(
+        Point {
             latitude: 120.163856,
             longitude: 30.2719716,
         },
-        timestamp_ms: Some(1697349116000),
-        accuracy: None,
-        altitude: None,
-        speed: None,
-    };
+        Some(1697349116000),
+    );
     assert_eq!(
         gps_preprocessor.preprocess(&data),
         ProcessResult::NewSegment
     );
 
-    let data = RawData {
-        point: Point {
+    let data = RawData::new(
+        Point {
             latitude: 125.0,
             longitude: 30.2719716,
         },
-        timestamp_ms: Some(1697349117000),
-        accuracy: None,
-        altitude: None,
-        speed: None,
-    };
+        Some(1697349117000),
+    );
     assert_eq!(
         gps_preprocessor.preprocess(&data),
         ProcessResult::NewSegment
     );
 }
 
+#[test]
+fn reorder_batch() {
+    let data = |timestamp_ms: Option<i64>, accuracy: f32| RawData {
+        accuracy: Some(accuracy),
+        ..RawData::new(
+            Point {
+                latitude: 30.0,
+                longitude: 120.0,
+            },
+            timestamp_ms,
+        )
+    };
+    assert_eq!(
+        gps_processor::reorder_batch(vec![
+            data(None, 1.),
+            data(Some(3000), 5.),
+            data(Some(1000), 5.),
+            data(Some(3000), 2.),
+            data(None, 2.),
+            data(Some(2000), 5.),
+            data(Some(1000), 8.),
+        ]),
+        vec![
+            data(Some(1000), 5.),
+            data(Some(2000), 5.),
+            data(Some(3000), 2.),
+            data(None, 1.),
+            data(None, 2.),
+        ]
+    );
+    assert!(gps_processor::reorder_batch(vec![]).is_empty());
+}
+
 fn run_though_test_data(name: &str) -> HashMap<ProcessResult, i32> {
     const GENERATE_RESULT_GPX_FOR_INSPECTION: bool = false;
     let mut gps_preprocessor = GpsPreprocessor::new();
@@ -192,3 +234,164 @@ fn run_though_test_data_laojunshan() {
     assert_eq!(counter[&ProcessResult::Append], 2595);
     assert_eq!(counter[&ProcessResult::Ignore], 348);
 }
+
+// Walking north at ~1.4m/s, but the data jumps ~8m east and west every second.
+fn zigzag_data(i: i64) -> RawData {
+    let offset_in_degree = if i % 2 == 0 { 0.00008 } else { -0.00008 };
+    RawData {
+        accuracy: Some(10.),
+        speed: Some(1.4),
+        ..RawData::new(
+            Point {
+                latitude: 31.0 + i as f64 * 0.0000126,
+                longitude: 121.0 + offset_in_degree,
+            },
+            Some(1697349116000 + i * 1000),
+        )
+    }
+}
+
+#[test]
+fn kalman_filter() {
+    let mut gps_preprocessor =
+        GpsPreprocessor::new_with_rule_and_filter(SegmentGapRule::Default, GpsFilter::Kalman);
+    assert_eq!(gps_preprocessor.filter(), GpsFilter::Kalman);
+
+    let mut raw_error = 0.;
+    let mut filtered_error = 0.;
+    for i in 0..100 {
+        let data = zigzag_data(i);
+        let (result, filtered_data) = gps_preprocessor.preprocess_and_filter(&data);
+        if i == 0 {
+            assert_eq!(result, ProcessResult::NewSegment);
+            assert_eq!(filtered_data, data);
+        } else {
+            assert_eq!(result, ProcessResult::Append);
+            // only the position is corrected
+            assert_eq!(filtered_data.timestamp_ms, data.timestamp_ms);
+            raw_error += (data.point.longitude - 121.0).abs();
+            filtered_error += (filtered_data.point.longitude - 121.0).abs();
+        }
+    }
+    assert!(filtered_error * 4. < raw_error);
+
+    // without timestamp, nothing can be done
+    let data = RawData {
+        timestamp_ms: None,
+        ..zigzag_data(100)
+    };
+    let (_, filtered_data) = gps_preprocessor.preprocess_and_filter(&data);
+    assert_eq!(filtered_data, data);
+}
+
+#[test]
+fn no_filter() {
+    let mut gps_preprocessor = GpsPreprocessor::new();
+    assert_eq!(gps_preprocessor.filter(), GpsFilter::None);
+    for i in 0..10 {
+        let data = zigzag_data(i);
+        let (_, filtered_data) = gps_preprocessor.preprocess_and_filter(&data);
+        assert_eq!(filtered_data, data);
+    }
+
+    // switching the filter keeps the current segment going
+    gps_preprocessor.set_filter(GpsFilter::Kalman);
+    assert_eq!(
+        gps_preprocessor.preprocess(&zigzag_data(10)),
+        ProcessResult::Append
+    );
+
+    gps_preprocessor.reset();
+    assert_eq!(gps_preprocessor.filter(), GpsFilter::Kalman);
+    assert!(gps_preprocessor.last_kept_point().is_none());
+}
+
+#[test]
+fn gps_filter_to_string() {
+    for gps_filter in [GpsFilter::None, GpsFilter::Kalman] {
+        assert_eq!(
+            GpsFilter::from_str(&gps_filter.to_string()).unwrap(),
+            gps_filter
+        );
+    }
+}
+
+#[test]
+fn preprocessing_profiles() {
+    for profile in PreprocessingProfile::iter() {
+        assert_eq!(
+            PreprocessingProfile::from_str(&profile.to_string()).unwrap(),
+            profile
+        );
+        match profile.builtin_thresholds() {
+            None => assert_eq!(profile, PreprocessingProfile::Custom),
+            Some(thresholds) => {
+                thresholds.validate().unwrap();
+                assert_eq!(
+                    PreprocessingThresholds::from_str(&thresholds.to_string()).unwrap(),
+                    thresholds
+                );
+            }
+        }
+    }
+    assert_eq!(
+        PreprocessingProfile::Default.builtin_thresholds().unwrap(),
+        PreprocessingThresholds::from_segment_gap_rule(SegmentGapRule::Default)
+    );
+}
+
+#[test]
+fn invalid_preprocessing_thresholds() {
+    let thresholds = PreprocessingThresholds::from_segment_gap_rule(SegmentGapRule::Default);
+
+    let mut invalid = thresholds.clone();
+    invalid.segment_gap_thresholds.reverse();
+    assert!(invalid.validate().is_err());
+
+    let invalid = PreprocessingThresholds {
+        min_acceleration_m_per_s2: 1.,
+        ..thresholds.clone()
+    };
+    assert!(invalid.validate().is_err());
+
+    let invalid = PreprocessingThresholds {
+        max_accuracy_m: f32::NAN,
+        ..thresholds
+    };
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn preprocessing_thresholds() {
+    let data = RawData {
+        accuracy: Some(40.),
+        ..RawData::new(
+            Point {
+                latitude: 120.163856,
+                longitude: 30.2719716,
+            },
+            Some(1697349116449),
+        )
+    };
+
+    let mut gps_preprocessor = GpsPreprocessor::new();
+    assert_eq!(
+        gps_preprocessor.preprocess(&data),
+        ProcessResult::NewSegment
+    );
+
+    let walking = PreprocessingProfile::Walking.builtin_thresholds().unwrap();
+    let mut gps_preprocessor =
+        GpsPreprocessor::new_with_thresholds_and_filter(walking.clone(), GpsFilter::None);
+    assert_eq!(gps_preprocessor.thresholds(), &walking);
+    assert_eq!(gps_preprocessor.preprocess(&data), ProcessResult::Ignore);
+
+    gps_preprocessor.set_thresholds(PreprocessingThresholds {
+        max_accuracy_m: 100.,
+        ..walking
+    });
+    assert_eq!(
+        gps_preprocessor.preprocess(&data),
+        ProcessResult::NewSegment
+    );
+}
@@ -2,10 +2,12 @@
 extern crate assert_float_eq;
 
 use itertools::Itertools;
-use memolanes_core::api::import::{ImportPreprocessor, JourneyInfo};
+use memolanes_core::api::import::{self, ImportPreprocessor, ImportSplitRule, JourneyInfo};
 use memolanes_core::export_data::raw_data_csv_to_gpx_file;
+use memolanes_core::gps_processor::{Point, RawData};
 use memolanes_core::gpx_file_utils::{normalize_generic_time, normalize_step_of_my_world_time};
 use memolanes_core::journey_vector::TrackPoint;
+use memolanes_core::preprocessor_detector::recommend_preprocessor;
 use memolanes_core::{export_data, import_data};
 use std::fs::File;
 use std::io::BufReader;
@@ -126,6 +128,101 @@ pub fn gpx() {
     assert!(matches!(preprocessor, ImportPreprocessor::Generic));
 }
 
+#[test]
+fn split_raw_vector_data() {
+    let raw_data = |timestamp_sec: Option<i64>| {
+        RawData::new(
+            Point {
+                latitude: 31.2,
+                longitude: 121.4,
+            },
+            timestamp_sec.map(|x| x * 1000),
+        )
+    };
+    // 2024-01-01T12:00:00Z
+    let day1 = 1704110400;
+    let day2 = day1 + 2 * 24 * 3600;
+    let raw_vector_data = vec![
+        vec![
+            raw_data(Some(day1)),
+            raw_data(Some(day1 + 60)),
+            raw_data(None),
+            raw_data(Some(day1 + 120)),
+        ],
+        vec![
+            raw_data(Some(day1 + 600)),
+            raw_data(Some(day2)),
+            raw_data(Some(day2 + 60)),
+        ],
+    ];
+
+    let shape = |journeys: Vec<Vec<Vec<RawData>>>| {
+        journeys
+            .iter()
+            .map(|journey| journey.iter().map(|segment| segment.len()).collect_vec())
+            .collect_vec()
+    };
+
+    assert_eq!(
+        shape(import_data::split_raw_vector_data(
+            &raw_vector_data,
+            ImportSplitRule::LocalDay
+        )),
+        vec![vec![4, 1], vec![2]]
+    );
+    assert_eq!(
+        shape(import_data::split_raw_vector_data(
+            &raw_vector_data,
+            ImportSplitRule::TimeGap { gap_minutes: 5 }
+        )),
+        vec![vec![4], vec![1], vec![2]]
+    );
+    assert_eq!(
+        shape(import_data::split_raw_vector_data(
+            &raw_vector_data,
+            ImportSplitRule::TimeGap {
+                gap_minutes: 7 * 24 * 60
+            }
+        )),
+        vec![vec![4, 3]]
+    );
+}
+
+#[test]
+fn preview_import() {
+    let (journey_info, raw_vector_data, _, warnings) =
+        import::load_gpx_or_kml("./tests/data/raw_gps_laojunshan.gpx".to_string()).unwrap();
+    assert_eq!(warnings, None);
+    let preview = import::preview_import(&raw_vector_data).unwrap();
+
+    assert_eq!(preview.start_time, journey_info.start_time);
+    assert_eq!(preview.end_time, journey_info.end_time);
+    assert!(preview.distance_m > 0.);
+    let bounding_box = preview.bounding_box.unwrap();
+    assert!(bounding_box.min_latitude < bounding_box.max_latitude);
+    assert!(bounding_box.min_longitude < bounding_box.max_longitude);
+
+    assert_eq!(preview.preprocessors.len(), 4);
+    for preprocessor in &preview.preprocessors {
+        if preprocessor.import_preprocessor != ImportPreprocessor::FlightTrack {
+            assert!(preprocessor.segment_count > 0);
+            assert!(preprocessor.camera_option.is_some());
+            assert!(preprocessor.distance_m <= preview.distance_m + 1.);
+            assert_eq!(
+                preprocessor.point_count + preprocessor.dropped_point_count,
+                preview.point_count
+            );
+        }
+    }
+    let no_preprocessor = &preview.preprocessors[0];
+    assert_eq!(
+        no_preprocessor.import_preprocessor,
+        ImportPreprocessor::None
+    );
+    assert_eq!(no_preprocessor.dropped_point_count, 0);
+    assert_eq!(no_preprocessor.segment_count, preview.segment_count);
+}
+
 #[test]
 fn test_normalize_times() {
     let input = "2025-07-02 18:07:33 +0000";
@@ -173,6 +270,71 @@ pub fn gpx_your_app() {
     assert!(matches!(preprocessor, ImportPreprocessor::Spare));
 }
 
+#[test]
+pub fn gpx_route_and_track() {
+    let (raw_data, preprocessor) =
+        import_data::load_gpx("./tests/data/route_and_track.gpx").unwrap();
+
+    // tracks first, then routes. empty segments are skipped.
+    assert_eq!(raw_data.iter().map(|x| x.len()).collect_vec(), vec![1, 2]);
+    let track_point = &raw_data[0][0];
+    assert_f64_near!(track_point.point.latitude, 31.2310);
+    assert_f64_near!(track_point.point.longitude, 121.4750);
+    assert_eq!(track_point.timestamp_ms, Some(1714550401000));
+    assert_eq!(track_point.altitude, Some(12.5));
+    assert_eq!(track_point.accuracy, Some(4.));
+    assert_eq!(track_point.speed, Some(2.));
+    // invalid time is treated as missing
+    assert_eq!(raw_data[1][1].timestamp_ms, None);
+    assert_eq!(preprocessor, ImportPreprocessor::Generic);
+
+    // but it is reported
+    let (_, _, warnings) =
+        import_data::load_gpx_with_warnings("./tests/data/route_and_track.gpx").unwrap();
+    let warnings = warnings.unwrap();
+    assert!(warnings.contains(": 1,"));
+    assert!(warnings.contains("`not a time`"));
+}
+
+#[test]
+pub fn gpx_by_local_day() {
+    for path in [
+        "./tests/data/StepOfMyWorld.gpx",
+        "./tests/data/raw_gps_shenzhen_stationary.gpx",
+    ] {
+        let mut parts = Vec::new();
+        let warnings = import_data::load_gpx_by_local_day(path, |data, preprocessor| {
+            assert_eq!(
+                preprocessor,
+                recommend_preprocessor(&data).preprocessor,
+                "{path}"
+            );
+            parts.push(data);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(warnings, None);
+
+        // same as loading everything and then splitting
+        let (raw_data, _) = import_data::load_gpx(path).unwrap();
+        let expected = import_data::split_raw_vector_data(&raw_data, ImportSplitRule::LocalDay);
+        assert!(!parts.is_empty());
+        assert_eq!(parts, expected, "{path}");
+    }
+
+    // errors from the callback stop the reading
+    let mut count = 0;
+    assert!(import_data::load_gpx_by_local_day(
+        "./tests/data/raw_gps_shenzhen_stationary.gpx",
+        |_, _| {
+            count += 1;
+            Err(anyhow::anyhow!("oops"))
+        }
+    )
+    .is_err());
+    assert_eq!(count, 1);
+}
+
 #[test]
 pub fn gpx_2bulu() {
     const IMPORT_PATH: &str = "./tests/data/2bulu.gpx";
@@ -216,6 +378,56 @@ pub fn kml_track() {
     assert!(matches!(preprocessor, ImportPreprocessor::Generic));
 }
 
+#[test]
+pub fn kmz() {
+    let (kml_data, kml_preprocessor) =
+        import_data::load_kml("./tests/data/raw_gps_laojunshan.kml").unwrap();
+    let (kmz_data, kmz_preprocessor) =
+        import_data::load_kml("./tests/data/raw_gps_laojunshan.kmz").unwrap();
+    assert_eq!(kml_data, kmz_data);
+    assert_eq!(kml_preprocessor, kmz_preprocessor);
+}
+
+#[test]
+pub fn kml_multi_track() {
+    let (raw_data, preprocessor) = import_data::load_kml("./tests/data/multi_track.kml").unwrap();
+
+    assert_eq!(
+        raw_data.iter().map(|x| x.len()).collect_vec(),
+        vec![4, 1, 2, 2]
+    );
+    assert_eq!(raw_data[0][0].timestamp_ms, Some(1714550400000));
+    assert_eq!(raw_data[0][3].timestamp_ms, Some(1714550710500));
+    assert_f64_near!(raw_data[0][3].point.latitude, 31.2312);
+    assert_f64_near!(raw_data[0][3].point.longitude, 121.4753);
+    assert_eq!(raw_data[1][0].timestamp_ms, Some(1714525200000));
+    assert_eq!(raw_data[3][0].timestamp_ms, None);
+    assert_eq!(preprocessor, ImportPreprocessor::Generic);
+}
+
+#[test]
+pub fn kml_preprocessor_detection() {
+    for name in ["CHH7867_XIAN_HANGZHOU", "TV9882-3bf27ed6"] {
+        let (_, preprocessor) =
+            import_data::load_kml(&format!("./tests/data/flight_{name}.kml")).unwrap();
+        assert_eq!(preprocessor, ImportPreprocessor::FlightTrack);
+    }
+
+    // tracker apps, the data alone is too short to tell
+    for (name, expected) in [
+        ("2bulu", ImportPreprocessor::Generic),
+        ("foooooot", ImportPreprocessor::Generic),
+        ("xingzhe", ImportPreprocessor::Generic),
+        ("oruxmaps", ImportPreprocessor::Generic),
+        ("step_of_my_world", ImportPreprocessor::Spare),
+    ] {
+        let (raw_data, preprocessor) =
+            import_data::load_kml(&format!("./tests/data/{name}.kml")).unwrap();
+        assert!(!raw_data.is_empty());
+        assert_eq!(preprocessor, expected, "{name}");
+    }
+}
+
 #[test]
 pub fn kml_line_string() {
     const IMPORT_PATH: &str = "./tests/data/2024-08-24-2104.kml";
@@ -228,6 +440,34 @@ pub fn kml_line_string() {
     assert_f64_near!(points[0].longitude, 117.1179554744);
 }
 
+#[test]
+fn load_raw_data_csv() {
+    const CSV_PATH: &str = "./tests/data/raw_data.csv";
+    let raw_data = import_data::load_raw_data_csv(CSV_PATH).unwrap();
+    assert_eq!(raw_data.len(), 929);
+    assert_f64_near!(raw_data[0].point.latitude, 51.520302);
+    assert_f64_near!(raw_data[0].point.longitude, -0.104277);
+    assert_eq!(raw_data[0].timestamp_ms, Some(1754726365283));
+    assert_eq!(raw_data[0].accuracy, Some(5000.));
+    // written before the extra columns were added
+    assert_eq!(raw_data[0].bearing, None);
+    assert_eq!(raw_data[0].satellite_count, None);
+
+    // multiple files are concatenated as a single segment
+    let vector_data =
+        import::load_raw_data_csv_files(vec![CSV_PATH.to_string(), CSV_PATH.to_string()]).unwrap();
+    let journeys = import::split_raw_vector_data(&vector_data, ImportSplitRule::LocalDay);
+    assert_eq!(journeys.len(), 1);
+    assert_eq!(
+        journeys[0].0.start_time.unwrap().timestamp_millis(),
+        1754726365283
+    );
+
+    let journey_vector =
+        import_data::journey_vector_from_raw_data_with_gps_preprocessor(&[raw_data], None).unwrap();
+    assert_eq!(journey_vector.track_segments.len(), 1);
+}
+
 #[test]
 fn test_raw_data_csv_to_gpx_file() {
     const CSV_PATH: &str = "./tests/data/raw_data.csv";
@@ -257,3 +497,60 @@ fn test_raw_data_csv_to_gpx_file() {
     let metadata = gpx.metadata.expect("GPX metadata should exist");
     assert_eq!(metadata.name.as_deref(), Some("MemoLanes RawData"));
 }
+
+#[test]
+fn load_flight_log_csv() {
+    let entries =
+        import_data::load_flight_log_csv("./tests/data/flight_log_openflights.csv").unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|x| (
+                x.date.to_string(),
+                x.origin.as_str(),
+                x.destination.as_str(),
+                x.flight_number.as_deref(),
+                x.airline.as_deref(),
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "2019-05-03".to_string(),
+                "PVG",
+                "NRT",
+                Some("MU523"),
+                Some("China Eastern Airlines")
+            ),
+            ("2019-05-10".to_string(), "NRT", "HNL", None, None),
+        ]
+    );
+
+    let entries =
+        import_data::load_flight_log_csv("./tests/data/flight_log_flightdiary.csv").unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|x| (
+                x.date.to_string(),
+                x.origin.as_str(),
+                x.destination.as_str(),
+                x.flight_number.as_deref(),
+                x.airline.as_deref(),
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "2021-10-01".to_string(),
+                "PEK",
+                "SHA",
+                Some("CA1501"),
+                Some("Air China (CA/CCA)")
+            ),
+            // unknown airports are only checked when importing
+            ("2021-10-07".to_string(), "SHA", "XXX", None, None),
+        ]
+    );
+
+    // not a flight log
+    assert!(import_data::load_flight_log_csv("./tests/data/raw_data.csv").is_err());
+}
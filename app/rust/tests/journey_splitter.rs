@@ -0,0 +1,124 @@
+use chrono::FixedOffset;
+use memolanes_core::{
+    gps_processor::Point,
+    journey_splitter::{self, JourneySplitOptions},
+    transport_mode::TimedPoint,
+};
+
+fn timed_point(timestamp_sec: i64) -> TimedPoint {
+    TimedPoint {
+        timestamp_sec,
+        point: Point {
+            latitude: 31.0 + timestamp_sec as f64 * 1e-9,
+            longitude: 121.0,
+        },
+        altitude: None,
+    }
+}
+
+// 2024-03-01 00:00 in UTC+8
+const MIDNIGHT_SEC: i64 = 1709222400;
+
+fn split(
+    segments: &[Vec<i64>],
+    options: &JourneySplitOptions,
+    tz: &FixedOffset,
+) -> Vec<Vec<Vec<i64>>> {
+    let segments: Vec<Vec<TimedPoint>> = segments
+        .iter()
+        .map(|x| x.iter().map(|x| timed_point(*x)).collect())
+        .collect();
+    journey_splitter::split_segments(&segments, options, tz)
+        .iter()
+        .map(|journey| {
+            journey
+                .iter()
+                .map(|segment| segment.iter().map(|x| x.timestamp_sec).collect())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn split_segments() {
+    let utc_8 = FixedOffset::east_opt(8 * 3600).unwrap();
+    let m = MIDNIGHT_SEC;
+    // driving across midnight, then a long break on the next day
+    let segments = vec![
+        vec![m - 1800, m - 60, m + 60],
+        vec![m + 120, m + 180],
+        vec![m + 5 * 3600, m + 5 * 3600 + 60],
+    ];
+
+    assert_eq!(
+        split(&segments, &JourneySplitOptions::default(), &utc_8),
+        vec![segments.clone()]
+    );
+    assert_eq!(
+        split(
+            &segments,
+            &JourneySplitOptions {
+                split_at_local_date: true,
+                max_gap_mins: None,
+            },
+            &utc_8
+        ),
+        vec![
+            vec![vec![m - 1800, m - 60]],
+            vec![
+                vec![m + 60],
+                vec![m + 120, m + 180],
+                vec![m + 5 * 3600, m + 5 * 3600 + 60]
+            ],
+        ]
+    );
+    assert_eq!(
+        split(
+            &segments,
+            &JourneySplitOptions {
+                split_at_local_date: true,
+                max_gap_mins: Some(60),
+            },
+            &utc_8
+        ),
+        vec![
+            vec![vec![m - 1800, m - 60]],
+            vec![vec![m + 60], vec![m + 120, m + 180]],
+            vec![vec![m + 5 * 3600, m + 5 * 3600 + 60]],
+        ]
+    );
+    // the same data is within a single day in UTC
+    let utc = FixedOffset::east_opt(0).unwrap();
+    assert_eq!(
+        split(
+            &segments,
+            &JourneySplitOptions {
+                split_at_local_date: true,
+                max_gap_mins: None,
+            },
+            &utc
+        ),
+        vec![segments.clone()]
+    );
+
+    assert!(split(&[], &JourneySplitOptions::default(), &utc).is_empty());
+}
+
+#[test]
+fn validate_and_persist() {
+    let options = JourneySplitOptions {
+        split_at_local_date: true,
+        max_gap_mins: Some(120),
+    };
+    options.validate().unwrap();
+    assert_eq!(
+        options.to_string().parse::<JourneySplitOptions>().unwrap(),
+        options
+    );
+    assert!(JourneySplitOptions {
+        split_at_local_date: false,
+        max_gap_mins: Some(0),
+    }
+    .validate()
+    .is_err());
+}
@@ -2,11 +2,13 @@ pub mod test_utils;
 
 use chrono::{DateTime, Datelike, NaiveDate};
 use memolanes_core::{
+    flight_track_processor,
     gps_processor::{self, Point, RawData},
     import_data,
     journey_data::JourneyData,
     journey_header::JourneyKind,
-    journey_vector::JourneyVector,
+    journey_splitter::JourneySplitOptions,
+    journey_vector::{JourneyVector, TrackPoint, TrackSegment},
     main_db::{self, Action, CacheEntry, MainDb},
 };
 use tempdir::TempDir;
@@ -51,8 +53,21 @@ fn basic() {
         JourneyData::Vector(vector) => vector,
         JourneyData::Bitmap(_) => panic!("invalid"),
     };
+    // all data are recorded as a single segment and then postprocessed
+    let expected_journey_vector = gps_processor::GpsPostprocessor::process(JourneyVector {
+        track_segments: vec![TrackSegment {
+            track_points: test_data
+                .iter()
+                .map(|x| TrackPoint {
+                    latitude: x.point.latitude,
+                    longitude: x.point.longitude,
+                })
+                .collect(),
+        }],
+    });
+    assert_eq!(journey_vector, &expected_journey_vector);
     let num_of_gpx_data = journey_vector.track_segments[0].track_points.len();
-    assert_eq!(num_of_gpx_data_in_input, num_of_gpx_data);
+    assert!(num_of_gpx_data < num_of_gpx_data_in_input);
 
     // benefit from zstd
     let mut rough_raw_size: usize = 0;
@@ -109,6 +124,60 @@ fn setting() {
     assert!(main_db.get_setting_with_default(main_db::Setting::RawDataMode, false));
 }
 
+#[test]
+fn record_while_paused() {
+    let temp_dir = TempDir::new("main_db-record_while_paused").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+    let record = |main_db: &mut MainDb, timestamp_ms| {
+        main_db
+            .record(
+                &RawData::new(
+                    Point {
+                        latitude: 31.2304,
+                        longitude: 121.4737,
+                    },
+                    Some(timestamp_ms),
+                ),
+                gps_processor::ProcessResult::Append,
+            )
+            .unwrap();
+    };
+    let timestamp_range = |main_db: &mut MainDb| {
+        main_db
+            .with_txn(|txn| txn.get_ongoing_journey_timestamp_range())
+            .unwrap()
+            .map(|(start, end)| (start.timestamp(), end.timestamp()))
+    };
+
+    record(&mut main_db, 1697349115000);
+    main_db.with_txn(|txn| txn.pause_recording()).unwrap();
+    record(&mut main_db, 1697349116000);
+    assert_eq!(
+        timestamp_range(&mut main_db),
+        Some((1697349115, 1697349115))
+    );
+
+    // finalizing, e.g. automatically, doesn't resume
+    main_db
+        .with_txn(|txn| txn.finalize_ongoing_journey())
+        .unwrap();
+    assert!(
+        main_db
+            .with_txn(|txn| txn.get_recorder_state())
+            .unwrap()
+            .paused
+    );
+    record(&mut main_db, 1697349116500);
+    assert_eq!(timestamp_range(&mut main_db), None);
+
+    main_db.with_txn(|txn| txn.resume_recording()).unwrap();
+    record(&mut main_db, 1697349117000);
+    assert_eq!(
+        timestamp_range(&mut main_db),
+        Some((1697349117, 1697349117))
+    );
+}
+
 #[test]
 fn get_ongoing_journey_timestamp_range() {
     let temp_dir = TempDir::new("main_db-get_lastest_timestamp_of_ongoing_journey").unwrap();
@@ -121,46 +190,37 @@ fn get_ongoing_journey_timestamp_range() {
     assert_eq!(result, None);
     main_db
         .record(
-            &RawData {
-                point: Point {
+            &RawData::new(
+                Point {
                     latitude: 120.163856,
                     longitude: 30.2719716,
                 },
-                timestamp_ms: Some(1697349115000),
-                accuracy: None,
-                altitude: None,
-                speed: None,
-            },
+                Some(1697349115000),
+            ),
             gps_processor::ProcessResult::Append,
         )
         .unwrap();
     main_db
         .record(
-            &RawData {
-                point: Point {
+            &RawData::new(
+                Point {
                     latitude: 120.163856,
                     longitude: 30.2719716,
                 },
-                timestamp_ms: Some(1697349116000),
-                accuracy: None,
-                altitude: None,
-                speed: None,
-            },
+                Some(1697349116000),
+            ),
             gps_processor::ProcessResult::Append,
         )
         .unwrap();
     main_db
         .record(
-            &RawData {
-                point: Point {
+            &RawData::new(
+                Point {
                     latitude: 120.163856,
                     longitude: 30.2719716,
                 },
-                timestamp_ms: Some(1697349117000),
-                accuracy: None,
-                altitude: None,
-                speed: None,
-            },
+                Some(1697349117000),
+            ),
             gps_processor::ProcessResult::Append,
         )
         .unwrap();
@@ -282,6 +342,71 @@ fn journey_query() {
         .is_empty(),);
 }
 
+#[test]
+fn query_journey_ids_in_time_range() {
+    let temp_dir = TempDir::new("main_db-query_journey_ids_in_time_range").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let time = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+    let add_journey = |txn: &mut main_db::Txn, start: Option<&str>, end: Option<&str>| {
+        txn.create_and_insert_journey(
+            NaiveDate::from_ymd_opt(2024, 8, 1).unwrap(),
+            start.map(time),
+            end.map(time),
+            None,
+            JourneyKind::DefaultKind,
+            None,
+            JourneyData::Vector(JourneyVector {
+                track_segments: vec![],
+            }),
+        )
+        .unwrap()
+    };
+
+    let (morning, afternoon, no_end) = main_db
+        .with_txn(|txn| {
+            // journeys without any time are never included
+            add_journey(txn, None, None);
+            Ok((
+                add_journey(
+                    txn,
+                    Some("2024-08-01T08:00:00Z"),
+                    Some("2024-08-01T10:00:00Z"),
+                ),
+                add_journey(
+                    txn,
+                    Some("2024-08-01T14:00:00Z"),
+                    Some("2024-08-01T16:00:00Z"),
+                ),
+                add_journey(txn, Some("2024-08-01T12:00:00Z"), None),
+            ))
+        })
+        .unwrap();
+
+    let query = |main_db: &mut MainDb, start, end| {
+        let mut ids = main_db
+            .with_txn(|txn| txn.query_journey_ids_in_time_range(time(start), time(end)))
+            .unwrap();
+        ids.sort();
+        ids
+    };
+    let sorted = |mut ids: Vec<String>| {
+        ids.sort();
+        ids
+    };
+
+    assert_eq!(
+        query(&mut main_db, "2024-08-01T09:00:00Z", "2024-08-01T09:30:00Z"),
+        vec![morning.clone()]
+    );
+    assert_eq!(
+        query(&mut main_db, "2024-08-01T09:00:00Z", "2024-08-01T15:00:00Z"),
+        sorted(vec![morning, afternoon, no_end])
+    );
+    assert!(query(&mut main_db, "2024-08-01T17:00:00Z", "2024-08-01T18:00:00Z").is_empty());
+    assert!(query(&mut main_db, "2024-08-03T00:00:00Z", "2024-08-03T18:00:00Z").is_empty());
+}
+
 // === Action generation and set_invalidate_action tests ===
 
 fn date(s: &str) -> NaiveDate {
@@ -1248,31 +1373,25 @@ fn finalize_ongoing_sets_merge_one() {
     // Record GPS data to create an ongoing journey
     main_db
         .record(
-            &gps_processor::RawData {
-                point: Point {
+            &gps_processor::RawData::new(
+                Point {
                     latitude: 30.27,
                     longitude: 120.16,
                 },
-                timestamp_ms: Some(1697349115000),
-                accuracy: None,
-                altitude: None,
-                speed: None,
-            },
+                Some(1697349115000),
+            ),
             gps_processor::ProcessResult::Append,
         )
         .unwrap();
     main_db
         .record(
-            &gps_processor::RawData {
-                point: Point {
+            &gps_processor::RawData::new(
+                Point {
                     latitude: 30.28,
                     longitude: 120.17,
                 },
-                timestamp_ms: Some(1697349116000),
-                accuracy: None,
-                altitude: None,
-                speed: None,
-            },
+                Some(1697349116000),
+            ),
             gps_processor::ProcessResult::Append,
         )
         .unwrap();
@@ -1334,3 +1453,235 @@ fn insert_same_date_different_kind_not_deduplicated() {
         other => panic!("Expected Invalidate with 2 entries, got {:?}", other),
     }
 }
+
+#[test]
+fn place_visits() {
+    let temp_dir = TempDir::new("main_db-place_visits").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let record = |main_db: &mut MainDb, timestamp_sec: i64, latitude: f64| {
+        main_db
+            .record(
+                &RawData::new(
+                    Point {
+                        latitude,
+                        longitude: 121.4737,
+                    },
+                    Some(timestamp_sec * 1000),
+                ),
+                gps_processor::ProcessResult::Append,
+            )
+            .unwrap();
+    };
+    // leaving home, then staying at the office for an hour
+    let record_journey = |main_db: &mut MainDb, start_sec: i64, office_latitude: f64| {
+        record(main_db, start_sec, 31.2004);
+        record(main_db, start_sec + 600, 31.2154);
+        record(main_db, start_sec + 1200, office_latitude);
+        record(main_db, start_sec + 4800, office_latitude);
+        record(main_db, start_sec + 5400, 31.2454);
+        main_db
+            .with_txn(|txn| txn.finalize_ongoing_journey())
+            .unwrap()
+    };
+
+    assert!(record_journey(&mut main_db, 1697349115, 31.2304));
+    // a bit off from the first visit, but still the same place
+    assert!(record_journey(&mut main_db, 1697435515, 31.2306));
+
+    let time = |timestamp_sec| DateTime::from_timestamp(timestamp_sec, 0).unwrap();
+    let visits = main_db
+        .with_txn(|txn| txn.query_place_visits(time(1697349115), time(1697349115 + 86400)))
+        .unwrap();
+    assert_eq!(visits.len(), 1);
+    assert_eq!(visits[0].arrival, time(1697349115 + 1200));
+    assert_eq!(visits[0].departure, time(1697349115 + 4800));
+
+    let places = main_db.with_txn(|txn| txn.list_places()).unwrap();
+    assert_eq!(places.len(), 1);
+    assert_eq!(places[0].visit_count, 2);
+    assert_eq!(places[0].total_duration_sec, 3600 * 2);
+    assert_eq!(places[0].last_departure, time(1697435515 + 4800));
+    assert!((places[0].latitude - 31.2305).abs() < 1e-6);
+
+    // visits are deleted together with the journey
+    let journey_ids: Vec<String> = main_db
+        .with_txn(|txn| txn.query_journeys(None, None))
+        .unwrap()
+        .into_iter()
+        .map(|x| x.id)
+        .collect();
+    assert_eq!(journey_ids.len(), 2);
+    main_db
+        .with_txn(|txn| txn.delete_journey(&journey_ids[0]))
+        .unwrap();
+    let places = main_db.with_txn(|txn| txn.list_places()).unwrap();
+    assert_eq!(places.len(), 1);
+    assert_eq!(places[0].visit_count, 1);
+    main_db
+        .with_txn(|txn| txn.delete_journey(&journey_ids[1]))
+        .unwrap();
+    assert!(main_db
+        .with_txn(|txn| txn.list_places())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn finalize_splits_flights() {
+    let temp_dir = TempDir::new("main_db-finalize_splits_flights").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let record = |main_db: &mut MainDb,
+                  timestamp_sec: i64,
+                  latitude: f64,
+                  longitude: f64,
+                  process_result: gps_processor::ProcessResult| {
+        main_db
+            .record(
+                &RawData::new(
+                    Point {
+                        latitude,
+                        longitude,
+                    },
+                    Some(timestamp_sec * 1000),
+                ),
+                process_result,
+            )
+            .unwrap();
+    };
+    // driving to the airport in Shanghai, then flying to Beijing with the
+    // phone off, and driving in Beijing
+    let start_sec = 1697349115;
+    for i in 0..10 {
+        record(
+            &mut main_db,
+            start_sec + i * 60,
+            31.15 + i as f64 * 0.005,
+            121.80,
+            gps_processor::ProcessResult::Append,
+        );
+    }
+    for i in 0..10 {
+        record(
+            &mut main_db,
+            start_sec + 3 * 3600 + i * 60,
+            40.08 - i as f64 * 0.005,
+            116.58,
+            if i == 0 {
+                gps_processor::ProcessResult::NewSegment
+            } else {
+                gps_processor::ProcessResult::Append
+            },
+        );
+    }
+    assert!(main_db
+        .with_txn(|txn| txn.finalize_ongoing_journey())
+        .unwrap());
+
+    let mut headers = main_db
+        .with_txn(|txn| txn.query_journeys(None, None))
+        .unwrap();
+    headers.sort_by_key(|x| x.start);
+    assert_eq!(
+        headers.iter().map(|x| x.journey_kind).collect::<Vec<_>>(),
+        vec![
+            JourneyKind::DefaultKind,
+            JourneyKind::Flight,
+            JourneyKind::DefaultKind
+        ]
+    );
+    let time = |timestamp_sec| DateTime::from_timestamp(timestamp_sec, 0);
+    assert_eq!(headers[1].start, time(start_sec + 9 * 60));
+    assert_eq!(headers[1].end, time(start_sec + 3 * 3600));
+
+    // the flight follows the great circle instead of a single straight line
+    let great_circle = flight_track_processor::great_circle_points(
+        &Point {
+            latitude: 31.195,
+            longitude: 121.80,
+        },
+        &Point {
+            latitude: 40.08,
+            longitude: 116.58,
+        },
+        1000.0,
+    );
+    let flight = main_db
+        .with_txn(|txn| txn.get_journey_data(&headers[1].id))
+        .unwrap();
+    match flight {
+        JourneyData::Vector(journey_vector) => {
+            let track_points: Vec<&TrackPoint> = journey_vector
+                .track_segments
+                .iter()
+                .flat_map(|x| &x.track_points)
+                .collect();
+            assert!(track_points.len() > 2);
+            for x in track_points {
+                let point = Point {
+                    latitude: x.latitude,
+                    longitude: x.longitude,
+                };
+                assert!(great_circle
+                    .iter()
+                    .any(|y| y.haversine_distance(&point) < 1000.0));
+            }
+        }
+        JourneyData::Bitmap(_) => panic!("Unexpected bitmap data"),
+    }
+}
+
+#[test]
+fn finalize_with_split() {
+    let temp_dir = TempDir::new("main_db-finalize_with_split").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    // two drives with a 3-hour break in between
+    let start_sec = 1697349115;
+    for (i, offset_sec) in [0, 60, 120, 3 * 3600, 3 * 3600 + 60, 3 * 3600 + 120]
+        .iter()
+        .enumerate()
+    {
+        main_db
+            .record(
+                &RawData::new(
+                    Point {
+                        latitude: 31.15 + i as f64 * 0.005,
+                        longitude: 121.80,
+                    },
+                    Some((start_sec + offset_sec) * 1000),
+                ),
+                if i == 0 {
+                    gps_processor::ProcessResult::NewSegment
+                } else {
+                    gps_processor::ProcessResult::Append
+                },
+            )
+            .unwrap();
+    }
+    let split_options = JourneySplitOptions {
+        split_at_local_date: false,
+        max_gap_mins: Some(60),
+    };
+    assert!(main_db
+        .with_txn(|txn| txn.finalize_ongoing_journey_with_split(&split_options))
+        .unwrap());
+
+    let mut headers = main_db
+        .with_txn(|txn| txn.query_journeys(None, None))
+        .unwrap();
+    headers.sort_by_key(|x| x.start);
+    let time = |timestamp_sec| DateTime::from_timestamp(timestamp_sec, 0);
+    assert_eq!(
+        headers.iter().map(|x| (x.start, x.end)).collect::<Vec<_>>(),
+        vec![
+            (time(start_sec), time(start_sec + 120)),
+            (time(start_sec + 3 * 3600), time(start_sec + 3 * 3600 + 120)),
+        ]
+    );
+    assert!(main_db
+        .with_txn(|txn| txn.get_ongoing_journey_timestamp_range())
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,103 @@
+use memolanes_core::{gps_processor::Point, places};
+
+fn point(latitude: f64, longitude: f64) -> Point {
+    Point {
+        latitude,
+        longitude,
+    }
+}
+
+// ~11m per step
+fn walk(start_sec: i64, from: &Point, steps: usize) -> Vec<(i64, Point)> {
+    (0..steps)
+        .map(|i| {
+            (
+                start_sec + i as i64 * 10,
+                point(from.latitude + i as f64 * 0.0001, from.longitude),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn sparse_stay() {
+    // the preprocessor ignores data when stationary, so there are only the
+    // points before and after the stay
+    let home = point(31.2304, 121.4737);
+    let mut points = walk(0, &point(31.2104, 121.4737), 100);
+    points.push((1000, home.clone()));
+    points.push((4600, point(31.23041, 121.4737)));
+    points.extend(walk(4610, &point(31.2404, 121.4737), 100));
+
+    let stay_points = places::detect_stay_points(&points);
+    assert_eq!(stay_points.len(), 1);
+    assert_eq!(stay_points[0].arrival.timestamp(), 1000);
+    assert_eq!(stay_points[0].departure.timestamp(), 4600);
+    assert!(stay_points[0].point.haversine_distance(&home) < 10.0);
+}
+
+#[test]
+fn dense_stay() {
+    let office = point(22.5431, 114.0579);
+    let mut points = walk(0, &point(22.5231, 114.0579), 100);
+    // drifting around for an hour
+    for i in 0..360 {
+        let offset = if i % 2 == 0 { 0.0002 } else { -0.0002 };
+        points.push((
+            1000 + i * 10,
+            point(office.latitude + offset, office.longitude - offset),
+        ));
+    }
+    points.extend(walk(5000, &point(22.5531, 114.0579), 100));
+
+    let stay_points = places::detect_stay_points(&points);
+    assert_eq!(stay_points.len(), 1);
+    assert_eq!(stay_points[0].arrival.timestamp(), 1000);
+    assert_eq!(stay_points[0].departure.timestamp(), 1000 + 359 * 10);
+    assert!(stay_points[0].point.haversine_distance(&office) < 10.0);
+}
+
+#[test]
+fn short_stops_and_split_stays() {
+    let shop = point(31.2304, 121.4737);
+    let far_away = point(31.2404, 121.4737);
+
+    // waiting at a traffic light is not a stay
+    let points = vec![
+        (0, shop.clone()),
+        (120, shop.clone()),
+        (130, far_away.clone()),
+    ];
+    assert!(places::detect_stay_points(&points).is_empty());
+
+    // a single bad point in the middle of a stay
+    let points = vec![
+        (0, shop.clone()),
+        (1800, shop.clone()),
+        (1810, far_away.clone()),
+        (1820, shop.clone()),
+        (3600, shop.clone()),
+    ];
+    let stay_points = places::detect_stay_points(&points);
+    assert_eq!(stay_points.len(), 1);
+    assert_eq!(stay_points[0].arrival.timestamp(), 0);
+    assert_eq!(stay_points[0].departure.timestamp(), 3600);
+
+    // coming back after a while is a new stay
+    let points = vec![
+        (0, shop.clone()),
+        (1800, shop.clone()),
+        (1810, far_away.clone()),
+        (5400, far_away.clone()),
+        (5410, shop.clone()),
+        (7200, shop.clone()),
+    ];
+    let stay_points = places::detect_stay_points(&points);
+    assert_eq!(
+        stay_points
+            .iter()
+            .map(|x| (x.arrival.timestamp(), x.departure.timestamp()))
+            .collect::<Vec<_>>(),
+        vec![(0, 1800), (1810, 5400), (5410, 7200)]
+    );
+}
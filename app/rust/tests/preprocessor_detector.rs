@@ -0,0 +1,96 @@
+use memolanes_core::api::import::ImportPreprocessor;
+use memolanes_core::gps_processor::{Point, RawData};
+use memolanes_core::import_data;
+use memolanes_core::preprocessor_detector::{recommend_preprocessor, PreprocessorReason};
+
+// Points going north with a fixed step, roughly `step_m` apart.
+fn make_track(
+    count: usize,
+    step_m: f64,
+    interval_sec: Option<i64>,
+    altitude: Option<f32>,
+) -> Vec<Vec<RawData>> {
+    let step_in_degree = step_m / 111_195.;
+    vec![(0..count)
+        .map(|i| RawData {
+            altitude,
+            ..RawData::new(
+                Point {
+                    latitude: 30. + i as f64 * step_in_degree,
+                    longitude: 120.,
+                },
+                interval_sec.map(|x| 1700000000000 + i as i64 * x * 1000),
+            )
+        })
+        .collect()]
+}
+
+#[test]
+fn dense_track() {
+    let recommendation = recommend_preprocessor(&make_track(100, 2., Some(1), None));
+    assert_eq!(recommendation.preprocessor, ImportPreprocessor::Generic);
+    assert!(recommendation.confidence > 0.9);
+}
+
+#[test]
+fn sparse_track() {
+    let recommendation = recommend_preprocessor(&make_track(100, 100., Some(30), None));
+    assert_eq!(recommendation.preprocessor, ImportPreprocessor::Spare);
+    assert!(recommendation.confidence > 0.9);
+    assert!(matches!(
+        recommendation.reasons.last(),
+        Some(PreprocessorReason::Sparse { default_break_ratio }) if *default_break_ratio > 0.9
+    ));
+}
+
+#[test]
+fn flight_by_speed() {
+    let recommendation = recommend_preprocessor(&make_track(100, 2500., Some(10), None));
+    assert_eq!(recommendation.preprocessor, ImportPreprocessor::FlightTrack);
+}
+
+#[test]
+fn flight_by_altitude() {
+    let recommendation = recommend_preprocessor(&make_track(10, 10000., None, Some(9000.)));
+    assert_eq!(recommendation.preprocessor, ImportPreprocessor::FlightTrack);
+    assert_eq!(
+        recommendation.reasons,
+        vec![PreprocessorReason::HighAltitude {
+            median_altitude_m: 9000.
+        }]
+    );
+}
+
+#[test]
+fn missing_timestamps() {
+    let recommendation = recommend_preprocessor(&make_track(100, 2., None, None));
+    assert_eq!(recommendation.preprocessor, ImportPreprocessor::Generic);
+    assert!(recommendation.confidence <= 0.5);
+    assert_eq!(
+        recommendation.reasons,
+        vec![PreprocessorReason::LowTimestampCoverage { coverage: 0. }]
+    );
+
+    let recommendation = recommend_preprocessor(&[]);
+    assert_eq!(recommendation.preprocessor, ImportPreprocessor::Generic);
+}
+
+#[test]
+fn real_data() {
+    for (name, expected) in [
+        ("raw_gps_laojunshan", ImportPreprocessor::Generic),
+        ("raw_gps_shanghai", ImportPreprocessor::Generic),
+        ("raw_gps_shenzhen_stationary", ImportPreprocessor::Generic),
+        ("StepOfMyWorld", ImportPreprocessor::Spare),
+        ("yourapp", ImportPreprocessor::Spare),
+    ] {
+        let (raw_data, preprocessor) =
+            import_data::load_gpx(&format!("./tests/data/{name}.gpx")).unwrap();
+        assert_eq!(preprocessor, expected, "{name}");
+        assert_eq!(
+            recommend_preprocessor(&raw_data).preprocessor,
+            expected,
+            "{name}"
+        );
+    }
+}
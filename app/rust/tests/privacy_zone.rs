@@ -0,0 +1,206 @@
+use memolanes_core::{
+    gps_processor::Point,
+    journey_bitmap::JourneyBitmap,
+    journey_vector::{JourneyVector, TrackPoint, TrackSegment},
+    main_db::MainDb,
+    privacy_zone::{self, PrivacyZone, PrivacyZoneShape},
+};
+use tempdir::TempDir;
+
+fn point(latitude: f64, longitude: f64) -> Point {
+    Point {
+        latitude,
+        longitude,
+    }
+}
+
+fn home() -> PrivacyZone {
+    PrivacyZone {
+        id: "home".to_string(),
+        name: "Home".to_string(),
+        shape: PrivacyZoneShape::Circle {
+            center: point(31.2, 121.4),
+            radius_m: 300.0,
+        },
+    }
+}
+
+fn work() -> PrivacyZone {
+    PrivacyZone {
+        id: "work".to_string(),
+        name: "Work".to_string(),
+        shape: PrivacyZoneShape::Polygon {
+            points: vec![
+                point(31.23, 121.47),
+                point(31.23, 121.48),
+                point(31.24, 121.48),
+                point(31.24, 121.47),
+            ],
+        },
+    }
+}
+
+fn count_bits(journey_bitmap: &JourneyBitmap) -> u32 {
+    journey_bitmap
+        .tiles
+        .values()
+        .flat_map(|tile| tile.iter().map(|(_, block)| block.count()))
+        .sum()
+}
+
+#[test]
+fn contains() {
+    let home = home().shape;
+    assert!(home.contains(&point(31.2, 121.4)));
+    assert!(home.contains(&point(31.202, 121.4)));
+    assert!(!home.contains(&point(31.204, 121.4)));
+
+    let work = work().shape;
+    assert!(work.contains(&point(31.235, 121.475)));
+    assert!(!work.contains(&point(31.235, 121.485)));
+    assert!(!work.contains(&point(31.245, 121.475)));
+
+    home.validate().unwrap();
+    work.validate().unwrap();
+    assert!(PrivacyZoneShape::Circle {
+        center: point(31.2, 121.4),
+        radius_m: 0.0,
+    }
+    .validate()
+    .is_err());
+    assert!(PrivacyZoneShape::Polygon {
+        points: vec![point(31.23, 121.47), point(31.23, 121.48)],
+    }
+    .validate()
+    .is_err());
+    // too large to be rasterized
+    assert!(PrivacyZoneShape::Polygon {
+        points: vec![point(31.0, 121.0), point(31.0, 122.0), point(32.0, 121.5)],
+    }
+    .validate()
+    .is_err());
+}
+
+#[test]
+fn trim_journey_vector() {
+    let track_points = |points: &[(f64, f64)]| -> Vec<TrackPoint> {
+        points
+            .iter()
+            .map(|(latitude, longitude)| TrackPoint {
+                latitude: *latitude,
+                longitude: *longitude,
+            })
+            .collect()
+    };
+    // leaving home, passing by work, then a segment at work
+    let journey_vector = JourneyVector {
+        track_segments: vec![
+            TrackSegment {
+                track_points: track_points(&[
+                    (31.2, 121.4),
+                    (31.201, 121.4),
+                    (31.21, 121.42),
+                    (31.235, 121.475),
+                    (31.25, 121.49),
+                ]),
+            },
+            TrackSegment {
+                track_points: track_points(&[(31.235, 121.475), (31.236, 121.476)]),
+            },
+        ],
+    };
+    assert_eq!(
+        privacy_zone::trim_journey_vector(&[home(), work()], &journey_vector),
+        JourneyVector {
+            track_segments: vec![
+                TrackSegment {
+                    track_points: track_points(&[(31.21, 121.42)]),
+                },
+                TrackSegment {
+                    track_points: track_points(&[(31.25, 121.49)]),
+                },
+            ],
+        }
+    );
+    assert_eq!(
+        privacy_zone::trim_journey_vector(&[], &journey_vector),
+        journey_vector
+    );
+}
+
+#[test]
+fn apply_to_journey_bitmap() {
+    let zones = [home(), work()];
+
+    // passing through home
+    let mut journey_bitmap = JourneyBitmap::new();
+    journey_bitmap.add_line(121.39, 31.2, 121.41, 31.2);
+    let before = count_bits(&journey_bitmap);
+    privacy_zone::apply_to_journey_bitmap(&zones, &mut journey_bitmap);
+    let after = count_bits(&journey_bitmap);
+    assert!(after < before);
+    assert!(after > before / 2);
+
+    // staying at home
+    let mut journey_bitmap = JourneyBitmap::new();
+    journey_bitmap.add_line(121.3995, 31.2, 121.4005, 31.2);
+    journey_bitmap.add_line(121.4, 31.199, 121.4, 31.201);
+    privacy_zone::apply_to_journey_bitmap(&zones, &mut journey_bitmap);
+    assert_eq!(journey_bitmap, JourneyBitmap::new());
+
+    // far away
+    let mut journey_bitmap = JourneyBitmap::new();
+    journey_bitmap.add_line(116.3, 39.9, 116.4, 39.9);
+    let expected = journey_bitmap.clone();
+    privacy_zone::apply_to_journey_bitmap(&zones, &mut journey_bitmap);
+    assert_eq!(journey_bitmap, expected);
+}
+
+#[test]
+fn storage() {
+    let temp_dir = TempDir::new("privacy_zone-storage").unwrap();
+    let mut main_db = MainDb::open(temp_dir.path().to_str().unwrap());
+
+    let home_id = main_db
+        .with_txn(|txn| txn.add_privacy_zone("Home", &home().shape))
+        .unwrap();
+    let work_id = main_db
+        .with_txn(|txn| txn.add_privacy_zone("Work", &work().shape))
+        .unwrap();
+    assert!(main_db
+        .with_txn(
+            |txn| txn.add_privacy_zone("Invalid", &PrivacyZoneShape::Polygon { points: vec![] })
+        )
+        .is_err());
+    assert_eq!(
+        main_db.with_txn(|txn| txn.list_privacy_zones()).unwrap(),
+        vec![
+            PrivacyZone {
+                id: home_id.clone(),
+                ..home()
+            },
+            PrivacyZone {
+                id: work_id.clone(),
+                ..work()
+            },
+        ]
+    );
+
+    main_db
+        .with_txn(|txn| txn.update_privacy_zone(&work_id, "Office", &home().shape))
+        .unwrap();
+    main_db
+        .with_txn(|txn| txn.delete_privacy_zone(&home_id))
+        .unwrap();
+    assert!(main_db
+        .with_txn(|txn| txn.delete_privacy_zone(&home_id))
+        .is_err());
+    assert_eq!(
+        main_db.with_txn(|txn| txn.list_privacy_zones()).unwrap(),
+        vec![PrivacyZone {
+            id: work_id,
+            name: "Office".to_string(),
+            shape: home().shape,
+        }]
+    );
+}
@@ -0,0 +1,319 @@
+use chrono::{DateTime, NaiveDate};
+use memolanes_core::{
+    raw_data_store::{self, RawDataRetention, RawDataStore},
+    storage::RawCsvRow,
+};
+use std::fs;
+use std::path::Path;
+use tempdir::TempDir;
+
+// The same as the recorder.
+fn write_rows(path: &Path, timestamps_ms: &[i64]) {
+    let mut writer = csv::Writer::from_path(path).unwrap();
+    for (i, timestamp_ms) in timestamps_ms.iter().enumerate() {
+        writer
+            .serialize(RawCsvRow {
+                timestamp_ms: Some(*timestamp_ms),
+                received_timestamp_ms: *timestamp_ms,
+                latitude: 31.0 + i as f64 * 0.001,
+                longitude: 121.0,
+                accuracy: Some(5.0),
+                altitude: None,
+                speed: None,
+                bearing: Some(90.0),
+                vertical_accuracy: None,
+                speed_accuracy: None,
+                provider: Some("fused".to_string()),
+                satellite_count: Some(12),
+                csv_version: Some(raw_data_store::CSV_VERSION),
+            })
+            .unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+fn date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+#[test]
+fn compress_and_query() {
+    let temp_dir = TempDir::new("raw_data_store-compress_and_query").unwrap();
+    let store = RawDataStore::new(temp_dir.path().to_str().unwrap());
+    assert!(store.list().unwrap().is_empty());
+    store
+        .compress_closed_files(None, date("2025-08-10"))
+        .unwrap();
+
+    let (path0, name0) = store.new_file(date("2025-08-09")).unwrap();
+    assert_eq!(name0, "gps-2025-08-09-0");
+    write_rows(&path0, &[1754726365000, 1754726366000, 1754726367000]);
+    let (path1, name1) = store.new_file(date("2025-08-09")).unwrap();
+    assert_eq!(name1, "gps-2025-08-09-1");
+    write_rows(&path1, &[1754726368000]);
+
+    // the current file is left alone
+    store
+        .compress_closed_files(Some(&name1), date("2025-08-09"))
+        .unwrap();
+    let entries = store.list().unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|x| (x.name.as_str(), x.compressed))
+            .collect::<Vec<_>>(),
+        vec![("gps-2025-08-09-1", false), ("gps-2025-08-09-0", true)]
+    );
+    assert!(!path0.exists());
+    assert_eq!(
+        raw_data_store::name_of_path(&entries[1].path).unwrap(),
+        name0
+    );
+    // the name is not reused after compression
+    let (_, name2) = store.new_file(date("2025-08-09")).unwrap();
+    assert_eq!(name2, "gps-2025-08-09-2");
+
+    let results = store
+        .query(
+            DateTime::from_timestamp_millis(1754726366000).unwrap(),
+            DateTime::from_timestamp_millis(1754726368000).unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        results.iter().map(|x| x.timestamp_ms).collect::<Vec<_>>(),
+        vec![
+            Some(1754726366000),
+            Some(1754726367000),
+            Some(1754726368000)
+        ]
+    );
+    assert_eq!(results[0].point.latitude, 31.001);
+    assert_eq!(results[0].bearing, Some(90.0));
+    assert_eq!(results[0].vertical_accuracy, None);
+    assert_eq!(results[0].provider.as_deref(), Some("fused"));
+    assert_eq!(results[0].satellite_count, Some(12));
+
+    let results = store
+        .query(
+            DateTime::from_timestamp_millis(1654726366000).unwrap(),
+            DateTime::from_timestamp_millis(1654726368000).unwrap(),
+        )
+        .unwrap();
+    assert!(results.is_empty());
+
+    store.delete(&name0).unwrap();
+    assert!(store.delete(&name0).is_err());
+    assert_eq!(store.list().unwrap().len(), 1);
+}
+
+#[test]
+fn interrupted_compression() {
+    let temp_dir = TempDir::new("raw_data_store-interrupted_compression").unwrap();
+    let store = RawDataStore::new(temp_dir.path().to_str().unwrap());
+
+    let (path, name) = store.new_file(date("2025-08-09")).unwrap();
+    write_rows(&path, &[1754726365000]);
+    store
+        .compress_closed_files(None, date("2025-08-10"))
+        .unwrap();
+    // pretend the app was killed before finishing
+    write_rows(&path, &[1754726365000]);
+    let temp_path = path.with_file_name(format!("{name}.csv.zst.tmp"));
+    fs::write(&temp_path, "partial").unwrap();
+
+    let entries = store.list().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].compressed);
+
+    store
+        .compress_closed_files(None, date("2025-08-10"))
+        .unwrap();
+    assert!(!path.exists());
+    assert!(!temp_path.exists());
+    assert_eq!(store.list().unwrap().len(), 1);
+}
+
+#[test]
+fn apply_retention() {
+    let temp_dir = TempDir::new("raw_data_store-apply_retention").unwrap();
+    let store = RawDataStore::new(temp_dir.path().to_str().unwrap());
+
+    let mut names = Vec::new();
+    for d in ["2025-08-01", "2025-08-05", "2025-08-09"] {
+        let (path, name) = store.new_file(date(d)).unwrap();
+        write_rows(&path, &[1754726365000]);
+        names.push(name);
+    }
+
+    let no_limit = RawDataRetention {
+        max_age_days: 0,
+        max_total_size_mb: 0,
+    };
+    assert!(store
+        .apply_retention(&no_limit, date("2025-08-10"), None)
+        .unwrap()
+        .is_empty());
+
+    let by_age = RawDataRetention {
+        max_age_days: 7,
+        max_total_size_mb: 0,
+    };
+    // the current file is kept even if it is too old
+    assert_eq!(
+        store
+            .apply_retention(&by_age, date("2025-08-20"), Some(&names[2]))
+            .unwrap(),
+        vec![names[0].clone(), names[1].clone()]
+    );
+    assert_eq!(store.list().unwrap().len(), 1);
+
+    // files are way below 1MB
+    let by_size = RawDataRetention {
+        max_age_days: 0,
+        max_total_size_mb: 1,
+    };
+    assert!(store
+        .apply_retention(&by_size, date("2025-08-20"), None)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn files_started_during_maintenance() {
+    let temp_dir = TempDir::new("raw_data_store-files_started_during_maintenance").unwrap();
+    let store = RawDataStore::new(temp_dir.path().to_str().unwrap());
+
+    let mut names = Vec::new();
+    for _ in 0..12 {
+        let (path, name) = store.new_file(date("2025-08-09")).unwrap();
+        write_rows(&path, &[1754726365000]);
+        names.push(name);
+    }
+    let (path, tomorrow) = store.new_file(date("2025-08-10")).unwrap();
+    write_rows(&path, &[1754812765000]);
+
+    // ordered by the index instead of the name
+    assert_eq!(
+        store
+            .list()
+            .unwrap()
+            .iter()
+            .map(|x| x.name.as_str())
+            .take(4)
+            .collect::<Vec<_>>(),
+        vec![
+            "gps-2025-08-10-0",
+            "gps-2025-08-09-11",
+            "gps-2025-08-09-10",
+            "gps-2025-08-09-9"
+        ]
+    );
+
+    // files newer than the current one could be started after it was read
+    let uncompressed = |store: &RawDataStore| {
+        store
+            .list()
+            .unwrap()
+            .into_iter()
+            .filter(|x| !x.compressed)
+            .map(|x| x.name)
+            .collect::<Vec<_>>()
+    };
+    store
+        .compress_closed_files(Some(&names[10]), date("2025-08-09"))
+        .unwrap();
+    assert_eq!(
+        uncompressed(&store),
+        vec![tomorrow.clone(), names[11].clone(), names[10].clone()]
+    );
+
+    // without a current file, the ones of today could be started any time
+    store
+        .compress_closed_files(None, date("2025-08-10"))
+        .unwrap();
+    assert_eq!(uncompressed(&store), vec![tomorrow.clone()]);
+
+    let by_age = RawDataRetention {
+        max_age_days: 1,
+        max_total_size_mb: 0,
+    };
+    assert_eq!(
+        store
+            .apply_retention(&by_age, date("2025-08-20"), Some(&names[11]))
+            .unwrap()
+            .len(),
+        11
+    );
+    assert_eq!(store.list().unwrap().len(), 2);
+}
+
+#[test]
+fn csv_version() {
+    let temp_dir = TempDir::new("raw_data_store-csv_version").unwrap();
+    let store = RawDataStore::new(temp_dir.path().to_str().unwrap());
+    let query = |store: &RawDataStore| {
+        store.query(
+            DateTime::from_timestamp_millis(1754726365000).unwrap(),
+            DateTime::from_timestamp_millis(1754726366000).unwrap(),
+        )
+    };
+
+    // version 1 doesn't have the version column
+    let (path, name) = store.new_file(date("2025-08-09")).unwrap();
+    fs::write(
+        &path,
+        "timestamp_ms,received_timestamp_ms,latitude,longitude,accuracy,altitude,speed\n\
+         1754726365000,1754726365000,31.0,121.0,5.0,,\n",
+    )
+    .unwrap();
+    let (path, _) = store.new_file(date("2025-08-09")).unwrap();
+    write_rows(&path, &[1754726366000]);
+    assert_eq!(query(&store).unwrap().len(), 2);
+
+    // files from a newer version of the app
+    store.delete(&name).unwrap();
+    let content = fs::read_to_string(&path).unwrap();
+    // plain CSV, the version is the last column
+    assert!(content.starts_with("timestamp_ms,"));
+    assert!(content
+        .lines()
+        .next()
+        .unwrap()
+        .ends_with(",satellite_count,csv_version"));
+    let version_suffix = format!(",{}\n", raw_data_store::CSV_VERSION);
+    assert!(content.ends_with(&version_suffix));
+    fs::write(
+        &path,
+        content.replace(
+            &version_suffix,
+            &format!(",{}\n", raw_data_store::CSV_VERSION + 1),
+        ),
+    )
+    .unwrap();
+    assert!(query(&store).is_err());
+}
+
+#[test]
+fn partial_last_row() {
+    let temp_dir = TempDir::new("raw_data_store-partial_last_row").unwrap();
+    let store = RawDataStore::new(temp_dir.path().to_str().unwrap());
+    let query = |store: &RawDataStore| {
+        store.query(
+            DateTime::from_timestamp_millis(1754726365000).unwrap(),
+            DateTime::from_timestamp_millis(1754726367000).unwrap(),
+        )
+    };
+
+    let (path, _) = store.new_file(date("2025-08-09")).unwrap();
+    write_rows(&path, &[1754726365000, 1754726366000]);
+    let content = fs::read_to_string(&path).unwrap();
+    // the last row is cut in the middle, e.g. after a crash
+    fs::write(&path, &content[..content.len() - 30]).unwrap();
+    assert_eq!(query(&store).unwrap().len(), 1);
+
+    // a broken row in the middle is still an error
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines[1] = "1754726365000,broken";
+    fs::write(&path, lines.join("\n") + "\n").unwrap();
+    assert!(query(&store).is_err());
+}
@@ -0,0 +1,95 @@
+use memolanes_core::{api::api, gps_processor::RawData, import_data};
+use std::fs;
+use tempdir::TempDir;
+
+#[test]
+fn recording_controls() {
+    let temp_dir = TempDir::new("recorder-recording_controls").unwrap();
+    let sub_folder = |sub| {
+        let path = temp_dir.path().join(sub);
+        fs::create_dir(&path).unwrap();
+        path.into_os_string().into_string().unwrap()
+    };
+    api::init(
+        sub_folder("temp/"),
+        sub_folder("doc/"),
+        sub_folder("support/"),
+        sub_folder("cache/"),
+        None,
+    )
+    .unwrap();
+
+    let (raw_data, _preprocessor) =
+        import_data::load_gpx("./tests/data/raw_gps_shanghai.gpx").unwrap();
+    let raw_data_list: Vec<RawData> = raw_data.into_iter().flatten().collect();
+    let chunks = &mut raw_data_list.chunks(500);
+    let mut record = || {
+        chunks
+            .next()
+            .unwrap()
+            .iter()
+            .filter(|x| api::on_location_update((*x).clone(), x.timestamp_ms.unwrap()))
+            .count()
+    };
+
+    // paused
+    assert_eq!(api::get_recorder_state().unwrap(), Default::default());
+    api::pause_recording().unwrap();
+    assert!(api::get_recorder_state().unwrap().paused);
+    assert_eq!(record(), 0);
+    assert!(!api::has_ongoing_journey().unwrap());
+    api::resume_recording().unwrap();
+    assert!(record() > 0);
+    assert!(api::has_ongoing_journey().unwrap());
+
+    // discarded
+    assert!(api::discard_ongoing_journey().unwrap());
+    assert!(!api::has_ongoing_journey().unwrap());
+    assert!(!api::discard_ongoing_journey().unwrap());
+
+    // the data before a manual journey becomes its own journey
+    assert!(record() > 0);
+    assert!(api::start_manual_journey("Hiking".to_string()).unwrap());
+    assert_eq!(
+        api::get_recorder_state().unwrap().manual_journey_title,
+        Some("Hiking".to_string())
+    );
+    assert!(record() > 0);
+    // the data is old enough to be auto finalized otherwise
+    assert!(!api::try_auto_finalize_journey().unwrap());
+    assert!(api::has_ongoing_journey().unwrap());
+    assert!(api::finalize_ongoing_journey().unwrap());
+    assert_eq!(api::get_recorder_state().unwrap(), Default::default());
+
+    let journeys = api::list_all_journeys().unwrap();
+    assert_eq!(journeys.len(), 2);
+    assert_eq!(
+        journeys
+            .iter()
+            .filter(|x| x.note.as_deref() == Some("Hiking"))
+            .count(),
+        1
+    );
+    assert!(api::start_manual_journey(" ".to_string()).is_err());
+
+    // a batch out of order and with duplicates is the same as the data in order
+    let ongoing_journey = || {
+        api::for_testing::get_storage()
+            .with_db_txn(|txn| txn.get_ongoing_journey(None))
+            .unwrap()
+            .unwrap()
+    };
+    let chunk = chunks.next().unwrap();
+    for raw_data in chunk {
+        api::on_location_update(raw_data.clone(), raw_data.timestamp_ms.unwrap());
+    }
+    let expected = ongoing_journey();
+    assert!(api::discard_ongoing_journey().unwrap());
+    let mut batch = Vec::new();
+    for x in chunk.chunks(5) {
+        batch.extend(x.iter().rev().cloned());
+        batch.push(x[0].clone());
+    }
+    assert!(api::on_location_updates(batch, 0));
+    assert_eq!(ongoing_journey(), expected);
+}
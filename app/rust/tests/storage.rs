@@ -2,8 +2,13 @@ pub mod test_utils;
 use crate::test_utils::{draw_line1, draw_line2, draw_line3};
 use chrono::NaiveDate;
 use memolanes_core::{
-    cache_db::LayerKind, gps_processor::ProcessResult, import_data, journey_bitmap::JourneyBitmap,
-    journey_data::JourneyData, journey_header::JourneyKind, storage::Storage,
+    cache_db::LayerKind,
+    gps_processor::{GpsFilter, PreprocessingProfile, PreprocessingThresholds, ProcessResult},
+    import_data,
+    journey_bitmap::JourneyBitmap,
+    journey_data::JourneyData,
+    journey_header::JourneyKind,
+    storage::Storage,
 };
 use std::fs;
 use tempdir::TempDir;
@@ -24,12 +29,14 @@ fn storage_for_main_map_renderer() {
         sub_folder("doc/"),
         sub_folder("support/"),
         sub_folder("cache/"),
-    );
+    )
+    .unwrap();
 
     let (raw_data_groups, _preprocessor) =
         import_data::load_gpx("./tests/data/raw_gps_shanghai.gpx").unwrap();
     for (i, raw_data) in raw_data_groups.iter().flatten().enumerate() {
         storage.record_gps_data(
+            raw_data,
             raw_data,
             ProcessResult::Append,
             raw_data.timestamp_ms.unwrap(),
@@ -71,7 +78,8 @@ where
         sub_folder("doc/"),
         sub_folder("support/"),
         sub_folder("cache/"),
-    );
+    )
+    .unwrap();
     f(storage);
 }
 
@@ -835,3 +843,92 @@ fn update_metadata_same_month_cache_still_correct() {
         assert_eq!(result, bitmap);
     });
 }
+
+#[test]
+fn preprocessing_settings() {
+    let temp_dir = TempDir::new("storage-preprocessing_settings").unwrap();
+    let sub_folder = |sub| {
+        let path = temp_dir.path().join(sub);
+        if !path.exists() {
+            fs::create_dir(&path).unwrap();
+        }
+        path.into_os_string().into_string().unwrap()
+    };
+    let init_storage = || {
+        Storage::init(
+            sub_folder("temp/"),
+            sub_folder("doc/"),
+            sub_folder("support/"),
+            sub_folder("cache/"),
+        )
+        .unwrap()
+    };
+
+    let storage = init_storage();
+    assert_eq!(storage.get_gps_filter(), GpsFilter::None);
+    assert_eq!(
+        storage.get_preprocessing_profile(),
+        PreprocessingProfile::Default
+    );
+    // falls back to the default one
+    assert_eq!(
+        storage.get_preprocessing_thresholds(PreprocessingProfile::Custom),
+        PreprocessingProfile::Default.builtin_thresholds().unwrap()
+    );
+
+    let custom_thresholds = PreprocessingThresholds {
+        max_accuracy_m: 100.,
+        ..PreprocessingProfile::Driving.builtin_thresholds().unwrap()
+    };
+    storage.set_gps_filter(GpsFilter::Kalman).unwrap();
+    storage
+        .set_preprocessing_profile(PreprocessingProfile::Custom)
+        .unwrap();
+    storage
+        .set_custom_preprocessing_thresholds(&custom_thresholds)
+        .unwrap();
+    let mut invalid_thresholds = custom_thresholds.clone();
+    invalid_thresholds.segment_gap_thresholds.reverse();
+    assert!(storage
+        .set_custom_preprocessing_thresholds(&invalid_thresholds)
+        .is_err());
+    drop(storage);
+
+    // restart
+    let storage = init_storage();
+    assert_eq!(storage.get_gps_filter(), GpsFilter::Kalman);
+    assert_eq!(
+        storage.get_preprocessing_profile(),
+        PreprocessingProfile::Custom
+    );
+    assert_eq!(
+        storage.get_preprocessing_thresholds(PreprocessingProfile::Custom),
+        custom_thresholds
+    );
+}
+
+#[test]
+fn init_with_wrong_db_key() {
+    let temp_dir = TempDir::new("storage-init_with_wrong_db_key").unwrap();
+    let sub_folder = |sub| {
+        let path = temp_dir.path().join(sub);
+        if !path.exists() {
+            fs::create_dir(&path).unwrap();
+        }
+        path.into_os_string().into_string().unwrap()
+    };
+    let init_storage = |db_key: Option<&[u8]>| {
+        Storage::init_with_db_key(
+            sub_folder("temp/"),
+            sub_folder("doc/"),
+            sub_folder("support/"),
+            sub_folder("cache/"),
+            db_key,
+        )
+    };
+
+    drop(init_storage(Some(&[1; 32])).unwrap());
+    assert!(init_storage(None).is_err());
+    assert!(init_storage(Some(&[2; 32])).is_err());
+    assert!(init_storage(Some(&[1; 32])).is_ok());
+}
@@ -0,0 +1,120 @@
+use memolanes_core::{
+    gps_processor::Point,
+    transport_mode::{self, TimedPoint, TransportMode},
+};
+
+// ~111km per degree of latitude
+const M_PER_DEGREE: f64 = 111_195.0;
+
+// Moving north with the given speed (m/s) for each interval of `interval_sec`.
+fn track(start_sec: i64, interval_sec: i64, speeds: &[f64]) -> Vec<TimedPoint> {
+    let mut latitude = 30.0;
+    let mut points = vec![TimedPoint {
+        timestamp_sec: start_sec,
+        point: Point {
+            latitude,
+            longitude: 120.0,
+        },
+        altitude: None,
+    }];
+    for (i, speed) in speeds.iter().enumerate() {
+        latitude += speed * interval_sec as f64 / M_PER_DEGREE;
+        points.push(TimedPoint {
+            timestamp_sec: start_sec + (i as i64 + 1) * interval_sec,
+            point: Point {
+                latitude,
+                longitude: 120.0,
+            },
+            altitude: None,
+        });
+    }
+    points
+}
+
+fn constant(speed: f64, n: usize) -> Vec<f64> {
+    vec![speed; n]
+}
+
+#[test]
+fn classify() {
+    assert_eq!(
+        transport_mode::classify(&track(0, 10, &constant(1.4, 60))),
+        Some(TransportMode::Walking)
+    );
+    assert_eq!(
+        transport_mode::classify(&track(0, 10, &constant(5.0, 60))),
+        Some(TransportMode::Cycling)
+    );
+
+    // stop and go in the city
+    let mut speeds = Vec::new();
+    for _ in 0..10 {
+        speeds.extend([0.0, 5.0, 12.0, 15.0, 12.0, 5.0]);
+    }
+    assert_eq!(
+        transport_mode::classify(&track(0, 5, &speeds)),
+        Some(TransportMode::Driving)
+    );
+
+    // smooth and stopping at stations
+    let mut speeds = Vec::new();
+    for _ in 0..3 {
+        speeds.extend(constant(0.0, 6));
+        speeds.extend(constant(32.0, 30));
+    }
+    assert_eq!(
+        transport_mode::classify(&track(0, 10, &speeds)),
+        Some(TransportMode::Train)
+    );
+
+    assert_eq!(
+        transport_mode::classify(&track(0, 10, &constant(230.0, 60))),
+        Some(TransportMode::Flight)
+    );
+
+    // not enough data
+    assert_eq!(
+        transport_mode::classify(&track(0, 10, &constant(1.4, 2))),
+        None
+    );
+    assert_eq!(transport_mode::classify(&[]), None);
+}
+
+#[test]
+fn classify_journey() {
+    // a short walk to the car, then a long drive
+    let mut speeds = Vec::new();
+    for _ in 0..30 {
+        speeds.extend([0.0, 5.0, 12.0, 20.0, 12.0, 5.0]);
+    }
+    let segments = vec![track(0, 10, &constant(1.4, 30)), track(1000, 5, &speeds)];
+    assert_eq!(
+        transport_mode::classify_journey(&segments),
+        Some(TransportMode::Driving)
+    );
+    assert_eq!(transport_mode::classify_journey(&[]), None);
+}
+
+#[test]
+fn split_by_transport_mode() {
+    // walking for 20 min, cycling for 1 min (noise), walking for 20 min, then
+    // cycling for 30 min
+    let mut speeds = constant(1.4, 120);
+    speeds.extend(constant(5.0, 6));
+    speeds.extend(constant(1.4, 120));
+    speeds.extend(constant(5.0, 180));
+    let points = track(0, 10, &speeds);
+
+    let runs = transport_mode::split_by_transport_mode(&points);
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0].0, TransportMode::Walking);
+    assert_eq!(runs[1].0, TransportMode::Cycling);
+    assert_eq!(runs[0].1.start, 0);
+    assert_eq!(runs[0].1.end, runs[1].1.start);
+    assert_eq!(runs[1].1.end, points.len());
+    // the switch is detected within a window
+    let switch_sec = points[runs[1].1.start].timestamp_sec;
+    assert!((switch_sec - 2460).abs() <= 3 * 60, "{switch_sec}");
+
+    assert!(transport_mode::split_by_transport_mode(&[]).is_empty());
+}